@@ -6,4 +6,15 @@ fn main() {
     println!("cargo:rustc-env={}={}", database_url_name, database_url);
 
     println!("cargo:rerun-if-changed=migrations");
+
+    // Expose the git commit for the `/status` endpoint
+    let git_commit = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={}", git_commit);
+    println!("cargo:rerun-if-changed=../.git/HEAD");
 }
@@ -0,0 +1,9 @@
+-- Token buckets of the rate limiter when RATE_LIMIT_STORE=postgres
+create table rate_limit_buckets
+(
+    key        varchar(255) primary key,
+    tokens     double precision not null,
+    updated_at timestamptz      not null default now()
+);
+
+create index rate_limit_buckets_updated_at_idx on rate_limit_buckets (updated_at);
@@ -0,0 +1,14 @@
+-- Responses of create requests sent with an Idempotency-Key, replayed on retries
+create table idempotency_keys
+(
+    user_id      integer      not null references users (id) on delete cascade,
+    key          varchar(255) not null,
+    fingerprint  varchar(64)           default null, -- Null until the request body was read
+    status       smallint              default null, -- Null while the request is processed
+    headers      jsonb                 default null, -- End-to-end headers as [name, value] pairs
+    body         bytea                 default null,
+    created_at   timestamptz  not null default now(),
+    primary key (user_id, key)
+);
+
+create index idempotency_keys_created_at_idx on idempotency_keys (created_at);
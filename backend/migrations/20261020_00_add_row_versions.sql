@@ -0,0 +1,9 @@
+-- row versions for optimistic concurrency, sent as ETags and checked against If-Match
+alter table natural_phenomenon_locations
+    add column version integer not null default 1;
+
+alter table weather_locations
+    add column version integer not null default 1;
+
+alter table settings
+    add column version integer not null default 1;
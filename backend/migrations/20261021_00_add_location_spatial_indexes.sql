@@ -0,0 +1,15 @@
+-- spatial indexes for the nearby queries; cube and earthdistance ship with the contrib modules
+create extension if not exists cube;
+create extension if not exists earthdistance;
+
+create index natural_phenomenon_locations_earth_idx
+    on natural_phenomenon_locations using gist (ll_to_earth(latitude, longitude));
+
+create index natural_phenomenon_locations_lat_lon_idx
+    on natural_phenomenon_locations (user_id, latitude, longitude);
+
+create index weather_locations_earth_idx
+    on weather_locations using gist (ll_to_earth(latitude, longitude));
+
+create index weather_locations_lat_lon_idx
+    on weather_locations (user_id, latitude, longitude);
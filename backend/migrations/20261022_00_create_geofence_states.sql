@@ -0,0 +1,14 @@
+-- Phenomenon locations each device of a user is currently inside, to report enter/exit once
+create table geofence_states
+(
+    user_id     integer      not null references users (id) on delete cascade,
+    device_id   varchar(100) not null,
+    location_id integer      not null references natural_phenomenon_locations (id) on delete cascade,
+    entered_at  timestamptz  not null default now(),
+    primary key (user_id, device_id, location_id)
+);
+
+create index geofence_states_location_id_idx on geofence_states (location_id);
+
+-- Largest alert radius of a user, how far a geofence check looks for locations
+create index natural_phenomenon_locations_user_radius_idx on natural_phenomenon_locations (user_id, radius);
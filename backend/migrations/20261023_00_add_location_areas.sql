@@ -0,0 +1,2 @@
+-- Optional GeoJSON polygon of a natural phenomenon location, used instead of its alert radius
+alter table natural_phenomenon_locations add column area jsonb default null;
@@ -0,0 +1,10 @@
+-- User-defined order of the weather locations, starting out as the creation order
+alter table weather_locations add column position integer not null default 0;
+
+update weather_locations w
+set position = o.position
+from (select id, row_number() over (partition by user_id order by created_at, id) - 1 as position
+      from weather_locations) o
+where w.id = o.id;
+
+create index weather_locations_user_id_position_idx on weather_locations (user_id, position, id);
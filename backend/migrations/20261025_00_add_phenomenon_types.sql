@@ -0,0 +1,3 @@
+-- Kind of phenomenon of a natural phenomenon location, used to style exported maps
+alter table natural_phenomenon_locations add column phenomenon_type text not null default 'other'
+    check (phenomenon_type in ('volcano', 'earthquake', 'wildfire', 'flood', 'storm', 'landslide', 'avalanche', 'tsunami', 'other'));
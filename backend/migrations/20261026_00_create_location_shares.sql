@@ -0,0 +1,52 @@
+-- Locations shared by their owner with other users, to read or to edit. Shares name the email
+-- address they were made for; one without an account yet waits for it, so sharing does not
+-- tell which addresses have an account
+create table location_shares
+(
+    id                             serial primary key,
+    weather_location_id            integer               default null references weather_locations (id) on delete cascade,
+    natural_phenomenon_location_id integer               default null references natural_phenomenon_locations (id) on delete cascade,
+    user_id                        integer               default null references users (id) on delete cascade, -- Null until an account with the email address exists
+    email                          varchar(255) not null,
+    permission                     text         not null check (permission in ('read', 'edit')),
+    created_at                     timestamptz  not null default now(),
+    updated_at                     timestamptz  not null default now(),
+    check (num_nonnulls(weather_location_id, natural_phenomenon_location_id) = 1)
+);
+
+create unique index location_shares_target_email_idx
+    on location_shares (weather_location_id, natural_phenomenon_location_id, lower(email)) nulls not distinct;
+create index location_shares_natural_phenomenon_location_id_idx on location_shares (natural_phenomenon_location_id);
+create index location_shares_user_id_idx on location_shares (user_id);
+create index location_shares_pending_email_idx on location_shares (lower(email)) where user_id is null;
+
+-- Read-only links to a location that work without an account, until revoked
+create table location_links
+(
+    id                             serial primary key,
+    weather_location_id            integer              default null references weather_locations (id) on delete cascade,
+    natural_phenomenon_location_id integer              default null references natural_phenomenon_locations (id) on delete cascade,
+    token_hash                     varchar(64) not null unique, -- SHA-256 of the token, which is only shown once
+    created_at                     timestamptz not null default now(),
+    check (num_nonnulls(weather_location_id, natural_phenomenon_location_id) = 1)
+);
+
+create index location_links_weather_location_id_idx on location_links (weather_location_id);
+create index location_links_natural_phenomenon_location_id_idx on location_links (natural_phenomenon_location_id);
+
+-- Who sees each location: its owner, and the users it is shared with
+create view weather_location_access as
+select id as weather_location_id, user_id as viewer_id, 'owner'::text as access
+from weather_locations
+union all
+select weather_location_id, user_id, permission
+from location_shares
+where weather_location_id is not null;
+
+create view natural_phenomenon_location_access as
+select id as natural_phenomenon_location_id, user_id as viewer_id, 'owner'::text as access
+from natural_phenomenon_locations
+union all
+select natural_phenomenon_location_id, user_id, permission
+from location_shares
+where natural_phenomenon_location_id is not null;
@@ -0,0 +1,106 @@
+-- Labels users put on the locations they see
+create table tags
+(
+    id         serial primary key,
+    user_id    integer     not null references users (id) on delete cascade,
+    name       varchar(50) not null,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now()
+);
+
+create unique index tags_user_id_name_idx on tags (user_id, lower(name));
+
+create table location_tags
+(
+    tag_id                         integer     not null references tags (id) on delete cascade,
+    weather_location_id            integer              default null references weather_locations (id) on delete cascade,
+    natural_phenomenon_location_id integer              default null references natural_phenomenon_locations (id) on delete cascade,
+    created_at                     timestamptz not null default now(),
+    check (num_nonnulls(weather_location_id, natural_phenomenon_location_id) = 1),
+    unique nulls not distinct (tag_id, weather_location_id, natural_phenomenon_location_id)
+);
+
+create index location_tags_weather_location_id_idx on location_tags (weather_location_id);
+create index location_tags_natural_phenomenon_location_id_idx on location_tags (natural_phenomenon_location_id);
+
+-- Named groups of locations, such as the sites of one field trip
+create table collections
+(
+    id          serial primary key,
+    user_id     integer      not null references users (id) on delete cascade,
+    name        varchar(100) not null,
+    description text         not null default '',
+    created_at  timestamptz  not null default now(),
+    updated_at  timestamptz  not null default now()
+);
+
+create unique index collections_user_id_name_idx on collections (user_id, lower(name));
+
+create table collection_locations
+(
+    collection_id                  integer     not null references collections (id) on delete cascade,
+    weather_location_id            integer              default null references weather_locations (id) on delete cascade,
+    natural_phenomenon_location_id integer              default null references natural_phenomenon_locations (id) on delete cascade,
+    created_at                     timestamptz not null default now(),
+    check (num_nonnulls(weather_location_id, natural_phenomenon_location_id) = 1),
+    unique nulls not distinct (collection_id, weather_location_id, natural_phenomenon_location_id)
+);
+
+create index collection_locations_weather_location_id_idx on collection_locations (weather_location_id);
+create index collection_locations_natural_phenomenon_location_id_idx on collection_locations (natural_phenomenon_location_id);
+
+-- Collections shared by their owner, giving access to the owner's locations in them
+alter table location_shares
+    add column collection_id integer default null references collections (id) on delete cascade;
+alter table location_shares
+    drop constraint location_shares_check,
+    add check (num_nonnulls(weather_location_id, natural_phenomenon_location_id, collection_id) = 1);
+
+drop index location_shares_target_email_idx;
+create unique index location_shares_target_email_idx
+    on location_shares (weather_location_id, natural_phenomenon_location_id, collection_id, lower(email)) nulls not distinct;
+create index location_shares_collection_id_idx on location_shares (collection_id);
+
+-- Who sees each collection: its owner, and the users it is shared with
+create view collection_access as
+select id as collection_id, user_id as viewer_id, 'owner'::text as access
+from collections
+union all
+select collection_id, user_id, permission
+from location_shares
+where collection_id is not null;
+
+-- A location reached both on its own and through collections counts with the most access
+create or replace view weather_location_access as
+select distinct on (weather_location_id, viewer_id) weather_location_id, viewer_id, access
+from (select id as weather_location_id, user_id as viewer_id, 'owner'::text as access
+      from weather_locations
+      union all
+      select weather_location_id, user_id, permission
+      from location_shares
+      where weather_location_id is not null
+      union all
+      select cl.weather_location_id, s.user_id, s.permission
+      from location_shares s
+               join collections c on c.id = s.collection_id
+               join collection_locations cl on cl.collection_id = c.id
+               join weather_locations w on w.id = cl.weather_location_id
+      where w.user_id = c.user_id) a
+order by weather_location_id, viewer_id, access = 'owner' desc, access = 'edit' desc;
+
+create or replace view natural_phenomenon_location_access as
+select distinct on (natural_phenomenon_location_id, viewer_id) natural_phenomenon_location_id, viewer_id, access
+from (select id as natural_phenomenon_location_id, user_id as viewer_id, 'owner'::text as access
+      from natural_phenomenon_locations
+      union all
+      select natural_phenomenon_location_id, user_id, permission
+      from location_shares
+      where natural_phenomenon_location_id is not null
+      union all
+      select cl.natural_phenomenon_location_id, s.user_id, s.permission
+      from location_shares s
+               join collections c on c.id = s.collection_id
+               join collection_locations cl on cl.collection_id = c.id
+               join natural_phenomenon_locations n on n.id = cl.natural_phenomenon_location_id
+      where n.user_id = c.user_id) a
+order by natural_phenomenon_location_id, viewer_id, access = 'owner' desc, access = 'edit' desc;
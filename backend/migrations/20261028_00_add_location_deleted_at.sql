@@ -0,0 +1,50 @@
+-- Deleted locations stay in the trash until restored or purged
+alter table weather_locations
+    add column deleted_at timestamptz default null;
+alter table natural_phenomenon_locations
+    add column deleted_at timestamptz default null;
+
+create index weather_locations_deleted_at_idx on weather_locations (deleted_at) where deleted_at is not null;
+create index natural_phenomenon_locations_deleted_at_idx on natural_phenomenon_locations (deleted_at) where deleted_at is not null;
+
+-- Locations in the trash are seen by nobody, not even the users they are shared with or the
+-- users of the collections they are in
+create or replace view weather_location_access as
+select distinct on (weather_location_id, viewer_id) weather_location_id, viewer_id, access
+from (select id as weather_location_id, user_id as viewer_id, 'owner'::text as access
+      from weather_locations
+      where deleted_at is null
+      union all
+      select s.weather_location_id, s.user_id, s.permission
+      from location_shares s
+               join weather_locations w on w.id = s.weather_location_id
+      where w.deleted_at is null
+      union all
+      select cl.weather_location_id, s.user_id, s.permission
+      from location_shares s
+               join collections c on c.id = s.collection_id
+               join collection_locations cl on cl.collection_id = c.id
+               join weather_locations w on w.id = cl.weather_location_id
+      where w.deleted_at is null
+        and w.user_id = c.user_id) a
+order by weather_location_id, viewer_id, access = 'owner' desc, access = 'edit' desc;
+
+create or replace view natural_phenomenon_location_access as
+select distinct on (natural_phenomenon_location_id, viewer_id) natural_phenomenon_location_id, viewer_id, access
+from (select id as natural_phenomenon_location_id, user_id as viewer_id, 'owner'::text as access
+      from natural_phenomenon_locations
+      where deleted_at is null
+      union all
+      select s.natural_phenomenon_location_id, s.user_id, s.permission
+      from location_shares s
+               join natural_phenomenon_locations n on n.id = s.natural_phenomenon_location_id
+      where n.deleted_at is null
+      union all
+      select cl.natural_phenomenon_location_id, s.user_id, s.permission
+      from location_shares s
+               join collections c on c.id = s.collection_id
+               join collection_locations cl on cl.collection_id = c.id
+               join natural_phenomenon_locations n on n.id = cl.natural_phenomenon_location_id
+      where n.deleted_at is null
+        and n.user_id = c.user_id) a
+order by natural_phenomenon_location_id, viewer_id, access = 'owner' desc, access = 'edit' desc;
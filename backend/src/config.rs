@@ -1,6 +1,11 @@
 use crate::shared::models::AppStage;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
 use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct WapSettings {
@@ -12,6 +17,526 @@ pub struct WapSettings {
     pub google_oauth_client_secret: Option<String>,
     pub google_oauth_redirect_url: Option<String>,
     pub stage: AppStage,
+    /// Time the readiness probe fails before the server stops on a shutdown signal
+    /// (`SHUTDOWN_DRAIN_SECS`).
+    pub shutdown_drain: Duration,
+    pub db_pool: DbPoolSettings,
+    pub telemetry: TelemetrySettings,
+    pub rate_limit: RateLimitSettings,
+    pub http: HttpSettings,
+    pub api: ApiSettings,
+    pub idempotency: IdempotencySettings,
+    pub concurrency: ConcurrencySettings,
+    pub metrics: MetricsSettings,
+    pub trash: TrashSettings,
+}
+
+/// Logging and tracing export settings.
+#[derive(Debug, Clone)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP collector endpoint, e.g. `http://localhost:4318/v1/traces`
+    /// (`OTEL_EXPORTER_OTLP_ENDPOINT`). Spans are only exported when set.
+    pub otlp_endpoint: Option<String>,
+    /// Service name reported with every span (`OTEL_SERVICE_NAME`).
+    pub service_name: String,
+    /// Output format of the log lines (`LOG_FORMAT`).
+    pub log_format: LogFormat,
+    /// Also write logs to rotating files in this directory (`LOG_DIR`).
+    pub log_dir: Option<PathBuf>,
+    /// How often the log file is rotated (`LOG_ROTATION`).
+    pub log_rotation: LogRotation,
+}
+
+/// Log line format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Multi-line, human friendly output for local development.
+    Pretty,
+    /// One line per event, human readable.
+    Compact,
+    /// One JSON object per line for log aggregation.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format: {}", s)),
+        }
+    }
+}
+
+/// Log file rotation period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "minutely" => Ok(LogRotation::Minutely),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "never" => Ok(LogRotation::Never),
+            _ => Err(format!("unknown log rotation: {}", s)),
+        }
+    }
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        TelemetrySettings {
+            otlp_endpoint: None,
+            service_name: "wap-backend".to_string(),
+            log_format: LogFormat::Compact,
+            log_dir: None,
+            log_rotation: LogRotation::Daily,
+        }
+    }
+}
+
+impl TelemetrySettings {
+    /// Read the telemetry settings from the environment, falling back to the defaults.
+    pub fn from_env() -> TelemetrySettings {
+        let defaults = TelemetrySettings::default();
+        TelemetrySettings {
+            otlp_endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|v| !v.is_empty()),
+            service_name: env_or("OTEL_SERVICE_NAME", defaults.service_name),
+            log_format: env_or("LOG_FORMAT", defaults.log_format),
+            log_dir: std::env::var("LOG_DIR")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from),
+            log_rotation: env_or("LOG_ROTATION", defaults.log_rotation),
+        }
+    }
+}
+
+/// Prometheus scrape endpoint, see `routes::metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSettings {
+    /// Bearer token the scraper must send to `/metrics` (`METRICS_TOKEN`). The endpoint is
+    /// not served without one.
+    pub token: Option<String>,
+}
+
+impl MetricsSettings {
+    /// Read the metrics settings from the environment.
+    pub fn from_env() -> MetricsSettings {
+        MetricsSettings {
+            token: env_opt("METRICS_TOKEN"),
+        }
+    }
+}
+
+/// Replay of create requests sent with an `Idempotency-Key`, see `shared::idempotency`.
+#[derive(Debug, Clone)]
+pub struct IdempotencySettings {
+    /// How long a stored response is replayed (`IDEMPOTENCY_TTL_SECS`).
+    pub ttl: Duration,
+    /// Interval of the job deleting expired responses (`IDEMPOTENCY_PURGE_INTERVAL_SECS`).
+    pub purge_interval: Duration,
+}
+
+impl Default for IdempotencySettings {
+    fn default() -> Self {
+        IdempotencySettings {
+            ttl: Duration::from_secs(24 * 60 * 60),
+            purge_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl IdempotencySettings {
+    /// Read the idempotency settings from the environment, falling back to the defaults.
+    pub fn from_env() -> IdempotencySettings {
+        let defaults = IdempotencySettings::default();
+        IdempotencySettings {
+            ttl: env_secs_or("IDEMPOTENCY_TTL_SECS", defaults.ttl),
+            purge_interval: env_interval_or(
+                "IDEMPOTENCY_PURGE_INTERVAL_SECS",
+                defaults.purge_interval,
+            ),
+        }
+    }
+}
+
+/// Optimistic concurrency control of updates, see `shared::preconditions`.
+#[derive(Debug, Clone, Default)]
+pub struct ConcurrencySettings {
+    /// Reject updates and deletes without `If-Match` with a 428 (`CONCURRENCY_REQUIRE_IF_MATCH`).
+    pub require_if_match: bool,
+}
+
+impl ConcurrencySettings {
+    /// Read the concurrency settings from the environment, falling back to the defaults.
+    pub fn from_env() -> ConcurrencySettings {
+        let defaults = ConcurrencySettings::default();
+        ConcurrencySettings {
+            require_if_match: env_or("CONCURRENCY_REQUIRE_IF_MATCH", defaults.require_if_match),
+        }
+    }
+}
+
+/// Deleted locations kept in the trash, see `shared::trash`.
+#[derive(Debug, Clone)]
+pub struct TrashSettings {
+    /// How long a deleted location can be restored (`TRASH_RETENTION_SECS`).
+    pub retention: Duration,
+    /// Interval of the job purging expired locations (`TRASH_PURGE_INTERVAL_SECS`).
+    pub purge_interval: Duration,
+}
+
+impl Default for TrashSettings {
+    fn default() -> Self {
+        TrashSettings {
+            retention: Duration::from_secs(30 * 24 * 60 * 60),
+            purge_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl TrashSettings {
+    /// Read the trash settings from the environment, falling back to the defaults.
+    pub fn from_env() -> TrashSettings {
+        let defaults = TrashSettings::default();
+        TrashSettings {
+            retention: env_secs_or("TRASH_RETENTION_SECS", defaults.retention),
+            purge_interval: env_interval_or("TRASH_PURGE_INTERVAL_SECS", defaults.purge_interval),
+        }
+    }
+}
+
+/// API versioning, see `shared::versioning`.
+#[derive(Debug, Clone)]
+pub struct ApiSettings {
+    /// Also serve the routes at their unversioned paths (`API_LEGACY_ROUTES`).
+    pub legacy_routes: bool,
+    /// Sent as `Deprecation` on legacy routes (`API_LEGACY_DEPRECATED_AT`, RFC 3339).
+    pub legacy_deprecated_at: Option<DateTime<Utc>>,
+    /// Sent as `Sunset` on legacy routes (`API_LEGACY_SUNSET`, RFC 3339).
+    pub legacy_sunset: Option<DateTime<Utc>>,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        ApiSettings {
+            legacy_routes: true,
+            legacy_deprecated_at: None,
+            legacy_sunset: None,
+        }
+    }
+}
+
+impl ApiSettings {
+    /// Read the versioning settings from the environment, falling back to the defaults.
+    pub fn from_env() -> ApiSettings {
+        let defaults = ApiSettings::default();
+        ApiSettings {
+            legacy_routes: env_or("API_LEGACY_ROUTES", defaults.legacy_routes),
+            legacy_deprecated_at: env_opt("API_LEGACY_DEPRECATED_AT"),
+            legacy_sunset: env_opt("API_LEGACY_SUNSET"),
+        }
+    }
+}
+
+/// Request timeouts, body size and concurrency limits, see `shared::limits`.
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    /// Time a request may take, including reading its body (`HTTP_REQUEST_TIMEOUT_SECS`).
+    pub request_timeout: Duration,
+    /// Time an upload request may take (`HTTP_UPLOAD_TIMEOUT_SECS`).
+    pub upload_timeout: Duration,
+    /// Largest request body of routes without their own limit (`HTTP_BODY_LIMIT_BYTES`).
+    pub body_limit: usize,
+    /// Requests handled at once; more are rejected with a 503 (`HTTP_MAX_CONCURRENT_REQUESTS`).
+    pub max_concurrent_requests: usize,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            request_timeout: Duration::from_secs(30),
+            upload_timeout: Duration::from_secs(5 * 60),
+            body_limit: 1024 * 1024,
+            max_concurrent_requests: 256,
+        }
+    }
+}
+
+impl HttpSettings {
+    /// Read the HTTP limits from the environment, falling back to the defaults.
+    pub fn from_env() -> HttpSettings {
+        let defaults = HttpSettings::default();
+        HttpSettings {
+            request_timeout: env_interval_or("HTTP_REQUEST_TIMEOUT_SECS", defaults.request_timeout),
+            upload_timeout: env_interval_or("HTTP_UPLOAD_TIMEOUT_SECS", defaults.upload_timeout),
+            body_limit: env_nonzero_or("HTTP_BODY_LIMIT_BYTES", defaults.body_limit),
+            max_concurrent_requests: env_nonzero_or(
+                "HTTP_MAX_CONCURRENT_REQUESTS",
+                defaults.max_concurrent_requests,
+            ),
+        }
+    }
+}
+
+/// Request rate limiting, see `shared::rate_limit`.
+#[derive(Debug, Clone)]
+pub struct RateLimitSettings {
+    /// Whether requests are limited at all (`RATE_LIMIT_ENABLED`).
+    pub enabled: bool,
+    /// Where the buckets are kept (`RATE_LIMIT_STORE`); use `postgres` with several instances.
+    pub store: RateLimitStoreKind,
+    /// Proxies whose `X-Forwarded-For` header is trusted, as addresses or CIDR ranges
+    /// (`RATE_LIMIT_TRUSTED_PROXIES`, comma separated).
+    pub trusted_proxies: Vec<IpNet>,
+    /// Budget of every route without a more specific one (`RATE_LIMIT_DEFAULT`).
+    pub default: RateBudget,
+    /// Budget of login, registration and token refresh, per client IP (`RATE_LIMIT_AUTH`).
+    pub auth: RateBudget,
+    /// Budget of image uploads (`RATE_LIMIT_UPLOAD`).
+    pub upload: RateBudget,
+    /// Interval of the job deleting idle buckets of the Postgres store
+    /// (`RATE_LIMIT_PRUNE_INTERVAL_SECS`).
+    pub prune_interval: Duration,
+}
+
+/// Where rate limit buckets are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitStoreKind {
+    /// In process memory, only correct for a single instance.
+    Memory,
+    /// Shared `rate_limit_buckets` table.
+    Postgres,
+}
+
+impl FromStr for RateLimitStoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(RateLimitStoreKind::Memory),
+            "postgres" => Ok(RateLimitStoreKind::Postgres),
+            _ => Err(format!("unknown rate limit store: {}", s)),
+        }
+    }
+}
+
+/// `requests` per `period`, written as `N/SECS`, e.g. `120/60`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateBudget {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl RateBudget {
+    pub const fn new(requests: u32, period_secs: u64) -> Self {
+        RateBudget {
+            requests,
+            period: Duration::from_secs(period_secs),
+        }
+    }
+}
+
+impl FromStr for RateBudget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("rate budget must look like 120/60: {}", s);
+        let (requests, secs) = s.split_once('/').ok_or_else(invalid)?;
+        let requests = requests.trim().parse::<u32>().map_err(|_| invalid())?;
+        let secs = secs.trim().parse::<u64>().map_err(|_| invalid())?;
+        if requests == 0 || secs == 0 {
+            return Err(invalid());
+        }
+        Ok(RateBudget::new(requests, secs))
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        RateLimitSettings {
+            enabled: true,
+            store: RateLimitStoreKind::Memory,
+            trusted_proxies: Vec::new(),
+            default: RateBudget::new(120, 60),
+            auth: RateBudget::new(10, 60),
+            upload: RateBudget::new(20, 60),
+            prune_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RateLimitSettings {
+    /// Read the rate limit settings from the environment, falling back to the defaults.
+    pub fn from_env() -> RateLimitSettings {
+        let defaults = RateLimitSettings::default();
+        let trusted_proxies = std::env::var("RATE_LIMIT_TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                // a plain address is a range of one
+                v.parse::<IpNet>()
+                    .or_else(|_| v.parse::<std::net::IpAddr>().map(IpNet::from))
+                    .unwrap_or_else(|_| {
+                        panic!("Invalid value for RATE_LIMIT_TRUSTED_PROXIES: {}", v)
+                    })
+            })
+            .collect();
+        RateLimitSettings {
+            enabled: env_or("RATE_LIMIT_ENABLED", defaults.enabled),
+            store: env_or("RATE_LIMIT_STORE", defaults.store),
+            trusted_proxies,
+            default: env_or("RATE_LIMIT_DEFAULT", defaults.default),
+            auth: env_or("RATE_LIMIT_AUTH", defaults.auth),
+            upload: env_or("RATE_LIMIT_UPLOAD", defaults.upload),
+            prune_interval: env_interval_or(
+                "RATE_LIMIT_PRUNE_INTERVAL_SECS",
+                defaults.prune_interval,
+            ),
+        }
+    }
+}
+
+/// Connection pool and startup tuning for Postgres.
+///
+/// Every value can be overridden through the environment; see `DbPoolSettings::from_env`.
+#[derive(Debug, Clone)]
+pub struct DbPoolSettings {
+    /// Upper bound of open connections (`DB_MAX_CONNECTIONS`).
+    pub max_connections: u32,
+    /// Connections kept open even when idle (`DB_MIN_CONNECTIONS`).
+    pub min_connections: u32,
+    /// Close connections idle for longer than this (`DB_IDLE_TIMEOUT_SECS`).
+    pub idle_timeout: Duration,
+    /// Recycle connections older than this (`DB_MAX_LIFETIME_SECS`).
+    pub max_lifetime: Duration,
+    /// How long a handler may wait for a free connection (`DB_ACQUIRE_TIMEOUT_SECS`).
+    pub acquire_timeout: Duration,
+    /// Postgres `statement_timeout` applied to every connection (`DB_STATEMENT_TIMEOUT_MS`).
+    pub statement_timeout: Duration,
+    /// First delay between connection attempts at startup (`DB_CONNECT_RETRY_INITIAL_MS`).
+    pub connect_retry_initial: Duration,
+    /// Cap for the exponential backoff delay (`DB_CONNECT_RETRY_MAX_MS`).
+    pub connect_retry_max: Duration,
+    /// Give up connecting after this much time in total (`DB_CONNECT_MAX_WAIT_SECS`).
+    pub connect_max_wait: Duration,
+    /// Interval of the background pool health monitor (`DB_MONITOR_INTERVAL_SECS`).
+    pub monitor_interval: Duration,
+    /// Ratio of busy connections from which the monitor warns (`DB_SATURATION_WARN_RATIO`).
+    pub saturation_warn_ratio: f64,
+}
+
+impl Default for DbPoolSettings {
+    fn default() -> Self {
+        DbPoolSettings {
+            max_connections: 5,
+            min_connections: 0,
+            idle_timeout: Duration::from_secs(10 * 60),
+            max_lifetime: Duration::from_secs(30 * 60),
+            acquire_timeout: Duration::from_secs(30),
+            statement_timeout: Duration::from_secs(30),
+            connect_retry_initial: Duration::from_millis(500),
+            connect_retry_max: Duration::from_secs(10),
+            connect_max_wait: Duration::from_secs(60),
+            monitor_interval: Duration::from_secs(30),
+            saturation_warn_ratio: 0.8,
+        }
+    }
+}
+
+impl DbPoolSettings {
+    /// Read the pool settings from the environment, falling back to the defaults.
+    pub fn from_env() -> DbPoolSettings {
+        let defaults = DbPoolSettings::default();
+        DbPoolSettings {
+            max_connections: env_or("DB_MAX_CONNECTIONS", defaults.max_connections),
+            min_connections: env_or("DB_MIN_CONNECTIONS", defaults.min_connections),
+            idle_timeout: env_secs_or("DB_IDLE_TIMEOUT_SECS", defaults.idle_timeout),
+            max_lifetime: env_secs_or("DB_MAX_LIFETIME_SECS", defaults.max_lifetime),
+            acquire_timeout: env_secs_or("DB_ACQUIRE_TIMEOUT_SECS", defaults.acquire_timeout),
+            statement_timeout: env_millis_or("DB_STATEMENT_TIMEOUT_MS", defaults.statement_timeout),
+            connect_retry_initial: env_millis_or(
+                "DB_CONNECT_RETRY_INITIAL_MS",
+                defaults.connect_retry_initial,
+            ),
+            connect_retry_max: env_millis_or("DB_CONNECT_RETRY_MAX_MS", defaults.connect_retry_max),
+            connect_max_wait: env_secs_or("DB_CONNECT_MAX_WAIT_SECS", defaults.connect_max_wait),
+            monitor_interval: env_interval_or(
+                "DB_MONITOR_INTERVAL_SECS",
+                defaults.monitor_interval,
+            ),
+            saturation_warn_ratio: env_or(
+                "DB_SATURATION_WARN_RATIO",
+                defaults.saturation_warn_ratio,
+            ),
+        }
+    }
+}
+
+/// Parse an optional environment variable, panicking on malformed values so typos are not
+/// silently ignored.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse::<T>()
+            .unwrap_or_else(|_| panic!("Invalid value for {}: {}", name, value)),
+        Err(_) => default,
+    }
+}
+
+/// Parse an optional environment variable without a default; empty values count as unset.
+pub fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .parse::<T>()
+                .unwrap_or_else(|_| panic!("Invalid value for {}: {}", name, value))
+        })
+}
+
+fn env_secs_or(name: &str, default: Duration) -> Duration {
+    Duration::from_secs(env_or(name, default.as_secs()))
+}
+
+/// Like `env_secs_or` for the period of a background job or a timeout, which must not be
+/// zero.
+fn env_interval_or(name: &str, default: Duration) -> Duration {
+    let interval = env_secs_or(name, default);
+    if interval.is_zero() {
+        panic!("Invalid value for {}: must be at least 1 second", name);
+    }
+    interval
+}
+
+/// Like `env_or` for a size or count limit, which must not be zero.
+fn env_nonzero_or(name: &str, default: usize) -> usize {
+    let value = env_or(name, default);
+    if value == 0 {
+        panic!("Invalid value for {}: must be at least 1", name);
+    }
+    value
+}
+
+fn env_millis_or(name: &str, default: Duration) -> Duration {
+    Duration::from_millis(env_or(name, default.as_millis() as u64))
 }
 
 #[async_trait]
@@ -42,6 +567,7 @@ impl WapSettings {
             google_oauth_client_id: Some(google_oauth_client_id),
             google_oauth_client_secret: Some(google_oauth_client_secret),
             google_oauth_redirect_url: Some(google_oauth_redirect_url),
+            shutdown_drain: env_secs_or("SHUTDOWN_DRAIN_SECS", Duration::from_secs(5)),
             stage: if stage == "development" {
                 AppStage::Development
             } else if stage == "staging" {
@@ -49,8 +575,19 @@ impl WapSettings {
             } else if stage == "production" {
                 AppStage::Production
             } else {
-                panic!("Invalid STAGE value, put into .env file: STAGE=development|staging|production");
+                panic!(
+                    "Invalid STAGE value, put into .env file: STAGE=development|staging|production"
+                );
             },
+            db_pool: DbPoolSettings::from_env(),
+            telemetry: TelemetrySettings::from_env(),
+            rate_limit: RateLimitSettings::from_env(),
+            http: HttpSettings::from_env(),
+            api: ApiSettings::from_env(),
+            idempotency: IdempotencySettings::from_env(),
+            concurrency: ConcurrencySettings::from_env(),
+            metrics: MetricsSettings::from_env(),
+            trash: TrashSettings::from_env(),
         }
     }
 }
@@ -79,8 +616,41 @@ impl fmt::Display for WapSettings {
   Database URL: {}
   JWT Secret: {}
   JWT Expires In: {}
-  JWT MaxAge: {}",
-            self.database_url, self.jwt_secret, self.jwt_expires_in, self.jwt_maxage
+  JWT MaxAge: {}
+  DB Pool: max={} min={} statement_timeout={:?}",
+            self.database_url,
+            self.jwt_secret,
+            self.jwt_expires_in,
+            self.jwt_maxage,
+            self.db_pool.max_connections,
+            self.db_pool.min_connections,
+            self.db_pool.statement_timeout
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_settings_parsing() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("Pretty".parse(), Ok(LogFormat::Pretty));
+        assert_eq!("COMPACT".parse(), Ok(LogFormat::Compact));
+        assert!("yaml".parse::<LogFormat>().is_err());
+
+        assert_eq!("hourly".parse(), Ok(LogRotation::Hourly));
+        assert_eq!("Never".parse(), Ok(LogRotation::Never));
+        assert!("weekly".parse::<LogRotation>().is_err());
+
+        // unset variables keep the defaults
+        let defaults = TelemetrySettings::default();
+        assert_eq!(defaults.log_format, LogFormat::Compact);
+        assert_eq!(defaults.log_rotation, LogRotation::Daily);
+        assert_eq!(
+            env_or("WAP_TEST_UNSET_LOG_FORMAT", LogFormat::Json),
+            LogFormat::Json
+        );
+    }
+}
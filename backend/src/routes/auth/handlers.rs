@@ -1,4 +1,4 @@
-use axum::extract::{Path, Query};
+use axum::extract::Path;
 use axum::{
     extract::{Json, State},
     http::{Response, StatusCode},
@@ -10,6 +10,7 @@ use serde_json::json;
 use std::sync::Arc;
 
 use crate::shared::models::{AppState, DatabaseId};
+use crate::shared::validation::{ValidatedJson, ValidatedQuery};
 
 // TODO: add to response also user dat
 // TODO: add endpoint to change user passwords
@@ -21,15 +22,16 @@ use crate::shared::models::{AppState, DatabaseId};
 // SIGNUP handler
 
 use crate::routes::auth::models::{
-    AuthError, AuthErrorKind, AuthSuccessKind, ChangePasswordRequest, LoginError, LoginSuccess,
-    LoginUser, LoginUserSchema, OAuthParams, RefreshSuccess, RegisterError,
-    RegisterResponseSuccess, RegisterUserRequestSchema, UpdateUserInfoRequest, UserData, UserDb,
-    UserRegisterResponse,
+    AuthSuccessKind, ChangePasswordRequest, LoginSuccess, LoginUser, LoginUserSchema, OAuthParams,
+    RefreshSuccess, RegisterResponseSuccess, RegisterUserRequestSchema, UpdateUserInfoRequest,
+    UserData, UserDb, UserRegisterResponse,
 };
 use crate::routes::auth::services::{
     create_login_response, AuthService, AuthServiceImpl, GoogleAuthService, JwtConfigImpl,
 };
 use crate::routes::auth::{middlewares, services};
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::metrics::record_auth_failure;
 use utoipa::ToSchema;
 use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouterExt};
 use utoipa_axum::routes;
@@ -39,24 +41,33 @@ pub struct SignupResponse {
     pub message: String,
 }
 
+/// Count the failure in the metrics before handing the error back to axum.
+fn auth_failure(err: ApiError) -> ApiError {
+    record_auth_failure(err.code());
+    err
+}
+
 #[utoipa::path(
     post,
     path = "/auth/register",
     request_body(content = RegisterUserRequestSchema, content_type = "application/json"),
     responses(
         (status = axum::http::StatusCode::OK, description = "Success", body = RegisterResponseSuccess, content_type = "application/json"),
-        (status = axum::http::StatusCode::BAD_REQUEST, body = RegisterError, description = "Error", content_type = "application/json")
+        (status = axum::http::StatusCode::CONFLICT, body = ProblemDetails, description = "User already exists", content_type = "application/problem+json"),
+        (status = axum::http::StatusCode::UNPROCESSABLE_ENTITY, body = ProblemDetails, description = "Validation failed", content_type = "application/problem+json")
     )
 )]
 pub async fn register<S>(
     State(service): State<Arc<S>>,
-    Json(body): Json<RegisterUserRequestSchema>,
-) -> Result<AuthSuccessKind<RegisterResponseSuccess>, (StatusCode, Json<AuthErrorKind>)>
-// ) -> Result<impl IntoResponse, (StatusCode, Json<AuthErrorKind>)>
+    ValidatedJson(body): ValidatedJson<RegisterUserRequestSchema>,
+) -> Result<AuthSuccessKind<RegisterResponseSuccess>, ApiError>
 where
     S: AuthServiceImpl,
 {
-    let user = service.register_new_user(&body).await?;
+    let user = service
+        .register_new_user(&body)
+        .await
+        .map_err(auth_failure)?;
 
     Ok(AuthSuccessKind::Created(
         StatusCode::CREATED,
@@ -86,24 +97,18 @@ fn filter_user_record(user: &UserDb) -> UserRegisterResponse {
     request_body(content = LoginUser, content_type = "application/json"),
     responses(
         (status = axum::http::StatusCode::OK, body=LoginSuccess, description = "Success", content_type = "application/json"),
-        (status = axum::http::StatusCode::BAD_REQUEST, body=LoginError, description = "Error", content_type = "application/json")
+        (status = axum::http::StatusCode::UNAUTHORIZED, body = ProblemDetails, description = "Invalid credentials", content_type = "application/problem+json"),
+        (status = axum::http::StatusCode::UNPROCESSABLE_ENTITY, body = ProblemDetails, description = "Validation failed", content_type = "application/problem+json")
     )
 )]
 pub async fn login<S>(
     State(service): State<Arc<S>>,
-    Json(body): Json<LoginUserSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<LoginError>)>
+    ValidatedJson(body): ValidatedJson<LoginUserSchema>,
+) -> Result<impl IntoResponse, ApiError>
 where
     S: AuthServiceImpl,
 {
-    let user = service.login(&body).await.map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(LoginError {
-                message: e.to_string(),
-            }),
-        )
-    })?;
+    let user = service.login(&body).await.map_err(auth_failure)?;
 
     let data = create_login_response(user.clone(), &*service).await;
     let mut response = Response::new(json!(data).to_string());
@@ -116,13 +121,13 @@ where
     path = "/auth/refresh",
     responses(
         (status = axum::http::StatusCode::OK, body=RefreshSuccess, description = "Success", content_type = "application/json"),
-        (status = axum::http::StatusCode::BAD_REQUEST, body=AuthError, description = "Error", content_type = "application/json")
+        (status = axum::http::StatusCode::UNAUTHORIZED, body = ProblemDetails, description = "Unauthorized", content_type = "application/problem+json")
     )
 )]
 pub async fn refresh<S>(
     State(state): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-) -> Result<impl IntoResponse, (StatusCode, Json<AuthError>)>
+) -> Result<impl IntoResponse, ApiError>
 where
     S: AuthServiceImpl,
 {
@@ -162,42 +167,41 @@ where
     path = "/auth/google",
     responses(
         (status = axum::http::StatusCode::OK, body=RefreshSuccess, description = "Success", content_type = "application/json"),
-        (status = axum::http::StatusCode::BAD_REQUEST, body=AuthError, description = "Error", content_type = "application/json")
+        (status = axum::http::StatusCode::UNPROCESSABLE_ENTITY, body = ProblemDetails, description = "Missing code", content_type = "application/problem+json"),
+        (status = axum::http::StatusCode::BAD_GATEWAY, body = ProblemDetails, description = "Google request failed", content_type = "application/problem+json")
     )
 )]
 pub async fn google_oauth_handler<S>(
     State(service): State<Arc<S>>,
-    Query(params): Query<OAuthParams>,
-) -> Result<impl IntoResponse, (StatusCode, Json<AuthErrorKind>)>
+    ValidatedQuery(params): ValidatedQuery<OAuthParams>,
+) -> Result<impl IntoResponse, ApiError>
 where
     S: GoogleAuthService,
 {
-    // 1) missing code → 400
-    if params.code.trim().is_empty() {
-        return Err((StatusCode::BAD_REQUEST, Json(AuthErrorKind::MissingCode)));
-    }
+    // 1) missing code is rejected by the extractor → 422
 
     // 2) exchange code → token_response or 502
-    let token_resp = service.request_token(&params.code).await.map_err(|e| {
-        (
-            StatusCode::BAD_GATEWAY,
-            Json(AuthErrorKind::TokenExchangeError(e.to_string())),
-        )
-    })?;
+    let token_resp = service
+        .request_token(&params.code)
+        .await
+        .map_err(|e| auth_failure(ApiError::BadGateway(format!("Token exchange error: {}", e))))?;
 
     // 3) fetch Google user → or 502
     let google_user = service
         .get_google_user(&token_resp.access_token, &token_resp.id_token)
         .await
         .map_err(|e| {
-            (
-                StatusCode::BAD_GATEWAY,
-                Json(AuthErrorKind::GoogleUserFetchError(e.to_string())),
-            )
+            auth_failure(ApiError::BadGateway(format!(
+                "Google user fetch error: {}",
+                e
+            )))
         })?;
 
     // 4) upsert into your DB & get back a user_id or 500
-    let user: UserDb = service.upsert_google_user(&google_user).await?;
+    let user: UserDb = service
+        .upsert_google_user(&google_user)
+        .await
+        .map_err(auth_failure)?;
 
     let data = create_login_response(user.clone(), &*service).await;
     let mut response = Response::new(json!(data).to_string());
@@ -210,22 +214,19 @@ where
     path = "/auth/me",
     responses(
         (status = 200, body = UserData, description = "Current user info", content_type = "application/json"),
-        (status = 401, body = AuthError, description = "Unauthorized", content_type = "application/json"),
-        (status = 500, body = AuthError, description = "Internal error", content_type = "application/json")
+        (status = 401, body = ProblemDetails, description = "Unauthorized", content_type = "application/problem+json"),
+        (status = 500, body = ProblemDetails, description = "Internal error", content_type = "application/problem+json")
     )
 )]
 pub async fn user_info<S>(
     State(svc): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-) -> Result<impl IntoResponse, (StatusCode, Json<AuthError>)>
+) -> Result<impl IntoResponse, ApiError>
 where
     S: AuthServiceImpl,
 {
     // 4) Load the user
-    let user: UserDb = svc
-        .refresh(user.id)
-        .await
-        .map_err(|(code, err)| (code, Json(AuthError::new(err.0.message.clone()))))?;
+    let user: UserDb = svc.refresh(user.id).await?;
 
     // 5) Map to your public DTO
     let me = UserData {
@@ -249,22 +250,24 @@ where
     request_body(content = ChangePasswordRequest, content_type = "application/json"),
     responses(
         (status = 204, description = "Password changed successfully"),
-        (status = 400, description = "Bad request", body = AuthError, content_type = "application/json"),
-        (status = 401, description = "Unauthorized", body = AuthError, content_type = "application/json"),
-        (status = 500, description = "Internal error", body = AuthError, content_type = "application/json")
+        (status = 400, description = "Bad request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn change_password<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-    Json(body): Json<ChangePasswordRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<AuthErrorKind>)>
+    ValidatedJson(body): ValidatedJson<ChangePasswordRequest>,
+) -> Result<impl IntoResponse, ApiError>
 where
     S: AuthServiceImpl,
 {
     service
         .change_password(user.id, &body.current_password, &body.new_password, false)
-        .await?;
+        .await
+        .map_err(auth_failure)?;
 
     Ok((StatusCode::NO_CONTENT, "Password changed successfully"))
 }
@@ -275,20 +278,25 @@ where
     request_body(content = UpdateUserInfoRequest, content_type = "application/json"),
     responses(
         (status = 200, body = UserData, description = "User info updated successfully", content_type = "application/json"),
-        (status = 400, description = "Bad request", body = AuthError, content_type = "application/json"),
-        (status = 401, description = "Unauthorized", body = AuthError, content_type = "application/json"),
-        (status = 500, description = "Internal error", body = AuthError, content_type = "application/json")
+        (status = 400, description = "Bad request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "User not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn update_user_info<S>(
     State(service): State<Arc<S>>,
     Path(user_id): Path<DatabaseId>,
-    Json(payload): Json<UpdateUserInfoRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<AuthErrorKind>)>
+    ValidatedJson(payload): ValidatedJson<UpdateUserInfoRequest>,
+) -> Result<impl IntoResponse, ApiError>
 where
     S: AuthServiceImpl,
 {
-    let updated = service.update_user_info(user_id, payload).await?;
+    let updated = service
+        .update_user_info(user_id, payload)
+        .await
+        .map_err(auth_failure)?;
 
     let user = UserData {
         id: updated.id,
@@ -1,13 +1,9 @@
-use crate::routes::auth::models::{AuthError, UserDb};
+use crate::routes::auth::models::UserDb;
 use crate::routes::auth::services::AuthServiceImpl;
-use axum::{
-    body::Body,
-    extract::State,
-    http::{Request, StatusCode},
-    middleware::Next,
-    response::Response,
-    Json,
-};
+use crate::shared::error::ApiError;
+use crate::shared::metrics::record_auth_failure;
+use crate::shared::telemetry::record_user_id;
+use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
 use axum_extra::extract::cookie::CookieJar;
 use std::sync::Arc;
 
@@ -20,13 +16,13 @@ use std::sync::Arc;
 /// * `req`:
 /// * `next`:
 ///
-/// returns: Result<Response<Body>, (StatusCode, Json<AuthError>)>
+/// returns: Result<Response<Body>, ApiError>
 pub async fn auth<S>(
     jar: CookieJar,
     State(service): State<Arc<S>>,
     mut req: Request<Body>,
     next: Next,
-) -> Result<Response, (StatusCode, Json<AuthError>)>
+) -> Result<Response, ApiError>
 where
     S: AuthServiceImpl,
 {
@@ -42,15 +38,19 @@ where
             v.strip_prefix("Bearer ").map(str::to_owned)
         })
         .ok_or_else(|| {
-            let err = AuthError::new("Missing Authorization Bearer token");
-            (StatusCode::UNAUTHORIZED, Json(err))
+            record_auth_failure("missing_token");
+            ApiError::Unauthorized("Missing Authorization Bearer token".to_string())
         })?;
 
     // 2) validate & fetch user
-    let user: UserDb = service.validate_token(&token).await?;
+    let user: UserDb = service
+        .validate_token(&token)
+        .await
+        .inspect_err(|_| record_auth_failure("invalid_token"))?;
 
     // 3) stash in request extensions
     tracing::debug!("Adding user: {:?}", user);
+    record_user_id(user.id.0);
     req.extensions_mut().insert(user);
 
     // 4) forward
@@ -1,13 +1,12 @@
+use crate::shared::error::ApiError;
 use crate::shared::models::DatabaseId;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
-use std::error::Error;
-use std::fmt;
-use std::fmt::{Display, Pointer};
 use std::str::FromStr;
 use utoipa::ToSchema;
+use validator::Validate;
 
 #[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize, Deserialize, ToSchema)]
 pub struct UserDb {
@@ -83,32 +82,38 @@ pub struct LoginSuccess {
     pub refresh_token: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
-pub struct LoginError {
-    pub message: String,
-}
-
 #[derive(Debug, Deserialize)]
 pub struct CreateUser {
     pub email: String,
     pub password: String,
 }
 
+/// Shortest password accepted for new accounts and password changes.
+pub const MIN_PASSWORD_LENGTH: u64 = 8;
+
 #[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct LoginUser {
+    #[schema(format = Email)]
     pub email: String,
+    #[schema(min_length = 1)]
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginUserSchema {
+    #[validate(length(min = 1, max = 255))]
     pub email: String,
+    #[validate(length(min = 1))]
     pub password: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct RegisterUserRequestSchema {
+    #[validate(email, length(max = 255))]
+    #[schema(format = Email, max_length = 255)]
     pub email: String,
+    #[validate(length(min = MIN_PASSWORD_LENGTH, max = 128))]
+    #[schema(min_length = 8, max_length = 128)]
     pub password: String,
 }
 
@@ -136,16 +141,6 @@ pub struct RegisterResponseSuccess {
 #[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct LogoutSuccess {}
 
-#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
-pub struct LogoutError {
-    pub message: String,
-}
-
-#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
-pub struct RegisterError {
-    pub message: String,
-}
-
 #[derive(Debug, Deserialize, Serialize, sqlx::FromRow, Clone, ToSchema)]
 pub struct LoginResponse {
     pub access_token: String,
@@ -173,43 +168,6 @@ pub struct TokenClaims {
 //     // kind: AuthResponseKind,
 // }
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
-pub struct AuthError {
-    /// A human‑readable message
-    pub message: String,
-}
-
-impl AuthError {
-    /// Create a new AuthError with the given HTTP status code and message.
-    pub fn new<S>(message: S) -> Self
-    where
-        S: Into<String>,
-    {
-        Self {
-            message: message.into(),
-        }
-    }
-
-    // Shortcut for 401 Unauthorized
-    // pub fn unauthorized<M: Into<String>>(msg: M) -> Self {
-    //     Self::new(StatusCode::UNAUTHORIZED, msg)
-    // }
-    //
-    // /// Shortcut for 400 Bad Request
-    // pub fn bad_request<M: Into<String>>(msg: M) -> Self {
-    //     Self::new(StatusCode::BAD_REQUEST, msg)
-    // }
-}
-
-// So you can do `?` on any AuthError
-impl fmt::Display for AuthError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
-    }
-}
-
-impl Error for AuthError {}
-
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct RefreshSuccess {
     pub access_token: String,
@@ -224,8 +182,9 @@ pub struct QueryCode {
     pub code: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct OAuthParams {
+    #[validate(length(min = 1))]
     pub code: String,
 }
 
@@ -287,65 +246,43 @@ where
         let (status, body) = match self {
             AuthSuccessKind::UserCreated(s, b) | AuthSuccessKind::Created(s, b) => (s, b),
         };
-        let json = serde_json::to_string(&body).unwrap_or_else(|_| {
+        let json = match serde_json::to_string(&body) {
+            Ok(json) => json,
             // if we can't serialize the body, just return a generic error
-            serde_json::to_string(&AuthError::new(
-                "Internal server error - Can not create body",
-            ))
-            .unwrap()
-        });
+            Err(e) => {
+                return ApiError::Internal(format!("Can not create body: {}", e)).into_response()
+            }
+        };
         let mut response = axum::response::Response::new(json.into());
         *response.status_mut() = status;
         response
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
-#[serde(tag = "type", content = "data")]
-pub enum AuthErrorKind {
-    UserCreate(String),
-    UserAlreadyExists,
-    DatabaseError,
-    HashingError,
-    SettingsCreate,
-    MissingCode,
-    TokenExchangeError(String),
-    GoogleUserFetchError(String),
-}
-
-impl Display for AuthErrorKind {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            AuthErrorKind::UserCreate(msg) => write!(f, "{}", msg),
-            AuthErrorKind::UserAlreadyExists => write!(f, "User already exists"),
-            AuthErrorKind::DatabaseError => write!(f, "Database error"),
-            AuthErrorKind::HashingError => write!(f, "Hashing error"),
-            AuthErrorKind::SettingsCreate => write!(f, "Settings error"),
-            AuthErrorKind::MissingCode => write!(f, "Missing code"),
-            AuthErrorKind::TokenExchangeError(msg) => write!(f, "Token exchange error: {}", msg),
-            AuthErrorKind::GoogleUserFetchError(msg) => {
-                write!(f, "Google user fetch error: {}", msg)
-            }
-        }
-    }
-}
-
 /// Request body for changing a user's password
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct ChangePasswordRequest {
     /// The user's current password
+    #[validate(length(min = 1))]
+    #[schema(min_length = 1)]
     pub current_password: String,
     /// The new password to set
+    #[validate(length(min = MIN_PASSWORD_LENGTH, max = 128))]
+    #[schema(min_length = 8, max_length = 128)]
     pub new_password: String,
 }
 
 /// Payload for updating a user's profile.
 /// All fields are optional; any `None` will leave the existing value unchanged.
-#[derive(Debug, Deserialize, Serialize, ToSchema)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct UpdateUserInfoRequest {
     /// Optional first name
+    #[validate(length(max = 100))]
+    #[schema(max_length = 100)]
     pub first_name: Option<String>,
 
     /// Optional last name
+    #[validate(length(max = 100))]
+    #[schema(max_length = 100)]
     pub last_name: Option<String>,
 }
@@ -1,18 +1,18 @@
 use crate::config::WapSettings;
 use crate::routes::auth::models;
 use crate::routes::auth::models::{
-    AuthError, AuthErrorKind, GoogleUser, LoginSuccess, LoginUserSchema, RegisterUserRequestSchema,
-    TokenClaims, TokenResponse, UpdateUserInfoRequest, UserDb,
+    GoogleUser, LoginSuccess, LoginUserSchema, RegisterUserRequestSchema, TokenClaims,
+    TokenResponse, UpdateUserInfoRequest, UserDb,
 };
 use crate::routes::auth::utils::hash_password;
 use crate::routes::settings::models::UserSettingsCreate;
 use crate::routes::settings::services::{SettingsService, SettingsServiceImpl};
+use crate::routes::sharing::services::claim_pending_shares;
+use crate::shared::error::ApiError;
 use crate::shared::models::DatabaseId;
 use anyhow::Result;
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use async_trait::async_trait;
-use axum::http::StatusCode;
-use axum::Json;
 use futures_util::FutureExt;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use reqwest::Client;
@@ -50,32 +50,32 @@ where
 
 #[async_trait]
 pub trait AuthServiceImpl: Send + Sync + 'static + JwtConfigImpl {
-    async fn validate_token(&self, token: &str) -> Result<UserDb, (StatusCode, Json<AuthError>)>;
-    async fn token_claim(&self, token: &str) -> Result<TokenClaims, (StatusCode, Json<AuthError>)>;
+    async fn validate_token(&self, token: &str) -> Result<UserDb, ApiError>;
+    async fn token_claim(&self, token: &str) -> Result<TokenClaims, ApiError>;
     async fn register_new_user(
         &self,
         request: &RegisterUserRequestSchema,
-    ) -> Result<UserDb, (StatusCode, Json<AuthErrorKind>)>;
-    async fn login(&self, request: &LoginUserSchema) -> Result<UserDb>;
-    async fn refresh(&self, user_id: DatabaseId) -> Result<UserDb, (StatusCode, Json<AuthError>)>;
+    ) -> Result<UserDb, ApiError>;
+    async fn login(&self, request: &LoginUserSchema) -> Result<UserDb, ApiError>;
+    async fn refresh(&self, user_id: DatabaseId) -> Result<UserDb, ApiError>;
     async fn get_user_by_id_or_email(
         &self,
         user_id: &Option<DatabaseId>,
         email: &Option<String>,
-    ) -> Result<UserDb, (StatusCode, Json<AuthError>)>;
+    ) -> Result<UserDb, ApiError>;
     async fn change_password(
         &self,
         user_id: DatabaseId,
         current: &str,
         new: &str,
         force: bool,
-    ) -> Result<(), (StatusCode, Json<AuthErrorKind>)>;
-    async fn delete_user(&self, user_id: DatabaseId) -> Result<(), (StatusCode, Json<AuthError>)>;
+    ) -> Result<(), ApiError>;
+    async fn delete_user(&self, user_id: DatabaseId) -> Result<(), ApiError>;
     async fn update_user_info(
         &self,
         user_id: DatabaseId,
         req: UpdateUserInfoRequest,
-    ) -> Result<UserDb, (StatusCode, Json<AuthErrorKind>)>;
+    ) -> Result<UserDb, ApiError>;
 }
 
 #[derive(Clone)]
@@ -122,36 +122,31 @@ impl JwtConfigImpl for AuthService {
 
 #[async_trait]
 impl AuthServiceImpl for AuthService {
-    async fn validate_token(&self, token: &str) -> Result<UserDb, (StatusCode, Json<AuthError>)> {
+    #[tracing::instrument(skip_all)]
+    async fn validate_token(&self, token: &str) -> Result<UserDb, ApiError> {
         // 1) decode JWT
         let claims = self.token_claim(token).await?;
 
         // 2) parse sub → user_id
-        let user_id: i32 = claims.sub.parse().map_err(|_| {
-            let err = AuthError::new("Invalid token subject");
-            (StatusCode::UNAUTHORIZED, Json(err))
-        })?;
+        let user_id: i32 = claims
+            .sub
+            .parse()
+            .map_err(|_| ApiError::Unauthorized("Invalid token subject".to_string()))?;
 
         // 3) lookup user
         let user = sqlx::query_as!(UserDb, "SELECT * FROM users WHERE id = $1", user_id)
             .fetch_optional(&self.db)
-            .await
-            .map_err(|e| {
-                let err = AuthError::new(format!("DB error: {}", e));
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-            })?
-            .ok_or_else(|| {
-                let err = AuthError::new("User no longer exists");
-                (StatusCode::UNAUTHORIZED, Json(err))
-            })?;
+            .await?
+            .ok_or_else(|| ApiError::Unauthorized("User no longer exists".to_string()))?;
 
         Ok(user)
     }
 
+    #[tracing::instrument(skip_all)]
     async fn register_new_user(
         &self,
         request: &RegisterUserRequestSchema,
-    ) -> Result<UserDb, (StatusCode, Json<AuthErrorKind>)> {
+    ) -> Result<UserDb, ApiError> {
         // 1) check if user already exists
         if let Some(user) = sqlx::query_as!(
             UserDb,
@@ -159,30 +154,16 @@ impl AuthServiceImpl for AuthService {
             request.email.to_ascii_lowercase()
         )
         .fetch_optional(&self.db)
-        .await
-        .map_err(|_| {
-            // database error
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorKind::DatabaseError),
-            )
-        })? {
+        .await?
+        {
             // user exists → return error
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(AuthErrorKind::UserAlreadyExists),
-            ));
+            return Err(ApiError::AlreadyExists("User already exists".to_string()));
         }
 
         // 2) otherwise, insert new user
         let hashed = hash_password(&request.password)
             .await
-            .map_err(|_| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AuthErrorKind::HashingError),
-                )
-            })?
+            .map_err(|e| ApiError::Internal(format!("Hashing error: {}", e)))?
             .to_string();
 
         let new_user = sqlx::query_as!(
@@ -192,13 +173,7 @@ impl AuthServiceImpl for AuthService {
             hashed
         )
         .fetch_one(&self.db)
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorKind::DatabaseError),
-            )
-        })?;
+        .await?;
 
         // 3) bootstrap default settings for them
         let settings_svc = SettingsService::new(self.db.clone(), self.settings.clone());
@@ -209,58 +184,46 @@ impl AuthServiceImpl for AuthService {
                 ..Default::default()
             })
             .await
-            .map_err(|_| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AuthErrorKind::SettingsCreate),
-                )
-            })?;
+            .map_err(|e| ApiError::Internal(format!("Settings could not be created: {}", e)))?;
+
+        // 4) locations shared with the address before the account existed
+        claim_pending_shares(&self.db, &new_user).await?;
 
         Ok(new_user)
     }
 
-    async fn delete_user(&self, user_id: DatabaseId) -> Result<(), (StatusCode, Json<AuthError>)> {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn delete_user(&self, user_id: DatabaseId) -> Result<(), ApiError> {
         // attempt to delete user row
         let result = sqlx::query!("DELETE FROM users WHERE id = $1", user_id.0)
             .execute(&self.db)
-            .await
-            .map_err(|e| {
-                tracing::error!("DB error: {}", e);
-                let err = AuthError::new(format!("DB error: {}", e));
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-            })?;
+            .await?;
 
         if result.rows_affected() == 0 {
-            tracing::error!("User not found");
-            let err = AuthError::new("User not found");
-            return Err((StatusCode::NOT_FOUND, Json(err)));
+            return Err(ApiError::NotFound("User not found".to_string()));
         }
 
         Ok(())
     }
 
-    async fn token_claim(&self, token: &str) -> Result<TokenClaims, (StatusCode, Json<AuthError>)> {
+    async fn token_claim(&self, token: &str) -> Result<TokenClaims, ApiError> {
         let token_data = decode::<TokenClaims>(
             token,
             &DecodingKey::from_secret(self.settings.jwt_secret.as_ref()),
             &Validation::default(),
         )
-        .map_err(|_| {
-            let err = AuthError {
-                message: "Invalid token".to_string(),
-            };
-            (StatusCode::UNAUTHORIZED, Json(err))
-        })?;
+        .map_err(|_| ApiError::Unauthorized("Invalid token".to_string()))?;
 
         Ok(token_data.claims)
     }
 
-    async fn login(&self, request: &LoginUserSchema) -> Result<UserDb> {
+    #[tracing::instrument(skip_all)]
+    async fn login(&self, request: &LoginUserSchema) -> Result<UserDb, ApiError> {
         let email = request.email.to_ascii_lowercase();
         let user = sqlx::query_as!(UserDb, "SELECT * FROM users WHERE email = $1", email)
             .fetch_optional(&self.db)
             .await?
-            .ok_or_else(|| anyhow::anyhow!("Invalid email or password"))?;
+            .ok_or(ApiError::InvalidCredentials)?;
 
         let is_valid = match PasswordHash::new(&user.clone().password_hash) {
             Ok(parsed_hash) => Argon2::default()
@@ -270,86 +233,55 @@ impl AuthServiceImpl for AuthService {
         };
 
         if !is_valid {
-            return Err(anyhow::anyhow!("Invalid email or password"));
+            return Err(ApiError::InvalidCredentials);
         }
 
         Ok(user.clone())
     }
 
+    #[tracing::instrument(skip_all)]
     async fn get_user_by_id_or_email(
         &self,
         user_id: &Option<DatabaseId>,
         email: &Option<String>,
-    ) -> Result<UserDb, (StatusCode, Json<AuthError>)> {
+    ) -> Result<UserDb, ApiError> {
         let user = match (user_id, email) {
             (Some(id), _) => sqlx::query_as!(UserDb, "SELECT * FROM users WHERE id = $1", id.0)
                 .fetch_optional(&self.db)
-                .await
-                .map_err(|e| {
-                    let err = AuthError {
-                        message: format!("DB error: {}", e),
-                    };
-                    (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-                })?
-                .ok_or_else(|| {
-                    let err = AuthError {
-                        message: "User not found".to_string(),
-                    };
-                    (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-                })?,
+                .await?
+                .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?,
             (_, Some(email)) => {
                 sqlx::query_as!(UserDb, "SELECT * FROM users WHERE email = $1", email)
                     .fetch_optional(&self.db)
-                    .await
-                    .map_err(|e| {
-                        let err = AuthError {
-                            message: format!("DB error: {}", e),
-                        };
-                        (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-                    })?
-                    .ok_or_else(|| {
-                        let err = AuthError {
-                            message: "User not found".to_string(),
-                        };
-                        (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-                    })?
+                    .await?
+                    .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?
             }
             (_, _) => {
-                let err = AuthError {
-                    message: "Either user_id or email must be provided".to_string(),
-                };
-                return Err((StatusCode::BAD_REQUEST, Json(err)));
+                return Err(ApiError::BadRequest(
+                    "Either user_id or email must be provided".to_string(),
+                ));
             }
         };
 
         Ok(user)
     }
 
-    async fn refresh(&self, user_id: DatabaseId) -> Result<UserDb, (StatusCode, Json<AuthError>)> {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn refresh(&self, user_id: DatabaseId) -> Result<UserDb, ApiError> {
         let user = sqlx::query_as!(UserDb, "SELECT * FROM users WHERE id = $1", user_id.0)
             .fetch_optional(&self.db)
-            .await
-            .map_err(|e| {
-                let err = AuthError {
-                    message: "Database error".to_string(),
-                };
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-            })?
-            .ok_or_else(|| {
-                let err = AuthError {
-                    message: "User not found".to_string(),
-                };
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(err))
-            })?;
+            .await?
+            .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
 
         Ok(user)
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
     async fn update_user_info(
         &self,
         user_id: DatabaseId,
         req: UpdateUserInfoRequest,
-    ) -> Result<UserDb, (StatusCode, Json<AuthErrorKind>)> {
+    ) -> Result<UserDb, ApiError> {
         let rec = sqlx::query_as!(
             UserDb,
             r#"
@@ -365,35 +297,26 @@ impl AuthServiceImpl for AuthService {
             req.last_name,
             user_id.0,
         )
-        .fetch_one(&self.db)
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorKind::DatabaseError),
-            )
-        })?;
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
 
         Ok(rec)
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
     async fn change_password(
         &self,
         user_id: DatabaseId,
         current: &str,
         new: &str,
         force: bool,
-    ) -> Result<(), (StatusCode, Json<AuthErrorKind>)> {
+    ) -> Result<(), ApiError> {
         // 1) load the user
         let user = sqlx::query_as!(UserDb, "SELECT * FROM users WHERE id = $1", user_id.0)
-            .fetch_one(&self.db)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AuthErrorKind::DatabaseError),
-                )
-            })?;
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(|| ApiError::NotFound("User not found".to_string()))?;
 
         // 2) if not forced, verify current password
         if !force {
@@ -405,24 +328,14 @@ impl AuthServiceImpl for AuthService {
             };
 
             if !matches {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(AuthErrorKind::UserCreate(
-                        "Invalid current password".to_string(),
-                    )),
-                ));
+                return Err(ApiError::BadRequest("Invalid current password".to_string()));
             }
         }
 
         // 3) hash the new password
         let new_hash = hash_password(new)
             .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AuthErrorKind::HashingError),
-                )
-            })?
+            .map_err(|e| ApiError::Internal(format!("Hashing error: {}", e)))?
             .to_string();
 
         // 4) persist
@@ -432,13 +345,7 @@ impl AuthServiceImpl for AuthService {
             user_id.0
         )
         .execute(&self.db)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(AuthErrorKind::DatabaseError),
-            )
-        })?;
+        .await?;
 
         Ok(())
     }
@@ -465,10 +372,7 @@ pub trait JwtConfigImpl: Sync + Send + 'static {
 pub trait GoogleAuthService: Send + Sync + 'static + JwtConfigImpl {
     async fn request_token(&self, code: &str) -> Result<TokenResponse>;
     async fn get_google_user(&self, access_token: &str, id_token: &str) -> Result<GoogleUser>;
-    async fn upsert_google_user(
-        &self,
-        google_user: &GoogleUser,
-    ) -> Result<UserDb, (StatusCode, Json<AuthErrorKind>)>;
+    async fn upsert_google_user(&self, google_user: &GoogleUser) -> Result<UserDb, ApiError>;
 }
 
 #[async_trait]
@@ -477,6 +381,7 @@ impl GoogleAuthService for AuthService {
     //     create_jwt_token(user_id, exp, self.settings.jwt_secret.as_str()).await
     // }
 
+    #[tracing::instrument(skip_all)]
     async fn request_token(&self, code: &str) -> Result<TokenResponse> {
         let params = [
             ("code", code),
@@ -505,6 +410,7 @@ impl GoogleAuthService for AuthService {
         Ok(token)
     }
 
+    #[tracing::instrument(skip_all)]
     async fn get_google_user(&self, access_token: &str, _id_token: &str) -> Result<GoogleUser> {
         let resp = self
             .http
@@ -518,10 +424,8 @@ impl GoogleAuthService for AuthService {
     }
 
     /// Insert or update a Google‐authenticated user, returning the full UserDb.
-    async fn upsert_google_user(
-        &self,
-        google_user: &GoogleUser,
-    ) -> Result<UserDb, (StatusCode, Json<AuthErrorKind>)> {
+    #[tracing::instrument(skip_all)]
+    async fn upsert_google_user(&self, google_user: &GoogleUser) -> Result<UserDb, ApiError> {
         // 1) check if user already exists
         let user: UserDb = sqlx::query_as!(
             UserDb,
@@ -543,13 +447,7 @@ impl GoogleAuthService for AuthService {
             google_user.sub,
         )
             .fetch_one(&self.db)
-            .await
-            .map_err(|_| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AuthErrorKind::DatabaseError),
-                )
-            })?;
+            .await?;
 
         // 2) bootstrap default settings for them
         let settings_svc = SettingsService::new(self.db.clone(), self.settings.clone());
@@ -560,12 +458,10 @@ impl GoogleAuthService for AuthService {
                 ..Default::default()
             })
             .await
-            .map_err(|_| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(AuthErrorKind::SettingsCreate),
-                )
-            })?;
+            .map_err(|e| ApiError::Internal(format!("Settings could not be created: {}", e)))?;
+
+        // 3) locations shared with the address before the account existed
+        claim_pending_shares(&self.db, &user).await?;
 
         Ok(user)
     }
@@ -578,6 +474,7 @@ mod tests {
     use crate::routes::auth::models::{LoginUserSchema, RegisterUserRequestSchema};
     use crate::routes::auth::services::AuthService;
     use crate::tests::tests::TestApp;
+    use axum::http::StatusCode;
     use sqlx::PgPool;
     use tracing_test::traced_test;
 
@@ -683,11 +580,11 @@ mod tests {
 
         // 5) token_claim should error on invalid JWT
         let err = svc.token_claim("not-a-token").await.unwrap_err();
-        assert_eq!(err.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
 
         // 6) refresh should error on missing user
         let err = svc.refresh(DatabaseId { 0: -999 }).await.unwrap_err();
-        assert_eq!(err.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
     }
 
     #[sqlx::test]
@@ -808,7 +705,7 @@ mod tests {
             .change_password(user.id, "wrongpass", "whatever", false)
             .await
             .unwrap_err();
-        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
     }
 
     #[sqlx::test]
@@ -857,7 +754,7 @@ mod tests {
 
         // neither ID nor email → BAD_REQUEST
         let bad = svc.get_user_by_id_or_email(&None, &None).await.unwrap_err();
-        assert_eq!(bad.0, StatusCode::BAD_REQUEST);
+        assert_eq!(bad.status(), StatusCode::BAD_REQUEST);
     }
 
     #[sqlx::test]
@@ -879,7 +776,7 @@ mod tests {
 
         // Attempt to fetch or refresh should fail
         let err = svc.refresh(user.id).await.unwrap_err();
-        assert_eq!(err.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
     }
 
     #[sqlx::test]
@@ -890,6 +787,6 @@ mod tests {
         // Try deleting a non-existent user
         let non = DatabaseId(99999);
         let err = svc.delete_user(non).await.unwrap_err();
-        assert_eq!(err.0, StatusCode::NOT_FOUND);
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
     }
 }
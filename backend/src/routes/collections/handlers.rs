@@ -0,0 +1,258 @@
+use crate::routes::auth::middlewares::auth;
+use crate::routes::auth::models::UserDb;
+use crate::routes::auth::services::AuthService;
+use crate::routes::collections::models::{
+    Collection, CreateCollectionRequest, UpdateCollectionRequest,
+};
+use crate::routes::collections::services::{CollectionService, CollectionServiceImpl};
+use crate::routes::sharing::models::WithAccess;
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::memberships::LocationIds;
+use crate::shared::models::{AppState, DatabaseId};
+use crate::shared::validation::ValidatedJson;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use std::sync::Arc;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+/// List the collections of the current user and those shared with them, by name.
+#[utoipa::path(
+    get,
+    path = "/collections",
+    responses(
+        (status = 200, description = "Collections of the user, with the user's access", body = [WithAccess<Collection>]),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_all_collections<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+) -> Result<Json<Vec<WithAccess<Collection>>>, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let collections = service.get_all(user.id).await?;
+    Ok(Json(collections))
+}
+
+/// Create an empty collection for the current user.
+#[utoipa::path(
+    post,
+    path = "/collections",
+    request_body = CreateCollectionRequest,
+    responses(
+        (status = 201, description = "Collection created", body = Collection),
+        (status = 409, description = "The user has a collection with this name", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn create_collection<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedJson(request): ValidatedJson<CreateCollectionRequest>,
+) -> Result<(StatusCode, Json<Collection>), ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let collection = service.create(user.id, &request).await?;
+    Ok((StatusCode::CREATED, Json(collection)))
+}
+
+/// Fetch a collection of the current user or shared with them.
+#[utoipa::path(
+    get,
+    path = "/collections/{id}",
+    responses(
+        (status = 200, description = "Collection found", body = WithAccess<Collection>),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn get_collection_by_id<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<Json<WithAccess<Collection>>, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let collection = service.get_by_id(user.id, id).await?;
+    Ok(Json(collection))
+}
+
+/// Change the name or description of a collection of the current user.
+#[utoipa::path(
+    patch,
+    path = "/collections/{id}",
+    request_body = UpdateCollectionRequest,
+    responses(
+        (status = 200, description = "Collection updated", body = Collection),
+        (status = 403, description = "Collection is only shared with the user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The user has another collection with this name", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn update_collection<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<UpdateCollectionRequest>,
+) -> Result<Json<Collection>, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let collection = service.update(user.id, id, &request).await?;
+    Ok(Json(collection))
+}
+
+/// Delete a collection of the current user; its locations are kept.
+#[utoipa::path(
+    delete,
+    path = "/collections/{id}",
+    responses(
+        (status = 204, description = "Collection deleted"),
+        (status = 403, description = "Collection is only shared with the user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn delete_collection<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<StatusCode, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    service.delete(user.id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the locations in a collection of the current user or shared with them.
+#[utoipa::path(
+    get,
+    path = "/collections/{id}/locations",
+    responses(
+        (status = 200, description = "IDs of the locations in the collection", body = LocationIds),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn get_collection_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<Json<LocationIds>, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let ids = service.locations(user.id, id).await?;
+    Ok(Json(ids))
+}
+
+/// Add many locations at once to a collection of the current user.
+///
+/// Locations already in it are skipped; if any location is not found, none is added.
+#[utoipa::path(
+    post,
+    path = "/collections/{id}/locations/add",
+    request_body = LocationIds,
+    responses(
+        (status = 200, description = "IDs of all the locations in the collection", body = LocationIds),
+        (status = 403, description = "Collection is only shared with the user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown locations or too many at once", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn add_collection_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<LocationIds>,
+) -> Result<Json<LocationIds>, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let ids = service.add_locations(user.id, id, &request).await?;
+    Ok(Json(ids))
+}
+
+/// Remove many locations at once from a collection of the current user.
+#[utoipa::path(
+    post,
+    path = "/collections/{id}/locations/remove",
+    request_body = LocationIds,
+    responses(
+        (status = 200, description = "IDs of the locations left in the collection", body = LocationIds),
+        (status = 403, description = "Collection is only shared with the user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Too many locations at once", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn remove_collection_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<LocationIds>,
+) -> Result<Json<LocationIds>, ApiError>
+where
+    S: CollectionServiceImpl,
+{
+    let ids = service.remove_locations(user.id, id, &request).await?;
+    Ok(Json(ids))
+}
+
+/// Generic router allowing injection of any implementation of the collection service
+pub fn router_with_service<S>(app: AppState, service: Arc<S>) -> OpenApiRouter
+where
+    S: CollectionServiceImpl,
+{
+    let auth_service = Arc::new(AuthService {
+        db: app.db.clone(),
+        settings: app.settings.clone(),
+        http: Default::default(),
+    });
+
+    OpenApiRouter::new()
+        .routes(routes!(get_all_collections))
+        .routes(routes!(create_collection))
+        .routes(routes!(get_collection_by_id))
+        .routes(routes!(update_collection))
+        .routes(routes!(delete_collection))
+        .routes(routes!(get_collection_locations))
+        .routes(routes!(add_collection_locations))
+        .routes(routes!(remove_collection_locations))
+        .layer(axum::middleware::from_fn_with_state(auth_service, auth))
+        .with_state(service)
+}
+
+/// Convenience router using the Postgres-backed implementation
+pub fn router(app: AppState) -> OpenApiRouter {
+    let service = Arc::new(CollectionService::new(app.db.clone()));
+    router_with_service(app, service)
+}
@@ -0,0 +1,48 @@
+use crate::shared::models::DatabaseId;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+/// A named group of locations, such as the sites of one field trip.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq, sqlx::FromRow)]
+pub struct Collection {
+    /// ID of the collection.
+    pub id: DatabaseId,
+
+    /// Name of the collection, unique for its owner ignoring case.
+    pub name: String,
+
+    /// What the collection is for.
+    pub description: String,
+
+    /// When the collection was created.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+
+    /// When the name or description last changed.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Payload to create a collection.
+#[derive(Debug, Clone, Deserialize, ToSchema, Validate)]
+pub struct CreateCollectionRequest {
+    /// Name of the collection.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub name: String,
+
+    /// What the collection is for.
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Payload changing some fields of a collection; missing fields are kept.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema, Validate)]
+pub struct UpdateCollectionRequest {
+    /// New name, if changing.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub name: Option<String>,
+
+    /// New description, if changing.
+    pub description: Option<String>,
+}
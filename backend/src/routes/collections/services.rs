@@ -0,0 +1,324 @@
+use crate::routes::collections::models::{
+    Collection, CreateCollectionRequest, UpdateCollectionRequest,
+};
+use crate::routes::sharing::models::WithAccess;
+use crate::shared::error::ApiError;
+use crate::shared::memberships::{LocationIds, COLLECTIONS};
+use crate::shared::models::DatabaseId;
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+/// Manages the collections of a user and the locations in them.
+///
+/// Collections can hold the user's own locations and those shared with them, and a location
+/// can be in any number of collections. Their owner can share them to read or to edit the
+/// owner's locations in them; only the owner changes a collection.
+#[async_trait]
+pub trait CollectionServiceImpl: Send + Sync + 'static {
+    /// The collections of `user_id` and those shared with them, by name.
+    async fn get_all(&self, user_id: DatabaseId) -> Result<Vec<WithAccess<Collection>>, ApiError>;
+
+    /// Create an empty collection; `ApiError::AlreadyExists` if the user has one with the
+    /// same name.
+    async fn create(
+        &self,
+        user_id: DatabaseId,
+        request: &CreateCollectionRequest,
+    ) -> Result<Collection, ApiError>;
+
+    /// The collection `id` of `user_id` or shared with them, or `ApiError::NotFound`.
+    async fn get_by_id(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+    ) -> Result<WithAccess<Collection>, ApiError>;
+
+    /// Change the name or description; `ApiError::AlreadyExists` if the user has another
+    /// collection with that name, `ApiError::Forbidden` if it is only shared with them.
+    async fn update(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        request: &UpdateCollectionRequest,
+    ) -> Result<Collection, ApiError>;
+
+    /// Delete the collection; its locations are kept. Only its owner can.
+    async fn delete(&self, user_id: DatabaseId, id: DatabaseId) -> Result<(), ApiError>;
+
+    /// The locations in the collection the user sees.
+    async fn locations(&self, user_id: DatabaseId, id: DatabaseId)
+        -> Result<LocationIds, ApiError>;
+
+    /// Add the locations to the collection, as its owner; returns all locations in it.
+    async fn add_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError>;
+
+    /// Remove the locations from the collection, as its owner; returns the locations left in
+    /// it.
+    async fn remove_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError>;
+}
+
+/// Postgres-backed implementation of `CollectionServiceImpl`.
+pub struct CollectionService {
+    /// SQLx Postgres connection pool.
+    pub db: PgPool,
+}
+
+impl CollectionService {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+/// A clearer conflict than the generic one for a name taken by another collection.
+fn name_taken(e: sqlx::Error) -> ApiError {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            ApiError::AlreadyExists("A collection with this name already exists".to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+#[async_trait]
+impl CollectionServiceImpl for CollectionService {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn get_all(&self, user_id: DatabaseId) -> Result<Vec<WithAccess<Collection>>, ApiError> {
+        let collections = sqlx::query_as(
+            "SELECT id, name, description, created_at, updated_at, access
+             FROM collections JOIN collection_access ON collection_id = id
+             WHERE viewer_id = $1 ORDER BY lower(name), id",
+        )
+        .bind(user_id.0)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(collections)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn create(
+        &self,
+        user_id: DatabaseId,
+        request: &CreateCollectionRequest,
+    ) -> Result<Collection, ApiError> {
+        let collection = sqlx::query_as!(
+            Collection,
+            "INSERT INTO collections (user_id, name, description) VALUES ($1, $2, $3)
+             RETURNING id, name, description, created_at, updated_at",
+            user_id.0,
+            request.name.trim(),
+            request.description,
+        )
+        .fetch_one(&self.db)
+        .await
+        .map_err(name_taken)?;
+        Ok(collection)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn get_by_id(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+    ) -> Result<WithAccess<Collection>, ApiError> {
+        sqlx::query_as(
+            "SELECT id, name, description, created_at, updated_at, access
+             FROM collections JOIN collection_access ON collection_id = id
+             WHERE id = $1 AND viewer_id = $2",
+        )
+        .bind(id.0)
+        .bind(user_id.0)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Collection not found".to_string()))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn update(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        request: &UpdateCollectionRequest,
+    ) -> Result<Collection, ApiError> {
+        COLLECTIONS.require_group(&self.db, user_id, id).await?;
+
+        sqlx::query_as!(
+            Collection,
+            r#"
+            UPDATE collections
+            SET name = COALESCE($3, name),
+                description = COALESCE($4, description),
+                updated_at = CASE WHEN (COALESCE($3, name), COALESCE($4, description))
+                                       = (name, description)
+                                  THEN updated_at ELSE now() END
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, name, description, created_at, updated_at
+            "#,
+            id.0,
+            user_id.0,
+            request.name.as_deref().map(str::trim),
+            request.description,
+        )
+        .fetch_optional(&self.db)
+        .await
+        .map_err(name_taken)?
+        .ok_or_else(|| ApiError::NotFound("Collection not found".to_string()))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn delete(&self, user_id: DatabaseId, id: DatabaseId) -> Result<(), ApiError> {
+        COLLECTIONS.require_group(&self.db, user_id, id).await?;
+
+        let deleted = sqlx::query!(
+            "DELETE FROM collections WHERE id = $1 AND user_id = $2",
+            id.0,
+            user_id.0
+        )
+        .execute(&self.db)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(ApiError::NotFound("Collection not found".to_string()));
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+    ) -> Result<LocationIds, ApiError> {
+        COLLECTIONS.members(&self.db, user_id, id).await
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn add_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError> {
+        COLLECTIONS.add(&self.db, user_id, id, ids).await
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn remove_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError> {
+        COLLECTIONS.remove(&self.db, user_id, id, ids).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::natural_phenomenon_locations::models::NaturalPhenomenonLocationListQuery;
+    use crate::routes::natural_phenomenon_locations::services::{
+        NaturalPhenomenonLocationService, NaturalPhenomenonLocationServiceImpl,
+    };
+    use crate::tests::tests::TestApp;
+
+    #[sqlx::test]
+    async fn test_collections(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user = test_app.users[0].user.id;
+        let mut sites = Vec::new();
+        for name in ["Etna", "Stromboli", "Vesuvius"] {
+            let id: DatabaseId = sqlx::query_scalar(
+                "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+                 VALUES ($1, $2, 38.0, 15.0, 10, NULL) RETURNING id",
+            )
+            .bind(user.0)
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            sites.push(id);
+        }
+        let service = CollectionService::new(pool.clone());
+        let phenomena = NaturalPhenomenonLocationService::new(pool.clone());
+
+        let sicily = service
+            .create(
+                user,
+                &CreateCollectionRequest {
+                    name: "Sicily 2026".to_string(),
+                    description: String::new(),
+                },
+            )
+            .await
+            .unwrap();
+        let err = service
+            .create(
+                user,
+                &CreateCollectionRequest {
+                    name: "sicily 2026".to_string(),
+                    description: String::new(),
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::AlreadyExists(_)));
+
+        // a missing field is kept
+        let updated = service
+            .update(
+                user,
+                sicily.id,
+                &UpdateCollectionRequest {
+                    description: Some("Field trip".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            (updated.name.as_str(), updated.description.as_str()),
+            ("Sicily 2026", "Field trip")
+        );
+
+        // the list keeps only the locations in the collection
+        let members = service
+            .add_locations(
+                user,
+                sicily.id,
+                &LocationIds {
+                    natural_phenomenon_location_ids: sites[..2].to_vec(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(members.natural_phenomenon_location_ids, sites[..2]);
+        let query = NaturalPhenomenonLocationListQuery {
+            collection: Some(sicily.id),
+            ..Default::default()
+        };
+        let listed = phenomena.get_all(user, &query).await.unwrap().items;
+        assert_eq!(
+            listed.iter().map(|l| l.item.id).collect::<Vec<_>>(),
+            sites[..2]
+        );
+
+        // deleting a location takes it out of the collection
+        sqlx::query("DELETE FROM natural_phenomenon_locations WHERE id = $1")
+            .bind(sites[0].0)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let members = service.locations(user, sicily.id).await.unwrap();
+        assert_eq!(members.natural_phenomenon_location_ids, sites[1..2]);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::routes::auth::middlewares::auth;
+use crate::routes::auth::services::AuthService;
+use crate::routes::health::models::{
+    DependencyCheck, HealthStatus, LivenessResponse, ReadinessCheck, ReadinessResponse,
+    StatusResponse,
+};
+use crate::routes::health::services::{HealthService, HealthServiceImpl};
+use crate::shared::models::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use std::sync::Arc;
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouterExt};
+use utoipa_axum::routes;
+
+/// Aggregate the dependency checks and the shutdown flag into one status.
+fn overall_status(checks: &[DependencyCheck], shutting_down: bool) -> HealthStatus {
+    if shutting_down || checks.iter().any(|c| c.status == HealthStatus::Failing) {
+        HealthStatus::Failing
+    } else {
+        HealthStatus::Ok
+    }
+}
+
+/// Liveness probe: answers as long as the process is running.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Process is alive", body = LivenessResponse, content_type = "application/json")
+    )
+)]
+pub async fn liveness() -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        status: HealthStatus::Ok,
+    })
+}
+
+/// Readiness probe: database, migrations, uploads directory and shutdown state.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = ReadinessResponse, content_type = "application/json"),
+        (status = 503, description = "Not ready", body = ReadinessResponse, content_type = "application/json")
+    )
+)]
+pub async fn readiness<S>(State(service): State<Arc<S>>) -> (StatusCode, Json<ReadinessResponse>)
+where
+    S: HealthServiceImpl,
+{
+    let checks = service.check_all().await;
+    let status = overall_status(&checks, service.is_shutting_down());
+    let code = match status {
+        HealthStatus::Ok => StatusCode::OK,
+        HealthStatus::Failing => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    // the failure details are logged by the checks, only the authenticated status shows them
+    let checks = checks.into_iter().map(ReadinessCheck::from).collect();
+    (code, Json(ReadinessResponse { status, checks }))
+}
+
+/// Detailed status with build information, uptime and dependency latency.
+#[utoipa::path(
+    get,
+    path = "/status",
+    responses(
+        (status = 200, description = "Status report", body = StatusResponse, content_type = "application/json"),
+        (status = 401, description = "Unauthorized", content_type = "application/json")
+    )
+)]
+pub async fn status<S>(State(service): State<Arc<S>>) -> Json<StatusResponse>
+where
+    S: HealthServiceImpl,
+{
+    let dependencies = service.check_all().await;
+    let shutting_down = service.is_shutting_down();
+    let started_at = service.started_at();
+
+    Json(StatusResponse {
+        status: overall_status(&dependencies, shutting_down),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: option_env!("GIT_COMMIT").unwrap_or("unknown").to_string(),
+        started_at,
+        uptime_seconds: (chrono::Utc::now() - started_at).num_seconds(),
+        shutting_down,
+        dependencies,
+    })
+}
+
+/// Generic router allowing injection of any implementation of the health service
+pub fn router_with_service<S>(app: AppState, service: Arc<S>) -> OpenApiRouter
+where
+    S: HealthServiceImpl,
+{
+    let auth_service = Arc::new(AuthService {
+        db: app.db.clone(),
+        settings: app.settings.clone(),
+        http: Default::default(),
+    });
+    OpenApiRouter::new()
+        .routes(routes!(liveness))
+        .routes(routes!(readiness))
+        .routes(routes!(status).layer(axum::middleware::from_fn_with_state(auth_service, auth)))
+        .with_state(service)
+}
+
+/// Convenience router using the real dependencies
+pub fn router(app: AppState) -> OpenApiRouter {
+    let service = Arc::new(HealthService::new(
+        app.db.clone(),
+        app.shutdown.clone(),
+        app.started_at,
+    ));
+    router_with_service(app, service)
+}
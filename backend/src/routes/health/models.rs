@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Overall or per-dependency health.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Everything works as expected.
+    Ok,
+    /// The component cannot serve traffic.
+    Failing,
+}
+
+/// Result of probing a single dependency.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct DependencyCheck {
+    /// Name of the dependency (e.g. "database", "migrations").
+    pub name: String,
+
+    /// Outcome of the probe.
+    pub status: HealthStatus,
+
+    /// How long the probe took, in milliseconds.
+    pub latency_ms: f64,
+
+    /// Explanation when the probe failed.
+    pub message: Option<String>,
+}
+
+/// Outcome of a dependency check as shown to unauthenticated callers.
+///
+/// The failure details of `DependencyCheck` are only logged and shown on `/status`.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct ReadinessCheck {
+    /// Name of the dependency (e.g. "database", "migrations").
+    pub name: String,
+
+    /// Outcome of the probe.
+    pub status: HealthStatus,
+}
+
+impl From<DependencyCheck> for ReadinessCheck {
+    fn from(check: DependencyCheck) -> Self {
+        ReadinessCheck {
+            name: check.name,
+            status: check.status,
+        }
+    }
+}
+
+/// Response of the liveness probe.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct LivenessResponse {
+    /// Always `ok` while the process is able to answer.
+    pub status: HealthStatus,
+}
+
+/// Response of the readiness probe.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct ReadinessResponse {
+    /// `ok` only when every check passed.
+    pub status: HealthStatus,
+
+    /// Individual dependency checks.
+    pub checks: Vec<ReadinessCheck>,
+}
+
+/// Detailed status report for operators.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
+pub struct StatusResponse {
+    /// Aggregated readiness.
+    pub status: HealthStatus,
+
+    /// Crate version of the running binary.
+    pub version: String,
+
+    /// Git commit the binary was built from.
+    pub git_commit: String,
+
+    /// When the process started.
+    pub started_at: chrono::DateTime<chrono::Utc>,
+
+    /// Seconds since the process started.
+    pub uptime_seconds: i64,
+
+    /// Whether graceful shutdown has begun.
+    pub shutting_down: bool,
+
+    /// Individual dependency checks with their latency.
+    pub dependencies: Vec<DependencyCheck>,
+}
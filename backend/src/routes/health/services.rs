@@ -0,0 +1,196 @@
+use crate::routes::health::models::{DependencyCheck, HealthStatus};
+use async_trait::async_trait;
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// Migrations embedded at compile time; the newest one is the schema version we expect.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Probes for the dependencies the backend needs to serve traffic.
+#[async_trait]
+pub trait HealthServiceImpl: Send + Sync + 'static {
+    /// Check that Postgres answers a trivial query.
+    async fn check_database(&self) -> DependencyCheck;
+
+    /// Check that the newest embedded migration has been applied.
+    async fn check_migrations(&self) -> DependencyCheck;
+
+    /// Check that the uploads directory exists and is writable.
+    async fn check_uploads(&self) -> DependencyCheck;
+
+    /// Whether graceful shutdown has begun.
+    fn is_shutting_down(&self) -> bool;
+
+    /// When the process started.
+    fn started_at(&self) -> chrono::DateTime<chrono::Utc>;
+
+    /// Run every dependency check.
+    async fn check_all(&self) -> Vec<DependencyCheck> {
+        vec![
+            self.check_database().await,
+            self.check_migrations().await,
+            self.check_uploads().await,
+        ]
+    }
+}
+
+/// Default implementation backed by the shared pool, the uploads folder and the
+/// shutdown token.
+#[derive(Clone)]
+pub struct HealthService {
+    /// SQLx Postgres connection pool.
+    pub db: PgPool,
+
+    /// Directory the uploads are written to.
+    pub uploads_dir: PathBuf,
+
+    /// Cancelled when graceful shutdown begins.
+    pub shutdown: CancellationToken,
+
+    /// When the process started.
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl HealthService {
+    pub fn new(
+        db: PgPool,
+        shutdown: CancellationToken,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        Self {
+            db,
+            uploads_dir: PathBuf::from("uploads"),
+            shutdown,
+            started_at,
+        }
+    }
+}
+
+/// Build a `DependencyCheck` from the probe result and the time it took.
+fn dependency_check(name: &str, started: Instant, result: Result<(), String>) -> DependencyCheck {
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok(()) => DependencyCheck {
+            name: name.to_string(),
+            status: HealthStatus::Ok,
+            latency_ms,
+            message: None,
+        },
+        Err(message) => {
+            tracing::warn!("Health check {} failed: {}", name, message);
+            DependencyCheck {
+                name: name.to_string(),
+                status: HealthStatus::Failing,
+                latency_ms,
+                message: Some(message),
+            }
+        }
+    }
+}
+
+/// The version of the newest migration compiled into the binary.
+pub fn expected_migration_version() -> Option<i64> {
+    MIGRATOR.iter().map(|m| m.version).max()
+}
+
+#[async_trait]
+impl HealthServiceImpl for HealthService {
+    async fn check_database(&self) -> DependencyCheck {
+        let started = Instant::now();
+        let result = sqlx::query("SELECT 1")
+            .execute(&self.db)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        dependency_check("database", started, result)
+    }
+
+    async fn check_migrations(&self) -> DependencyCheck {
+        let started = Instant::now();
+        let applied: Result<Option<i64>, String> =
+            sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+                .fetch_one(&self.db)
+                .await
+                .map_err(|e| e.to_string());
+
+        let result = match (applied, expected_migration_version()) {
+            (Err(e), _) => Err(e),
+            (Ok(applied), Some(expected)) if applied < Some(expected) => Err(format!(
+                "Database schema is at version {:?}, expected {}",
+                applied, expected
+            )),
+            (Ok(_), _) => Ok(()),
+        };
+        dependency_check("migrations", started, result)
+    }
+
+    async fn check_uploads(&self) -> DependencyCheck {
+        let started = Instant::now();
+        let probe = self.uploads_dir.join(".health-probe");
+        // a tiny probe file, cheap enough to write synchronously
+        let result = fs::create_dir_all(&self.uploads_dir)
+            .and_then(|_| fs::write(&probe, b"ok"))
+            .and_then(|_| fs::remove_file(&probe))
+            .map_err(|e| e.to_string());
+        dependency_check("uploads", started, result)
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    fn started_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.started_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::health::models::ReadinessCheck;
+    use sqlx::PgPool;
+
+    #[sqlx::test]
+    async fn test_health_checks(pool: PgPool) {
+        let token = CancellationToken::new();
+        let dir = std::env::temp_dir().join(format!("wap-health-{}", uuid::Uuid::new_v4()));
+        let svc = HealthService {
+            uploads_dir: dir.clone(),
+            ..HealthService::new(pool, token.clone(), chrono::Utc::now())
+        };
+
+        // sqlx::test applies all migrations, so every check passes
+        let checks = svc.check_all().await;
+        assert_eq!(checks.len(), 3);
+        for check in &checks {
+            assert_eq!(check.status, HealthStatus::Ok, "{:?}", check);
+        }
+        assert!(!svc.is_shutting_down());
+
+        // a missing migration row makes the schema look outdated
+        sqlx::query("DELETE FROM _sqlx_migrations")
+            .execute(&svc.db)
+            .await
+            .unwrap();
+        let check = svc.check_migrations().await;
+        assert_eq!(check.status, HealthStatus::Failing);
+        assert!(check.message.is_some());
+
+        // readiness only tells which check failed, not why
+        let public = serde_json::to_value(ReadinessCheck::from(check)).unwrap();
+        assert_eq!(
+            public,
+            serde_json::json!({"name": "migrations", "status": "failing"})
+        );
+
+        // shutdown flips the flag used by readiness
+        token.cancel();
+        assert!(svc.is_shutting_down());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
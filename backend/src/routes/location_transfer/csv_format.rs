@@ -0,0 +1,299 @@
+use crate::routes::location_transfer::models::{ColumnMapping, ExportedLocation, ImportCandidate};
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::geo::{parse_degrees, Area};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use std::borrow::Cow;
+
+/// Media type of CSV files.
+pub const MEDIA_TYPE: &str = "text/csv; charset=utf-8";
+
+/// Byte order mark, which spreadsheets need to open a CSV file as UTF-8.
+const BOM: &str = "\u{feff}";
+
+/// Columns written on export, each read back under its own header.
+const HEADERS: [&str; 8] = [
+    "kind",
+    "name",
+    "latitude",
+    "longitude",
+    "radius",
+    "description",
+    "phenomenon_type",
+    "area",
+];
+
+/// Cells starting with one of these are taken for formulas by spreadsheets.
+const FORMULA_STARTS: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// CSV file with a header row and one row per location.
+///
+/// Text cells that a spreadsheet would run as a formula are prefixed with `'`; `read` takes
+/// the prefix off again.
+pub fn write(locations: &[ExportedLocation]) -> Result<Vec<u8>, ApiError> {
+    let mut csv = WriterBuilder::new().from_writer(BOM.as_bytes().to_vec());
+    let csv_error = |e: csv::Error| ApiError::Internal(format!("Error writing CSV: {}", e));
+    csv.write_record(HEADERS).map_err(csv_error)?;
+    for location in locations {
+        let kind = serde_json::to_value(location.kind()).expect("kinds serialize");
+        let (latitude, longitude) = location.point();
+        let (radius, phenomenon_type, area) = match location {
+            ExportedLocation::Weather(_) => (String::new(), "", String::new()),
+            ExportedLocation::NaturalPhenomenon(row) => (
+                row.radius.to_string(),
+                row.phenomenon_type.as_str(),
+                row.area
+                    .as_ref()
+                    .map(|area| area.to_string())
+                    .unwrap_or_default(),
+            ),
+        };
+        csv.write_record([
+            kind.as_str().unwrap_or_default(),
+            &neutralize(location.name()),
+            &latitude.to_string(),
+            &longitude.to_string(),
+            &radius,
+            &neutralize(location.description()),
+            phenomenon_type,
+            &area,
+        ])
+        .map_err(csv_error)?;
+    }
+    csv.into_inner()
+        .map_err(|e| ApiError::Internal(format!("Error writing CSV: {}", e)))
+}
+
+fn neutralize(text: &str) -> Cow<'_, str> {
+    if text.starts_with(FORMULA_STARTS) {
+        Cow::Owned(format!("'{}", text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn restore(text: &str) -> &str {
+    match text.strip_prefix('\'') {
+        Some(rest) if rest.starts_with(FORMULA_STARTS) => rest,
+        _ => text,
+    }
+}
+
+/// A column that can be read, and where to find it.
+struct Column {
+    /// Query parameter naming the column, `None` for the ones only found by header.
+    parameter: Option<&'static str>,
+    /// Headers looked for when the parameter is not given, in lower case.
+    headers: &'static [&'static str],
+    required: bool,
+}
+
+const NAME: Column = Column {
+    parameter: Some("name_column"),
+    headers: &["name"],
+    required: true,
+};
+const LATITUDE: Column = Column {
+    parameter: Some("latitude_column"),
+    headers: &["latitude", "lat"],
+    required: true,
+};
+const LONGITUDE: Column = Column {
+    parameter: Some("longitude_column"),
+    headers: &["longitude", "lon", "lng"],
+    required: true,
+};
+const RADIUS: Column = Column {
+    parameter: Some("radius_column"),
+    headers: &["radius"],
+    required: false,
+};
+const DESCRIPTION: Column = Column {
+    parameter: Some("description_column"),
+    headers: &["description"],
+    required: false,
+};
+const KIND: Column = Column {
+    parameter: None,
+    headers: &["kind"],
+    required: false,
+};
+const PHENOMENON_TYPE: Column = Column {
+    parameter: None,
+    headers: &["phenomenon_type"],
+    required: false,
+};
+const AREA: Column = Column {
+    parameter: None,
+    headers: &["area"],
+    required: false,
+};
+
+impl Column {
+    /// Index of the column in `headers`: the one `requested`, by header or 1-based number,
+    /// or else the first with a usual header.
+    fn find(
+        &self,
+        headers: &StringRecord,
+        requested: Option<&str>,
+        errors: &mut Vec<FieldError>,
+    ) -> Option<usize> {
+        let position = |wanted: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(wanted.trim()))
+        };
+        let parameter = self.parameter.unwrap_or_default();
+        match requested {
+            Some(requested) => {
+                let number = requested
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| (1..=headers.len()).contains(n));
+                let index = position(requested).or(number.map(|n| n - 1));
+                if index.is_none() {
+                    errors.push(FieldError::new(
+                        parameter,
+                        "unknown",
+                        format!("no column `{}` in the header", requested),
+                    ));
+                }
+                index
+            }
+            None => {
+                let index = self.headers.iter().find_map(|header| position(header));
+                if index.is_none() && self.required {
+                    errors.push(FieldError::new(
+                        parameter,
+                        "required",
+                        format!(
+                            "no `{}` column in the header, name it with `{}`",
+                            self.headers[0], parameter
+                        ),
+                    ));
+                }
+                index
+            }
+        }
+    }
+}
+
+/// The rows of a CSV file with a header row, comma, semicolon or tab separated.
+///
+/// `columns` picks the columns of name, coordinates, radius and description; kind,
+/// phenomenon type and area are read from the columns `write` puts them in. Coordinates may
+/// be decimal or degrees, minutes and seconds. Rows are numbered like in a spreadsheet, the
+/// header being `rows[1]`; empty rows are skipped.
+pub fn read(body: &[u8], columns: &ColumnMapping) -> Result<Vec<ImportCandidate>, ApiError> {
+    let text = std::str::from_utf8(body)
+        .map_err(|_| ApiError::BadRequest("CSV must be UTF-8 text".to_string()))?;
+    let text = text.strip_prefix(BOM).unwrap_or(text);
+    let mut csv = ReaderBuilder::new()
+        .delimiter(delimiter(text))
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let invalid = |e: csv::Error| ApiError::BadRequest(format!("Invalid CSV: {}", e));
+
+    let headers = csv.headers().map_err(invalid)?.clone();
+    let mut errors = Vec::new();
+    let name_column = NAME.find(&headers, columns.name.as_deref(), &mut errors);
+    let latitude_column = LATITUDE.find(&headers, columns.latitude.as_deref(), &mut errors);
+    let longitude_column = LONGITUDE.find(&headers, columns.longitude.as_deref(), &mut errors);
+    let radius_column = RADIUS.find(&headers, columns.radius.as_deref(), &mut errors);
+    let description_column =
+        DESCRIPTION.find(&headers, columns.description.as_deref(), &mut errors);
+    let kind_column = KIND.find(&headers, None, &mut errors);
+    let phenomenon_type_column = PHENOMENON_TYPE.find(&headers, None, &mut errors);
+    let area_column = AREA.find(&headers, None, &mut errors);
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let mut candidates = Vec::new();
+    for (index, record) in csv.records().enumerate() {
+        let record = record.map_err(invalid)?;
+        if record.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let cell = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty())
+        };
+        let mut candidate = ImportCandidate {
+            source: format!("rows[{}]", index + 2),
+            ..Default::default()
+        };
+
+        candidate.name = cell(name_column).map(|name| restore(name).to_string());
+        candidate.latitude =
+            coordinate(&mut candidate, cell(latitude_column), "latitude", 'N', 'S');
+        candidate.longitude = coordinate(
+            &mut candidate,
+            cell(longitude_column),
+            "longitude",
+            'E',
+            'W',
+        );
+        candidate.description = cell(description_column)
+            .map(restore)
+            .unwrap_or_default()
+            .to_string();
+        if let Some(radius) = cell(radius_column) {
+            match radius.parse() {
+                Ok(radius) => candidate.radius = Some(radius),
+                Err(_) => candidate.error("radius", "type", "must be an integer"),
+            }
+        }
+        if let Some(kind) = cell(kind_column) {
+            match serde_json::from_value(serde_json::Value::String(kind.to_string())) {
+                Ok(kind) => candidate.kind = Some(kind),
+                Err(_) => {
+                    candidate.error("kind", "type", "must be `weather` or `natural_phenomenon`")
+                }
+            }
+        }
+        if let Some(phenomenon_type) = cell(phenomenon_type_column) {
+            match phenomenon_type.parse() {
+                Ok(phenomenon_type) => candidate.phenomenon_type = Some(phenomenon_type),
+                Err(e) => candidate.error("phenomenon_type", "type", e),
+            }
+        }
+        if let Some(area) = cell(area_column) {
+            match area.parse::<Area>() {
+                Ok(area) => candidate.area = Some(area),
+                Err(_) => candidate.error("area", "type", "must be a GeoJSON geometry"),
+            }
+        }
+        candidates.push(candidate);
+    }
+    Ok(candidates)
+}
+
+fn coordinate(
+    candidate: &mut ImportCandidate,
+    cell: Option<&str>,
+    field: &str,
+    positive: char,
+    negative: char,
+) -> Option<f64> {
+    match parse_degrees(cell?, positive, negative) {
+        Ok(degrees) => Some(degrees),
+        Err(message) => {
+            candidate.error(field, "type", message);
+            None
+        }
+    }
+}
+
+/// Separator of the header line: commas unless there are more semicolons or tabs, which
+/// spreadsheets use where the comma is the decimal separator.
+fn delimiter(text: &str) -> u8 {
+    let header = text.lines().next().unwrap_or_default();
+    // max_by_key keeps the last of equal counts, so the comma wins ties
+    [b'\t', b';', b',']
+        .into_iter()
+        .max_by_key(|&delimiter| header.bytes().filter(|&b| b == delimiter).count())
+        .unwrap_or(b',')
+}
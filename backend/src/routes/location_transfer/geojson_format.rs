@@ -0,0 +1,141 @@
+use crate::routes::location_transfer::models::{ExportedLocation, ImportCandidate, LocationKind};
+use crate::shared::error::ApiError;
+use crate::shared::geo::Area;
+use geojson::{Feature, FeatureCollection, GeoJson, Geometry, JsonObject, JsonValue};
+
+/// Media type of GeoJSON files.
+pub const MEDIA_TYPE: &str = "application/geo+json";
+
+/// Properties of a stored row that are not written out: the owner, the point, which is the
+/// geometry, and the version, which only means something to this service.
+const OMITTED_PROPERTIES: [&str; 4] = ["user_id", "latitude", "longitude", "version"];
+
+/// FeatureCollection with one Point feature per location, carrying the columns of its row
+/// and its `kind` as properties.
+pub fn write(locations: &[ExportedLocation]) -> Result<String, ApiError> {
+    let features = locations
+        .iter()
+        .map(|location| {
+            let row = match location {
+                ExportedLocation::Weather(row) => serde_json::to_value(row),
+                ExportedLocation::NaturalPhenomenon(row) => serde_json::to_value(row),
+            };
+            let JsonValue::Object(mut properties) =
+                row.map_err(|e| ApiError::Internal(format!("Error serializing location: {}", e)))?
+            else {
+                return Err(ApiError::Internal("Location is not an object".to_string()));
+            };
+            for name in OMITTED_PROPERTIES {
+                properties.remove(name);
+            }
+            let kind = serde_json::to_value(location.kind()).expect("kinds serialize");
+            properties.insert("kind".to_string(), kind);
+
+            let (latitude, longitude) = location.point();
+            Ok(Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geojson::Value::Point(vec![
+                    longitude, latitude,
+                ]))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            })
+        })
+        .collect::<Result<_, ApiError>>()?;
+
+    Ok(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    }
+    .to_string())
+}
+
+/// The locations of a GeoJSON FeatureCollection, or of a single Feature.
+///
+/// Features must be Points; `kind`, `name`, `description`, `radius`, `area` and
+/// `phenomenon_type` are read from their properties, the way `write` puts them there.
+pub fn read(body: &[u8]) -> Result<Vec<ImportCandidate>, ApiError> {
+    let text = std::str::from_utf8(body)
+        .map_err(|_| ApiError::BadRequest("GeoJSON must be UTF-8 text".to_string()))?;
+    let features = match text.parse::<GeoJson>() {
+        Ok(GeoJson::FeatureCollection(collection)) => collection.features,
+        Ok(GeoJson::Feature(feature)) => vec![feature],
+        Ok(GeoJson::Geometry(_)) => {
+            return Err(ApiError::BadRequest(
+                "GeoJSON must be a FeatureCollection or a Feature".to_string(),
+            ))
+        }
+        Err(e) => return Err(ApiError::BadRequest(format!("Invalid GeoJSON: {}", e))),
+    };
+
+    Ok(features
+        .into_iter()
+        .enumerate()
+        .map(|(index, feature)| candidate(index, feature))
+        .collect())
+}
+
+fn candidate(index: usize, feature: Feature) -> ImportCandidate {
+    let mut candidate = ImportCandidate {
+        source: format!("features[{}]", index),
+        ..Default::default()
+    };
+
+    match feature.geometry.map(|geometry| geometry.value) {
+        Some(geojson::Value::Point(position)) => {
+            candidate.longitude = position.first().copied();
+            candidate.latitude = position.get(1).copied();
+        }
+        Some(_) => candidate.error("geometry", "type", "must be a Point"),
+        None => candidate.error("geometry", "required", "is required"),
+    }
+
+    let properties = feature.properties.unwrap_or_default();
+    candidate.kind = property(&mut candidate, &properties, "kind", |value| {
+        serde_json::from_value::<LocationKind>(value.clone())
+            .map_err(|_| "must be `weather` or `natural_phenomenon`")
+    });
+    candidate.name = property(&mut candidate, &properties, "name", string);
+    candidate.description =
+        property(&mut candidate, &properties, "description", string).unwrap_or_default();
+    candidate.radius = property(&mut candidate, &properties, "radius", |value| {
+        value
+            .as_i64()
+            .and_then(|radius| i32::try_from(radius).ok())
+            .ok_or("must be an integer")
+    });
+    candidate.area = property(&mut candidate, &properties, "area", |value| {
+        Area::try_from(value.clone()).map_err(|_| "must be a GeoJSON geometry")
+    });
+    candidate.phenomenon_type = property(&mut candidate, &properties, "phenomenon_type", |value| {
+        value
+            .as_str()
+            .and_then(|kind| kind.parse().ok())
+            .ok_or("must be a known phenomenon type")
+    });
+    candidate
+}
+
+/// Property `name` read with `parse`; a missing or `null` property is `None`, one that does
+/// not parse is recorded as an error.
+fn property<T>(
+    candidate: &mut ImportCandidate,
+    properties: &JsonObject,
+    name: &str,
+    parse: impl FnOnce(&JsonValue) -> Result<T, &'static str>,
+) -> Option<T> {
+    let value = properties.get(name).filter(|value| !value.is_null())?;
+    match parse(value) {
+        Ok(value) => Some(value),
+        Err(message) => {
+            candidate.error(name, "type", message);
+            None
+        }
+    }
+}
+
+fn string(value: &JsonValue) -> Result<String, &'static str> {
+    value.as_str().map(str::to_string).ok_or("must be a string")
+}
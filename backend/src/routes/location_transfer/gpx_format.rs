@@ -0,0 +1,145 @@
+use crate::routes::location_transfer::models::{ExportedLocation, ImportCandidate, LocationKind};
+use crate::routes::location_transfer::xml::{child_text, children, escape, parse};
+use crate::routes::natural_phenomenon_locations::models::PhenomenonType;
+use crate::shared::error::ApiError;
+use roxmltree::Node;
+use std::fmt::Write;
+
+/// Media type of GPX files.
+pub const MEDIA_TYPE: &str = "application/gpx+xml";
+
+/// Namespace of the elements this service adds to `<extensions>`.
+const NAMESPACE: &str = "urn:wap:locations:1";
+
+/// Value of `<type>` for weather locations; natural phenomenon locations carry their
+/// phenomenon type.
+const WEATHER_TYPE: &str = "weather";
+
+/// GPX 1.1 file with one waypoint per location.
+///
+/// The `<type>` is the phenomenon type, or `weather`, and picks the `<sym>` devices show;
+/// the alert radius goes into `<extensions>`. Areas have no place in GPX and are left out.
+pub fn write(locations: &[ExportedLocation]) -> String {
+    let mut gpx = String::new();
+    gpx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        gpx,
+        "<gpx version=\"1.1\" creator=\"wap\" xmlns=\"http://www.topografix.com/GPX/1/1\" xmlns:wap=\"{}\">",
+        NAMESPACE
+    );
+    for location in locations {
+        let (latitude, longitude) = location.point();
+        let _ = writeln!(gpx, "  <wpt lat=\"{}\" lon=\"{}\">", latitude, longitude);
+        let _ = writeln!(gpx, "    <name>{}</name>", escape(location.name()));
+        if !location.description().is_empty() {
+            let _ = writeln!(gpx, "    <desc>{}</desc>", escape(location.description()));
+        }
+        let (kind, radius) = match location {
+            ExportedLocation::Weather(_) => (None, None),
+            ExportedLocation::NaturalPhenomenon(row) => {
+                (Some(row.phenomenon_type), Some(row.radius))
+            }
+        };
+        let _ = writeln!(gpx, "    <sym>{}</sym>", symbol(kind));
+        let _ = writeln!(
+            gpx,
+            "    <type>{}</type>",
+            kind.map_or(WEATHER_TYPE, PhenomenonType::as_str)
+        );
+        if let Some(radius) = radius {
+            let _ = writeln!(
+                gpx,
+                "    <extensions><wap:radius>{}</wap:radius></extensions>",
+                radius
+            );
+        }
+        gpx.push_str("  </wpt>\n");
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+/// Waypoint symbol of a phenomenon type, or of weather locations; common Garmin names.
+fn symbol(kind: Option<PhenomenonType>) -> &'static str {
+    match kind {
+        None => "Pin, Green",
+        Some(PhenomenonType::Volcano) => "Summit",
+        Some(PhenomenonType::Earthquake) => "Danger Area",
+        Some(PhenomenonType::Wildfire) => "Flag, Red",
+        Some(PhenomenonType::Flood) => "Pin, Blue",
+        Some(PhenomenonType::Storm) => "Flag, Blue",
+        Some(PhenomenonType::Landslide) => "Pin, Red",
+        Some(PhenomenonType::Avalanche) => "Skiing Area",
+        Some(PhenomenonType::Tsunami) => "Beach",
+        Some(PhenomenonType::Other) => "Flag, Green",
+    }
+}
+
+/// The waypoints of a GPX 1.0 or 1.1 file; routes and tracks are ignored.
+///
+/// `<name>`, `<desc>` (or `<cmt>`) and `<type>` are read from each waypoint, and the
+/// alert radius from the `<extensions>` `write` adds.
+pub fn read(body: &[u8]) -> Result<Vec<ImportCandidate>, ApiError> {
+    let document = parse(body, "GPX")?;
+    let root = document.root_element();
+    if root.tag_name().name() != "gpx" {
+        return Err(ApiError::BadRequest(
+            "GPX must have a <gpx> root element".to_string(),
+        ));
+    }
+
+    Ok(children(root, "wpt")
+        .enumerate()
+        .map(|(index, waypoint)| candidate(index, waypoint))
+        .collect())
+}
+
+fn candidate(index: usize, waypoint: Node) -> ImportCandidate {
+    let mut candidate = ImportCandidate {
+        source: format!("wpt[{}]", index),
+        ..Default::default()
+    };
+
+    candidate.latitude = coordinate(&mut candidate, waypoint, "lat", "latitude");
+    candidate.longitude = coordinate(&mut candidate, waypoint, "lon", "longitude");
+    candidate.name = child_text(waypoint, "name");
+    candidate.description = child_text(waypoint, "desc")
+        .or_else(|| child_text(waypoint, "cmt"))
+        .unwrap_or_default();
+
+    // devices put anything into <type>, only the values this service writes mean something
+    match child_text(waypoint, "type") {
+        Some(kind) if kind == WEATHER_TYPE => candidate.kind = Some(LocationKind::Weather),
+        Some(kind) => candidate.phenomenon_type = kind.parse().ok(),
+        None => {}
+    }
+
+    let radius = children(waypoint, "extensions")
+        .flat_map(|extensions| extensions.children())
+        .find(|e| e.tag_name().namespace() == Some(NAMESPACE) && e.tag_name().name() == "radius")
+        .and_then(|e| e.text().map(str::trim).map(str::to_string));
+    if let Some(radius) = radius {
+        match radius.parse() {
+            Ok(radius) => candidate.radius = Some(radius),
+            Err(_) => candidate.error("radius", "type", "must be an integer"),
+        }
+    }
+    candidate
+}
+
+/// Attribute `attribute` of `waypoint` as a number; a missing one is left to the validation.
+fn coordinate(
+    candidate: &mut ImportCandidate,
+    waypoint: Node,
+    attribute: &str,
+    field: &str,
+) -> Option<f64> {
+    let value = waypoint.attribute(attribute)?;
+    match value.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() => Some(value),
+        _ => {
+            candidate.error(field, "type", "must be a number");
+            None
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::routes::auth::middlewares::auth;
+use crate::routes::auth::models::UserDb;
+use crate::routes::auth::services::AuthService;
+use crate::routes::location_transfer::models::{
+    ExportQuery, ImportCandidate, ImportDefaults, ImportQuery, ImportReport, TransferFormat,
+    MAX_IMPORT_SIZE,
+};
+use crate::routes::location_transfer::services::{
+    LocationTransferService, LocationTransferServiceImpl,
+};
+use crate::routes::location_transfer::{
+    csv_format, geojson_format, gpx_format, kml_format, kmz_format,
+};
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::idempotency::{idempotent, Idempotency};
+use crate::shared::models::AppState;
+use crate::shared::validation::ValidatedQuery;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use bytes::Bytes;
+use std::sync::Arc;
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouterExt};
+use utoipa_axum::routes;
+
+/// Export the weather and natural phenomenon locations of the current user as a file.
+#[utoipa::path(
+    get,
+    path = "/locations/export",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The locations as a GeoJSON FeatureCollection, GPX waypoints, KML placemarks styled per phenomenon type, KMZ with their images, or CSV rows",
+            content(
+                (String = "text/csv"),
+                (String = "application/geo+json"),
+                (String = "application/gpx+xml"),
+                (String = "application/vnd.google-earth.kml+xml"),
+                (Vec<u8> = "application/vnd.google-earth.kmz")
+            ),
+            headers(("Content-Disposition" = String, description = "Suggested file name"))),
+        (status = 422, description = "Invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn export_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedQuery(query): ValidatedQuery<ExportQuery>,
+) -> Result<Response, ApiError>
+where
+    S: LocationTransferServiceImpl,
+{
+    let locations = service.export(user.id, query.kind).await?;
+    let (media_type, extension, body) = match query.format {
+        TransferFormat::Geojson => (
+            geojson_format::MEDIA_TYPE,
+            "geojson",
+            geojson_format::write(&locations)?.into_bytes(),
+        ),
+        TransferFormat::Gpx => (
+            gpx_format::MEDIA_TYPE,
+            "gpx",
+            gpx_format::write(&locations).into_bytes(),
+        ),
+        TransferFormat::Kml => (
+            kml_format::MEDIA_TYPE,
+            "kml",
+            kml_format::write(&locations, &Default::default()).into_bytes(),
+        ),
+        TransferFormat::Kmz => {
+            // reads the images from disk
+            let body = tokio::task::spawn_blocking(move || kmz_format::write(&locations))
+                .await
+                .map_err(|e| ApiError::Internal(format!("KMZ export failed: {}", e)))??;
+            (kmz_format::MEDIA_TYPE, "kmz", body)
+        }
+        TransferFormat::Csv => (
+            csv_format::MEDIA_TYPE,
+            "csv",
+            csv_format::write(&locations)?,
+        ),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, media_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"locations.{}\"", extension),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Import weather and natural phenomenon locations for the current user from a file.
+///
+/// GPX waypoints and KML placemarks become natural phenomenon locations unless `kind` says
+/// otherwise; images embedded in a KMZ are stored with their locations. CSV columns are
+/// picked with the `*_column` parameters. Locations the user already has, with the same kind
+/// and name within 50 m, are skipped. If any location is invalid nothing is imported and
+/// every problem is reported, per feature, waypoint, placemark or row; with `dry_run` the
+/// file is only checked and the locations to be created are listed.
+#[utoipa::path(
+    post,
+    path = "/locations/import",
+    params(
+        ImportQuery,
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries return the first response instead of importing twice")
+    ),
+    request_body(
+        description = "GeoJSON FeatureCollection of Point features, GPX file, KML document, KMZ archive or CSV file with a header row",
+        content(
+            (String = "text/csv"),
+            (String = "application/geo+json"),
+            (String = "application/gpx+xml"),
+            (String = "application/vnd.google-earth.kml+xml"),
+            (Vec<u8> = "application/vnd.google-earth.kmz")
+        )
+    ),
+    responses(
+        (status = 200, description = "Locations created, or to be created, and duplicates skipped", body = ImportReport),
+        (status = 400, description = "The body is not a file of the format", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Idempotency-Key reused for a different or still running request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "File or number of locations too large", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid locations, nothing was imported", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn import_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedQuery(query): ValidatedQuery<ImportQuery>,
+    body: Bytes,
+) -> Result<Json<ImportReport>, ApiError>
+where
+    S: LocationTransferServiceImpl,
+{
+    // unpacking and parsing up to MAX_IMPORT_SIZE takes a while, off the async threads
+    let format = query.format;
+    let columns = query.columns();
+    let candidates: Vec<ImportCandidate> = tokio::task::spawn_blocking(move || match format {
+        TransferFormat::Geojson => geojson_format::read(&body),
+        TransferFormat::Gpx => gpx_format::read(&body),
+        TransferFormat::Kml => kml_format::read(&body, None),
+        TransferFormat::Kmz => kmz_format::read(&body),
+        TransferFormat::Csv => csv_format::read(&body, &columns),
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("Import failed: {}", e)))??;
+    let defaults = ImportDefaults {
+        kind: query.kind.or(query.format.default_kind()),
+        radius: query.radius,
+    };
+    let report = service
+        .import(user.id, candidates, defaults, query.dry_run)
+        .await?;
+    Ok(Json(report))
+}
+
+/// Generic router allowing injection of any implementation of the transfer service
+pub fn router_with_service<S>(app: AppState, service: Arc<S>) -> OpenApiRouter
+where
+    S: LocationTransferServiceImpl,
+{
+    let auth_service = Arc::new(AuthService {
+        db: app.db.clone(),
+        settings: app.settings.clone(),
+        http: Default::default(),
+    });
+    OpenApiRouter::new()
+        .routes(routes!(export_locations))
+        .routes(
+            routes!(import_locations)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE))
+                .layer(axum::middleware::from_fn_with_state(
+                    Idempotency::new(&app),
+                    idempotent,
+                )),
+        )
+        .layer(axum::middleware::from_fn_with_state(auth_service, auth))
+        .with_state(service)
+}
+
+/// Convenience router using the Postgres-backed implementation
+pub fn router(app: AppState) -> OpenApiRouter {
+    let service = Arc::new(LocationTransferService::new(app.db.clone()));
+    router_with_service(app, service)
+}
@@ -0,0 +1,333 @@
+use crate::routes::location_transfer::models::{ExportedLocation, ImportCandidate, ImportImage};
+use crate::routes::location_transfer::xml::{child_text, children, escape, parse};
+use crate::routes::natural_phenomenon_locations::models::PhenomenonType;
+use crate::shared::error::ApiError;
+use crate::shared::geo::Area;
+use crate::shared::models::DatabaseId;
+use geojson::{Geometry, PolygonType, Position};
+use roxmltree::Node;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Media type of KML files.
+pub const MEDIA_TYPE: &str = "application/vnd.google-earth.kml+xml";
+
+/// Icon of every placemark, tinted with the color of its style.
+const ICON: &str = "https://maps.google.com/mapfiles/kml/shapes/placemark_circle.png";
+
+/// Style of weather locations; natural phenomenon locations get one per phenomenon type.
+const WEATHER_STYLE: &str = "weather";
+
+/// KML document with one placemark per location and a style per phenomenon type.
+///
+/// Kind, phenomenon type, radius and image go into the `ExtendedData` of each placemark,
+/// an area becomes polygons next to its point. `images` maps locations to the path of their
+/// image inside a KMZ archive; plain KML files carry none.
+pub fn write(locations: &[ExportedLocation], images: &HashMap<DatabaseId, String>) -> String {
+    let mut kml = String::new();
+    kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    kml.push_str("  <name>Locations</name>\n");
+    style(&mut kml, WEATHER_STYLE, "1b9e77");
+    for kind in PhenomenonType::ALL {
+        style(&mut kml, &style_id(kind), color(kind));
+    }
+
+    for location in locations {
+        let phenomenon = match location {
+            ExportedLocation::Weather(_) => None,
+            ExportedLocation::NaturalPhenomenon(row) => Some(row),
+        };
+        kml.push_str("  <Placemark>\n");
+        let _ = writeln!(kml, "    <name>{}</name>", escape(location.name()));
+        if !location.description().is_empty() {
+            let _ = writeln!(
+                kml,
+                "    <description>{}</description>",
+                escape(location.description())
+            );
+        }
+        let style = phenomenon.map_or(WEATHER_STYLE.to_string(), |row| {
+            style_id(row.phenomenon_type)
+        });
+        let _ = writeln!(kml, "    <styleUrl>#{}</styleUrl>", style);
+
+        kml.push_str("    <ExtendedData>\n");
+        let kind = serde_json::to_value(location.kind()).expect("kinds serialize");
+        data(&mut kml, "kind", kind.as_str().unwrap_or_default());
+        if let Some(row) = phenomenon {
+            data(&mut kml, "phenomenon_type", row.phenomenon_type.as_str());
+            data(&mut kml, "radius", &row.radius.to_string());
+            if let Some(path) = images.get(&row.id) {
+                data(&mut kml, "image", path);
+            }
+        }
+        kml.push_str("    </ExtendedData>\n");
+
+        let (latitude, longitude) = location.point();
+        let point = format!(
+            "<Point><coordinates>{},{}</coordinates></Point>",
+            longitude, latitude
+        );
+        let area = phenomenon
+            .and_then(|row| row.area.clone())
+            .and_then(|area| Area::try_from(area).ok());
+        match area.map(|area| polygons(&area.0)) {
+            Some(polygons) if !polygons.is_empty() => {
+                let _ = writeln!(
+                    kml,
+                    "    <MultiGeometry>{}{}</MultiGeometry>",
+                    point, polygons
+                );
+            }
+            _ => {
+                let _ = writeln!(kml, "    {}", point);
+            }
+        }
+        kml.push_str("  </Placemark>\n");
+    }
+
+    kml.push_str("</Document>\n</kml>\n");
+    kml
+}
+
+fn style_id(kind: PhenomenonType) -> String {
+    format!("phenomenon-{}", kind.as_str())
+}
+
+/// Map color of a phenomenon type, as `rrggbb`.
+fn color(kind: PhenomenonType) -> &'static str {
+    match kind {
+        PhenomenonType::Volcano => "d7301f",
+        PhenomenonType::Earthquake => "8c510a",
+        PhenomenonType::Wildfire => "fd8d3c",
+        PhenomenonType::Flood => "2171b5",
+        PhenomenonType::Storm => "6a51a3",
+        PhenomenonType::Landslide => "a6761d",
+        PhenomenonType::Avalanche => "9ecae1",
+        PhenomenonType::Tsunami => "08519c",
+        PhenomenonType::Other => "737373",
+    }
+}
+
+/// Style `id` drawing icons and outlines in `rgb` and filling areas with it, half transparent.
+fn style(kml: &mut String, id: &str, rgb: &str) {
+    // KML colors are aabbggrr
+    let bgr = format!("{}{}{}", &rgb[4..6], &rgb[2..4], &rgb[0..2]);
+    let _ = writeln!(
+        kml,
+        "  <Style id=\"{id}\"><IconStyle><color>ff{bgr}</color><Icon><href>{ICON}</href></Icon></IconStyle>\
+         <LineStyle><color>ff{bgr}</color><width>2</width></LineStyle><PolyStyle><color>80{bgr}</color></PolyStyle></Style>"
+    );
+}
+
+fn data(kml: &mut String, name: &str, value: &str) {
+    let _ = writeln!(
+        kml,
+        "      <Data name=\"{}\"><value>{}</value></Data>",
+        name,
+        escape(value)
+    );
+}
+
+/// KML polygons of an area geometry.
+fn polygons(geometry: &Geometry) -> String {
+    let polygons: Vec<&PolygonType> = match &geometry.value {
+        geojson::Value::Polygon(polygon) => vec![polygon],
+        geojson::Value::MultiPolygon(polygons) => polygons.iter().collect(),
+        _ => Vec::new(),
+    };
+    let mut kml = String::new();
+    for rings in polygons {
+        kml.push_str("<Polygon>");
+        for (index, ring) in rings.iter().enumerate() {
+            let boundary = if index == 0 {
+                "outerBoundaryIs"
+            } else {
+                "innerBoundaryIs"
+            };
+            let coordinates: Vec<String> = ring
+                .iter()
+                .map(|position| format!("{},{}", position[0], position[1]))
+                .collect();
+            let _ = write!(
+                kml,
+                "<{boundary}><LinearRing><coordinates>{}</coordinates></LinearRing></{boundary}>",
+                coordinates.join(" ")
+            );
+        }
+        kml.push_str("</Polygon>");
+    }
+    kml
+}
+
+/// The placemarks of a KML document, in any folder.
+///
+/// Each placemark needs a Point, its polygons become the area. `name` and `description` are
+/// read, and kind, phenomenon type, radius and image from the `ExtendedData` `write` adds.
+/// `files` are the other files of a KMZ archive: the image of a placemark is taken from its
+/// `image` data or the first `<img>` of its description. Plain KML files carry no images.
+pub fn read(
+    body: &[u8],
+    files: Option<&HashMap<String, Vec<u8>>>,
+) -> Result<Vec<ImportCandidate>, ApiError> {
+    let document = parse(body, "KML")?;
+    if document.root_element().tag_name().name() != "kml" {
+        return Err(ApiError::BadRequest(
+            "KML must have a <kml> root element".to_string(),
+        ));
+    }
+
+    Ok(document
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "Placemark")
+        .enumerate()
+        .map(|(index, placemark)| candidate(index, placemark, files))
+        .collect())
+}
+
+fn candidate(
+    index: usize,
+    placemark: Node,
+    files: Option<&HashMap<String, Vec<u8>>>,
+) -> ImportCandidate {
+    let mut candidate = ImportCandidate {
+        source: format!("Placemark[{}]", index),
+        ..Default::default()
+    };
+    candidate.name = child_text(placemark, "name");
+    candidate.description = child_text(placemark, "description").unwrap_or_default();
+
+    let data: HashMap<String, String> = children(placemark, "ExtendedData")
+        .flat_map(|extended| children(extended, "Data"))
+        .filter_map(|data| {
+            Some((
+                data.attribute("name")?.to_string(),
+                child_text(data, "value")?,
+            ))
+        })
+        .collect();
+    if let Some(kind) = data.get("kind") {
+        match serde_json::from_value(serde_json::Value::String(kind.clone())) {
+            Ok(kind) => candidate.kind = Some(kind),
+            Err(_) => candidate.error("kind", "type", "must be `weather` or `natural_phenomenon`"),
+        }
+    }
+    if let Some(kind) = data.get("phenomenon_type") {
+        match kind.parse() {
+            Ok(kind) => candidate.phenomenon_type = Some(kind),
+            Err(e) => candidate.error("phenomenon_type", "type", e),
+        }
+    }
+    if let Some(radius) = data.get("radius") {
+        match radius.parse() {
+            Ok(radius) => candidate.radius = Some(radius),
+            Err(_) => candidate.error("radius", "type", "must be an integer"),
+        }
+    }
+
+    let geometries: Vec<Node> = placemark
+        .descendants()
+        .filter(|node| node.is_element())
+        .collect();
+    match geometries
+        .iter()
+        .find(|node| node.tag_name().name() == "Point")
+    {
+        Some(point) => match child_text(*point, "coordinates")
+            .as_deref()
+            .map(positions)
+            .and_then(|positions| positions.ok()?.into_iter().next())
+        {
+            Some(position) => {
+                candidate.longitude = Some(position[0]);
+                candidate.latitude = Some(position[1]);
+            }
+            None => candidate.error("geometry", "type", "must have valid Point coordinates"),
+        },
+        None => candidate.error("geometry", "required", "must contain a Point"),
+    }
+
+    let polygons: Result<Vec<PolygonType>, ()> = geometries
+        .iter()
+        .filter(|node| node.tag_name().name() == "Polygon")
+        .map(|polygon| polygon_rings(*polygon))
+        .collect();
+    match polygons {
+        Ok(mut polygons) if polygons.len() == 1 => {
+            let polygon = polygons.remove(0);
+            candidate.area = Some(Area(Geometry::new(geojson::Value::Polygon(polygon))));
+        }
+        Ok(polygons) if !polygons.is_empty() => {
+            candidate.area = Some(Area(Geometry::new(geojson::Value::MultiPolygon(polygons))));
+        }
+        Ok(_) => {}
+        Err(()) => candidate.error("area", "type", "must have valid Polygon coordinates"),
+    }
+
+    if let Some(files) = files {
+        let from_data = data.get("image").map(String::as_str);
+        let reference = from_data.or_else(|| image_source(&candidate.description));
+        if let Some(reference) = reference.filter(|r| !r.contains("://")) {
+            let name = reference.trim_start_matches("./");
+            match files.get(name) {
+                Some(bytes) => {
+                    candidate.image = Some(ImportImage {
+                        file_name: name.to_string(),
+                        bytes: bytes.clone(),
+                    })
+                }
+                None => candidate.error(
+                    "image",
+                    "missing",
+                    format!("`{}` is not in the archive", name),
+                ),
+            }
+        }
+    }
+    candidate
+}
+
+/// Rings of a KML polygon, the outer boundary first.
+fn polygon_rings(polygon: Node) -> Result<PolygonType, ()> {
+    let boundaries =
+        children(polygon, "outerBoundaryIs").chain(children(polygon, "innerBoundaryIs"));
+    boundaries
+        .map(|boundary| {
+            let ring = children(boundary, "LinearRing").next().ok_or(())?;
+            positions(&child_text(ring, "coordinates").ok_or(())?)
+        })
+        .collect()
+}
+
+/// Positions of a KML `coordinates` text, `lon,lat[,alt]` tuples separated by whitespace.
+fn positions(coordinates: &str) -> Result<Vec<Position>, ()> {
+    coordinates
+        .split_whitespace()
+        .map(|tuple| {
+            let mut values = tuple.split(',').map(|value| value.parse::<f64>());
+            match (values.next(), values.next()) {
+                (Some(Ok(longitude)), Some(Ok(latitude)))
+                    if longitude.is_finite() && latitude.is_finite() =>
+                {
+                    Ok(vec![longitude, latitude])
+                }
+                _ => Err(()),
+            }
+        })
+        .collect()
+}
+
+/// `src` of the first `<img>` in an HTML description.
+fn image_source(description: &str) -> Option<&str> {
+    let start = description.to_ascii_lowercase().find("<img")?;
+    let tag = &description[start..];
+    let tag = &tag[..tag.find('>')?];
+    let src = tag.to_ascii_lowercase().find("src=")? + 4;
+    let quote = tag[src..]
+        .chars()
+        .next()
+        .filter(|c| *c == '"' || *c == '\'')?;
+    let value = &tag[src + 1..];
+    Some(&value[..value.find(quote)?])
+}
@@ -0,0 +1,99 @@
+use crate::routes::location_transfer::kml_format;
+use crate::routes::location_transfer::models::{
+    ExportedLocation, ImportCandidate, MAX_ARCHIVE_SIZE,
+};
+use crate::shared::error::ApiError;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Media type of KMZ files.
+pub const MEDIA_TYPE: &str = "application/vnd.google-earth.kmz";
+
+/// Name of the KML document inside the archives this service writes.
+const DOCUMENT: &str = "doc.kml";
+
+/// ZIP archive with the KML document of the locations and their images under `files/`.
+///
+/// Reads the images from disk, so it blocks; images that are gone are left out.
+pub fn write(locations: &[ExportedLocation]) -> Result<Vec<u8>, ApiError> {
+    let mut images = HashMap::new();
+    let mut files = Vec::new();
+    for location in locations {
+        let ExportedLocation::NaturalPhenomenon(row) = location else {
+            continue;
+        };
+        let Some(path) = row.image_path.as_deref() else {
+            continue;
+        };
+        let Ok(bytes) = std::fs::read(path) else {
+            tracing::warn!(path, "image of location {} is missing", row.id.0);
+            continue;
+        };
+        let name = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("image");
+        let archive_name = format!("files/{}", name);
+        images.insert(row.id, archive_name.clone());
+        files.push((archive_name, bytes));
+    }
+    let document = kml_format::write(locations, &images);
+
+    let archive_error =
+        |e: zip::result::ZipError| ApiError::Internal(format!("Error writing KMZ: {}", e));
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // images are compressed already
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    // readers take the first KML file of the archive as the document
+    zip.start_file(DOCUMENT, deflated).map_err(archive_error)?;
+    zip.write_all(document.as_bytes())?;
+    for (name, bytes) in files {
+        zip.start_file(name, stored).map_err(archive_error)?;
+        zip.write_all(&bytes)?;
+    }
+    Ok(zip.finish().map_err(archive_error)?.into_inner())
+}
+
+/// The placemarks of the KML document of a KMZ archive, with the images they refer to.
+///
+/// The document is `doc.kml`, or else the first KML file of the archive.
+pub fn read(body: &[u8]) -> Result<Vec<ImportCandidate>, ApiError> {
+    let invalid = |e: zip::result::ZipError| ApiError::BadRequest(format!("Invalid KMZ: {}", e));
+    let mut archive = ZipArchive::new(Cursor::new(body)).map_err(invalid)?;
+
+    // unpack everything, the declared sizes cannot be trusted to keep archive bombs out
+    let mut files = HashMap::new();
+    let mut remaining = MAX_ARCHIVE_SIZE;
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(invalid)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_string();
+        let mut bytes = Vec::new();
+        file.take(remaining + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid KMZ: {}", e)))?;
+        remaining = remaining.checked_sub(bytes.len() as u64).ok_or_else(|| {
+            ApiError::PayloadTooLarge(format!("KMZ content exceeds {} bytes", MAX_ARCHIVE_SIZE))
+        })?;
+        files.insert(name, bytes);
+    }
+
+    let is_kml = |name: &String| name.to_ascii_lowercase().ends_with(".kml");
+    let document = if files.contains_key(DOCUMENT) {
+        DOCUMENT.to_string()
+    } else {
+        (0..archive.len())
+            .filter_map(|index| archive.name_for_index(index).map(str::to_string))
+            .find(is_kml)
+            .ok_or_else(|| ApiError::BadRequest("KMZ holds no KML document".to_string()))?
+    };
+    let document = files.remove(&document).unwrap_or_default();
+    kml_format::read(&document, Some(&files))
+}
@@ -0,0 +1,9 @@
+pub mod csv_format;
+pub mod geojson_format;
+pub mod gpx_format;
+pub mod handlers;
+pub mod kml_format;
+pub mod kmz_format;
+pub mod models;
+pub mod services;
+pub mod xml;
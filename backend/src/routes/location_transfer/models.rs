@@ -0,0 +1,285 @@
+use crate::routes::natural_phenomenon_locations::models::{
+    NaturalPhenomenonLocationDb, PhenomenonType, MAX_RADIUS_KM,
+};
+use crate::routes::weather_locations::models::WeatherLocation;
+use crate::shared::error::FieldError;
+use crate::shared::geo::{validate_area, Area};
+use crate::shared::models::DatabaseId;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+/// Most locations accepted in one import.
+pub const MAX_IMPORT_LOCATIONS: usize = 5000;
+
+/// Largest accepted import file, in bytes.
+pub const MAX_IMPORT_SIZE: usize = 20 * 1024 * 1024;
+
+/// Largest accepted content of a KMZ archive once unpacked, in bytes.
+pub const MAX_ARCHIVE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Locations of the same kind and name at most this far apart are duplicates, in kilometers.
+pub const DUPLICATE_DISTANCE_KM: f64 = 0.05;
+
+/// Which of the two location tables a location belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationKind {
+    Weather,
+    NaturalPhenomenon,
+}
+
+/// File format of an import or export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferFormat {
+    /// GeoJSON FeatureCollection of points.
+    Geojson,
+    /// GPX waypoints, as recorded by GPS devices.
+    Gpx,
+    /// KML placemarks, as used by Google Earth.
+    Kml,
+    /// Zipped KML with the images of the locations.
+    Kmz,
+    /// Spreadsheet with one location per row.
+    Csv,
+}
+
+impl TransferFormat {
+    /// Kind of the imported locations that state none, unless the request names one.
+    ///
+    /// Waypoints and placemarks are sites recorded in the field, so natural phenomenon
+    /// locations.
+    pub fn default_kind(self) -> Option<LocationKind> {
+        match self {
+            TransferFormat::Geojson | TransferFormat::Csv => None,
+            TransferFormat::Gpx | TransferFormat::Kml | TransferFormat::Kmz => {
+                Some(LocationKind::NaturalPhenomenon)
+            }
+        }
+    }
+}
+
+/// Query parameters of an export.
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Format of the file.
+    #[param(inline)]
+    pub format: TransferFormat,
+
+    /// Only locations of this kind; both kinds when left out.
+    #[param(inline)]
+    pub kind: Option<LocationKind>,
+}
+
+/// Query parameters of an import.
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Format of the request body.
+    #[param(inline)]
+    pub format: TransferFormat,
+
+    /// Kind of the locations that do not state their own; natural phenomenon locations for
+    /// GPX, KML and KMZ files.
+    #[param(inline)]
+    pub kind: Option<LocationKind>,
+
+    /// Alert radius of the natural phenomenon locations that do not state their own.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    #[param(minimum = 1, maximum = 20000)]
+    pub radius: Option<i32>,
+
+    /// Only check the file and report what would be created.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// CSV column of the names, by header or 1-based number; `name` by default.
+    #[validate(length(min = 1, max = 100))]
+    pub name_column: Option<String>,
+
+    /// CSV column of the latitudes, decimal or degrees, minutes and seconds; `latitude` or
+    /// `lat` by default.
+    #[validate(length(min = 1, max = 100))]
+    pub latitude_column: Option<String>,
+
+    /// CSV column of the longitudes, decimal or degrees, minutes and seconds; `longitude`,
+    /// `lon` or `lng` by default.
+    #[validate(length(min = 1, max = 100))]
+    pub longitude_column: Option<String>,
+
+    /// CSV column of the alert radii; `radius` by default.
+    #[validate(length(min = 1, max = 100))]
+    pub radius_column: Option<String>,
+
+    /// CSV column of the descriptions; `description` by default.
+    #[validate(length(min = 1, max = 100))]
+    pub description_column: Option<String>,
+}
+
+impl ImportQuery {
+    pub fn columns(&self) -> ColumnMapping {
+        ColumnMapping {
+            name: self.name_column.clone(),
+            latitude: self.latitude_column.clone(),
+            longitude: self.longitude_column.clone(),
+            radius: self.radius_column.clone(),
+            description: self.description_column.clone(),
+        }
+    }
+}
+
+/// Which CSV columns hold what, each by header or 1-based number; `None` looks for the
+/// usual headers.
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMapping {
+    pub name: Option<String>,
+    pub latitude: Option<String>,
+    pub longitude: Option<String>,
+    pub radius: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A location of either kind, as stored.
+#[derive(Debug, Clone)]
+pub enum ExportedLocation {
+    Weather(WeatherLocation),
+    NaturalPhenomenon(NaturalPhenomenonLocationDb),
+}
+
+impl ExportedLocation {
+    pub fn kind(&self) -> LocationKind {
+        match self {
+            ExportedLocation::Weather(_) => LocationKind::Weather,
+            ExportedLocation::NaturalPhenomenon(_) => LocationKind::NaturalPhenomenon,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ExportedLocation::Weather(location) => &location.name,
+            ExportedLocation::NaturalPhenomenon(location) => &location.name,
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        match self {
+            ExportedLocation::Weather(location) => &location.description,
+            ExportedLocation::NaturalPhenomenon(location) => &location.description,
+        }
+    }
+
+    /// `(latitude, longitude)` of the location.
+    pub fn point(&self) -> (f64, f64) {
+        match self {
+            ExportedLocation::Weather(location) => (location.latitude, location.longitude),
+            ExportedLocation::NaturalPhenomenon(location) => {
+                (location.latitude, location.longitude)
+            }
+        }
+    }
+}
+
+/// One location read from an import file, before validation.
+///
+/// Format readers fill in what the file holds and record what they could not read in
+/// `errors`; everything else is checked by `validate` and the import itself.
+#[derive(Debug, Clone, Default, Validate)]
+pub struct ImportCandidate {
+    /// Where the location is in the file, e.g. `features[3]`; prefixes its errors.
+    #[validate(skip)]
+    pub source: String,
+
+    /// Kind stated by the file, if any.
+    #[validate(skip)]
+    pub kind: Option<LocationKind>,
+
+    #[validate(required, length(min = 1, max = 100))]
+    pub name: Option<String>,
+
+    #[validate(required, range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+
+    #[validate(required, range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+
+    #[validate(skip)]
+    pub description: String,
+
+    /// Alert radius, required for natural phenomenon locations.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    pub radius: Option<i32>,
+
+    /// Area of a natural phenomenon location.
+    #[validate(custom(function = "validate_area"))]
+    pub area: Option<Area>,
+
+    /// Kind of phenomenon of a natural phenomenon location.
+    #[validate(skip)]
+    pub phenomenon_type: Option<PhenomenonType>,
+
+    /// Image of a natural phenomenon location, stored with it.
+    #[validate(skip)]
+    pub image: Option<ImportImage>,
+
+    /// Fields the reader could not make sense of.
+    #[validate(skip)]
+    pub errors: Vec<FieldError>,
+}
+
+impl ImportCandidate {
+    /// Record that `field` could not be read.
+    pub fn error(&mut self, field: &str, code: &str, message: impl Into<String>) {
+        self.errors.push(FieldError::new(field, code, message));
+    }
+}
+
+/// An image carried by an import file.
+#[derive(Debug, Clone, Default)]
+pub struct ImportImage {
+    /// Name of the image in the file, e.g. `files/etna.jpg`.
+    pub file_name: String,
+
+    pub bytes: Vec<u8>,
+}
+
+/// What applies to the candidates of an import that do not state it themselves.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImportDefaults {
+    pub kind: Option<LocationKind>,
+
+    /// Alert radius of natural phenomenon locations.
+    pub radius: Option<i32>,
+}
+
+/// An imported location, or one that would be with `dry_run`.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct ImportItem {
+    /// Where the location is in the file, e.g. `features[3]` or `rows[4]`.
+    pub source: String,
+
+    pub kind: LocationKind,
+
+    pub name: String,
+
+    pub latitude: f64,
+
+    pub longitude: f64,
+
+    /// ID of the created location, or of the existing one a duplicate matches.
+    pub id: Option<DatabaseId>,
+}
+
+/// Outcome of an import; an import with any invalid location fails as a whole instead.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct ImportReport {
+    /// Whether this was only a preview.
+    pub dry_run: bool,
+
+    /// Locations created, or to be created.
+    pub created: Vec<ImportItem>,
+
+    /// Locations skipped because the user already has them, or the file has them twice.
+    pub duplicates: Vec<ImportItem>,
+}
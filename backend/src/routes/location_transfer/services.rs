@@ -0,0 +1,792 @@
+use crate::routes::location_transfer::models::{
+    ExportedLocation, ImportCandidate, ImportDefaults, ImportImage, ImportItem, ImportReport,
+    LocationKind, DUPLICATE_DISTANCE_KM, MAX_IMPORT_LOCATIONS,
+};
+use crate::routes::natural_phenomenon_locations::models::{
+    NaturalPhenomenonLocationDb, PhenomenonType, MAX_IMAGE_SIZE,
+};
+use crate::routes::weather_locations::models::WeatherLocation;
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::geo::{haversine_km, Area};
+use crate::shared::metrics::record_upload;
+use crate::shared::models::DatabaseId;
+use crate::shared::validation::field_errors;
+use async_trait::async_trait;
+use sanitize_filename::sanitize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use tokio::fs;
+use tracing::{debug, warn};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Moves the locations of a user in and out of the service, independent of the file format.
+#[async_trait]
+pub trait LocationTransferServiceImpl: Send + Sync + 'static {
+    /// All locations of `user_id`, only those of `kind` if given.
+    ///
+    /// Weather locations come first in the user's order, then the natural phenomenon
+    /// locations oldest first.
+    async fn export(
+        &self,
+        user_id: DatabaseId,
+        kind: Option<LocationKind>,
+    ) -> Result<Vec<ExportedLocation>, ApiError>;
+
+    /// Create the locations read from a file for `user_id`, skipping duplicates.
+    ///
+    /// `defaults` apply to the candidates that do not state their own kind or radius. Either
+    /// all locations are created in one transaction, their images stored in `uploads/`, or,
+    /// if any of them is invalid, none is and every problem is reported as an
+    /// `ApiError::Validation`. With `dry_run` nothing is written.
+    async fn import(
+        &self,
+        user_id: DatabaseId,
+        candidates: Vec<ImportCandidate>,
+        defaults: ImportDefaults,
+        dry_run: bool,
+    ) -> Result<ImportReport, ApiError>;
+}
+
+/// Postgres-backed implementation of `LocationTransferServiceImpl`.
+pub struct LocationTransferService {
+    /// SQLx Postgres connection pool.
+    pub db: PgPool,
+}
+
+impl LocationTransferService {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+/// A validated candidate, ready to be inserted.
+struct NewLocation {
+    source: String,
+    kind: LocationKind,
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    description: String,
+    radius: i32,
+    area: Option<Area>,
+    phenomenon_type: PhenomenonType,
+    image: Option<ImportImage>,
+}
+
+impl NewLocation {
+    /// Check `candidate`, returning its problems with the `source` prefixed to each field.
+    fn check(
+        candidate: ImportCandidate,
+        defaults: ImportDefaults,
+    ) -> Result<NewLocation, Vec<FieldError>> {
+        // a field the reader could not read is also missing, once is enough; readers
+        // report an unusable point as `geometry`
+        let unread = |field: &str| {
+            candidate.errors.iter().any(|e| {
+                e.field == field
+                    || (e.field == "geometry" && matches!(field, "latitude" | "longitude"))
+            })
+        };
+        let mut errors = candidate.errors.clone();
+        if let Err(e) = candidate.validate() {
+            errors.extend(field_errors(&e).into_iter().filter(|e| !unread(&e.field)));
+        }
+        let kind = candidate.kind.or(defaults.kind);
+        let radius = candidate.radius.or(defaults.radius);
+        match kind {
+            None => errors.push(FieldError::new(
+                "kind",
+                "required",
+                "is required, in the file or in the `kind` parameter",
+            )),
+            Some(LocationKind::NaturalPhenomenon) if radius.is_none() && !unread("radius") => {
+                errors.push(FieldError::new(
+                    "radius",
+                    "required",
+                    "is required, in the file or in the `radius` parameter",
+                ))
+            }
+            _ => {}
+        }
+        if let Some(image) = &candidate.image {
+            if kind == Some(LocationKind::Weather) {
+                errors.push(FieldError::new(
+                    "image",
+                    "kind",
+                    "only natural phenomenon locations have images",
+                ));
+            } else if mime_guess::from_path(&image.file_name)
+                .first()
+                .is_none_or(|mime| mime.type_() != mime_guess::mime::IMAGE)
+            {
+                errors.push(FieldError::new("image", "type", "must be an image"));
+            } else if image.bytes.len() > MAX_IMAGE_SIZE {
+                errors.push(FieldError::new(
+                    "image",
+                    "size",
+                    format!("must be at most {} bytes", MAX_IMAGE_SIZE),
+                ));
+            }
+        }
+        if let (Some(area), Some(LocationKind::NaturalPhenomenon)) = (&candidate.area, kind) {
+            if let (Some(latitude), Some(longitude)) = (candidate.latitude, candidate.longitude) {
+                if let Err(ApiError::Validation(e)) = area.check_extent(latitude, longitude) {
+                    errors.extend(e);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            for error in &mut errors {
+                error.field = format!("{}.{}", candidate.source, error.field);
+            }
+            return Err(errors);
+        }
+        let kind = kind.expect("checked above");
+        Ok(NewLocation {
+            source: candidate.source,
+            kind,
+            name: candidate.name.unwrap_or_default(),
+            latitude: candidate.latitude.unwrap_or_default(),
+            longitude: candidate.longitude.unwrap_or_default(),
+            description: candidate.description,
+            radius: radius.unwrap_or_default(),
+            // weather locations have no area or type
+            area: candidate
+                .area
+                .filter(|_| kind == LocationKind::NaturalPhenomenon),
+            phenomenon_type: candidate.phenomenon_type.unwrap_or_default(),
+            image: candidate.image,
+        })
+    }
+
+    fn item(&self, id: Option<DatabaseId>) -> ImportItem {
+        ImportItem {
+            source: self.source.clone(),
+            kind: self.kind,
+            name: self.name.clone(),
+            latitude: self.latitude,
+            longitude: self.longitude,
+            id,
+        }
+    }
+}
+
+/// Write `image` to `uploads/` under a fresh name, returning its path.
+async fn store_image(image: &ImportImage) -> Result<String, ApiError> {
+    fs::create_dir_all("uploads")
+        .await
+        .map_err(|e| ApiError::Internal(format!("Error creating uploads/ dir: {}", e)))?;
+    let original = image.file_name.rsplit('/').next().unwrap_or("image");
+    let path = format!("uploads/{}_{}", Uuid::new_v4(), sanitize(original));
+    fs::write(&path, &image.bytes)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Error writing image to disk: {}", e)))?;
+    record_upload(image.bytes.len());
+    Ok(path)
+}
+
+/// Images written by an import, removed again unless it `keep`s them.
+///
+/// A timeout or a disconnect drops the import at any `.await`, so the cleanup is done on drop
+/// rather than on the error path.
+#[derive(Default)]
+struct StoredImages {
+    paths: Vec<String>,
+    kept: bool,
+}
+
+impl StoredImages {
+    /// The import committed, the images belong to its locations now.
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for StoredImages {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        // no runtime to await on while dropping; the files are gone before the next request
+        for path in &self.paths {
+            match std::fs::remove_file(path) {
+                Ok(()) => debug!(path, "Removed image of a failed import"),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!(path, "Failed to remove image of a failed import: {}", e),
+            }
+        }
+    }
+}
+
+/// ID, unless the location is from the file, latitude and longitude of a known location.
+type KnownLocation = (Option<DatabaseId>, f64, f64);
+
+/// Locations by kind and case-folded name, to find duplicates.
+#[derive(Default)]
+struct KnownLocations(HashMap<(LocationKind, String), Vec<KnownLocation>>);
+
+impl KnownLocations {
+    fn add(&mut self, kind: LocationKind, name: &str, id: Option<DatabaseId>, point: (f64, f64)) {
+        self.0
+            .entry((kind, name.trim().to_lowercase()))
+            .or_default()
+            .push((id, point.0, point.1));
+    }
+
+    /// The known location `location` duplicates, with its ID unless it is from the file.
+    fn find(&self, location: &NewLocation) -> Option<Option<DatabaseId>> {
+        self.0
+            .get(&(location.kind, location.name.trim().to_lowercase()))?
+            .iter()
+            .find(|(_, latitude, longitude)| {
+                haversine_km(location.latitude, location.longitude, *latitude, *longitude)
+                    <= DUPLICATE_DISTANCE_KM
+            })
+            .map(|(id, _, _)| *id)
+    }
+}
+
+#[async_trait]
+impl LocationTransferServiceImpl for LocationTransferService {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn export(
+        &self,
+        user_id: DatabaseId,
+        kind: Option<LocationKind>,
+    ) -> Result<Vec<ExportedLocation>, ApiError> {
+        let mut locations = Vec::new();
+        if kind != Some(LocationKind::NaturalPhenomenon) {
+            let rows = sqlx::query_as!(
+                WeatherLocation,
+                "SELECT * FROM weather_locations
+                 WHERE user_id = $1 AND deleted_at IS NULL ORDER BY position, id",
+                user_id.0
+            )
+            .fetch_all(&self.db)
+            .await?;
+            locations.extend(rows.into_iter().map(ExportedLocation::Weather));
+        }
+        if kind != Some(LocationKind::Weather) {
+            let rows = sqlx::query_as!(
+                NaturalPhenomenonLocationDb,
+                "SELECT * FROM natural_phenomenon_locations
+                 WHERE user_id = $1 AND deleted_at IS NULL ORDER BY id",
+                user_id.0
+            )
+            .fetch_all(&self.db)
+            .await?;
+            locations.extend(rows.into_iter().map(ExportedLocation::NaturalPhenomenon));
+        }
+        Ok(locations)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, count = candidates.len(), dry_run))]
+    async fn import(
+        &self,
+        user_id: DatabaseId,
+        candidates: Vec<ImportCandidate>,
+        defaults: ImportDefaults,
+        dry_run: bool,
+    ) -> Result<ImportReport, ApiError> {
+        if candidates.len() > MAX_IMPORT_LOCATIONS {
+            return Err(ApiError::PayloadTooLarge(format!(
+                "At most {} locations can be imported at once",
+                MAX_IMPORT_LOCATIONS
+            )));
+        }
+
+        // 1) validate everything before touching the database
+        let mut locations = Vec::with_capacity(candidates.len());
+        let mut errors = Vec::new();
+        for candidate in candidates {
+            match NewLocation::check(candidate, defaults) {
+                Ok(location) => locations.push(location),
+                Err(e) => errors.extend(e),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::Validation(errors));
+        }
+
+        // 2) sort out what the user already has, or the file has twice
+        let mut tx = self.db.begin().await?;
+        let mut known = KnownLocations::default();
+        let weather = sqlx::query!(
+            "SELECT id, name, latitude, longitude FROM weather_locations
+             WHERE user_id = $1 AND deleted_at IS NULL",
+            user_id.0
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in weather {
+            let point = (row.latitude, row.longitude);
+            known.add(
+                LocationKind::Weather,
+                &row.name,
+                Some(DatabaseId(row.id)),
+                point,
+            );
+        }
+        let phenomena = sqlx::query!(
+            "SELECT id, name, latitude, longitude FROM natural_phenomenon_locations
+             WHERE user_id = $1 AND deleted_at IS NULL",
+            user_id.0
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in phenomena {
+            let point = (row.latitude, row.longitude);
+            let id = Some(DatabaseId(row.id));
+            known.add(LocationKind::NaturalPhenomenon, &row.name, id, point);
+        }
+
+        let mut report = ImportReport {
+            dry_run,
+            created: Vec::new(),
+            duplicates: Vec::new(),
+        };
+        let mut new_locations = Vec::new();
+        for location in locations {
+            if let Some(id) = known.find(&location) {
+                report.duplicates.push(location.item(id));
+                continue;
+            }
+            let point = (location.latitude, location.longitude);
+            known.add(location.kind, &location.name, None, point);
+            new_locations.push(location);
+        }
+        if dry_run {
+            report.created = new_locations.iter().map(|l| l.item(None)).collect();
+            return Ok(report);
+        }
+
+        // 3) all or nothing: the transaction is only committed at the end and the images
+        // stored so far are removed again if anything fails or the request is cancelled
+        let mut stored = StoredImages::default();
+        for location in &new_locations {
+            let image_path = match &location.image {
+                Some(image) => {
+                    let path = store_image(image).await?;
+                    stored.paths.push(path.clone());
+                    Some(path)
+                }
+                None => None,
+            };
+            let id = match location.kind {
+                LocationKind::Weather => {
+                    sqlx::query_scalar!(
+                        r#"
+                        INSERT INTO weather_locations (user_id, name, latitude, longitude, description, position)
+                        VALUES ($1, $2, $3, $4, $5,
+                                (SELECT COALESCE(MAX(position) + 1, 0) FROM weather_locations WHERE user_id = $1))
+                        RETURNING id
+                        "#,
+                        user_id.0,
+                        location.name,
+                        location.latitude,
+                        location.longitude,
+                        location.description,
+                    )
+                    .fetch_one(&mut *tx)
+                    .await?
+                }
+                LocationKind::NaturalPhenomenon => {
+                    sqlx::query_scalar!(
+                        r#"
+                        INSERT INTO natural_phenomenon_locations
+                            (user_id, name, latitude, longitude, description, radius, area, phenomenon_type, image_path)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                        RETURNING id
+                        "#,
+                        user_id.0,
+                        location.name,
+                        location.latitude,
+                        location.longitude,
+                        location.description,
+                        location.radius,
+                        location.area.as_ref().map(serde_json::Value::from),
+                        location.phenomenon_type.as_str(),
+                        image_path,
+                    )
+                    .fetch_one(&mut *tx)
+                    .await?
+                }
+            };
+            report.created.push(location.item(Some(DatabaseId(id))));
+        }
+        tx.commit().await?;
+        stored.keep();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::location_transfer::models::{ColumnMapping, TransferFormat};
+    use crate::routes::location_transfer::{
+        csv_format, geojson_format, gpx_format, kml_format, kmz_format,
+    };
+    use crate::tests::tests::TestApp;
+
+    #[test]
+    fn test_stored_images_removed_unless_kept() {
+        let stored = |name: &str| {
+            let path = std::env::temp_dir().join(format!("{}_{}", Uuid::new_v4(), name));
+            std::fs::write(&path, b"jpeg").unwrap();
+            StoredImages {
+                paths: vec![path.to_string_lossy().into_owned()],
+                kept: false,
+            }
+        };
+
+        // dropped mid-import, e.g. by the request timeout
+        let images = stored("cancelled.jpg");
+        let path = images.paths[0].clone();
+        drop(images);
+        assert!(!std::path::Path::new(&path).exists());
+
+        let images = stored("committed.jpg");
+        let path = images.paths[0].clone();
+        images.keep();
+        assert!(std::path::Path::new(&path).exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_geojson_round_trip(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let other: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('other@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO weather_locations (user_id, name, latitude, longitude) VALUES ($1, 'Bern', 46.95, 7.45)",
+        )
+        .bind(user_id.0)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path, area)
+               VALUES ($1, 'Etna', 37.75, 14.99, 10, NULL,
+                       '{"type":"Polygon","coordinates":[[[14.9,37.7],[15.1,37.7],[15.1,37.8],[14.9,37.7]]]}')"#,
+        )
+        .bind(user_id.0)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let service = LocationTransferService::new(pool);
+
+        let exported = service.export(user_id, None).await.unwrap();
+        let file = geojson_format::write(&exported).unwrap();
+        let candidates = || geojson_format::read(file.as_bytes()).unwrap();
+
+        // importing the export again only finds duplicates
+        let report = service
+            .import(user_id, candidates(), ImportDefaults::default(), false)
+            .await
+            .unwrap();
+        assert!(report.created.is_empty());
+        assert_eq!(report.duplicates.len(), 2);
+
+        let preview = service
+            .import(other, candidates(), ImportDefaults::default(), true)
+            .await
+            .unwrap();
+        assert_eq!(preview.created.len(), 2);
+        assert!(service.export(other, None).await.unwrap().is_empty());
+
+        let report = service
+            .import(other, candidates(), ImportDefaults::default(), false)
+            .await
+            .unwrap();
+        assert!(report.created.iter().all(|item| item.id.is_some()));
+        let imported = service.export(other, None).await.unwrap();
+        match &imported[..] {
+            [ExportedLocation::Weather(bern), ExportedLocation::NaturalPhenomenon(etna)] => {
+                assert_eq!((bern.name.as_str(), bern.latitude), ("Bern", 46.95));
+                assert_eq!(etna.radius, 10);
+                assert!(etna.area.is_some());
+            }
+            other => panic!("unexpected export {:?}", other),
+        }
+
+        // one bad feature fails the whole file, every problem is reported
+        let file = r#"{"type":"FeatureCollection","features":[
+            {"type":"Feature","geometry":{"type":"Point","coordinates":[8.5,47.4]},"properties":{"name":"Zurich"}},
+            {"type":"Feature","geometry":{"type":"Point","coordinates":[8.5,95]},"properties":{"name":"Nowhere","kind":"weather"}},
+            {"type":"Feature","geometry":{"type":"LineString","coordinates":[[0,0],[1,1]]},"properties":{"name":7,"kind":"natural_phenomenon"}}
+        ]}"#;
+        let candidates = geojson_format::read(file.as_bytes()).unwrap();
+        let Err(ApiError::Validation(errors)) = service
+            .import(other, candidates, ImportDefaults::default(), false)
+            .await
+        else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "features[0].kind",
+                "features[1].latitude",
+                "features[2].geometry",
+                "features[2].name",
+                "features[2].radius",
+            ]
+        );
+        assert_eq!(service.export(other, None).await.unwrap().len(), 2);
+
+        // the same place twice in one file is created once
+        let candidates = geojson_format::read(file.as_bytes())
+            .unwrap()
+            .into_iter()
+            .take(1)
+            .cycle()
+            .take(2)
+            .collect();
+        let weather = ImportDefaults {
+            kind: Some(LocationKind::Weather),
+            radius: None,
+        };
+        let report = service
+            .import(other, candidates, weather, false)
+            .await
+            .unwrap();
+        assert_eq!(report.created.len(), 1);
+        assert_eq!(report.duplicates[0].id, None);
+    }
+
+    #[sqlx::test]
+    async fn test_gpx_and_kml_transfer(pool: PgPool) {
+        // sqlx tests run on async-std, storing images needs the blocking pool of tokio
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _tokio = runtime.enter();
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let other: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('other@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        std::fs::create_dir_all("uploads").unwrap();
+        let image_path = format!("uploads/{}_etna.png", Uuid::new_v4());
+        std::fs::write(&image_path, b"png!").unwrap();
+        sqlx::query(
+            r#"INSERT INTO natural_phenomenon_locations
+                   (user_id, name, latitude, longitude, radius, image_path, phenomenon_type, area)
+               VALUES ($1, 'Etna & Co', 37.75, 14.99, 10, $2, 'volcano',
+                       '{"type":"Polygon","coordinates":[[[14.9,37.7],[15.1,37.7],[15.1,37.8],[14.9,37.7]]]}')"#,
+        )
+        .bind(user_id.0)
+        .bind(&image_path)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let service = LocationTransferService::new(pool);
+        let exported = service.export(user_id, None).await.unwrap();
+
+        // KMZ carries the image along, styled by phenomenon type
+        let kmz = kmz_format::write(&exported).unwrap();
+        let kml = kml_format::write(&exported, &HashMap::new());
+        assert!(kml.contains("<styleUrl>#phenomenon-volcano</styleUrl>"));
+        assert!(kml.contains("Etna &amp; Co"));
+        let report = service
+            .import(
+                other,
+                kmz_format::read(&kmz).unwrap(),
+                ImportDefaults::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.created.len(), 1);
+        match &service.export(other, None).await.unwrap()[..] {
+            [ExportedLocation::NaturalPhenomenon(etna)] => {
+                assert_eq!(etna.name, "Etna & Co");
+                assert_eq!(etna.phenomenon_type, PhenomenonType::Volcano);
+                assert_eq!(etna.radius, 10);
+                assert!(etna.area.is_some());
+                let copy = etna.image_path.as_deref().unwrap();
+                assert_ne!(copy, image_path);
+                assert_eq!(std::fs::read(copy).unwrap(), b"png!");
+                std::fs::remove_file(copy).unwrap();
+            }
+            other => panic!("unexpected import {:?}", other),
+        }
+        std::fs::remove_file(&image_path).unwrap();
+
+        // GPX round trips too, only without the area
+        let gpx = gpx_format::write(&exported);
+        let candidates = gpx_format::read(gpx.as_bytes()).unwrap();
+        assert_eq!(candidates[0].radius, Some(10));
+        assert_eq!(candidates[0].phenomenon_type, Some(PhenomenonType::Volcano));
+        assert!(candidates[0].area.is_none());
+
+        // waypoints of a device carry no radius, the request can fill it in
+        let device = r#"<?xml version="1.0"?>
+            <gpx version="1.1" creator="device" xmlns="http://www.topografix.com/GPX/1/1">
+              <wpt lat="46.5" lon="9.8"><name>Slide</name><cmt>after the storm</cmt><type>landslide</type></wpt>
+              <wpt lat="north" lon="9.9"><name>Hut</name></wpt>
+              <trk><name>Way up</name></trk>
+            </gpx>"#;
+        let Err(ApiError::Validation(errors)) = service
+            .import(
+                other,
+                gpx_format::read(device.as_bytes()).unwrap(),
+                ImportDefaults {
+                    kind: TransferFormat::Gpx.default_kind(),
+                    radius: None,
+                },
+                true,
+            )
+            .await
+        else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["wpt[0].radius", "wpt[1].latitude", "wpt[1].radius"]
+        );
+
+        let candidates = gpx_format::read(device.as_bytes()).unwrap();
+        let slide = candidates.into_iter().next().unwrap();
+        assert_eq!(slide.description, "after the storm");
+        let defaults = ImportDefaults {
+            kind: TransferFormat::Gpx.default_kind(),
+            radius: Some(2),
+        };
+        let report = service
+            .import(other, vec![slide], defaults, false)
+            .await
+            .unwrap();
+        assert_eq!(report.created[0].kind, LocationKind::NaturalPhenomenon);
+
+        // an image a KMZ placemark refers to must be in the archive
+        let kml = r#"<kml xmlns="http://www.opengis.net/kml/2.2"><Document><Folder>
+              <Placemark><name>Crater</name><description><![CDATA[<p><img src="files/crater.jpg"></p>]]></description>
+                <Point><coordinates>14.99,37.75,3000</coordinates></Point></Placemark>
+            </Folder></Document></kml>"#;
+        let files = HashMap::from([("files/other.jpg".to_string(), b"jpg!".to_vec())]);
+        let candidates = kml_format::read(kml.as_bytes(), Some(&files)).unwrap();
+        assert_eq!(candidates[0].errors[0].field, "image");
+        let candidates = kml_format::read(kml.as_bytes(), None).unwrap();
+        assert_eq!(candidates[0].longitude, Some(14.99));
+        assert!(candidates[0].image.is_none() && candidates[0].errors.is_empty());
+    }
+
+    #[sqlx::test]
+    async fn test_csv_transfer(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let other: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('other@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO weather_locations (user_id, name, latitude, longitude, description)
+               VALUES ($1, '=HYPERLINK("x")', 46.95, 7.45, 'capital, "Bundesstadt"')"#,
+        )
+        .bind(user_id.0)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, phenomenon_type, area)
+               VALUES ($1, 'Etna', 37.75, 14.99, 10, 'volcano',
+                       '{"type":"Polygon","coordinates":[[[14.9,37.7],[15.1,37.7],[15.1,37.8],[14.9,37.7]]]}')"#,
+        )
+        .bind(user_id.0)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let service = LocationTransferService::new(pool);
+
+        // formulas are defused for spreadsheets and restored on import
+        let exported = service.export(user_id, None).await.unwrap();
+        let csv = csv_format::write(&exported).unwrap();
+        assert!(String::from_utf8_lossy(&csv).contains("'=HYPERLINK"));
+        let candidates = csv_format::read(&csv, &ColumnMapping::default()).unwrap();
+        assert_eq!(candidates[0].name.as_deref(), Some("=HYPERLINK(\"x\")"));
+        assert_eq!(candidates[0].description, "capital, \"Bundesstadt\"");
+        assert!(candidates[1].area.is_some());
+        let report = service
+            .import(other, candidates, ImportDefaults::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(report.created.len(), 2);
+        match &service.export(other, None).await.unwrap()[..] {
+            [ExportedLocation::Weather(_), ExportedLocation::NaturalPhenomenon(etna)] => {
+                assert_eq!(etna.phenomenon_type, PhenomenonType::Volcano);
+                assert_eq!((etna.radius, etna.area.is_some()), (10, true));
+            }
+            other => panic!("unexpected import {:?}", other),
+        }
+
+        // a colleague's spreadsheet: own headers, semicolons, DMS and decimal commas
+        let sheet = "Site;Notes;N;E;Km\n\
+                     Vesuvius;active;40°49'17\"N;14°25'32\"E;5\n\
+                     ;;;;\n\
+                     Stromboli;;38,789;15,213;3\n\
+                     Nowhere;;95;east;wide\n";
+        let columns = ColumnMapping {
+            name: Some("site".to_string()),
+            latitude: Some("3".to_string()),
+            longitude: Some("E".to_string()),
+            radius: Some("Km".to_string()),
+            description: Some("Notes".to_string()),
+        };
+        let defaults = ImportDefaults {
+            kind: Some(LocationKind::NaturalPhenomenon),
+            radius: None,
+        };
+        let candidates = csv_format::read(sheet.as_bytes(), &columns).unwrap();
+        let Err(ApiError::Validation(errors)) =
+            service.import(user_id, candidates, defaults, true).await
+        else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["rows[5].longitude", "rows[5].radius", "rows[5].latitude"]
+        );
+
+        let rows: String = sheet
+            .lines()
+            .take(4)
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let candidates = csv_format::read(rows.as_bytes(), &columns).unwrap();
+        let preview = service
+            .import(user_id, candidates, defaults, true)
+            .await
+            .unwrap();
+        let sources: Vec<_> = preview.created.iter().map(|i| i.source.as_str()).collect();
+        assert_eq!(sources, vec!["rows[2]", "rows[4]"]);
+        assert!((preview.created[0].latitude - 40.821_389).abs() < 1e-6);
+        assert!((preview.created[1].longitude - 15.213).abs() < 1e-9);
+
+        // columns that are not there are reported once, not per row
+        let Err(ApiError::Validation(errors)) =
+            csv_format::read(sheet.as_bytes(), &ColumnMapping::default())
+        else {
+            panic!("expected validation errors");
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["name_column", "latitude_column", "longitude_column"]
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use crate::shared::error::ApiError;
+use roxmltree::{Document, Node};
+
+/// Parse `body` as an XML document of `format`, e.g. `GPX`.
+///
+/// Documents with a DTD are rejected, so entities cannot be expanded.
+pub fn parse<'a>(body: &'a [u8], format: &str) -> Result<Document<'a>, ApiError> {
+    let text = std::str::from_utf8(body)
+        .map_err(|_| ApiError::BadRequest(format!("{} must be UTF-8 text", format)))?;
+    Document::parse(text).map_err(|e| ApiError::BadRequest(format!("Invalid {}: {}", format, e)))
+}
+
+/// Child elements of `node` named `name`, in any namespace.
+pub fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Trimmed text of the first child element `name` of `node`; `None` when missing or empty.
+pub fn child_text(node: Node, name: &str) -> Option<String> {
+    children(node, name).next().and_then(text)
+}
+
+/// Trimmed text of `node`, including CDATA sections; `None` when empty.
+pub fn text(node: Node) -> Option<String> {
+    let text: String = node
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// `text` with the markup characters replaced by entities, for text and attribute values.
+///
+/// Control characters, which XML 1.0 does not allow at all, are left out.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
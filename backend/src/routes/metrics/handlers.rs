@@ -0,0 +1,134 @@
+use crate::shared::db::PoolStats;
+use crate::shared::error::ApiError;
+use crate::shared::metrics::{init_metrics, record_pool_stats};
+use crate::shared::models::AppState;
+use axum::extract::State;
+use axum::http::{header, HeaderMap};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use metrics_exporter_prometheus::PrometheusHandle;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// Everything the scrape endpoint needs.
+#[derive(Clone)]
+pub struct MetricsState {
+    /// Handle of the installed Prometheus recorder.
+    pub handle: PrometheusHandle,
+
+    /// Pool whose usage is sampled on every scrape.
+    pub db: PgPool,
+
+    /// Bearer token the scraper has to send.
+    pub token: String,
+}
+
+impl MetricsState {
+    /// Whether the request carries the scrape token.
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(sent) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        // comparing digests takes the same time however much of the token matches
+        Sha256::digest(sent.trim()) == Sha256::digest(&self.token)
+    }
+}
+
+/// Expose all collected metrics in the Prometheus text format.
+pub async fn metrics(
+    State(state): State<Arc<MetricsState>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    if !state.authorized(&headers) {
+        return Err(ApiError::Unauthorized(
+            "A valid metrics token is required".to_string(),
+        ));
+    }
+    record_pool_stats(&PoolStats::from_pool(&state.db));
+    state.handle.run_upkeep();
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.handle.render(),
+    ))
+}
+
+/// Router serving `/metrics` at the root, outside the versioned API; installs the global
+/// recorder on first use.
+///
+/// Empty when no `METRICS_TOKEN` is configured, the counters are not for the public.
+pub fn router(app: &AppState) -> Router {
+    let Some(token) = app.settings.metrics.token.clone() else {
+        tracing::info!("METRICS_TOKEN is not set, /metrics is disabled");
+        return Router::new();
+    };
+    let state = Arc::new(MetricsState {
+        handle: init_metrics(),
+        db: app.db.clone(),
+        token,
+    });
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::metrics::track_metrics;
+    use crate::tests::tests::init_app_state;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn test_scrape_after_request(pool: PgPool) {
+        let mut app = init_app_state(pool).await;
+        assert!(router(&app)
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .is_ok_and(|r| r.status() == StatusCode::NOT_FOUND));
+
+        app.settings.metrics.token = Some("scrape-me".to_string());
+        let router = Router::new()
+            .route("/probes/{id}", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn(track_metrics))
+            .merge(router(&app));
+        let send = |auth: Option<&str>, uri: &str| {
+            let mut req = Request::get(uri);
+            if let Some(auth) = auth {
+                req = req.header(header::AUTHORIZATION, auth);
+            }
+            router.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        let response = send(None, "/probes/42").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for auth in [None, Some("Bearer wrong"), Some("scrape-me")] {
+            let response = send(auth, "/metrics").await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{:?}", auth);
+        }
+
+        // requests are labelled with the route template, not the raw path
+        let response = send(Some("Bearer scrape-me"), "/metrics").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.contains(r#"http_request_duration_seconds_bucket{method="GET",route="/probes/{id}",le="0.005"}"#),
+            "{}",
+            body
+        );
+        assert!(body
+            .contains(r#"http_requests_total{method="GET",route="/probes/{id}",status="200"} 1"#));
+        assert!(!body.contains("/probes/42"));
+    }
+}
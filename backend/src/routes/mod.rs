@@ -1,5 +1,11 @@
 pub mod auth;
+pub mod collections;
+pub mod health;
+pub mod location_transfer;
+pub mod metrics;
 pub mod natural_phenomenon_locations;
 pub mod settings;
+pub mod sharing;
+pub mod tags;
 pub mod uploads;
 pub mod weather_locations;
@@ -1,4 +1,3 @@
-use serde::Serialize;
 use std::sync::Arc;
 
 use crate::routes::auth::middlewares::auth;
@@ -6,54 +5,113 @@ use crate::routes::auth::models::UserDb;
 use crate::routes::auth::services::AuthService;
 use crate::routes::natural_phenomenon_locations::models::{
     CreateAndUpdateResponseSuccess, CreateNaturalPhenomenonLocationInnerWithImage,
-    CreateNaturalPhenomenonLocationRequest, GetAllNaturalPhenomenonLocationResponseSuccess,
-    GetByIdNaturalPhenomenonLocationResponseSuccess, NaturalPhenomenonLocationError,
-    NaturalPhenomenonLocationResponseSuccess, PostNaturalPhenomenonLocationSchema,
-    PostNaturalPhenomenonLocationService, UpdateNaturalPhenomenonLocationRequest,
-    UpdateNaturalPhenomenonLocationRequestWithIds, UpdateNaturalPhenomenonLocationResponseSuccess,
+    CreateNaturalPhenomenonLocationRequest, GeofenceCheckRequest, GeofenceCheckResponse,
+    GetAllNaturalPhenomenonLocationResponseSuccess,
+    GetByIdNaturalPhenomenonLocationResponseSuccess, NaturalPhenomenonLocationListQuery,
+    NaturalPhenomenonLocationResponseSuccess, PostNaturalPhenomenonLocationForm,
+    PostNaturalPhenomenonLocationSchema, PostNaturalPhenomenonLocationService,
+    UpdateNaturalPhenomenonLocationRequest, UpdateNaturalPhenomenonLocationRequestWithIds,
+    UpdateNaturalPhenomenonLocationResponseSuccess, MAX_AREA_SIZE, MAX_IMAGE_SIZE,
 };
 use crate::routes::natural_phenomenon_locations::services::{
     NaturalPhenomenonLocationService, NaturalPhenomenonLocationServiceImpl,
 };
+use crate::routes::sharing::models::WithAccess;
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::geo::{Nearby, NearbyQuery};
+use crate::shared::idempotency::{idempotent, Idempotency};
 use crate::shared::models::{AppState, DatabaseId};
-use axum::extract::{Extension, Json, Multipart, Path, State};
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use crate::shared::multipart::{TypedMultipart, DEFAULT_TEXT_LIMIT};
+use crate::shared::pagination::Page;
+use crate::shared::preconditions::{precondition_failed, require_if_match, version_etag, IfMatch};
+use crate::shared::validation::{ValidatedJson, ValidatedQuery};
+use axum::extract::{DefaultBodyLimit, Extension, Json, OriginalUri, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
 use tokio::fs;
 use tracing::debug;
 use utoipa::ToSchema;
-use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouterExt};
 use utoipa_axum::routes;
 
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
-    error: String,
-}
-
-/// Fetch all natural phenomenon locations for the current user.
+/// Fetch a page of the natural phenomenon locations of the current user and of those shared
+/// with them.
 #[utoipa::path(
     get,
     path = "/natural_phenomenon_locations",
+    params(NaturalPhenomenonLocationListQuery),
     responses(
-        (status = 200, description = "All user locations", body = Vec<CreateAndUpdateResponseSuccess>),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "One page of user locations", body = Page<WithAccess<GetAllNaturalPhenomenonLocationResponseSuccess>>,
+            headers(("Link" = String, description = "Links to the `first` and `next` page"))),
+        (status = 422, description = "Invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_all_locations<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-) -> Result<
-    Json<Vec<GetAllNaturalPhenomenonLocationResponseSuccess>>,
-    (StatusCode, Json<NaturalPhenomenonLocationError>),
->
+    OriginalUri(uri): OriginalUri,
+    ValidatedQuery(query): ValidatedQuery<NaturalPhenomenonLocationListQuery>,
+) -> Result<Response, ApiError>
 where
     S: NaturalPhenomenonLocationServiceImpl,
 {
-    let locations = service.get_all(user.id).await?;
+    let page = service.get_all(user.id, &query).await?;
+    Ok(page.into_response_with_links(&uri))
+}
+
+/// Fetch the natural phenomenon locations of the current user nearest to a point.
+#[utoipa::path(
+    get,
+    path = "/natural_phenomenon_locations/nearby",
+    params(NearbyQuery),
+    responses(
+        (status = 200, description = "Locations with their distance, closest first", body = [Nearby<GetAllNaturalPhenomenonLocationResponseSuccess>]),
+        (status = 422, description = "Invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_nearby_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedQuery(query): ValidatedQuery<NearbyQuery>,
+) -> Result<Json<Vec<Nearby<GetAllNaturalPhenomenonLocationResponseSuccess>>>, ApiError>
+where
+    S: NaturalPhenomenonLocationServiceImpl,
+{
+    let locations = service.nearby(user.id, &query).await?;
     Ok(Json(locations))
 }
 
-/// Fetch a single natural phenomenon location by its ID for the current user.
+/// Check which natural phenomenon locations of the current user contain the device.
+///
+/// The device posts its current position and optionally the positions since its previous
+/// check; every entry into or exit from an area, or the alert radius of a location without
+/// one, is reported once per device.
+#[utoipa::path(
+    post,
+    path = "/natural_phenomenon_locations/geofence",
+    request_body = GeofenceCheckRequest,
+    responses(
+        (status = 200, description = "Locations containing the device and the transitions since the previous check", body = GeofenceCheckResponse),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn check_geofence<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedJson(request): ValidatedJson<GeofenceCheckRequest>,
+) -> Result<Json<GeofenceCheckResponse>, ApiError>
+where
+    S: NaturalPhenomenonLocationServiceImpl,
+{
+    let result = service.check_geofence(user.id, &request).await?;
+    Ok(Json(result))
+}
+
+/// Fetch a single natural phenomenon location by its ID, of the current user or shared with
+/// them.
 #[utoipa::path(
     get,
     path = "/natural_phenomenon_locations/{id}",
@@ -61,24 +119,25 @@ where
         ("id" = DatabaseId, Path, description = "Location ID to retrieve"),
     ),
     responses(
-        (status = 200, description = "Location found", body = CreateAndUpdateResponseSuccess),
-        (status = 404, description = "Location not found"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Location found", body = WithAccess<GetByIdNaturalPhenomenonLocationResponseSuccess>,
+            headers(("ETag" = String, description = "Version of the location, for `If-Match`"))),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_location_by_id<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
     Path(id): Path<DatabaseId>,
-) -> Result<
-    Json<GetByIdNaturalPhenomenonLocationResponseSuccess>,
-    (StatusCode, Json<NaturalPhenomenonLocationError>),
->
+) -> Result<impl IntoResponse, ApiError>
 where
     S: NaturalPhenomenonLocationServiceImpl,
 {
     let location = service.get_by_id(user.id, id).await?;
-    Ok(Json(location))
+    Ok((
+        [(header::ETAG, version_etag(location.item.version))],
+        Json(location),
+    ))
 }
 
 /// Create a new natural phenomenon location for the current user.
@@ -87,123 +146,77 @@ where
     path = "/natural_phenomenon_locations",
     request_body(content = PostNaturalPhenomenonLocationSchema, content_type = "multipart/form-data"
     ),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Makes retries return the first response instead of creating a duplicate")),
     responses(
-        (status = 201, description = "Location created", body = CreateAndUpdateResponseSuccess),
-        (status = 500, description = "Internal server error")
+        (status = 201, description = "Location created", body = CreateAndUpdateResponseSuccess,
+            headers(("ETag" = String, description = "Version of the location, for `If-Match`"))),
+        (status = 400, description = "Invalid multipart body", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Idempotency-Key reused for a different or still running request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "Image too large", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn create_location<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-    mut multipart: Multipart,
-) -> Result<impl IntoResponse, (StatusCode, Json<NaturalPhenomenonLocationError>)>
+    TypedMultipart(form): TypedMultipart<PostNaturalPhenomenonLocationForm>,
+) -> Result<impl IntoResponse, ApiError>
 where
     S: NaturalPhenomenonLocationServiceImpl,
 {
-    // 1) pull all fields + image into our DTO
-    let mut dto = PostNaturalPhenomenonLocationService {
+    let dto = PostNaturalPhenomenonLocationService {
         user_id: user.id,
-        name: String::new(),
-        latitude: 0.0,
-        longitude: 0.0,
-        description: String::new(),
-        image_bytes: vec![],
-        radius: 0,
-        image_filename: String::new(),
+        name: form.name,
+        latitude: form.latitude,
+        longitude: form.longitude,
+        description: form.description,
+        phenomenon_type: form.phenomenon_type,
+        radius: form.radius,
+        area: form.area,
+        image: form.image,
     };
-
-    debug!("processing multipart form data");
-    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-        )
-    })? {
-        let name = field.name().unwrap_or_default();
-        debug!("processing field: {}", name);
-        match name {
-            "name" => {
-                dto.name = field.text().await.unwrap_or_default();
-            }
-            "latitude" => {
-                dto.latitude = field
-                    .text()
-                    .await
-                    .unwrap_or_default()
-                    .parse::<f64>()
-                    .unwrap_or_default();
-            }
-            "longitude" => {
-                dto.longitude = field
-                    .text()
-                    .await
-                    .unwrap_or_default()
-                    .parse::<f64>()
-                    .unwrap_or_default();
-            }
-            "description" => {
-                dto.description = field.text().await.unwrap_or_default();
-            }
-            "radius" => {
-                dto.radius = field
-                    .text()
-                    .await
-                    .unwrap_or_default()
-                    .parse::<i32>()
-                    .unwrap_or_default();
-            }
-            // this branch pulls _both_ the bytes and the filename
-            "image" => {
-                if let Some(filename) = field.file_name() {
-                    dto.image_filename = filename.to_string();
-                }
-                dto.image_bytes = field
-                    .bytes()
-                    .await
-                    .map_err(|e| {
-                        (
-                            StatusCode::BAD_REQUEST,
-                            Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-                        )
-                    })?
-                    .to_vec();
-            }
-            _ => {
-                // ignore any unexpected fields
-            }
-        }
-    }
-
-    // 2) hand off to service
     let created = service.create(dto).await?;
 
     debug!("created location: {:?}", created);
-    Ok((StatusCode::CREATED, Json(created)))
+    Ok((
+        StatusCode::CREATED,
+        [(header::ETAG, version_etag(created.version))],
+        Json(created),
+    ))
 }
 
-/// Update a natural phenomenon location for the current user.
+/// Update a natural phenomenon location of the current user, or shared with them to edit.
+///
+/// With `If-Match` the update only applies to the given version; otherwise the current
+/// location is returned with a 412.
 #[utoipa::path(
     put,
     path = "/natural_phenomenon_locations/{id}",
     request_body = UpdateNaturalPhenomenonLocationRequest,
     params(
-        ("id" = DatabaseId, Path, description = "Location ID to update")
+        ("id" = DatabaseId, Path, description = "Location ID to update"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being updated")
     ),
     responses(
-        (status = 200, description = "Location updated", body = CreateAndUpdateResponseSuccess),
-        (status = 401, description = "Unauthorized"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Location updated", body = UpdateNaturalPhenomenonLocationResponseSuccess,
+            headers(("ETag" = String, description = "New version of the location"))),
+        (status = 401, description = "Unauthorized", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Location is shared read-only", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Location was modified, the current one is returned", body = WithAccess<GetByIdNaturalPhenomenonLocationResponseSuccess>),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn update_location<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
     Path(id): Path<DatabaseId>,
-    Json(payload): Json<UpdateNaturalPhenomenonLocationRequest>, // ← body extractor
-) -> Result<
-    Json<UpdateNaturalPhenomenonLocationResponseSuccess>,
-    (StatusCode, Json<NaturalPhenomenonLocationError>),
->
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<UpdateNaturalPhenomenonLocationRequest>, // ← body extractor
+) -> Result<Response, ApiError>
 where
     S: NaturalPhenomenonLocationServiceImpl,
 {
@@ -213,33 +226,134 @@ where
         payload,
     };
 
-    let updated = service.update(dto).await?;
-
-    Ok(Json(updated))
+    match service.update(dto, &if_match).await {
+        Ok(updated) => Ok((
+            [(header::ETAG, version_etag(updated.version))],
+            Json(updated),
+        )
+            .into_response()),
+        Err(ApiError::PreconditionFailed(_)) => {
+            let current = service.get_by_id(user.id, id).await?;
+            Ok(precondition_failed(&current, current.item.version))
+        }
+        Err(e) => Err(e),
+    }
 }
 
-/// Delete a natural phenomenon location for the current user.
+/// Move a natural phenomenon location of the current user to the trash.
+///
+/// It keeps its image and can be restored until it is purged, after the retention or on
+/// request.
 #[utoipa::path(
     delete,
     path = "/natural_phenomenon_locations/{id}",
     params(
-        ("id" = DatabaseId, Path, description = "Location ID to delete")
+        ("id" = DatabaseId, Path, description = "Location ID to delete"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being deleted")
     ),
     responses(
-        (status = 204, description = "Location deleted"),
-        (status = 500, description = "Internal server error")
+        (status = 204, description = "Location moved to the trash"),
+        (status = 403, description = "Location is only shared with the user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Location was modified, the current one is returned", body = WithAccess<GetByIdNaturalPhenomenonLocationResponseSuccess>),
+        (status = 428, description = "If-Match is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn delete_location<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
     Path(id): Path<DatabaseId>,
-) -> Result<impl IntoResponse, (StatusCode, Json<NaturalPhenomenonLocationError>)>
+    if_match: IfMatch,
+) -> Result<Response, ApiError>
 where
     S: NaturalPhenomenonLocationServiceImpl,
 {
     // forward straight through — the service already returns the proper Result<Success, Error> tuple
-    service.delete(user.id, id).await
+    match service.delete(user.id, id, &if_match).await {
+        Ok(deleted) => Ok(deleted.into_response()),
+        Err(ApiError::PreconditionFailed(_)) => {
+            let current = service.get_by_id(user.id, id).await?;
+            Ok(precondition_failed(&current, current.item.version))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// List the natural phenomenon locations of the current user in the trash, most recently
+/// deleted first.
+#[utoipa::path(
+    get,
+    path = "/natural_phenomenon_locations/trash",
+    responses(
+        (status = 200, description = "Deleted locations, with their `deleted_at`", body = [GetAllNaturalPhenomenonLocationResponseSuccess]),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_trashed_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+) -> Result<Json<Vec<GetAllNaturalPhenomenonLocationResponseSuccess>>, ApiError>
+where
+    S: NaturalPhenomenonLocationServiceImpl,
+{
+    let locations = service.trash(user.id).await?;
+    Ok(Json(locations))
+}
+
+/// Restore a natural phenomenon location of the current user from the trash.
+#[utoipa::path(
+    post,
+    path = "/natural_phenomenon_locations/trash/{id}/restore",
+    params(
+        ("id" = DatabaseId, Path, description = "Location ID to restore")
+    ),
+    responses(
+        (status = 200, description = "Location restored", body = GetAllNaturalPhenomenonLocationResponseSuccess,
+            headers(("ETag" = String, description = "New version of the location"))),
+        (status = 404, description = "Location not in the trash", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn restore_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<impl IntoResponse, ApiError>
+where
+    S: NaturalPhenomenonLocationServiceImpl,
+{
+    let location = service.restore(user.id, id).await?;
+    Ok((
+        [(header::ETAG, version_etag(location.version))],
+        Json(location),
+    ))
+}
+
+/// Permanently delete a natural phenomenon location of the current user from the trash, with
+/// its image.
+#[utoipa::path(
+    delete,
+    path = "/natural_phenomenon_locations/trash/{id}",
+    params(
+        ("id" = DatabaseId, Path, description = "Location ID to purge")
+    ),
+    responses(
+        (status = 204, description = "Location and image purged"),
+        (status = 404, description = "Location not in the trash", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn purge_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<StatusCode, ApiError>
+where
+    S: NaturalPhenomenonLocationServiceImpl,
+{
+    service.purge(user.id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
 /// Generic router allowing injection of any implementation of the domain service
@@ -252,12 +366,35 @@ where
         settings: app.settings.clone(),
         http: Default::default(),
     });
+    let trash = OpenApiRouter::new()
+        .routes(routes!(get_trashed_locations))
+        .routes(routes!(restore_location))
+        .routes(routes!(purge_location));
+
     OpenApiRouter::new()
         .routes(routes!(get_all_locations))
+        .routes(routes!(get_nearby_locations))
+        .routes(routes!(check_geofence))
         .routes(routes!(get_location_by_id))
-        .routes(routes!(create_location))
+        .routes(
+            routes!(create_location)
+                // room for the image and area plus the text fields
+                .layer(DefaultBodyLimit::max(
+                    MAX_IMAGE_SIZE + MAX_AREA_SIZE + 8 * DEFAULT_TEXT_LIMIT,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    Idempotency::new(&app),
+                    idempotent,
+                )),
+        )
         .routes(routes!(update_location))
         .routes(routes!(delete_location))
+        .layer(axum::middleware::from_fn_with_state(
+            app.settings.concurrency.clone(),
+            require_if_match,
+        ))
+        // a trashed location has no version worth sending back
+        .merge(trash)
         .layer(axum::middleware::from_fn_with_state(auth_service, auth))
         .with_state(service)
 }
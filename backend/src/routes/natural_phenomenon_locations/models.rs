@@ -1,12 +1,93 @@
+use crate::shared::geo::{validate_area, Area, BoundingBox, LocationFilter, LocationSort, Nearby};
 use crate::shared::models::DatabaseId;
+use crate::shared::multipart::{FieldSpec, FromMultipart, MultipartForm, TempFile};
+use crate::shared::pagination::{SortOrder, MAX_PAGE_LIMIT};
+use crate::shared::validation::finite;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
-use utoipa::ToSchema;
+use std::str::FromStr;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+/// Largest accepted alert radius, in kilometers (roughly half the Earth's circumference).
+pub const MAX_RADIUS_KM: i32 = 20_000;
+
+/// Largest accepted location image, in bytes.
+pub const MAX_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// Largest accepted GeoJSON text of an area, in bytes.
+pub const MAX_AREA_SIZE: usize = 64 * 1024;
+
+/// Most positions accepted in the track of a geofence check.
+pub const MAX_TRACK_LENGTH: u64 = 1000;
+
+/// Kind of natural phenomenon a location is about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PhenomenonType {
+    Volcano,
+    Earthquake,
+    Wildfire,
+    Flood,
+    Storm,
+    Landslide,
+    Avalanche,
+    Tsunami,
+    #[default]
+    Other,
+}
+
+impl PhenomenonType {
+    pub const ALL: [PhenomenonType; 9] = [
+        PhenomenonType::Volcano,
+        PhenomenonType::Earthquake,
+        PhenomenonType::Wildfire,
+        PhenomenonType::Flood,
+        PhenomenonType::Storm,
+        PhenomenonType::Landslide,
+        PhenomenonType::Avalanche,
+        PhenomenonType::Tsunami,
+        PhenomenonType::Other,
+    ];
+
+    /// Name as stored and sent, e.g. `volcano`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PhenomenonType::Volcano => "volcano",
+            PhenomenonType::Earthquake => "earthquake",
+            PhenomenonType::Wildfire => "wildfire",
+            PhenomenonType::Flood => "flood",
+            PhenomenonType::Storm => "storm",
+            PhenomenonType::Landslide => "landslide",
+            PhenomenonType::Avalanche => "avalanche",
+            PhenomenonType::Tsunami => "tsunami",
+            PhenomenonType::Other => "other",
+        }
+    }
+}
+
+impl FromStr for PhenomenonType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PhenomenonType::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("unknown phenomenon type `{}`", s))
+    }
+}
+
+/// The column only holds known names, see its check constraint.
+impl From<String> for PhenomenonType {
+    fn from(s: String) -> Self {
+        s.parse().unwrap_or_default()
+    }
+}
 
 /// Database representation of a natural phenomenon location.
 ///
-/// Contains all persisted fields, including optional image path
-/// and geofence radius.
+/// Contains all persisted fields, including optional image path,
+/// geofence radius and area.
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema, Clone, PartialEq)]
 pub struct NaturalPhenomenonLocationDb {
     /// Primary key of the location record.
@@ -33,32 +114,145 @@ pub struct NaturalPhenomenonLocationDb {
     /// User‐provided description or notes about this location.
     pub description: String,
 
+    /// Kind of phenomenon, `other` unless set.
+    #[sqlx(try_from = "String")]
+    pub phenomenon_type: PhenomenonType,
+
+    /// GeoJSON Polygon or MultiPolygon used instead of the radius, if any.
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<serde_json::Value>,
+
+    /// Incremented on every update, sent as the ETag.
+    pub version: i32,
+
     /// Timestamp when the row was created.
     pub created_at: chrono::DateTime<chrono::Utc>,
 
     /// Timestamp when the row was last updated.
     pub updated_at: chrono::DateTime<chrono::Utc>,
+
+    /// When the location was moved to the trash, if it is in it.
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Query parameters for listing natural phenomenon locations.
+#[derive(Debug, Default, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct NaturalPhenomenonLocationListQuery {
+    /// Page size (default 50).
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT))]
+    #[param(minimum = 1, maximum = 200)]
+    pub limit: Option<u32>,
+
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+
+    /// Sort field (default `created_at`); `distance` needs `near_lat` and `near_lon`.
+    #[serde(default)]
+    #[param(inline)]
+    pub sort: LocationSort,
+
+    /// Sort direction (default `asc`).
+    #[serde(default)]
+    #[param(inline)]
+    pub order: SortOrder,
+
+    /// Only names containing this text, ignoring case.
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+
+    /// Only locations created at or after this time.
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only locations created before this time.
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only locations inside `min_lon,min_lat,max_lon,max_lat`.
+    #[param(value_type = Option<String>, example = "5.9,45.8,10.5,47.8")]
+    pub bbox: Option<BoundingBox>,
+
+    /// Only locations with this tag of the user.
+    #[param(value_type = Option<i32>)]
+    pub tag: Option<DatabaseId>,
+
+    /// Only locations in this collection of the user or shared with them.
+    #[param(value_type = Option<i32>)]
+    pub collection: Option<DatabaseId>,
+
+    /// Only locations with at least this alert radius.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    pub min_radius: Option<i32>,
+
+    /// Only locations with at most this alert radius.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    pub max_radius: Option<i32>,
+
+    /// Latitude of the reference point for `sort=distance`.
+    #[validate(range(min = -90.0, max = 90.0), custom(function = "finite"))]
+    pub near_lat: Option<f64>,
+
+    /// Longitude of the reference point for `sort=distance`.
+    #[validate(range(min = -180.0, max = 180.0), custom(function = "finite"))]
+    pub near_lon: Option<f64>,
+}
+
+impl NaturalPhenomenonLocationListQuery {
+    /// Filters on the columns; the `bbox` is left out, areas need `BoundingBox::push_area_sql`.
+    pub fn filter(&self) -> LocationFilter {
+        LocationFilter {
+            name: self.name.clone(),
+            created_after: self.created_after,
+            created_before: self.created_before,
+            bbox: None,
+        }
+    }
 }
 
 /// Request payload for updating an existing location.
 ///
 /// Any field set to `None` will not be changed.
-#[derive(Debug, Deserialize, ToSchema, Clone, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, ToSchema, Clone, Serialize, PartialEq, Validate)]
 pub struct UpdateNaturalPhenomenonLocationRequest {
     /// New name, if updating.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
     pub name: Option<String>,
 
     /// New latitude, if updating.
+    #[validate(range(min = -90.0, max = 90.0))]
+    #[schema(minimum = -90, maximum = 90)]
     pub latitude: Option<f64>,
 
     /// New longitude, if updating.
+    #[validate(range(min = -180.0, max = 180.0))]
+    #[schema(minimum = -180, maximum = 180)]
     pub longitude: Option<f64>,
 
     /// New alert radius, if updating.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    #[schema(minimum = 1, maximum = 20000)]
     pub radius: Option<i32>,
 
     /// New description, if updating.
     pub description: Option<String>,
+
+    /// New kind of phenomenon, if updating.
+    pub phenomenon_type: Option<PhenomenonType>,
+
+    /// New GeoJSON Polygon or MultiPolygon, if updating; `null` removes the area.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[validate(custom(function = "validate_area"))]
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<Option<Area>>,
+}
+
+/// Tell an explicit `null` (`Some(None)`) apart from a missing field (`None`).
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
 
 /// Wrapper combining path parameters and update payload.
@@ -102,9 +296,19 @@ pub struct CreateAndUpdateResponseSuccess {
     /// User-provided description.
     pub description: String,
 
+    /// Kind of phenomenon.
+    pub phenomenon_type: PhenomenonType,
+
     /// Stored image path or empty string if none.
     pub image_path: String,
 
+    /// GeoJSON Polygon or MultiPolygon replacing the radius, if any.
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<serde_json::Value>,
+
+    /// Row version, to be sent back in `If-Match`.
+    pub version: i32,
+
     /// Record creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
 
@@ -136,8 +340,41 @@ pub struct GetAllNaturalPhenomenonLocationResponseSuccess {
     /// Description.
     pub description: String,
 
+    /// Kind of phenomenon.
+    pub phenomenon_type: PhenomenonType,
+
     /// Image path or empty string.
     pub image_path: String,
+
+    /// GeoJSON Polygon or MultiPolygon replacing the radius, if any.
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<serde_json::Value>,
+
+    /// Row version, to be sent back in `If-Match`.
+    pub version: i32,
+
+    /// When the location was moved to the trash; only set for locations in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<NaturalPhenomenonLocationDb> for GetAllNaturalPhenomenonLocationResponseSuccess {
+    fn from(rec: NaturalPhenomenonLocationDb) -> Self {
+        GetAllNaturalPhenomenonLocationResponseSuccess {
+            id: rec.id,
+            user_id: rec.user_id,
+            name: rec.name,
+            latitude: rec.latitude,
+            longitude: rec.longitude,
+            description: rec.description,
+            phenomenon_type: rec.phenomenon_type,
+            radius: rec.radius,
+            image_path: rec.image_path.unwrap_or_default(),
+            area: rec.area,
+            version: rec.version,
+            deleted_at: rec.deleted_at,
+        }
+    }
 }
 
 /// Response DTO when fetching a single location by ID.
@@ -164,8 +401,18 @@ pub struct GetByIdNaturalPhenomenonLocationResponseSuccess {
     /// Description.
     pub description: String,
 
+    /// Kind of phenomenon.
+    pub phenomenon_type: PhenomenonType,
+
     /// Image path or empty string.
     pub image_path: String,
+
+    /// GeoJSON Polygon or MultiPolygon replacing the radius, if any.
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<serde_json::Value>,
+
+    /// Row version, to be sent back in `If-Match`.
+    pub version: i32,
 }
 
 /// Response DTO after a successful update.
@@ -192,8 +439,18 @@ pub struct UpdateNaturalPhenomenonLocationResponseSuccess {
     /// Description.
     pub description: String,
 
+    /// Kind of phenomenon.
+    pub phenomenon_type: PhenomenonType,
+
     /// Image path or empty string.
     pub image_path: String,
+
+    /// GeoJSON Polygon or MultiPolygon replacing the radius, if any.
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<serde_json::Value>,
+
+    /// Row version, to be sent back in `If-Match`.
+    pub version: i32,
 }
 
 /// Payload for creating a location via JSON.
@@ -233,8 +490,72 @@ impl Display for CreateNaturalPhenomenonLocationRequest {
     }
 }
 
-/// Internal service payload including raw image bytes and filename.
-#[derive(Debug, ToSchema, Deserialize, Serialize, Clone)]
+/// Multipart form of a create request, see `PostNaturalPhenomenonLocationSchema` for the docs.
+#[derive(Debug, Validate)]
+pub struct PostNaturalPhenomenonLocationForm {
+    /// Location name.
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+
+    /// Latitude.
+    #[validate(range(min = -90.0, max = 90.0), custom(function = "finite"))]
+    pub latitude: f64,
+
+    /// Longitude.
+    #[validate(range(min = -180.0, max = 180.0), custom(function = "finite"))]
+    pub longitude: f64,
+
+    /// Description, empty when not sent.
+    pub description: String,
+
+    /// Kind of phenomenon, `other` when not sent.
+    pub phenomenon_type: PhenomenonType,
+
+    /// Alert radius.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    pub radius: i32,
+
+    /// Area replacing the radius, if any.
+    #[validate(custom(function = "validate_area"))]
+    pub area: Option<Area>,
+
+    /// Uploaded image, if any.
+    pub image: Option<TempFile>,
+}
+
+impl FromMultipart for PostNaturalPhenomenonLocationForm {
+    const FIELDS: &'static [FieldSpec] = &[
+        // ignored, the owner is always the authenticated user
+        FieldSpec::text("user_id"),
+        FieldSpec::text("name"),
+        FieldSpec::text("latitude"),
+        FieldSpec::text("longitude"),
+        FieldSpec::text("description"),
+        FieldSpec::text("phenomenon_type"),
+        FieldSpec::text("radius"),
+        FieldSpec {
+            max_size: MAX_AREA_SIZE,
+            ..FieldSpec::text("area")
+        },
+        FieldSpec::file("image", MAX_IMAGE_SIZE),
+    ];
+
+    fn from_multipart(form: &mut MultipartForm) -> Self {
+        PostNaturalPhenomenonLocationForm {
+            name: form.required("name"),
+            latitude: form.required("latitude"),
+            longitude: form.required("longitude"),
+            description: form.optional("description").unwrap_or_default(),
+            phenomenon_type: form.optional("phenomenon_type").unwrap_or_default(),
+            radius: form.required("radius"),
+            area: form.optional("area"),
+            image: form.file("image"),
+        }
+    }
+}
+
+/// Internal service payload including the uploaded image.
+#[derive(Debug)]
 pub struct PostNaturalPhenomenonLocationService {
     /// Owning user ID.
     pub user_id: DatabaseId,
@@ -251,17 +572,17 @@ pub struct PostNaturalPhenomenonLocationService {
     /// Description.
     pub description: String,
 
+    /// Kind of phenomenon.
+    pub phenomenon_type: PhenomenonType,
+
     /// Alert radius.
     pub radius: i32,
 
-    /// Raw image bytes for upload.
-    #[schema(format = Binary, content_media_type = "application/octet-stream")]
-    #[serde(with = "serde_bytes")]
-    pub image_bytes: Vec<u8>,
+    /// Area replacing the radius, if any.
+    pub area: Option<Area>,
 
-    /// Original filename (for extension/preservation).
-    #[schema(example = "photo.jpg")]
-    pub image_filename: String,
+    /// Uploaded image, moved into `uploads/` on create.
+    pub image: Option<TempFile>,
 }
 
 impl Display for PostNaturalPhenomenonLocationService {
@@ -283,20 +604,31 @@ pub struct PostNaturalPhenomenonLocationSchema {
     pub user_id: DatabaseId,
 
     /// Location name.
+    #[schema(min_length = 1, max_length = 100)]
     pub name: String,
 
     /// Latitude.
+    #[schema(minimum = -90, maximum = 90)]
     pub latitude: f64,
 
     /// Longitude.
+    #[schema(minimum = -180, maximum = 180)]
     pub longitude: f64,
 
     /// Description.
     pub description: String,
 
+    /// Kind of phenomenon, `other` when left out.
+    pub phenomenon_type: Option<PhenomenonType>,
+
     /// Alert radius.
+    #[schema(minimum = 1, maximum = 20000)]
     pub radius: i32,
 
+    /// GeoJSON Polygon or MultiPolygon used instead of the radius, at most 1000 vertices
+    /// within 500 km of the latitude and longitude.
+    pub area: Option<String>,
+
     /// Raw image bytes field for multipart/form-data.
     #[schema(format = Binary, content_media_type = "application/octet-stream")]
     #[serde(with = "serde_bytes")]
@@ -344,44 +676,6 @@ impl Display for NaturalPhenomenonLocationResponseSuccess {
     }
 }
 
-/// Error variants for location operations.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(tag = "type", content = "data")]
-pub enum NaturalPhenomenonLocationError {
-    /// The requested location was not found.
-    NotFound,
-
-    /// A location with the same key already exists.
-    AlreadyExists,
-
-    /// A database error occurred; contains the error message.
-    DatabaseError(String),
-
-    /// The record could not be deleted.
-    LocationCouldNotBeDeleted,
-
-    /// The associated image could not be removed from storage.
-    ImageInLocationCouldNotBeDeleted,
-}
-
-impl Display for NaturalPhenomenonLocationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            NaturalPhenomenonLocationError::NotFound => write!(f, "Not Found"),
-            NaturalPhenomenonLocationError::AlreadyExists => write!(f, "Already Exists"),
-            NaturalPhenomenonLocationError::DatabaseError(err) => {
-                write!(f, "Database Error: {}", err)
-            }
-            NaturalPhenomenonLocationError::LocationCouldNotBeDeleted => {
-                write!(f, "Location Could Not Be Deleted")
-            }
-            NaturalPhenomenonLocationError::ImageInLocationCouldNotBeDeleted => {
-                write!(f, "Image Could Not Be Deleted")
-            }
-        }
-    }
-}
-
 /// Shared DTO for create/update responses without timestamps.
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq)]
 pub struct ServiceCreateAndUpdateResponseSuccess {
@@ -406,3 +700,73 @@ pub struct ServiceCreateAndUpdateResponseSuccess {
     /// User description.
     pub description: String,
 }
+
+/// A position reported by a device.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Validate)]
+pub struct Position {
+    /// Latitude.
+    #[validate(range(min = -90.0, max = 90.0))]
+    #[schema(minimum = -90, maximum = 90)]
+    pub latitude: f64,
+
+    /// Longitude.
+    #[validate(range(min = -180.0, max = 180.0))]
+    #[schema(minimum = -180, maximum = 180)]
+    pub longitude: f64,
+
+    /// When the device was here, if known.
+    #[serde(default)]
+    pub recorded_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Request payload of a geofence check.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, Validate)]
+pub struct GeofenceCheckRequest {
+    /// Stable ID of the reporting device; enter and exit are tracked per device.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub device_id: String,
+
+    /// Current position of the device.
+    #[validate(nested)]
+    pub position: Position,
+
+    /// Positions since the previous check, oldest first.
+    #[serde(default)]
+    #[validate(length(max = MAX_TRACK_LENGTH), nested)]
+    #[schema(max_items = 1000)]
+    pub track: Vec<Position>,
+}
+
+/// Kind of a geofence transition.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GeofenceEvent {
+    /// The device moved into the area or radius of the location.
+    Enter,
+    /// The device left the area or radius of the location.
+    Exit,
+}
+
+/// A device entering or leaving the area or alert radius of a location.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct GeofenceTransition {
+    /// Location whose area or radius was crossed.
+    pub location_id: DatabaseId,
+
+    /// Whether the device entered or left.
+    pub event: GeofenceEvent,
+
+    /// First reported position after the crossing.
+    pub position: Position,
+}
+
+/// Response DTO of a geofence check.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct GeofenceCheckResponse {
+    /// Locations whose area or radius contains the current position, closest first.
+    pub inside: Vec<Nearby<GetAllNaturalPhenomenonLocationResponseSuccess>>,
+
+    /// Enters and exits since the previous check of the device, in track order.
+    pub transitions: Vec<GeofenceTransition>,
+}
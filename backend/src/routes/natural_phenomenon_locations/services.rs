@@ -1,88 +1,148 @@
+use crate::routes::location_transfer::models::LocationKind;
 use crate::routes::natural_phenomenon_locations::models::{
     CreateAndUpdateResponseSuccess, CreateNaturalPhenomenonLocationInnerWithImage,
-    CreateNaturalPhenomenonLocationRequest, GetAllNaturalPhenomenonLocationResponseSuccess,
+    CreateNaturalPhenomenonLocationRequest, GeofenceCheckRequest, GeofenceCheckResponse,
+    GeofenceEvent, GeofenceTransition, GetAllNaturalPhenomenonLocationResponseSuccess,
     GetByIdNaturalPhenomenonLocationResponseSuccess, NaturalPhenomenonLocationDb,
-    NaturalPhenomenonLocationError, NaturalPhenomenonLocationResponseSuccess,
-    PostNaturalPhenomenonLocationService, ServiceCreateAndUpdateResponseSuccess,
+    NaturalPhenomenonLocationListQuery, NaturalPhenomenonLocationResponseSuccess, PhenomenonType,
+    Position, PostNaturalPhenomenonLocationService, ServiceCreateAndUpdateResponseSuccess,
     UpdateNaturalPhenomenonLocationRequestWithIds, UpdateNaturalPhenomenonLocationResponseSuccess,
 };
+use crate::routes::sharing::models::{Access, WithAccess};
+use crate::routes::sharing::services::location_access;
+use crate::shared::error::ApiError;
+use crate::shared::geo::{
+    area_distance_km, distance_sql, haversine_km, push_reference_point, Area, BoundingBox,
+    LocationSort, Nearby, NearbyQuery, EARTH_RADIUS_KM, MAX_AREA_EXTENT_KM, REFERENCE_EARTH_SQL,
+};
+use crate::shared::memberships::{COLLECTIONS, TAGS};
+use crate::shared::metrics::record_upload;
 use crate::shared::models::DatabaseId;
+use crate::shared::pagination::{Keyed, Page, PageRequest, SortOrder, DEFAULT_PAGE_LIMIT};
+use crate::shared::preconditions::IfMatch;
+use crate::shared::trash::remove_upload;
 use anyhow::Result;
 use async_trait::async_trait;
 use axum::http::StatusCode;
-use axum::response::IntoResponse;
 use axum::Json;
+use geo::{Intersects, MultiPolygon, Point};
 use sanitize_filename::sanitize_with_options;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::BTreeSet;
 use tokio::fs;
 use tracing::debug;
 use uuid::Uuid;
 
 /// Core service trait defining CRUD operations for natural phenomenon locations,
-/// including file-based image storage cleanup on delete.
+/// including the trash of deleted ones and the cleanup of their images on purge.
 #[async_trait]
 pub trait NaturalPhenomenonLocationServiceImpl: Send + Sync + 'static {
     /// Create a new natural phenomenon location with optional image upload.
     ///
     /// The `req` contains both metadata and the raw image bytes.
-    /// Returns the created record on success, or an HTTP‐style error tuple on failure.
+    /// Returns the created record on success, or an `ApiError` on failure.
     async fn create(
         &self,
         req: PostNaturalPhenomenonLocationService,
-    ) -> Result<CreateAndUpdateResponseSuccess, (StatusCode, Json<NaturalPhenomenonLocationError>)>;
+    ) -> Result<CreateAndUpdateResponseSuccess, ApiError>;
 
-    /// Retrieve all phenomenon locations belonging to the given `user_id`.
+    /// Retrieve one page of the phenomenon locations belonging to the given `user_id` or
+    /// shared with them.
     ///
-    /// Returns a vector of response DTOs or an HTTP‐style error on failure.
+    /// Filters, sort and cursor come from `query`; `bbox` and the distance sort use the area of
+    /// the locations that have one.
+    /// Returns the page of response DTOs with the user's access or an `ApiError` on failure.
     async fn get_all(
         &self,
         user_id: DatabaseId,
-    ) -> Result<
-        Vec<GetAllNaturalPhenomenonLocationResponseSuccess>,
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    >;
+        query: &NaturalPhenomenonLocationListQuery,
+    ) -> Result<Page<WithAccess<GetAllNaturalPhenomenonLocationResponseSuccess>>, ApiError>;
+
+    /// Fetch the locations of `user_id` nearest to the point of `query`, closest first.
+    ///
+    /// Each comes with its great-circle distance, to the closest point of its area when it has
+    /// one; `query` may also bound the distance or the box searched.
+    async fn nearby(
+        &self,
+        user_id: DatabaseId,
+        query: &NearbyQuery,
+    ) -> Result<Vec<Nearby<GetAllNaturalPhenomenonLocationResponseSuccess>>, ApiError>;
+
+    /// Evaluate the areas, or else the alert radii, of the locations of `user_id` along the
+    /// reported positions.
+    ///
+    /// The locations each device is inside are kept between checks, so every crossing is
+    /// reported once. Returns the locations containing the current position and the
+    /// transitions along the track.
+    async fn check_geofence(
+        &self,
+        user_id: DatabaseId,
+        req: &GeofenceCheckRequest,
+    ) -> Result<GeofenceCheckResponse, ApiError>;
 
-    /// Fetch a single location by its `id` for the specified `user_id`.
+    /// Fetch a single location by its `id` for the specified `user_id`, their own or shared
+    /// with them.
     ///
-    /// Returns the matching DTO or an HTTP‐style error if not found or on DB error.
+    /// Returns the matching DTO with the user's access, `ApiError::NotFound` if missing or an
+    /// `ApiError` on DB error.
     async fn get_by_id(
         &self,
         user_id: DatabaseId,
         id: DatabaseId,
-    ) -> Result<
-        GetByIdNaturalPhenomenonLocationResponseSuccess,
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    >;
+    ) -> Result<WithAccess<GetByIdNaturalPhenomenonLocationResponseSuccess>, ApiError>;
 
-    /// Update an existing location’s fields (name, coords, radius, description).
+    /// Update an existing location’s fields (name, coords, radius, description, area).
     ///
-    /// Only non-`None` fields in the DTO will be overwritten.
-    /// Returns the updated DTO or an HTTP‐style error.
+    /// Only non-`None` fields in the DTO will be overwritten and the version is incremented;
+    /// the area must stay close to the resulting point. Users the location is shared with to
+    /// edit can update it too.
+    /// Returns the updated DTO, `ApiError::Forbidden` if it is shared read-only,
+    /// `ApiError::PreconditionFailed` if the current version does not match `if_match`, or
+    /// another `ApiError`.
     async fn update(
         &self,
         location: UpdateNaturalPhenomenonLocationRequestWithIds,
-    ) -> Result<
-        UpdateNaturalPhenomenonLocationResponseSuccess,
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    >;
+        if_match: &IfMatch,
+    ) -> Result<UpdateNaturalPhenomenonLocationResponseSuccess, ApiError>;
 
-    /// Delete the record and its on-disk image (if any).
+    /// Move the record to the trash, keeping its image until it is purged; only its owner
+    /// can.
     ///
-    /// Returns a `(204, Deleted)` response on success, or an HTTP‐style error tuple.
+    /// Returns a `(204, Deleted)` response on success, `ApiError::Forbidden` if it is only
+    /// shared with the user, `ApiError::PreconditionFailed` if the current version does not
+    /// match `if_match`, or another `ApiError`.
     async fn delete(
         &self,
         user_id: DatabaseId,
         id: DatabaseId,
-    ) -> Result<
-        (StatusCode, Json<NaturalPhenomenonLocationResponseSuccess>),
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    >;
+        if_match: &IfMatch,
+    ) -> Result<(StatusCode, Json<NaturalPhenomenonLocationResponseSuccess>), ApiError>;
+
+    /// The locations of `user_id` in the trash, most recently deleted first.
+    async fn trash(
+        &self,
+        user_id: DatabaseId,
+    ) -> Result<Vec<GetAllNaturalPhenomenonLocationResponseSuccess>, ApiError>;
+
+    /// Take the location `id` out of the trash.
+    ///
+    /// Returns the restored DTO, or `ApiError::NotFound` if it is not in the trash.
+    async fn restore(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+    ) -> Result<GetAllNaturalPhenomenonLocationResponseSuccess, ApiError>;
+
+    /// Delete the location `id` from the trash for good, with its on-disk image (if any).
+    ///
+    /// Returns `ApiError::NotFound` if it is not in the trash.
+    async fn purge(&self, user_id: DatabaseId, id: DatabaseId) -> Result<(), ApiError>;
 }
 
 /// Postgres-backed implementation of the `NaturalPhenomenonLocationServiceImpl` trait.
 ///
 /// Handles file-system writes for images, transactional DB operations,
-/// and cleanup of image files upon deletion.
+/// and cleanup of image files upon purge.
 pub struct NaturalPhenomenonLocationService {
     /// SQLx Postgres connection pool.
     pub db: PgPool,
@@ -93,39 +153,122 @@ impl NaturalPhenomenonLocationService {
     pub fn new(db: PgPool) -> Self {
         Self { db }
     }
+
+    /// One page of `get_all` sorted by the distance to `lat`/`lon`, to the closest point of the
+    /// area for the locations with one.
+    ///
+    /// Like `nearby`, the candidates come nearest point first and their distances are refined,
+    /// so the page is cut in memory; its cursor key is the distance followed by the id.
+    async fn list_by_distance(
+        &self,
+        user_id: DatabaseId,
+        query: &NaturalPhenomenonLocationListQuery,
+        page: &PageRequest,
+        lat: f64,
+        lon: f64,
+    ) -> Result<Page<WithAccess<NaturalPhenomenonLocationDb>>, ApiError> {
+        let key = |row: &Nearby<WithAccess<NaturalPhenomenonLocationDb>>| {
+            format!("{:015.6}/{:010}", row.distance_km, row.item.item.id.0)
+        };
+        let after = page
+            .after_key()
+            .and_then(|key| key.split('/').next()?.parse::<f64>().ok());
+        let limit = page.limit as usize;
+
+        let mut rows: Vec<Nearby<WithAccess<NaturalPhenomenonLocationDb>>> = distance_candidates(
+            user_id,
+            query,
+            page.order,
+            (lat, lon),
+            (after, None),
+            &[],
+            Some(limit + 1),
+        )
+        .build_query_as()
+        .fetch_all(&self.db)
+        .await?;
+        let full = rows.len() > limit;
+        refine_list(query, lat, lon, &mut rows);
+
+        if full {
+            // every row that may still come before the one after the page
+            let mut distances: Vec<f64> = rows
+                .iter()
+                .filter(|row| page.follows(&key(row)))
+                .map(|row| row.distance_km)
+                .collect();
+            distances.sort_by(f64::total_cmp);
+            if page.order == SortOrder::Desc {
+                distances.reverse();
+            }
+            let until = distances.get(limit).copied();
+            let ids: Vec<i32> = rows.iter().map(|row| row.item.item.id.0).collect();
+            let mut more: Vec<Nearby<WithAccess<NaturalPhenomenonLocationDb>>> =
+                distance_candidates(
+                    user_id,
+                    query,
+                    page.order,
+                    (lat, lon),
+                    (after, until),
+                    &ids,
+                    None,
+                )
+                .build_query_as()
+                .fetch_all(&self.db)
+                .await?;
+            refine_list(query, lat, lon, &mut more);
+            rows.extend(more);
+        }
+
+        Ok(page.paginate(rows, key).map(|row| row.item))
+    }
+
+    /// Error for a conditional write that matched no row: 404 if the user cannot see the
+    /// row, 403 if their access is less than `needed`, else 412.
+    async fn write_failed(&self, user_id: DatabaseId, id: DatabaseId, needed: Access) -> ApiError {
+        match location_access(&self.db, LocationKind::NaturalPhenomenon, user_id, id).await {
+            Ok(access) => match access.require(needed) {
+                Ok(()) => ApiError::PreconditionFailed(
+                    "Location was modified in the meantime".to_string(),
+                ),
+                Err(e) => e,
+            },
+            Err(e) => e,
+        }
+    }
 }
 #[async_trait]
 impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
+    #[tracing::instrument(skip_all, fields(user_id = req.user_id.0))]
     async fn create(
         &self,
         req: PostNaturalPhenomenonLocationService,
-    ) -> Result<CreateAndUpdateResponseSuccess, (StatusCode, Json<NaturalPhenomenonLocationError>)>
-    {
+    ) -> Result<CreateAndUpdateResponseSuccess, ApiError> {
         debug!("\n|| creating location: {:?}", req);
 
+        if let Some(area) = &req.area {
+            area.check_extent(req.latitude, req.longitude)?;
+        }
+        let area = req.area.as_ref().map(serde_json::Value::from);
+
         // make sure uploads/ exists
-        fs::create_dir_all("uploads").await.map_err(|e| {
-            tracing::error!("\n|| Error creating uploads/ dir: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-            )
-        })?;
+        fs::create_dir_all("uploads")
+            .await
+            .map_err(|e| ApiError::Internal(format!("Error creating uploads/ dir: {}", e)))?;
 
-        // if there's image data, write it and record a path; otherwise leave it None
-        debug!("\n|| req.image_bytes.len(): {}", req.image_bytes.len());
-        let image_path_opt = if !req.image_bytes.is_empty() {
-            let safe = sanitize_with_options(&req.image_filename, Default::default());
+        // if there's an image, move it into uploads/ and record a path; otherwise leave it None
+        let image_path_opt = if let Some(image) = req.image {
+            let original = image.file_name.as_deref().unwrap_or("image");
+            let safe = sanitize_with_options(original, Default::default());
             let filename = format!("{}_{}", Uuid::new_v4(), safe);
             let path = format!("uploads/{}", filename);
 
-            fs::write(&path, &req.image_bytes).await.map_err(|e| {
-                tracing::error!("\n|| Error writing image to disk: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-                )
-            })?;
+            let size = image.size;
+            image
+                .persist(&path)
+                .await
+                .map_err(|e| ApiError::Internal(format!("Error writing image to disk: {}", e)))?;
+            record_upload(size);
             debug!("\n|| wrote image to disk at {}", path);
             Some(path)
         } else {
@@ -141,8 +284,8 @@ impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
             NaturalPhenomenonLocationDb,
             r#"
             INSERT INTO natural_phenomenon_locations
-                (user_id, name, latitude, longitude, description, image_path, radius)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+                (user_id, name, latitude, longitude, description, image_path, radius, area, phenomenon_type)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             RETURNING *
             "#,
             req.user_id.0,
@@ -153,16 +296,11 @@ impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
             image_path_opt,
             // db_image_path,
             req.radius,
+            area,
+            req.phenomenon_type.as_str(),
         )
         .fetch_one(&self.db)
-        .await
-        .map_err(|e| {
-            tracing::error!("\n|| DB insert error: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-            )
-        })?;
+        .await?;
 
         tracing::debug!("\n|| DB row created: {:?}", rec);
 
@@ -174,105 +312,266 @@ impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
             latitude: rec.latitude,
             longitude: rec.longitude,
             description: rec.description,
+            phenomenon_type: rec.phenomenon_type,
             image_path: rec.image_path.unwrap_or_default(),
             radius: rec.radius,
+            area: rec.area,
+            version: rec.version,
             created_at: rec.created_at,
             updated_at: rec.updated_at,
         })
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
     async fn get_all(
         &self,
         user_id: DatabaseId,
-    ) -> Result<
-        Vec<GetAllNaturalPhenomenonLocationResponseSuccess>,
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    > {
-        // 1) try to fetch all rows, and map any SQL error into our tuple
-        let records = sqlx::query_as!(
+        query: &NaturalPhenomenonLocationListQuery,
+    ) -> Result<Page<WithAccess<GetAllNaturalPhenomenonLocationResponseSuccess>>, ApiError> {
+        let (sort_expr, sort_type) = query.sort.sql((query.near_lat, query.near_lon))?;
+        let page = PageRequest::new(
+            query.limit,
+            query.cursor.as_deref(),
+            query.sort.key(),
+            query.order,
+        )?;
+        if let (LocationSort::Distance, Some(lat), Some(lon)) =
+            (query.sort, query.near_lat, query.near_lon)
+        {
+            let page = self
+                .list_by_distance(user_id, query, &page, lat, lon)
+                .await?;
+            return Ok(page.map(|row| row.map(Into::into)));
+        }
+
+        // 1) fetch the filtered and sorted rows of one page, more when areas miss the box
+        let mut rows: Vec<Keyed<WithAccess<NaturalPhenomenonLocationDb>>> = Vec::new();
+        let mut batch_page = page.clone();
+        loop {
+            let mut sql = batch_page.select(LIST_TABLES, &sort_expr);
+            sql.push(" WHERE ");
+            push_list_filters(&mut sql, user_id, query);
+            batch_page.push_keyset(&mut sql, &sort_expr, sort_type)?;
+            let batch: Vec<Keyed<WithAccess<NaturalPhenomenonLocationDb>>> =
+                sql.build_query_as().fetch_all(&self.db).await?;
+
+            let full = batch.len() > page.limit as usize;
+            if let Some(last) = batch.last() {
+                batch_page = batch_page.after_row(last);
+            }
+            rows.extend(
+                batch
+                    .into_iter()
+                    .filter(|row| area_in_bbox(query.bbox.as_ref(), &row.item.item)),
+            );
+            if !full || rows.len() > page.limit as usize {
+                break;
+            }
+        }
+
+        // 2) now map the rows into our response DTOs
+        Ok(page.finish(rows).map(|row| row.map(Into::into)))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn nearby(
+        &self,
+        user_id: DatabaseId,
+        query: &NearbyQuery,
+    ) -> Result<Vec<Nearby<GetAllNaturalPhenomenonLocationResponseSuccess>>, ApiError> {
+        // nearest points first; an area can be closer or farther than its point, so the
+        // distances are refined and every row that might still beat the last one is fetched
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize;
+        let mut rows: Vec<Nearby<NaturalPhenomenonLocationDb>> =
+            nearby_candidates(query, user_id, query.within_km, &[], Some(limit))
+                .build_query_as()
+                .fetch_all(&self.db)
+                .await?;
+        let fetched = rows.len();
+        refine_distances(query, &mut rows);
+
+        if fetched == limit {
+            let bound = rows
+                .get(limit - 1)
+                .map(|row| row.distance_km)
+                .or(query.within_km);
+            let ids: Vec<i32> = rows.iter().map(|row| row.item.id.0).collect();
+            let mut more: Vec<Nearby<NaturalPhenomenonLocationDb>> =
+                nearby_candidates(query, user_id, bound, &ids, None)
+                    .build_query_as()
+                    .fetch_all(&self.db)
+                    .await?;
+            refine_distances(query, &mut more);
+            rows.extend(more);
+            rows.sort_by(|a, b| {
+                a.distance_km
+                    .total_cmp(&b.distance_km)
+                    .then(a.item.id.cmp(&b.item.id))
+            });
+            rows.truncate(limit);
+        }
+
+        Ok(rows.into_iter().map(|row| row.map(Into::into)).collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, device_id = %req.device_id))]
+    async fn check_geofence(
+        &self,
+        user_id: DatabaseId,
+        req: &GeofenceCheckRequest,
+    ) -> Result<GeofenceCheckResponse, ApiError> {
+        let mut tx = self.db.begin().await?;
+
+        // checks of one device are applied one after the other
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(format!("geofence:{}:{}", user_id.0, req.device_id))
+            .execute(&mut *tx)
+            .await?;
+
+        // only locations the track may reach, through the GiST index, and those the device
+        // is inside so that leaving them is seen; the exact test is `geofence_transitions`
+        let (lats, lons): (Vec<f64>, Vec<f64>) = req
+            .track
+            .iter()
+            .chain([&req.position])
+            .map(|position| (position.latitude, position.longitude))
+            .unzip();
+        let locations = sqlx::query_as!(
             NaturalPhenomenonLocationDb,
             r#"
-                SELECT *
-                FROM natural_phenomenon_locations
-                WHERE user_id = $1
+            WITH points AS (SELECT * FROM unnest($3::float8[], $4::float8[]) AS p (lat, lon)),
+                 reach AS (SELECT max(radius) AS km FROM natural_phenomenon_locations
+                           WHERE user_id = $1 AND deleted_at IS NULL AND area IS NULL)
+            SELECT * FROM natural_phenomenon_locations
+            WHERE user_id = $1 AND deleted_at IS NULL
+              AND id IN (SELECT location_id FROM geofence_states
+                         WHERE user_id = $1 AND device_id = $2
+                         UNION
+                         SELECT n.id FROM points p CROSS JOIN reach r
+                         JOIN natural_phenomenon_locations n
+                           ON earth_box(ll_to_earth(p.lat, p.lon), r.km * earth() / $6)
+                              @> ll_to_earth(n.latitude, n.longitude)
+                         WHERE n.user_id = $1 AND n.area IS NULL
+                         UNION
+                         SELECT n.id FROM points p
+                         JOIN natural_phenomenon_locations n
+                           ON earth_box(ll_to_earth(p.lat, p.lon), $5 * earth() / $6)
+                              @> ll_to_earth(n.latitude, n.longitude)
+                         WHERE n.user_id = $1 AND n.area IS NOT NULL)
             "#,
-            user_id.0
+            user_id.0,
+            req.device_id,
+            &lats,
+            &lons,
+            MAX_AREA_EXTENT_KM,
+            EARTH_RADIUS_KM,
         )
-        .fetch_all(&self.db)
-        .await
-        .map_err(|e| {
-            tracing::error!("\nError fetching locations: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-            )
-        })?;
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut inside: BTreeSet<DatabaseId> = sqlx::query_scalar!(
+            "SELECT location_id FROM geofence_states WHERE user_id = $1 AND device_id = $2",
+            user_id.0,
+            req.device_id
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(DatabaseId)
+        .collect();
+
+        let transitions = geofence_transitions(
+            &locations,
+            &mut inside,
+            req.track.iter().chain([&req.position]),
+        );
 
-        // 2) now map the Vec<NaturalPhenomenonLocationDb> into our response DTOs
-        let locations = records
+        let inside_ids: Vec<i32> = inside.iter().map(|id| id.0).collect();
+        sqlx::query!(
+            "DELETE FROM geofence_states
+             WHERE user_id = $1 AND device_id = $2 AND location_id <> ALL($3)",
+            user_id.0,
+            req.device_id,
+            &inside_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO geofence_states (user_id, device_id, location_id)
+             SELECT $1, $2, unnest($3::int4[])
+             ON CONFLICT DO NOTHING",
+            user_id.0,
+            req.device_id,
+            &inside_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        let mut inside: Vec<_> = locations
             .into_iter()
-            .map(|rec| GetAllNaturalPhenomenonLocationResponseSuccess {
-                id: rec.id,
-                user_id: rec.user_id,
-                name: rec.name,
-                latitude: rec.latitude,
-                longitude: rec.longitude,
-                description: rec.description,
-                radius: rec.radius,
-                image_path: rec.image_path.unwrap_or_default(),
+            .filter(|location| inside.contains(&location.id))
+            .map(|location| Nearby {
+                distance_km: Fence::new(&location).distance_km(&req.position),
+                item: location.into(),
             })
             .collect();
+        inside.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
 
-        Ok(locations)
+        Ok(GeofenceCheckResponse {
+            inside,
+            transitions,
+        })
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
     async fn get_by_id(
         &self,
         user_id: DatabaseId,
         id: DatabaseId,
-    ) -> Result<
-        GetByIdNaturalPhenomenonLocationResponseSuccess,
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    > {
-        let rec = sqlx::query_as!(
-            NaturalPhenomenonLocationDb,
+    ) -> Result<WithAccess<GetByIdNaturalPhenomenonLocationResponseSuccess>, ApiError> {
+        let row: WithAccess<NaturalPhenomenonLocationDb> = sqlx::query_as(
             r#"
                 SELECT *
                 FROM natural_phenomenon_locations
-                WHERE id = $1 AND user_id = $2
+                JOIN natural_phenomenon_location_access ON natural_phenomenon_location_id = id
+                WHERE id = $1 AND viewer_id = $2
                 "#,
-            id.0,
-            user_id.0
         )
-        .fetch_one(&self.db)
-        .await
-        .map_err(|_| {
-            tracing::error!("\nError fetching location by ID: {:?}", id);
-            (
-                StatusCode::NOT_FOUND,
-                Json(NaturalPhenomenonLocationError::NotFound),
-            )
-        })?;
+        .bind(id.0)
+        .bind(user_id.0)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Location not found".to_string()))?;
 
-        Ok(GetByIdNaturalPhenomenonLocationResponseSuccess {
-            id: rec.id,
-            user_id: rec.user_id,
-            name: rec.name,
-            latitude: rec.latitude,
-            longitude: rec.longitude,
-            radius: rec.radius,
-            description: rec.description,
-            image_path: rec.image_path.unwrap_or_default(),
-        })
+        Ok(
+            row.map(|rec| GetByIdNaturalPhenomenonLocationResponseSuccess {
+                id: rec.id,
+                user_id: rec.user_id,
+                name: rec.name,
+                latitude: rec.latitude,
+                longitude: rec.longitude,
+                radius: rec.radius,
+                description: rec.description,
+                phenomenon_type: rec.phenomenon_type,
+                image_path: rec.image_path.unwrap_or_default(),
+                area: rec.area,
+                version: rec.version,
+            }),
+        )
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = location.user_id.0, id = location.id.0))]
     async fn update(
         &self,
         location: UpdateNaturalPhenomenonLocationRequestWithIds,
-    ) -> Result<
-        UpdateNaturalPhenomenonLocationResponseSuccess,
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    > {
+        if_match: &IfMatch,
+    ) -> Result<UpdateNaturalPhenomenonLocationResponseSuccess, ApiError> {
+        let versions = if_match.versions();
+        let area = match &location.payload.area {
+            Some(Some(area)) => Some(serde_json::Value::from(area)),
+            _ => None,
+        };
+        let mut tx = self.db.begin().await?;
         let record = sqlx::query_as!(
             NaturalPhenomenonLocationDb,
             r#"
@@ -282,8 +581,14 @@ impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
             latitude    = COALESCE($2, latitude),
             longitude   = COALESCE($3, longitude),
             radius      = COALESCE($4, radius),
-            description = COALESCE($5, description)
-        WHERE id = $6 AND user_id = $7
+            description = COALESCE($5, description),
+            area        = CASE WHEN $9 THEN $10 ELSE area END,
+            phenomenon_type = COALESCE($11, phenomenon_type),
+            version     = version + 1,
+            updated_at  = now()
+        WHERE id = $6 AND ($8::int4[] IS NULL OR version = ANY($8))
+          AND id IN (SELECT natural_phenomenon_location_id FROM natural_phenomenon_location_access
+                     WHERE viewer_id = $7 AND access <> 'read')
         RETURNING *
         "#,
             // these are Option<...>, so COALESCE will pick the existing value when None:
@@ -294,16 +599,27 @@ impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
             location.payload.description,
             location.id.0,
             location.user_id.0,
+            versions.as_deref(),
+            // an explicit null clears the area
+            location.payload.area.is_some(),
+            area,
+            location.payload.phenomenon_type.map(PhenomenonType::as_str),
         )
-        .fetch_one(&self.db)
-        .await
-        .map_err(|_| {
-            tracing::error!("\nError updating location: {:?}", location);
-            (
-                StatusCode::NOT_FOUND,
-                Json(NaturalPhenomenonLocationError::NotFound),
-            )
-        })?;
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(record) = record else {
+            return Err(self
+                .write_failed(location.user_id, location.id, Access::Edit)
+                .await);
+        };
+
+        // moving the point can take a kept area out of reach too
+        if let Some(area) = record.area.clone() {
+            let area = Area::try_from(area)
+                .map_err(|e| ApiError::Internal(format!("Stored area is invalid: {}", e)))?;
+            area.check_extent(record.latitude, record.longitude)?;
+        }
+        tx.commit().await?;
 
         Ok(UpdateNaturalPhenomenonLocationResponseSuccess {
             id: record.id,
@@ -312,51 +628,622 @@ impl NaturalPhenomenonLocationServiceImpl for NaturalPhenomenonLocationService {
             latitude: record.latitude,
             longitude: record.longitude,
             description: record.description,
+            phenomenon_type: record.phenomenon_type,
             image_path: record.image_path.unwrap_or_default(),
             radius: record.radius,
+            area: record.area,
+            version: record.version,
         })
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
     async fn delete(
         &self,
         user_id: DatabaseId,
         id: DatabaseId,
-    ) -> Result<
-        (StatusCode, Json<NaturalPhenomenonLocationResponseSuccess>),
-        (StatusCode, Json<NaturalPhenomenonLocationError>),
-    > {
-        // 1) Delete the DB row, grabbing the image_path
+        if_match: &IfMatch,
+    ) -> Result<(StatusCode, Json<NaturalPhenomenonLocationResponseSuccess>), ApiError> {
+        // 1) Move the row to the trash; the image stays until it is purged
+        let versions = if_match.versions();
         let rec = sqlx::query!(
             r#"
-            DELETE FROM natural_phenomenon_locations
-             WHERE id = $1 AND user_id = $2
-            RETURNING image_path
+            UPDATE natural_phenomenon_locations SET deleted_at = now()
+             WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+               AND ($3::int4[] IS NULL OR version = ANY($3))
             "#,
             id.0,
             user_id.0,
+            versions.as_deref(),
         )
-        .fetch_one(&self.db)
-        .await
-        .map_err(|e| {
-            tracing::error!("\nDB delete error: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(NaturalPhenomenonLocationError::DatabaseError(e.to_string())),
-            )
-        })?;
-
-        // 2) If there was an image_path, remove the file (ignore FS errors)
-        if let Some(path) = rec.image_path {
-            tracing::debug!("\nremoving image file at {}", path);
-            let _ = fs::remove_file(&path).await;
-        } else {
-            tracing::debug!("\nno image file to remove");
+        .execute(&self.db)
+        .await?;
+        if rec.rows_affected() == 0 {
+            return Err(self.write_failed(user_id, id, Access::Owner).await);
         }
 
-        // 3) Success → return a 204 + our Deleted enum
+        // 2) Success → return a 204 + our Deleted enum
         Ok((
             StatusCode::NO_CONTENT,
             Json(NaturalPhenomenonLocationResponseSuccess::Deleted),
         ))
     }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn trash(
+        &self,
+        user_id: DatabaseId,
+    ) -> Result<Vec<GetAllNaturalPhenomenonLocationResponseSuccess>, ApiError> {
+        let rows = sqlx::query_as!(
+            NaturalPhenomenonLocationDb,
+            "SELECT * FROM natural_phenomenon_locations
+             WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id",
+            user_id.0
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn restore(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+    ) -> Result<GetAllNaturalPhenomenonLocationResponseSuccess, ApiError> {
+        let rec = sqlx::query_as!(
+            NaturalPhenomenonLocationDb,
+            r#"
+            UPDATE natural_phenomenon_locations
+            SET deleted_at = NULL, version = version + 1, updated_at = now()
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL
+            RETURNING *
+            "#,
+            id.0,
+            user_id.0,
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Location not found in the trash".to_string()))?;
+        Ok(rec.into())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn purge(&self, user_id: DatabaseId, id: DatabaseId) -> Result<(), ApiError> {
+        let rec = sqlx::query!(
+            "DELETE FROM natural_phenomenon_locations
+             WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL
+             RETURNING image_path",
+            id.0,
+            user_id.0,
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Location not found in the trash".to_string()))?;
+
+        if let Some(path) = rec.image_path {
+            remove_upload(&path).await;
+        }
+        Ok(())
+    }
+}
+
+/// Locations joined with the access of their viewers, the source of the lists.
+const LIST_TABLES: &str = "natural_phenomenon_locations JOIN natural_phenomenon_location_access \
+     ON natural_phenomenon_location_id = id";
+
+/// Append the conditions of `query` for the list of `user_id`, without cursor or order.
+///
+/// Rows with an area are only matched coarsely by `bbox`, `area_in_bbox` has the last word.
+fn push_list_filters(
+    sql: &mut QueryBuilder<'static, Postgres>,
+    user_id: DatabaseId,
+    query: &NaturalPhenomenonLocationListQuery,
+) {
+    sql.push("viewer_id = ").push_bind(user_id.0);
+    query.filter().push_sql(sql);
+    if let Some(bbox) = &query.bbox {
+        bbox.push_area_sql(sql);
+    }
+    if let Some(tag) = query.tag {
+        TAGS.push_filter(sql, "natural_phenomenon_location_id", user_id, tag);
+    }
+    if let Some(collection) = query.collection {
+        COLLECTIONS.push_filter(sql, "natural_phenomenon_location_id", user_id, collection);
+    }
+    if let Some(min_radius) = query.min_radius {
+        sql.push(" AND radius >= ").push_bind(min_radius);
+    }
+    if let Some(max_radius) = query.max_radius {
+        sql.push(" AND radius <= ").push_bind(max_radius);
+    }
+}
+
+/// Whether the area of `location`, if it has one, reaches into `bbox`.
+fn area_in_bbox(bbox: Option<&BoundingBox>, location: &NaturalPhenomenonLocationDb) -> bool {
+    match bbox {
+        Some(bbox) => Fence::new(location)
+            .area
+            .is_none_or(|area| bbox.intersects(&area)),
+        None => true,
+    }
+}
+
+/// List `SELECT` of the locations of `user_id` whose distance to `lat`/`lon` may lie within
+/// `(after, until)` in the direction of `order`, skipping `exclude`, nearest point first for
+/// `asc`.
+///
+/// Like in `nearby_candidates`, rows with an area are matched `MAX_AREA_EXTENT_KM` further and
+/// `distance_km` is the one to the point.
+fn distance_candidates(
+    user_id: DatabaseId,
+    query: &NaturalPhenomenonLocationListQuery,
+    order: SortOrder,
+    (lat, lon): (f64, f64),
+    (after, until): (Option<f64>, Option<f64>),
+    exclude: &[i32],
+    limit: Option<usize>,
+) -> QueryBuilder<'static, Postgres> {
+    let distance = distance_sql();
+    let slack = format!(
+        "(CASE WHEN area IS NULL THEN 0 ELSE {} END)",
+        MAX_AREA_EXTENT_KM
+    );
+
+    let mut sql = QueryBuilder::new(format!(
+        "SELECT *, {} AS distance_km FROM {}",
+        distance, LIST_TABLES
+    ));
+    push_reference_point(&mut sql, lat, lon);
+    sql.push(" WHERE ");
+    push_list_filters(&mut sql, user_id, query);
+    let (from, to, direction) = match order {
+        SortOrder::Asc => ((">=", "-"), ("<=", "+"), "ASC"),
+        SortOrder::Desc => (("<=", "+"), (">=", "-"), "DESC"),
+    };
+    for (bound, (compare, sign)) in [(after, from), (until, to)] {
+        if let Some(bound) = bound {
+            sql.push(format!(" AND {} {} ", distance, compare))
+                .push_bind(bound)
+                .push(format!(" {} {}", sign, slack));
+        }
+    }
+    if !exclude.is_empty() {
+        sql.push(" AND id <> ALL(")
+            .push_bind(exclude.to_vec())
+            .push(")");
+    }
+    sql.push(format!(
+        " ORDER BY {} {}, id {}",
+        distance, direction, direction
+    ));
+    if let Some(limit) = limit {
+        sql.push(" LIMIT ").push_bind(limit as i64);
+    }
+    sql
+}
+
+/// Replace the point distances of list `rows` by the ones to their areas, dropping the areas
+/// outside the `bbox` of `query`.
+fn refine_list(
+    query: &NaturalPhenomenonLocationListQuery,
+    lat: f64,
+    lon: f64,
+    rows: &mut Vec<Nearby<WithAccess<NaturalPhenomenonLocationDb>>>,
+) {
+    rows.retain_mut(|row| {
+        let Some(area) = Fence::new(&row.item.item).area else {
+            return true;
+        };
+        row.distance_km = area_distance_km(&area, lat, lon);
+        query.bbox.is_none_or(|bbox| bbox.intersects(&area))
+    });
+}
+
+/// The area or else the alert radius of a location, for containment and distances.
+struct Fence<'a> {
+    location: &'a NaturalPhenomenonLocationDb,
+    area: Option<MultiPolygon<f64>>,
+}
+
+impl<'a> Fence<'a> {
+    fn new(location: &'a NaturalPhenomenonLocationDb) -> Self {
+        // stored areas were validated on write
+        let area = location
+            .area
+            .clone()
+            .and_then(|area| Area::try_from(area).ok())
+            .and_then(|area| area.polygons().ok());
+        Fence { location, area }
+    }
+
+    /// Distance to the area, or to the point when there is none.
+    fn distance_km(&self, position: &Position) -> f64 {
+        match &self.area {
+            Some(area) => area_distance_km(area, position.latitude, position.longitude),
+            None => haversine_km(
+                position.latitude,
+                position.longitude,
+                self.location.latitude,
+                self.location.longitude,
+            ),
+        }
+    }
+
+    fn contains(&self, position: &Position) -> bool {
+        match &self.area {
+            Some(area) => area.intersects(&Point::new(position.longitude, position.latitude)),
+            None => self.distance_km(position) <= f64::from(self.location.radius),
+        }
+    }
+}
+
+/// Nearest-first `SELECT` of the locations of `user_id` that may be at most `max_km` from the
+/// point of `query`, skipping `exclude`.
+///
+/// Rows without an area are matched by the distance of their point, rows with one by the
+/// lower bound `MAX_AREA_EXTENT_KM` closer; `distance_km` is always the one to the point.
+fn nearby_candidates(
+    query: &NearbyQuery,
+    user_id: DatabaseId,
+    max_km: Option<f64>,
+    exclude: &[i32],
+    limit: Option<usize>,
+) -> QueryBuilder<'static, Postgres> {
+    let distance = distance_sql();
+
+    let mut sql = QueryBuilder::new(format!(
+        "SELECT *, {} AS distance_km FROM natural_phenomenon_locations",
+        distance
+    ));
+    push_reference_point(&mut sql, query.lat, query.lon);
+    sql.push(" WHERE deleted_at IS NULL AND user_id = ")
+        .push_bind(user_id.0);
+    if let Some(max_km) = max_km {
+        // one earth_box per kind keeps both served by the GiST index
+        let within = |sql: &mut QueryBuilder<'static, Postgres>, has_area: &str, km: f64| {
+            sql.push(format!(
+                "(area IS {} AND earth_box({}, ",
+                has_area, REFERENCE_EARTH_SQL
+            ))
+            .push_bind(km)
+            .push(format!(
+                " * earth() / {}) @> ll_to_earth(latitude, longitude) AND {} <= ",
+                EARTH_RADIUS_KM, distance
+            ))
+            .push_bind(km)
+            .push(")");
+        };
+        sql.push(" AND (");
+        within(&mut sql, "NULL", max_km);
+        sql.push(" OR ");
+        within(&mut sql, "NOT NULL", max_km + MAX_AREA_EXTENT_KM);
+        sql.push(")");
+    }
+    if let Some(bbox) = &query.bbox {
+        bbox.push_area_sql(&mut sql);
+    }
+    if !exclude.is_empty() {
+        sql.push(" AND id <> ALL(")
+            .push_bind(exclude.to_vec())
+            .push(")");
+    }
+    sql.push(format!(
+        " ORDER BY ll_to_earth(latitude, longitude) <-> {}, id",
+        REFERENCE_EARTH_SQL
+    ));
+    if let Some(limit) = limit {
+        sql.push(" LIMIT ").push_bind(limit as i64);
+    }
+    sql
+}
+
+/// Replace the point distances of `rows` by the ones to their areas, dropping the rows then
+/// beyond `within_km` or with an area outside the `bbox`, and sort them closest first.
+fn refine_distances(query: &NearbyQuery, rows: &mut Vec<Nearby<NaturalPhenomenonLocationDb>>) {
+    let position = Position {
+        latitude: query.lat,
+        longitude: query.lon,
+        recorded_at: None,
+    };
+    rows.retain_mut(|row| {
+        let fence = Fence::new(&row.item);
+        let Some(area) = &fence.area else {
+            return true;
+        };
+        row.distance_km = fence.distance_km(&position);
+        query.bbox.is_none_or(|bbox| bbox.intersects(area))
+    });
+    if let Some(within_km) = query.within_km {
+        rows.retain(|row| row.distance_km <= within_km);
+    }
+    rows.sort_by(|a, b| {
+        a.distance_km
+            .total_cmp(&b.distance_km)
+            .then(a.item.id.cmp(&b.item.id))
+    });
+}
+
+/// Walk `positions` starting inside the locations `inside`, returning every area or radius
+/// crossed.
+///
+/// `inside` is left at the locations containing the last position.
+fn geofence_transitions<'a>(
+    locations: &[NaturalPhenomenonLocationDb],
+    inside: &mut BTreeSet<DatabaseId>,
+    positions: impl IntoIterator<Item = &'a Position>,
+) -> Vec<GeofenceTransition> {
+    let fences: Vec<Fence> = locations.iter().map(Fence::new).collect();
+    let mut transitions = Vec::new();
+    for position in positions {
+        for fence in &fences {
+            let location = fence.location;
+            let contains = fence.contains(position);
+            let event = match (contains, inside.contains(&location.id)) {
+                (true, false) => GeofenceEvent::Enter,
+                (false, true) => GeofenceEvent::Exit,
+                _ => continue,
+            };
+            if contains {
+                inside.insert(location.id);
+            } else {
+                inside.remove(&location.id);
+            }
+            transitions.push(GeofenceTransition {
+                location_id: location.id,
+                event,
+                position: *position,
+            });
+        }
+    }
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::tests::TestApp;
+
+    #[sqlx::test]
+    async fn test_check_geofence(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let etna: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+             VALUES ($1, 'Etna', 37.75, 14.99, 10, NULL) RETURNING id",
+        )
+        .bind(user_id.0)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let service = NaturalPhenomenonLocationService::new(pool);
+
+        let at = |latitude, longitude| Position {
+            latitude,
+            longitude,
+            recorded_at: None,
+        };
+        let catania = at(37.50, 15.09);
+        let crater = at(37.76, 14.99);
+        let check = |device_id: &str, track: Vec<Position>, position: Position| {
+            let req = GeofenceCheckRequest {
+                device_id: device_id.to_string(),
+                position,
+                track,
+            };
+            let service = &service;
+            async move { service.check_geofence(user_id, &req).await.unwrap() }
+        };
+        let events = |response: &GeofenceCheckResponse| {
+            response
+                .transitions
+                .iter()
+                .map(|t| (t.location_id, t.event))
+                .collect::<Vec<_>>()
+        };
+
+        let response = check("phone", vec![], catania).await;
+        assert!(response.inside.is_empty());
+        assert!(response.transitions.is_empty());
+
+        // passing by between two checks
+        let response = check("phone", vec![crater], catania).await;
+        assert_eq!(
+            events(&response),
+            vec![(etna, GeofenceEvent::Enter), (etna, GeofenceEvent::Exit)]
+        );
+        assert!(response.inside.is_empty());
+
+        let response = check("phone", vec![], crater).await;
+        assert_eq!(events(&response), vec![(etna, GeofenceEvent::Enter)]);
+        assert_eq!(response.inside.len(), 1);
+        assert!(response.inside[0].distance_km < 2.0);
+
+        // repeated pings do not trigger again, other devices have their own state
+        let response = check("phone", vec![], crater).await;
+        assert!(response.transitions.is_empty());
+        assert_eq!(response.inside[0].item.id, etna);
+        let response = check("tablet", vec![], crater).await;
+        assert_eq!(events(&response), vec![(etna, GeofenceEvent::Enter)]);
+
+        let response = check("phone", vec![], catania).await;
+        assert_eq!(events(&response), vec![(etna, GeofenceEvent::Exit)]);
+
+        // far away locations are found by the largest alert radius
+        let sea: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+             VALUES ($1, 'Tyrrhenian Sea', 40.0, 12.0, 400, NULL) RETURNING id",
+        )
+        .bind(user_id.0)
+        .fetch_one(&service.db)
+        .await
+        .unwrap();
+        let response = check("phone", vec![], catania).await;
+        assert_eq!(events(&response), vec![(sea, GeofenceEvent::Enter)]);
+    }
+
+    #[sqlx::test]
+    async fn test_areas_in_geofence_and_nearby(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let insert = |name: &str, longitude: f64, area: Option<serde_json::Value>| {
+            sqlx::query_scalar(
+                "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path, area)
+                 VALUES ($1, $2, 46.0, $3, 1, NULL, $4) RETURNING id",
+            )
+            .bind(user_id.0)
+            .bind(name.to_string())
+            .bind(longitude)
+            .bind(area)
+            .fetch_one(&pool)
+        };
+        // a long valley whose point is at its western end, and a plain radius further east
+        let valley = serde_json::json!({
+            "type": "Polygon",
+            "coordinates": [[[8.0, 45.9], [8.6, 45.9], [8.6, 46.1], [8.0, 46.1], [8.0, 45.9]]]
+        });
+        let valley: DatabaseId = insert("Valley", 8.0, Some(valley)).await.unwrap();
+        let peak: DatabaseId = insert("Peak", 8.3, None).await.unwrap();
+        let service = NaturalPhenomenonLocationService::new(pool);
+
+        let query = |limit, within_km| NearbyQuery {
+            lat: 46.0,
+            lon: 8.45,
+            within_km,
+            bbox: None,
+            limit: Some(limit),
+        };
+        let nearby = service.nearby(user_id, &query(1, None)).await.unwrap();
+        assert_eq!(nearby[0].item.id, valley);
+        assert_eq!(nearby[0].distance_km, 0.0);
+        let nearby = service
+            .nearby(user_id, &query(10, Some(5.0)))
+            .await
+            .unwrap();
+        assert_eq!(nearby.len(), 1);
+        let nearby = service.nearby(user_id, &query(10, None)).await.unwrap();
+        let ids: Vec<_> = nearby.iter().map(|n| n.item.id).collect();
+        assert_eq!(ids, vec![valley, peak]);
+
+        // the lists filter and sort by the area too, also page by page
+        let list = |query: NaturalPhenomenonLocationListQuery| {
+            let service = &service;
+            async move {
+                let page = service.get_all(user_id, &query).await.unwrap();
+                let ids: Vec<_> = page.items.iter().map(|row| row.item.id).collect();
+                (ids, page.next_cursor)
+            }
+        };
+        let in_bbox = |bbox: &str| NaturalPhenomenonLocationListQuery {
+            bbox: Some(bbox.parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(list(in_bbox("8.4,45.9,8.5,46.1")).await.0, vec![valley]);
+        assert_eq!(
+            list(in_bbox("8.25,45.9,8.35,46.1")).await.0,
+            vec![valley, peak]
+        );
+        assert!(list(in_bbox("8.7,45.9,9.0,46.1")).await.0.is_empty());
+
+        let by_distance = |order, cursor| NaturalPhenomenonLocationListQuery {
+            limit: Some(1),
+            cursor,
+            sort: LocationSort::Distance,
+            order,
+            near_lat: Some(46.0),
+            near_lon: Some(8.45),
+            ..Default::default()
+        };
+        let (ids, cursor) = list(by_distance(SortOrder::Asc, None)).await;
+        assert_eq!(ids, vec![valley]);
+        let (ids, cursor) = list(by_distance(SortOrder::Asc, cursor)).await;
+        assert_eq!((ids, cursor), (vec![peak], None));
+        let (ids, cursor) = list(by_distance(SortOrder::Desc, None)).await;
+        assert_eq!(ids, vec![peak]);
+        let (ids, _) = list(by_distance(SortOrder::Desc, cursor)).await;
+        assert_eq!(ids, vec![valley]);
+
+        let req = GeofenceCheckRequest {
+            device_id: "phone".to_string(),
+            position: Position {
+                latitude: 46.0,
+                longitude: 8.45,
+                recorded_at: None,
+            },
+            track: vec![],
+        };
+        let response = service.check_geofence(user_id, &req).await.unwrap();
+        assert_eq!(response.transitions.len(), 1);
+        assert_eq!(response.transitions[0].location_id, valley);
+    }
+
+    #[sqlx::test]
+    async fn test_trash_keeps_image_until_purged(pool: PgPool) {
+        // sqlx tests run on async-std, removing images needs the blocking pool of tokio
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _tokio = runtime.enter();
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("etna.jpg");
+        std::fs::write(&image, b"jpeg").unwrap();
+        let etna: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+             VALUES ($1, 'Etna', 37.75, 14.99, 10, $2) RETURNING id",
+        )
+        .bind(user_id.0)
+        .bind(image.to_str().unwrap())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let service = NaturalPhenomenonLocationService::new(pool);
+
+        // in the trash the location is hidden, even from the geofence, but keeps its image
+        let (status, _) = service
+            .delete(user_id, etna, &IfMatch::Absent)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(image.exists());
+        let err = service.get_by_id(user_id, etna).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let err = service
+            .delete(user_id, etna, &IfMatch::Absent)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let req = GeofenceCheckRequest {
+            device_id: "phone".to_string(),
+            position: Position {
+                latitude: 37.75,
+                longitude: 14.99,
+                recorded_at: None,
+            },
+            track: Vec::new(),
+        };
+        let response = service.check_geofence(user_id, &req).await.unwrap();
+        assert!(response.inside.is_empty());
+        let trash = service.trash(user_id).await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert!(trash[0].deleted_at.is_some());
+
+        let restored = service.restore(user_id, etna).await.unwrap();
+        assert_eq!(restored.deleted_at, None);
+        assert_eq!(restored.version, 2);
+        assert_eq!(
+            service.get_by_id(user_id, etna).await.unwrap().item.id,
+            etna
+        );
+
+        // purging removes the image with the location
+        let err = service.purge(user_id, etna).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let (status, _) = service
+            .delete(user_id, etna, &IfMatch::Absent)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        service.purge(user_id, etna).await.unwrap();
+        assert!(!image.exists());
+        assert!(service.trash(user_id).await.unwrap().is_empty());
+    }
 }
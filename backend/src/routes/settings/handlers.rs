@@ -3,45 +3,68 @@ use crate::routes::auth::models::UserDb;
 use crate::routes::auth::services::AuthService;
 use crate::routes::settings::models::{UserSettingsServiceSuccess, UserSettingsUpdateRequest};
 use crate::routes::settings::services::{SettingsService, SettingsServiceImpl};
+use crate::shared::error::{ApiError, ProblemDetails};
 use crate::shared::models::AppState;
+use crate::shared::preconditions::{precondition_failed, require_if_match, version_etag, IfMatch};
+use crate::shared::validation::ValidatedJson;
 use axum::{
     extract::{Json, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Extension,
 };
 use std::sync::Arc;
-use tracing::error;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 
 /// Fetch all user settings for the current user.
+///
+/// With `If-Match` the update only applies to the given version; otherwise the current
+/// settings are returned with a 412.
 #[utoipa::path(
     method(put),
     path = "/user/settings",
     request_body = UserSettingsUpdateRequest,
+    params(("If-Match" = Option<String>, Header, description = "ETag of the version being updated")),
     responses(
-        (status = 200, description = "Settings updated", content_type = "application/json"),
-        (status = 400, description = "Bad request", content_type = "application/json")
+        (status = 200, description = "Settings updated", content_type = "application/json",
+            headers(("ETag" = String, description = "New version of the settings"))),
+        (status = 400, description = "Bad request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No settings found for user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Settings were modified, the current ones are returned", body = UserSettingsServiceSuccess),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn put_settings<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-    Json(payload): Json<UserSettingsUpdateRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)>
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<UserSettingsUpdateRequest>,
+) -> Result<Response, ApiError>
 where
     S: SettingsServiceImpl,
 {
-    service
-        .update_settings(&user.id, &payload)
+    match service
+        .update_settings(&user.id, &payload, &if_match)
         .await
-        .map_err(|err| {
-            let msg = serde_json::json!({ "error": format!("Failed to update settings: {}", err) });
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(msg))
-        })?;
-
-    Ok((StatusCode::OK, "Settings saved successfully"))
+        .map_err(ApiError::from)
+    {
+        Ok(version) => Ok((
+            StatusCode::OK,
+            [(header::ETAG, version_etag(version))],
+            "Settings saved successfully",
+        )
+            .into_response()),
+        Err(ApiError::PreconditionFailed(_)) => match service.get_settings(&user.id).await? {
+            Some(current) => Ok(precondition_failed(&current, current.version)),
+            None => Err(ApiError::NotFound(
+                "Settings not found for user".to_string(),
+            )),
+        },
+        Err(e) => Err(e),
+    }
 }
 
 /// Fetch all user settings for the current user.
@@ -49,38 +72,33 @@ where
     get,
     path = "/user/settings",
     responses(
-        (status = 200, description = "User settings returned", body = UserSettingsServiceSuccess, content_type = "application/json"),
-        (status = 404, description = "No settings found for user", content_type = "application/json"),
-        (status = 500, description = "Internal server error", content_type = "application/json")
+        (status = 200, description = "User settings returned", body = UserSettingsServiceSuccess, content_type = "application/json",
+            headers(("ETag" = String, description = "Version of the settings, for `If-Match`"))),
+        (status = 404, description = "No settings found for user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_settings<S>(
     Extension(user): Extension<UserDb>,
     State(service): State<Arc<S>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)>
+) -> Result<impl IntoResponse, ApiError>
 where
     S: SettingsServiceImpl,
 {
-    let settings = service.get_settings(&user.id).await.map_err(|err| {
-        tracing::error!("Error in service.get_settings: {:?}", err);
-        let json =
-            serde_json::json!({ "error": format!("Error in service.get_settings: {}", err) });
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(json))
-    })?;
+    let settings = service.get_settings(&user.id).await?;
 
     match settings {
         Some(s) => {
             tracing::debug!("User settings: {:?}", s);
-            Ok((StatusCode::OK, Json(s)))
-        }
-        None => {
-            tracing::error!("Settings not found for user: {:?}", user.id);
-            let json = serde_json::json!({
-                "status": "fail",
-                "message": "Settings not found for user"
-            });
-            Err((StatusCode::NOT_FOUND, Json(json)))
+            Ok((
+                StatusCode::OK,
+                [(header::ETAG, version_etag(s.version))],
+                Json(s),
+            ))
         }
+        None => Err(ApiError::NotFound(
+            "Settings not found for user".to_string(),
+        )),
     }
 }
 
@@ -97,6 +115,10 @@ where
     OpenApiRouter::new()
         .routes(routes!(get_settings))
         .routes(routes!(put_settings))
+        .layer(axum::middleware::from_fn_with_state(
+            app.settings.concurrency.clone(),
+            require_if_match,
+        ))
         .layer(axum::middleware::from_fn_with_state(auth_service, auth))
         .with_state(service)
 }
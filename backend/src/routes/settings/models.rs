@@ -1,6 +1,8 @@
+use crate::routes::natural_phenomenon_locations::models::MAX_RADIUS_KM;
 use crate::shared::models::DatabaseId;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use validator::Validate;
 
 /// Available UI themes for the application.
 #[derive(Debug, Deserialize, Serialize, ToSchema, sqlx::Type, Copy, Clone, PartialEq)]
@@ -15,7 +17,9 @@ pub enum Theme {
 /// Payload for updating a user’s settings.
 ///
 /// Contains all fields that can be modified in one call.
-#[derive(Debug, Deserialize, Serialize, ToSchema, sqlx::FromRow, Clone, Copy, PartialEq)]
+#[derive(
+    Debug, Deserialize, Serialize, ToSchema, sqlx::FromRow, Clone, Copy, PartialEq, Validate,
+)]
 pub struct UserSettingsUpdateRequest {
     /// The UI theme to apply.
     pub theme: Theme,
@@ -24,6 +28,8 @@ pub struct UserSettingsUpdateRequest {
     pub notifications_enabled: bool,
 
     /// The radius (in kilometers) used for location-based alerts.
+    #[validate(range(min = 1, max = MAX_RADIUS_KM))]
+    #[schema(minimum = 1, maximum = 20000)]
     pub radius: i32,
 }
 
@@ -45,6 +51,9 @@ pub struct UserSettingsDb {
     /// The radius (in kilometers) for geofencing or alerts.
     pub radius: i32,
 
+    /// Incremented on every update, sent as the ETag.
+    pub version: i32,
+
     /// When this settings record was created.
     pub created_at: chrono::DateTime<chrono::Utc>,
 
@@ -89,4 +98,7 @@ pub struct UserSettingsServiceSuccess {
 
     /// The radius (in kilometers) configured for the user.
     pub radius: i32,
+
+    /// Version of the settings, to be sent back in `If-Match`.
+    pub version: i32,
 }
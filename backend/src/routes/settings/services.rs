@@ -3,7 +3,9 @@ use crate::routes::settings::models::{
     Theme, UserSettingsCreate, UserSettingsDb, UserSettingsServiceSuccess,
     UserSettingsUpdateRequest,
 };
+use crate::shared::error::ApiError;
 use crate::shared::models::DatabaseId;
+use crate::shared::preconditions::IfMatch;
 use anyhow::Result;
 use async_trait::async_trait;
 use mockall::automock;
@@ -28,12 +30,14 @@ pub trait SettingsServiceImpl: Send + Sync + 'static {
     /// Update the existing settings for the given user.
     ///
     /// Fields not provided in `UserSettingsUpdateRequest` will be left unchanged.
-    /// Returns `Ok(())` on success or an error on failure.
+    /// Returns the new version on success, `ApiError::PreconditionFailed` if the current
+    /// version does not match `if_match`, or an error on failure.
     async fn update_settings(
         &self,
         user_id: &DatabaseId,
         settings: &UserSettingsUpdateRequest,
-    ) -> Result<()>;
+        if_match: &IfMatch,
+    ) -> Result<i32>;
 
     /// Insert a new settings row for the given user.
     ///
@@ -71,6 +75,7 @@ impl SettingsService {
 
 #[async_trait]
 impl SettingsServiceImpl for SettingsService {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
     async fn get_settings(
         &self,
         user_id: &DatabaseId,
@@ -78,7 +83,7 @@ impl SettingsServiceImpl for SettingsService {
         tracing::debug!("Getting settings for user_id: {:?}", user_id);
         let settings = sqlx::query_as::<_, UserSettingsServiceSuccess>(
             r#"
-            SELECT theme, notifications_enabled, radius, user_id
+            SELECT theme, notifications_enabled, radius, user_id, version
             FROM settings
             WHERE user_id = $1
             "#,
@@ -94,24 +99,38 @@ impl SettingsServiceImpl for SettingsService {
         Ok(settings)
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
     async fn update_settings(
         &self,
         user_id: &DatabaseId,
         settings: &UserSettingsUpdateRequest,
-    ) -> Result<()> {
-        sqlx::query!(
-            "UPDATE settings SET theme = $1, notifications_enabled = $2, radius = $3, updated_at = NOW() WHERE user_id = $4",
+        if_match: &IfMatch,
+    ) -> Result<i32> {
+        let versions = if_match.versions();
+        let version = sqlx::query_scalar!(
+            "UPDATE settings SET theme = $1, notifications_enabled = $2, radius = $3, version = version + 1, updated_at = NOW()
+             WHERE user_id = $4 AND ($5::int4[] IS NULL OR version = ANY($5))
+             RETURNING version",
             settings.theme as _,
             settings.notifications_enabled,
             settings.radius,
-            user_id.0
+            user_id.0,
+            versions.as_deref(),
         )
-            .execute(&self.db)
+            .fetch_optional(&self.db)
             .await?;
 
-        Ok(())
+        match version {
+            Some(version) => Ok(version),
+            None if self.get_settings(user_id).await?.is_some() => Err(
+                ApiError::PreconditionFailed("Settings were modified in the meantime".to_string())
+                    .into(),
+            ),
+            None => Err(ApiError::NotFound("Settings not found for user".to_string()).into()),
+        }
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = setting.user_id.0))]
     async fn create_settings(&self, setting: &UserSettingsCreate) -> Result<UserSettingsDb> {
         // supply defaults here in Rust:
         let theme = setting
@@ -140,7 +159,7 @@ impl SettingsServiceImpl for SettingsService {
             r#"
         INSERT INTO settings (user_id, theme, notifications_enabled, radius)
         VALUES ($1, $2, $3, $4)
-        RETURNING id, theme, notifications_enabled, radius, user_id, created_at, updated_at
+        RETURNING id, theme, notifications_enabled, radius, user_id, version, created_at, updated_at
         "#,
         )
         .bind(setting.user_id.0) // DatabaseId is transparent newtype over i32
@@ -153,6 +172,7 @@ impl SettingsServiceImpl for SettingsService {
         Ok(settings)
     }
 
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
     async fn delete_settings(&self, user_id: &DatabaseId) -> Result<()> {
         // Delete the row; if it wasn't there, we still return Ok(())
         sqlx::query!("DELETE FROM settings WHERE user_id = $1", user_id.0)
@@ -225,8 +245,19 @@ mod tests {
         };
 
         // Test updating settings
-        let result = service.update_settings(user_id, &update_request).await;
-        assert!(result.is_ok());
+        let result = service
+            .update_settings(user_id, &update_request, &IfMatch::Versions(vec![1]))
+            .await;
+        assert_eq!(result.unwrap(), 2);
+
+        // the first version is gone
+        let result = service
+            .update_settings(user_id, &update_request, &IfMatch::Versions(vec![1]))
+            .await;
+        assert!(matches!(
+            ApiError::from(result.unwrap_err()),
+            ApiError::PreconditionFailed(_)
+        ));
 
         // Verify the update
         let settings = service
@@ -0,0 +1,342 @@
+use crate::routes::auth::middlewares::auth;
+use crate::routes::auth::models::UserDb;
+use crate::routes::auth::services::AuthService;
+use crate::routes::location_transfer::models::LocationKind;
+use crate::routes::sharing::models::{
+    CreatedLocationLink, LocationLink, LocationShare, PublicLocation, ShareLocationRequest,
+    ShareTarget,
+};
+use crate::routes::sharing::services::{SharingService, SharingServiceImpl};
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::models::{AppState, DatabaseId};
+use crate::shared::validation::ValidatedJson;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use std::sync::Arc;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+/// Share a location of the current user with another user, to read or to edit.
+///
+/// Sharing it with the same user again changes the permission. An email address without an
+/// account is answered the same way, its user gets the share on signing up.
+#[utoipa::path(
+    post,
+    path = "/locations/{kind}/{id}/shares",
+    request_body = ShareLocationRequest,
+    responses(
+        (status = 200, description = "Location shared", body = LocationShare),
+        (status = 403, description = "Location belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Own email address", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("kind" = LocationKind, Path, description = "Kind of location"),
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn share_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((kind, id)): Path<(LocationKind, DatabaseId)>,
+    ValidatedJson(request): ValidatedJson<ShareLocationRequest>,
+) -> Result<Json<LocationShare>, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let share = service
+        .share(user.id, ShareTarget::Location(kind, id), &request)
+        .await?;
+    Ok(Json(share))
+}
+
+/// List the users a location of the current user is shared with.
+#[utoipa::path(
+    get,
+    path = "/locations/{kind}/{id}/shares",
+    responses(
+        (status = 200, description = "Shares of the location, oldest first", body = [LocationShare]),
+        (status = 403, description = "Location belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("kind" = LocationKind, Path, description = "Kind of location"),
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn get_location_shares<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((kind, id)): Path<(LocationKind, DatabaseId)>,
+) -> Result<Json<Vec<LocationShare>>, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let shares = service
+        .shares(user.id, ShareTarget::Location(kind, id))
+        .await?;
+    Ok(Json(shares))
+}
+
+/// Revoke a share of a location; the user it is shared with can also leave it.
+#[utoipa::path(
+    delete,
+    path = "/locations/{kind}/{id}/shares/{share_id}",
+    responses(
+        (status = 204, description = "Share revoked"),
+        (status = 404, description = "Location or share not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("kind" = LocationKind, Path, description = "Kind of location"),
+        ("id" = i32, Path, description = "Location ID"),
+        ("share_id" = i32, Path, description = "Share ID")
+    )
+)]
+pub async fn delete_location_share<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((kind, id, share_id)): Path<(LocationKind, DatabaseId, DatabaseId)>,
+) -> Result<StatusCode, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    service
+        .unshare(user.id, ShareTarget::Location(kind, id), share_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Share a collection of the current user with another user, to read or to edit.
+///
+/// The user gets the permission on the collection and on the locations of the current user
+/// in it. Sharing it with the same user again changes the permission; an email address
+/// without an account is answered the same way.
+#[utoipa::path(
+    post,
+    path = "/collections/{id}/shares",
+    request_body = ShareLocationRequest,
+    responses(
+        (status = 200, description = "Collection shared", body = LocationShare),
+        (status = 403, description = "Collection belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Own email address", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn share_collection<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<ShareLocationRequest>,
+) -> Result<Json<LocationShare>, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let share = service
+        .share(user.id, ShareTarget::Collection(id), &request)
+        .await?;
+    Ok(Json(share))
+}
+
+/// List the users a collection of the current user is shared with.
+#[utoipa::path(
+    get,
+    path = "/collections/{id}/shares",
+    responses(
+        (status = 200, description = "Shares of the collection, oldest first", body = [LocationShare]),
+        (status = 403, description = "Collection belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Collection not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID")
+    )
+)]
+pub async fn get_collection_shares<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<Json<Vec<LocationShare>>, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let shares = service.shares(user.id, ShareTarget::Collection(id)).await?;
+    Ok(Json(shares))
+}
+
+/// Revoke a share of a collection; the user it is shared with can also leave it.
+#[utoipa::path(
+    delete,
+    path = "/collections/{id}/shares/{share_id}",
+    responses(
+        (status = 204, description = "Share revoked"),
+        (status = 404, description = "Collection or share not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Collection ID"),
+        ("share_id" = i32, Path, description = "Share ID")
+    )
+)]
+pub async fn delete_collection_share<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((id, share_id)): Path<(DatabaseId, DatabaseId)>,
+) -> Result<StatusCode, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    service
+        .unshare(user.id, ShareTarget::Collection(id), share_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Create a public read-only link to a location of the current user.
+///
+/// The token is only returned now; anyone with the link sees the location until it is
+/// revoked.
+#[utoipa::path(
+    post,
+    path = "/locations/{kind}/{id}/links",
+    responses(
+        (status = 201, description = "Link created", body = CreatedLocationLink),
+        (status = 403, description = "Location belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("kind" = LocationKind, Path, description = "Kind of location"),
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn create_location_link<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((kind, id)): Path<(LocationKind, DatabaseId)>,
+) -> Result<(StatusCode, Json<CreatedLocationLink>), ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let link = service.create_link(user.id, kind, id).await?;
+    Ok((StatusCode::CREATED, Json(link)))
+}
+
+/// List the public links to a location of the current user.
+#[utoipa::path(
+    get,
+    path = "/locations/{kind}/{id}/links",
+    responses(
+        (status = 200, description = "Links to the location, oldest first", body = [LocationLink]),
+        (status = 403, description = "Location belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("kind" = LocationKind, Path, description = "Kind of location"),
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn get_location_links<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((kind, id)): Path<(LocationKind, DatabaseId)>,
+) -> Result<Json<Vec<LocationLink>>, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let links = service.links(user.id, kind, id).await?;
+    Ok(Json(links))
+}
+
+/// Revoke a public link to a location of the current user.
+#[utoipa::path(
+    delete,
+    path = "/locations/{kind}/{id}/links/{link_id}",
+    responses(
+        (status = 204, description = "Link revoked"),
+        (status = 403, description = "Location belongs to another user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location or link not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("kind" = LocationKind, Path, description = "Kind of location"),
+        ("id" = i32, Path, description = "Location ID"),
+        ("link_id" = i32, Path, description = "Link ID")
+    )
+)]
+pub async fn delete_location_link<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path((kind, id, link_id)): Path<(LocationKind, DatabaseId, DatabaseId)>,
+) -> Result<StatusCode, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    service.revoke_link(user.id, kind, id, link_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the location a public link leads to; no account is needed.
+#[utoipa::path(
+    get,
+    path = "/public/locations/{token}",
+    responses(
+        (status = 200, description = "The location, without owner or image", body = PublicLocation),
+        (status = 404, description = "Unknown or revoked link", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("token" = String, Path, description = "Token of the link")
+    )
+)]
+pub async fn get_public_location<S>(
+    State(service): State<Arc<S>>,
+    Path(token): Path<String>,
+) -> Result<Json<PublicLocation>, ApiError>
+where
+    S: SharingServiceImpl,
+{
+    let location = service.public_location(&token).await?;
+    Ok(Json(location))
+}
+
+/// Generic router allowing injection of any implementation of the sharing service
+pub fn router_with_service<S>(app: AppState, service: Arc<S>) -> OpenApiRouter
+where
+    S: SharingServiceImpl,
+{
+    let auth_service = Arc::new(AuthService {
+        db: app.db.clone(),
+        settings: app.settings.clone(),
+        http: Default::default(),
+    });
+    let public = OpenApiRouter::new().routes(routes!(get_public_location));
+
+    OpenApiRouter::new()
+        .routes(routes!(share_location))
+        .routes(routes!(get_location_shares))
+        .routes(routes!(delete_location_share))
+        .routes(routes!(share_collection))
+        .routes(routes!(get_collection_shares))
+        .routes(routes!(delete_collection_share))
+        .routes(routes!(create_location_link))
+        .routes(routes!(get_location_links))
+        .routes(routes!(delete_location_link))
+        .layer(axum::middleware::from_fn_with_state(auth_service, auth))
+        .merge(public)
+        .with_state(service)
+}
+
+/// Convenience router using the Postgres-backed implementation
+pub fn router(app: AppState) -> OpenApiRouter {
+    let service = Arc::new(SharingService::new(app.db.clone()));
+    router_with_service(app, service)
+}
@@ -0,0 +1,210 @@
+use crate::routes::location_transfer::models::LocationKind;
+use crate::routes::natural_phenomenon_locations::models::PhenomenonType;
+use crate::shared::error::ApiError;
+use crate::shared::models::DatabaseId;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, Row};
+use utoipa::ToSchema;
+use validator::Validate;
+
+/// What a user may do with a location or collection, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    /// Shared with the user to look at.
+    Read,
+    /// Shared with the user to change; only the owner deletes or shares it.
+    Edit,
+    /// The user's own location.
+    Owner,
+}
+
+impl Access {
+    /// `ApiError::Forbidden` unless this access allows what `needed` does.
+    pub fn require(self, needed: Access) -> Result<(), ApiError> {
+        if self >= needed {
+            return Ok(());
+        }
+        Err(ApiError::Forbidden(
+            match needed {
+                Access::Owner => "Only the owner of the location can do this",
+                _ => "Location is shared with you read-only",
+            }
+            .to_string(),
+        ))
+    }
+}
+
+impl TryFrom<String> for Access {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "read" => Ok(Access::Read),
+            "edit" => Ok(Access::Edit),
+            "owner" => Ok(Access::Owner),
+            _ => Err(format!("unknown access `{}`", value)),
+        }
+    }
+}
+
+/// A location or collection together with the access the current user has to it.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct WithAccess<T> {
+    #[serde(flatten)]
+    #[schema(inline)]
+    pub item: T,
+
+    /// `owner` for the user's own locations, else the permission it was shared with.
+    pub access: Access,
+}
+
+impl<T> WithAccess<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> WithAccess<U> {
+        WithAccess {
+            item: f(self.item),
+            access: self.access,
+        }
+    }
+}
+
+/// Reads the `access` column of the `*_access` views next to the row.
+impl<'r, T: FromRow<'r, PgRow>> FromRow<'r, PgRow> for WithAccess<T> {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        let access: String = row.try_get("access")?;
+        Ok(WithAccess {
+            item: T::from_row(row)?,
+            access: access
+                .try_into()
+                .map_err(|e: String| sqlx::Error::ColumnDecode {
+                    index: "access".to_string(),
+                    source: e.into(),
+                })?,
+        })
+    }
+}
+
+/// What a share gives access to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareTarget {
+    /// One location.
+    Location(LocationKind, DatabaseId),
+    /// The locations of the owner in a collection, and the collection itself.
+    Collection(DatabaseId),
+}
+
+/// Permission given to the user a location is shared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SharePermission {
+    /// Look at the location.
+    Read,
+    /// Also change its fields.
+    Edit,
+}
+
+impl SharePermission {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SharePermission::Read => "read",
+            SharePermission::Edit => "edit",
+        }
+    }
+}
+
+impl From<String> for SharePermission {
+    /// Reads the `permission` column, which only holds `read` and `edit`.
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "edit" => SharePermission::Edit,
+            _ => SharePermission::Read,
+        }
+    }
+}
+
+/// Payload to share a location or collection with another user.
+#[derive(Debug, Clone, Deserialize, ToSchema, Validate)]
+pub struct ShareLocationRequest {
+    /// Email address of the user; without an account yet, they get the share on signing up.
+    #[validate(email, length(max = 255))]
+    #[schema(format = Email, max_length = 255)]
+    pub email: String,
+
+    /// What the user may do with the location, or with the locations in the collection.
+    pub permission: SharePermission,
+}
+
+/// A user a location or collection is shared with.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct LocationShare {
+    /// ID of the share, to revoke it.
+    pub id: DatabaseId,
+
+    /// Email address the location is shared with, whether or not it has an account yet.
+    pub email: String,
+
+    /// What that user may do with the location.
+    pub permission: SharePermission,
+
+    /// When the location was first shared with the user.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+
+    /// When the permission last changed.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A public link to a location; the token is not kept.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct LocationLink {
+    /// ID of the link, to revoke it.
+    pub id: DatabaseId,
+
+    /// When the link was created.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A public link just created, with the only copy of its token.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct CreatedLocationLink {
+    /// ID of the link, to revoke it.
+    pub id: DatabaseId,
+
+    /// Unguessable token of the link; it cannot be shown again.
+    pub token: String,
+
+    /// Path of the read-only view of the location, to be given out.
+    pub path: String,
+
+    /// When the link was created.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A location as seen through a public link: no owner, image or version.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct PublicLocation {
+    /// Which kind of location this is.
+    pub kind: LocationKind,
+
+    /// Location name.
+    pub name: String,
+
+    /// Latitude.
+    pub latitude: f64,
+
+    /// Longitude.
+    pub longitude: f64,
+
+    /// Description.
+    pub description: String,
+
+    /// Alert radius in kilometers, for natural phenomenon locations.
+    pub radius: Option<i32>,
+
+    /// Kind of phenomenon, for natural phenomenon locations.
+    pub phenomenon_type: Option<PhenomenonType>,
+
+    /// GeoJSON Polygon or MultiPolygon replacing the radius, if any.
+    #[schema(value_type = Option<Object>)]
+    pub area: Option<serde_json::Value>,
+}
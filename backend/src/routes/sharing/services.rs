@@ -0,0 +1,910 @@
+use crate::routes::auth::models::UserDb;
+use crate::routes::location_transfer::models::LocationKind;
+use crate::routes::natural_phenomenon_locations::models::NaturalPhenomenonLocationDb;
+use crate::routes::sharing::models::{
+    Access, CreatedLocationLink, LocationLink, LocationShare, PublicLocation, ShareLocationRequest,
+    ShareTarget,
+};
+use crate::routes::weather_locations::models::WeatherLocation;
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::memberships::COLLECTIONS;
+use crate::shared::models::DatabaseId;
+use crate::shared::versioning::API_V1_PREFIX;
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+
+/// Random bytes in a link token, 43 characters once encoded.
+const TOKEN_BYTES: usize = 32;
+
+/// Shares locations and collections with other users, and locations through public links.
+///
+/// Only the owner of a location or collection shares it or creates links to it; a user it
+/// is shared with can leave the share.
+#[async_trait]
+pub trait SharingServiceImpl: Send + Sync + 'static {
+    /// Share the location or collection `target` owned by `user_id` with the user whose email
+    /// is in `request`.
+    ///
+    /// Sharing it with the same user again changes the permission. An address without an
+    /// account gets the share once it signs up, so the answer is the same whether or not it
+    /// has one. Returns `ApiError::Validation` for the owner's own email.
+    async fn share(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+        request: &ShareLocationRequest,
+    ) -> Result<LocationShare, ApiError>;
+
+    /// The users the location or collection is shared with, oldest share first.
+    async fn shares(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+    ) -> Result<Vec<LocationShare>, ApiError>;
+
+    /// Revoke the share `share_id` of the location or collection, as its owner or as the
+    /// user it is shared with.
+    async fn unshare(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+        share_id: DatabaseId,
+    ) -> Result<(), ApiError>;
+
+    /// Create a read-only public link to the location.
+    ///
+    /// Only a hash of the token is stored, so the returned link is the only copy.
+    async fn create_link(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+    ) -> Result<CreatedLocationLink, ApiError>;
+
+    /// The public links to the location, oldest first.
+    async fn links(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+    ) -> Result<Vec<LocationLink>, ApiError>;
+
+    /// Revoke the public link `link_id` to the location.
+    async fn revoke_link(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+        link_id: DatabaseId,
+    ) -> Result<(), ApiError>;
+
+    /// The location a public link `token` leads to, or `ApiError::NotFound` also while it
+    /// is in the trash.
+    async fn public_location(&self, token: &str) -> Result<PublicLocation, ApiError>;
+}
+
+/// Access of `user_id` to the location `id` of `kind`: owner, or shared to read or edit.
+///
+/// Returns `ApiError::NotFound` when the user has none, so other users' locations stay
+/// hidden.
+pub async fn location_access<'e, E: PgExecutor<'e>>(
+    db: E,
+    kind: LocationKind,
+    user_id: DatabaseId,
+    id: DatabaseId,
+) -> Result<Access, ApiError> {
+    let access = match kind {
+        LocationKind::Weather => {
+            sqlx::query_scalar!(
+                "SELECT access FROM weather_location_access
+                 WHERE weather_location_id = $1 AND viewer_id = $2",
+                id.0,
+                user_id.0,
+            )
+            .fetch_optional(db)
+            .await?
+        }
+        LocationKind::NaturalPhenomenon => {
+            sqlx::query_scalar!(
+                "SELECT access FROM natural_phenomenon_location_access
+                 WHERE natural_phenomenon_location_id = $1 AND viewer_id = $2",
+                id.0,
+                user_id.0,
+            )
+            .fetch_optional(db)
+            .await?
+        }
+    };
+    access
+        .flatten()
+        .ok_or_else(|| ApiError::NotFound("Location not found".to_string()))?
+        .try_into()
+        .map_err(ApiError::Internal)
+}
+
+/// Give the new account `user` the shares made for its email address before it existed.
+pub async fn claim_pending_shares<'e, E: PgExecutor<'e>>(
+    db: E,
+    user: &UserDb,
+) -> Result<(), ApiError> {
+    sqlx::query!(
+        "UPDATE location_shares SET user_id = $1 WHERE user_id IS NULL AND lower(email) = lower($2)",
+        user.id.0,
+        user.email,
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// The `weather_location_id` and `natural_phenomenon_location_id` of a share or link.
+fn target(kind: LocationKind, id: DatabaseId) -> (Option<i32>, Option<i32>) {
+    match kind {
+        LocationKind::Weather => (Some(id.0), None),
+        LocationKind::NaturalPhenomenon => (None, Some(id.0)),
+    }
+}
+
+/// The `weather_location_id`, `natural_phenomenon_location_id` and `collection_id` of a
+/// share.
+fn share_target(target: ShareTarget) -> (Option<i32>, Option<i32>, Option<i32>) {
+    match target {
+        ShareTarget::Location(kind, id) => {
+            let (weather, phenomenon) = self::target(kind, id);
+            (weather, phenomenon, None)
+        }
+        ShareTarget::Collection(id) => (None, None, Some(id.0)),
+    }
+}
+
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Postgres-backed implementation of `SharingServiceImpl`.
+pub struct SharingService {
+    /// SQLx Postgres connection pool.
+    pub db: PgPool,
+}
+
+impl SharingService {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+
+    /// `ApiError::Forbidden` unless `user_id` owns the location, `ApiError::NotFound` if they
+    /// cannot see it.
+    async fn require_owner(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+    ) -> Result<(), ApiError> {
+        location_access(&self.db, kind, user_id, id)
+            .await?
+            .require(Access::Owner)
+    }
+
+    /// Access of `user_id` to the location or collection `target`, `ApiError::NotFound` if
+    /// they have none.
+    async fn target_access(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+    ) -> Result<Access, ApiError> {
+        match target {
+            ShareTarget::Location(kind, id) => location_access(&self.db, kind, user_id, id).await,
+            ShareTarget::Collection(id) => COLLECTIONS.group_access(&self.db, user_id, id).await,
+        }
+    }
+
+    /// `require_owner` for a location or collection.
+    async fn require_target_owner(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+    ) -> Result<(), ApiError> {
+        match target {
+            ShareTarget::Location(kind, id) => self.require_owner(user_id, kind, id).await,
+            ShareTarget::Collection(id) => COLLECTIONS.require_group(&self.db, user_id, id).await,
+        }
+    }
+}
+
+#[async_trait]
+impl SharingServiceImpl for SharingService {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, target = ?target))]
+    async fn share(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+        request: &ShareLocationRequest,
+    ) -> Result<LocationShare, ApiError> {
+        self.require_target_owner(user_id, target).await?;
+
+        let email = request.email.trim();
+        let recipient =
+            sqlx::query_scalar!("SELECT id FROM users WHERE lower(email) = lower($1)", email,)
+                .fetch_optional(&self.db)
+                .await?;
+        if recipient == Some(user_id.0) {
+            return Err(ApiError::Validation(vec![FieldError::new(
+                "email",
+                "owner",
+                "is your own email address",
+            )]));
+        }
+
+        let (weather_location_id, natural_phenomenon_location_id, collection_id) =
+            share_target(target);
+        let share = sqlx::query_as!(
+            LocationShare,
+            r#"
+            INSERT INTO location_shares
+                (weather_location_id, natural_phenomenon_location_id, collection_id, user_id, email, permission)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (weather_location_id, natural_phenomenon_location_id, collection_id, lower(email))
+                DO UPDATE SET permission = excluded.permission,
+                              updated_at = CASE WHEN location_shares.permission = excluded.permission
+                                                THEN location_shares.updated_at ELSE now() END
+            RETURNING id, email, permission, created_at, updated_at
+            "#,
+            weather_location_id,
+            natural_phenomenon_location_id,
+            collection_id,
+            recipient,
+            email,
+            request.permission.as_str(),
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(share)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, target = ?target))]
+    async fn shares(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+    ) -> Result<Vec<LocationShare>, ApiError> {
+        self.require_target_owner(user_id, target).await?;
+
+        let (weather_location_id, natural_phenomenon_location_id, collection_id) =
+            share_target(target);
+        let shares = sqlx::query_as!(
+            LocationShare,
+            r#"
+            SELECT id, email, permission, created_at, updated_at
+            FROM location_shares
+            WHERE weather_location_id = $1 OR natural_phenomenon_location_id = $2 OR collection_id = $3
+            ORDER BY created_at, id
+            "#,
+            weather_location_id,
+            natural_phenomenon_location_id,
+            collection_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(shares)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, target = ?target, share_id = share_id.0))]
+    async fn unshare(
+        &self,
+        user_id: DatabaseId,
+        target: ShareTarget,
+        share_id: DatabaseId,
+    ) -> Result<(), ApiError> {
+        let access = self.target_access(user_id, target).await?;
+
+        // anyone else may only leave their own share
+        let recipient = (access != Access::Owner).then_some(user_id.0);
+        let (weather_location_id, natural_phenomenon_location_id, collection_id) =
+            share_target(target);
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM location_shares
+            WHERE id = $1
+              AND (weather_location_id = $2 OR natural_phenomenon_location_id = $3 OR collection_id = $4)
+              AND ($5::int4 IS NULL OR user_id = $5)
+            "#,
+            share_id.0,
+            weather_location_id,
+            natural_phenomenon_location_id,
+            collection_id,
+            recipient,
+        )
+        .execute(&self.db)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(ApiError::NotFound("Share not found".to_string()));
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn create_link(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+    ) -> Result<CreatedLocationLink, ApiError> {
+        self.require_owner(user_id, kind, id).await?;
+
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+        let (weather_location_id, natural_phenomenon_location_id) = target(kind, id);
+        let link = sqlx::query!(
+            r#"
+            INSERT INTO location_links (weather_location_id, natural_phenomenon_location_id, token_hash)
+            VALUES ($1, $2, $3)
+            RETURNING id, created_at
+            "#,
+            weather_location_id,
+            natural_phenomenon_location_id,
+            token_hash(&token),
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(CreatedLocationLink {
+            id: DatabaseId(link.id),
+            path: format!("{}/public/locations/{}", API_V1_PREFIX, token),
+            token,
+            created_at: link.created_at,
+        })
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn links(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+    ) -> Result<Vec<LocationLink>, ApiError> {
+        self.require_owner(user_id, kind, id).await?;
+
+        let (weather_location_id, natural_phenomenon_location_id) = target(kind, id);
+        let links = sqlx::query_as!(
+            LocationLink,
+            r#"
+            SELECT id, created_at
+            FROM location_links
+            WHERE weather_location_id = $1 OR natural_phenomenon_location_id = $2
+            ORDER BY created_at, id
+            "#,
+            weather_location_id,
+            natural_phenomenon_location_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(links)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0, link_id = link_id.0))]
+    async fn revoke_link(
+        &self,
+        user_id: DatabaseId,
+        kind: LocationKind,
+        id: DatabaseId,
+        link_id: DatabaseId,
+    ) -> Result<(), ApiError> {
+        self.require_owner(user_id, kind, id).await?;
+
+        let (weather_location_id, natural_phenomenon_location_id) = target(kind, id);
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM location_links
+            WHERE id = $1 AND (weather_location_id = $2 OR natural_phenomenon_location_id = $3)
+            "#,
+            link_id.0,
+            weather_location_id,
+            natural_phenomenon_location_id,
+        )
+        .execute(&self.db)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(ApiError::NotFound("Link not found".to_string()));
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn public_location(&self, token: &str) -> Result<PublicLocation, ApiError> {
+        let not_found = || ApiError::NotFound("Link not found".to_string());
+        let link = sqlx::query!(
+            "SELECT weather_location_id, natural_phenomenon_location_id
+             FROM location_links WHERE token_hash = $1",
+            token_hash(token),
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(not_found)?;
+
+        if let Some(id) = link.weather_location_id {
+            let location = sqlx::query_as!(
+                WeatherLocation,
+                "SELECT * FROM weather_locations WHERE id = $1 AND deleted_at IS NULL",
+                id
+            )
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(not_found)?;
+            return Ok(PublicLocation {
+                kind: LocationKind::Weather,
+                name: location.name,
+                latitude: location.latitude,
+                longitude: location.longitude,
+                description: location.description,
+                radius: None,
+                phenomenon_type: None,
+                area: None,
+            });
+        }
+
+        let id = link.natural_phenomenon_location_id.ok_or_else(not_found)?;
+        let location = sqlx::query_as!(
+            NaturalPhenomenonLocationDb,
+            "SELECT * FROM natural_phenomenon_locations WHERE id = $1 AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(not_found)?;
+        Ok(PublicLocation {
+            kind: LocationKind::NaturalPhenomenon,
+            name: location.name,
+            latitude: location.latitude,
+            longitude: location.longitude,
+            description: location.description,
+            radius: Some(location.radius),
+            phenomenon_type: Some(location.phenomenon_type),
+            area: location.area,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::auth::models::RegisterUserRequestSchema;
+    use crate::routes::auth::services::{AuthService, AuthServiceImpl};
+    use crate::routes::collections::models::{CreateCollectionRequest, UpdateCollectionRequest};
+    use crate::routes::collections::services::{CollectionService, CollectionServiceImpl};
+    use crate::routes::natural_phenomenon_locations::models::NaturalPhenomenonLocationListQuery;
+    use crate::routes::natural_phenomenon_locations::models::UpdateNaturalPhenomenonLocationRequestWithIds;
+    use crate::routes::natural_phenomenon_locations::services::{
+        NaturalPhenomenonLocationService, NaturalPhenomenonLocationServiceImpl,
+    };
+    use crate::routes::sharing::models::SharePermission;
+    use crate::routes::weather_locations::models::UpdateWeatherLocationRequest;
+    use crate::routes::weather_locations::services::{
+        WeatherLocationService, WeatherLocationServiceImpl,
+    };
+    use crate::shared::memberships::LocationIds;
+    use crate::shared::preconditions::IfMatch;
+    use crate::tests::tests::TestApp;
+
+    #[sqlx::test]
+    async fn test_sharing(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let owner = test_app.users[0].user.id;
+        let friend: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('friend@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let bern: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO weather_locations (user_id, name, latitude, longitude) VALUES ($1, 'Bern', 46.95, 7.45) RETURNING id",
+        )
+        .bind(owner.0)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let etna: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+             VALUES ($1, 'Etna', 37.75, 14.99, 10, NULL) RETURNING id",
+        )
+        .bind(owner.0)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let service = SharingService::new(pool.clone());
+        let weather = WeatherLocationService { db: pool.clone() };
+        let phenomena = NaturalPhenomenonLocationService::new(pool.clone());
+        let request = |email: &str, permission| ShareLocationRequest {
+            email: email.to_string(),
+            permission,
+        };
+
+        // locations are not shared with their owner, nor by other users
+        let err = service
+            .share(
+                owner,
+                ShareTarget::Location(LocationKind::Weather, bern),
+                &request(&test_app.users[0].user.email, SharePermission::Read),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Validation(_)));
+        let err = service
+            .share(
+                friend,
+                ShareTarget::Location(LocationKind::Weather, bern),
+                &request("test1@wap.com", SharePermission::Read),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        assert!(weather
+            .get_all(&friend, &Default::default())
+            .await
+            .unwrap()
+            .items
+            .is_empty());
+
+        // shared read-only, the location is listed but cannot be changed
+        let share = service
+            .share(
+                owner,
+                ShareTarget::Location(LocationKind::Weather, bern),
+                &request("Friend@wap.com", SharePermission::Read),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            (share.email.as_str(), share.permission),
+            ("Friend@wap.com", SharePermission::Read)
+        );
+        let listed = weather
+            .get_all(&friend, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].item.id, listed[0].access), (bern, Access::Read));
+        let own = weather.get_by_id(&owner, &bern).await.unwrap();
+        assert_eq!(own.access, Access::Owner);
+        let rename = UpdateWeatherLocationRequest {
+            name: Some("Berne".to_string()),
+            is_default: Some(true),
+            ..Default::default()
+        };
+        let err = weather
+            .update(&friend, &bern, &rename, &IfMatch::Absent)
+            .await;
+        assert!(matches!(
+            ApiError::from(err.unwrap_err()),
+            ApiError::Forbidden(_)
+        ));
+
+        // sharing again to edit changes the permission; the default stays the owner's
+        let again = service
+            .share(
+                owner,
+                ShareTarget::Location(LocationKind::Weather, bern),
+                &request("friend@wap.com", SharePermission::Edit),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            (again.id, again.permission),
+            (share.id, SharePermission::Edit)
+        );
+        let renamed = weather
+            .update(&friend, &bern, &rename, &IfMatch::Absent)
+            .await
+            .unwrap();
+        assert_eq!(
+            (renamed.name.as_str(), renamed.is_default),
+            ("Berne", false)
+        );
+        let err = weather.delete(&friend, &bern, &IfMatch::Absent).await;
+        assert!(matches!(
+            ApiError::from(err.unwrap_err()),
+            ApiError::Forbidden(_)
+        ));
+        let err = service
+            .shares(friend, ShareTarget::Location(LocationKind::Weather, bern))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        assert_eq!(
+            service
+                .shares(owner, ShareTarget::Location(LocationKind::Weather, bern))
+                .await
+                .unwrap(),
+            vec![again.clone()]
+        );
+
+        // the user it is shared with can leave the share
+        service
+            .unshare(
+                friend,
+                ShareTarget::Location(LocationKind::Weather, bern),
+                again.id,
+            )
+            .await
+            .unwrap();
+        let err = weather.get_by_id(&friend, &bern).await.unwrap_err();
+        assert!(matches!(ApiError::from(err), ApiError::NotFound(_)));
+
+        // an address without an account is answered alike, the share waits for its sign-up
+        let pending = service
+            .share(
+                owner,
+                ShareTarget::Location(LocationKind::Weather, bern),
+                &request("Nobody@wap.com", SharePermission::Read),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            (pending.email.as_str(), pending.permission),
+            ("Nobody@wap.com", SharePermission::Read)
+        );
+        assert_eq!(
+            service
+                .shares(owner, ShareTarget::Location(LocationKind::Weather, bern))
+                .await
+                .unwrap(),
+            vec![pending]
+        );
+        let nobody = AuthService::new(pool.clone(), &test_app.app.settings)
+            .register_new_user(&RegisterUserRequestSchema {
+                email: "nobody@wap.com".to_string(),
+                password: "password123".to_string(),
+            })
+            .await
+            .unwrap();
+        let listed = weather
+            .get_all(&nobody.id, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!((listed[0].item.id, listed[0].access), (bern, Access::Read));
+
+        // phenomenon locations, shared to edit
+        service
+            .share(
+                owner,
+                ShareTarget::Location(LocationKind::NaturalPhenomenon, etna),
+                &request("friend@wap.com", SharePermission::Edit),
+            )
+            .await
+            .unwrap();
+        let listed = phenomena
+            .get_all(friend, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!((listed[0].item.id, listed[0].access), (etna, Access::Edit));
+        let update: UpdateNaturalPhenomenonLocationRequestWithIds = serde_json::from_value(
+            serde_json::json!({"id": etna.0, "user_id": friend.0, "payload": {"radius": 20}}),
+        )
+        .unwrap();
+        let updated = phenomena.update(update, &IfMatch::Versions(vec![0])).await;
+        assert!(matches!(
+            updated.unwrap_err(),
+            ApiError::PreconditionFailed(_)
+        ));
+        let update = serde_json::from_value(
+            serde_json::json!({"id": etna.0, "user_id": friend.0, "payload": {"radius": 20}}),
+        )
+        .unwrap();
+        assert_eq!(
+            phenomena
+                .update(update, &IfMatch::Absent)
+                .await
+                .unwrap()
+                .radius,
+            20
+        );
+        let err = phenomena
+            .delete(friend, etna, &IfMatch::Absent)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+
+        // public links work until revoked, only a hash of the token is stored
+        let link = service
+            .create_link(owner, LocationKind::NaturalPhenomenon, etna)
+            .await
+            .unwrap();
+        assert!(link.path.ends_with(&link.token));
+        assert_eq!(link.token.len(), 43);
+        let err = service
+            .create_link(friend, LocationKind::NaturalPhenomenon, etna)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        let public = service.public_location(&link.token).await.unwrap();
+        assert_eq!(
+            (public.kind, public.name.as_str(), public.radius),
+            (LocationKind::NaturalPhenomenon, "Etna", Some(20))
+        );
+        let stored: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM location_links WHERE token_hash = $1")
+                .bind(&link.token)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, 0);
+        assert_eq!(
+            service
+                .links(owner, LocationKind::NaturalPhenomenon, etna)
+                .await
+                .unwrap()[0]
+                .id,
+            link.id
+        );
+        service
+            .revoke_link(owner, LocationKind::NaturalPhenomenon, etna, link.id)
+            .await
+            .unwrap();
+        let err = service.public_location(&link.token).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+    }
+
+    #[sqlx::test]
+    async fn test_sharing_collections(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let owner = test_app.users[0].user.id;
+        let friend: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('friend@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let mut sites = Vec::new();
+        for (user, name) in [(owner, "Etna"), (owner, "Stromboli"), (friend, "Vesuvius")] {
+            let id: DatabaseId = sqlx::query_scalar(
+                "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+                 VALUES ($1, $2, 38.0, 15.0, 10, NULL) RETURNING id",
+            )
+            .bind(user.0)
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            sites.push(id);
+        }
+        let service = SharingService::new(pool.clone());
+        let collections = CollectionService::new(pool.clone());
+        let phenomena = NaturalPhenomenonLocationService::new(pool.clone());
+        let sicily = collections
+            .create(
+                owner,
+                &CreateCollectionRequest {
+                    name: "Sicily".to_string(),
+                    description: String::new(),
+                },
+            )
+            .await
+            .unwrap();
+        collections
+            .add_locations(
+                owner,
+                sicily.id,
+                &LocationIds {
+                    natural_phenomenon_location_ids: sites[..1].to_vec(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let request = |permission| ShareLocationRequest {
+            email: "friend@wap.com".to_string(),
+            permission,
+        };
+
+        // only the owner shares the collection
+        let err = service
+            .share(
+                friend,
+                ShareTarget::Collection(sicily.id),
+                &request(SharePermission::Read),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+
+        // shared read-only, the collection and its locations are seen but not changed
+        let share = service
+            .share(
+                owner,
+                ShareTarget::Collection(sicily.id),
+                &request(SharePermission::Read),
+            )
+            .await
+            .unwrap();
+        let listed = collections.get_all(friend).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            (listed[0].item.id, listed[0].access),
+            (sicily.id, Access::Read)
+        );
+        let err = collections
+            .update(
+                friend,
+                sicily.id,
+                &UpdateCollectionRequest {
+                    name: Some("Sicilia".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        let err = service
+            .shares(friend, ShareTarget::Collection(sicily.id))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+
+        // locations added later are shared too, the list of the collection has them
+        collections
+            .add_locations(
+                owner,
+                sicily.id,
+                &LocationIds {
+                    natural_phenomenon_location_ids: sites[1..2].to_vec(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let members = collections.locations(friend, sicily.id).await.unwrap();
+        assert_eq!(members.natural_phenomenon_location_ids, sites[..2]);
+        let query = NaturalPhenomenonLocationListQuery {
+            collection: Some(sicily.id),
+            ..Default::default()
+        };
+        let listed = phenomena.get_all(friend, &query).await.unwrap().items;
+        assert_eq!(
+            listed
+                .iter()
+                .map(|l| (l.item.id, l.access))
+                .collect::<Vec<_>>(),
+            vec![(sites[0], Access::Read), (sites[1], Access::Read)]
+        );
+        let all = phenomena
+            .get_all(friend, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(all.len(), 3);
+
+        // shared to edit, the locations can be changed; leaving the share hides them again
+        service
+            .share(
+                owner,
+                ShareTarget::Collection(sicily.id),
+                &request(SharePermission::Edit),
+            )
+            .await
+            .unwrap();
+        let listed = phenomena.get_all(friend, &query).await.unwrap().items;
+        assert!(listed.iter().all(|l| l.access == Access::Edit));
+        service
+            .unshare(friend, ShareTarget::Collection(sicily.id), share.id)
+            .await
+            .unwrap();
+        let err = collections.get_by_id(friend, sicily.id).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let all = phenomena
+            .get_all(friend, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(
+            all.iter().map(|l| l.item.id).collect::<Vec<_>>(),
+            sites[2..]
+        );
+    }
+}
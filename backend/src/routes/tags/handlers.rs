@@ -0,0 +1,251 @@
+use crate::routes::auth::middlewares::auth;
+use crate::routes::auth::models::UserDb;
+use crate::routes::auth::services::AuthService;
+use crate::routes::tags::models::{SaveTagRequest, Tag};
+use crate::routes::tags::services::{TagService, TagServiceImpl};
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::memberships::LocationIds;
+use crate::shared::models::{AppState, DatabaseId};
+use crate::shared::validation::ValidatedJson;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use std::sync::Arc;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+/// List the tags of the current user, by name.
+#[utoipa::path(
+    get,
+    path = "/tags",
+    responses(
+        (status = 200, description = "Tags of the user", body = [Tag]),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_all_tags<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+) -> Result<Json<Vec<Tag>>, ApiError>
+where
+    S: TagServiceImpl,
+{
+    let tags = service.get_all(user.id).await?;
+    Ok(Json(tags))
+}
+
+/// Create a tag for the current user.
+#[utoipa::path(
+    post,
+    path = "/tags",
+    request_body = SaveTagRequest,
+    responses(
+        (status = 201, description = "Tag created", body = Tag),
+        (status = 409, description = "The user has a tag with this name", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn create_tag<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedJson(request): ValidatedJson<SaveTagRequest>,
+) -> Result<(StatusCode, Json<Tag>), ApiError>
+where
+    S: TagServiceImpl,
+{
+    let tag = service.create(user.id, &request).await?;
+    Ok((StatusCode::CREATED, Json(tag)))
+}
+
+/// Fetch a tag of the current user.
+#[utoipa::path(
+    get,
+    path = "/tags/{id}",
+    responses(
+        (status = 200, description = "Tag found", body = Tag),
+        (status = 404, description = "Tag not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Tag ID")
+    )
+)]
+pub async fn get_tag_by_id<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<Json<Tag>, ApiError>
+where
+    S: TagServiceImpl,
+{
+    let tag = service.get_by_id(user.id, id).await?;
+    Ok(Json(tag))
+}
+
+/// Rename a tag of the current user.
+#[utoipa::path(
+    patch,
+    path = "/tags/{id}",
+    request_body = SaveTagRequest,
+    responses(
+        (status = 200, description = "Tag renamed", body = Tag),
+        (status = 404, description = "Tag not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The user has another tag with this name", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Tag ID")
+    )
+)]
+pub async fn update_tag<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<SaveTagRequest>,
+) -> Result<Json<Tag>, ApiError>
+where
+    S: TagServiceImpl,
+{
+    let tag = service.update(user.id, id, &request).await?;
+    Ok(Json(tag))
+}
+
+/// Delete a tag of the current user; its locations are kept.
+#[utoipa::path(
+    delete,
+    path = "/tags/{id}",
+    responses(
+        (status = 204, description = "Tag deleted"),
+        (status = 404, description = "Tag not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Tag ID")
+    )
+)]
+pub async fn delete_tag<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<StatusCode, ApiError>
+where
+    S: TagServiceImpl,
+{
+    service.delete(user.id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the locations a tag of the current user is on.
+#[utoipa::path(
+    get,
+    path = "/tags/{id}/locations",
+    responses(
+        (status = 200, description = "IDs of the tagged locations", body = LocationIds),
+        (status = 404, description = "Tag not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Tag ID")
+    )
+)]
+pub async fn get_tag_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<Json<LocationIds>, ApiError>
+where
+    S: TagServiceImpl,
+{
+    let ids = service.locations(user.id, id).await?;
+    Ok(Json(ids))
+}
+
+/// Put a tag of the current user on many locations at once.
+///
+/// Locations already tagged are skipped; if any location is not found, none is tagged.
+#[utoipa::path(
+    post,
+    path = "/tags/{id}/locations/add",
+    request_body = LocationIds,
+    responses(
+        (status = 200, description = "IDs of all the tagged locations", body = LocationIds),
+        (status = 404, description = "Tag not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Unknown locations or too many at once", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Tag ID")
+    )
+)]
+pub async fn add_tag_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<LocationIds>,
+) -> Result<Json<LocationIds>, ApiError>
+where
+    S: TagServiceImpl,
+{
+    let ids = service.add_locations(user.id, id, &request).await?;
+    Ok(Json(ids))
+}
+
+/// Take a tag of the current user off many locations at once.
+#[utoipa::path(
+    post,
+    path = "/tags/{id}/locations/remove",
+    request_body = LocationIds,
+    responses(
+        (status = 200, description = "IDs of the locations still tagged", body = LocationIds),
+        (status = 404, description = "Tag not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Too many locations at once", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Tag ID")
+    )
+)]
+pub async fn remove_tag_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    ValidatedJson(request): ValidatedJson<LocationIds>,
+) -> Result<Json<LocationIds>, ApiError>
+where
+    S: TagServiceImpl,
+{
+    let ids = service.remove_locations(user.id, id, &request).await?;
+    Ok(Json(ids))
+}
+
+/// Generic router allowing injection of any implementation of the tag service
+pub fn router_with_service<S>(app: AppState, service: Arc<S>) -> OpenApiRouter
+where
+    S: TagServiceImpl,
+{
+    let auth_service = Arc::new(AuthService {
+        db: app.db.clone(),
+        settings: app.settings.clone(),
+        http: Default::default(),
+    });
+
+    OpenApiRouter::new()
+        .routes(routes!(get_all_tags))
+        .routes(routes!(create_tag))
+        .routes(routes!(get_tag_by_id))
+        .routes(routes!(update_tag))
+        .routes(routes!(delete_tag))
+        .routes(routes!(get_tag_locations))
+        .routes(routes!(add_tag_locations))
+        .routes(routes!(remove_tag_locations))
+        .layer(axum::middleware::from_fn_with_state(auth_service, auth))
+        .with_state(service)
+}
+
+/// Convenience router using the Postgres-backed implementation
+pub fn router(app: AppState) -> OpenApiRouter {
+    let service = Arc::new(TagService::new(app.db.clone()));
+    router_with_service(app, service)
+}
@@ -0,0 +1,29 @@
+use crate::shared::models::DatabaseId;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+/// A label of the current user, put on any location they see.
+#[derive(Debug, Clone, Serialize, ToSchema, PartialEq)]
+pub struct Tag {
+    /// ID of the tag.
+    pub id: DatabaseId,
+
+    /// Name of the tag, unique for the user ignoring case.
+    pub name: String,
+
+    /// When the tag was created.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+
+    /// When the tag was last renamed.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Payload to create or rename a tag.
+#[derive(Debug, Clone, Deserialize, ToSchema, Validate)]
+pub struct SaveTagRequest {
+    /// Name of the tag.
+    #[validate(length(min = 1, max = 50))]
+    #[schema(min_length = 1, max_length = 50)]
+    pub name: String,
+}
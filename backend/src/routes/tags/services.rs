@@ -0,0 +1,350 @@
+use crate::routes::tags::models::{SaveTagRequest, Tag};
+use crate::shared::error::ApiError;
+use crate::shared::memberships::{LocationIds, TAGS};
+use crate::shared::models::DatabaseId;
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+/// Manages the tags of a user and the locations they are put on.
+///
+/// Tags are private to their user; they can be put on the user's own locations and on
+/// those shared with them.
+#[async_trait]
+pub trait TagServiceImpl: Send + Sync + 'static {
+    /// The tags of `user_id`, by name.
+    async fn get_all(&self, user_id: DatabaseId) -> Result<Vec<Tag>, ApiError>;
+
+    /// Create a tag; `ApiError::AlreadyExists` if the user has one with the same name.
+    async fn create(&self, user_id: DatabaseId, request: &SaveTagRequest) -> Result<Tag, ApiError>;
+
+    /// The tag `id` of `user_id`, or `ApiError::NotFound`.
+    async fn get_by_id(&self, user_id: DatabaseId, id: DatabaseId) -> Result<Tag, ApiError>;
+
+    /// Rename the tag; `ApiError::AlreadyExists` if the user has another with that name.
+    async fn update(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        request: &SaveTagRequest,
+    ) -> Result<Tag, ApiError>;
+
+    /// Delete the tag, taking it off its locations.
+    async fn delete(&self, user_id: DatabaseId, id: DatabaseId) -> Result<(), ApiError>;
+
+    /// The locations the tag is on.
+    async fn locations(&self, user_id: DatabaseId, id: DatabaseId)
+        -> Result<LocationIds, ApiError>;
+
+    /// Put the tag on the locations; returns all locations it is on.
+    async fn add_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError>;
+
+    /// Take the tag off the locations; returns all locations it is still on.
+    async fn remove_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError>;
+}
+
+/// Postgres-backed implementation of `TagServiceImpl`.
+pub struct TagService {
+    /// SQLx Postgres connection pool.
+    pub db: PgPool,
+}
+
+impl TagService {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+/// A clearer conflict than the generic one for a name taken by another tag.
+fn name_taken(e: sqlx::Error) -> ApiError {
+    match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => {
+            ApiError::AlreadyExists("A tag with this name already exists".to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+#[async_trait]
+impl TagServiceImpl for TagService {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn get_all(&self, user_id: DatabaseId) -> Result<Vec<Tag>, ApiError> {
+        let tags = sqlx::query_as!(
+            Tag,
+            "SELECT id, name, created_at, updated_at FROM tags
+             WHERE user_id = $1 ORDER BY lower(name), id",
+            user_id.0
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(tags)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn create(&self, user_id: DatabaseId, request: &SaveTagRequest) -> Result<Tag, ApiError> {
+        let tag = sqlx::query_as!(
+            Tag,
+            "INSERT INTO tags (user_id, name) VALUES ($1, $2)
+             RETURNING id, name, created_at, updated_at",
+            user_id.0,
+            request.name.trim(),
+        )
+        .fetch_one(&self.db)
+        .await
+        .map_err(name_taken)?;
+        Ok(tag)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn get_by_id(&self, user_id: DatabaseId, id: DatabaseId) -> Result<Tag, ApiError> {
+        sqlx::query_as!(
+            Tag,
+            "SELECT id, name, created_at, updated_at FROM tags WHERE id = $1 AND user_id = $2",
+            id.0,
+            user_id.0
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Tag not found".to_string()))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn update(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        request: &SaveTagRequest,
+    ) -> Result<Tag, ApiError> {
+        sqlx::query_as!(
+            Tag,
+            r#"
+            UPDATE tags
+            SET name = $3::varchar,
+                updated_at = CASE WHEN name = $3 THEN updated_at ELSE now() END
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, name, created_at, updated_at
+            "#,
+            id.0,
+            user_id.0,
+            request.name.trim(),
+        )
+        .fetch_optional(&self.db)
+        .await
+        .map_err(name_taken)?
+        .ok_or_else(|| ApiError::NotFound("Tag not found".to_string()))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn delete(&self, user_id: DatabaseId, id: DatabaseId) -> Result<(), ApiError> {
+        let deleted = sqlx::query!(
+            "DELETE FROM tags WHERE id = $1 AND user_id = $2",
+            id.0,
+            user_id.0
+        )
+        .execute(&self.db)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(ApiError::NotFound("Tag not found".to_string()));
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+    ) -> Result<LocationIds, ApiError> {
+        TAGS.members(&self.db, user_id, id).await
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn add_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError> {
+        TAGS.add(&self.db, user_id, id, ids).await
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn remove_locations(
+        &self,
+        user_id: DatabaseId,
+        id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError> {
+        TAGS.remove(&self.db, user_id, id, ids).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::natural_phenomenon_locations::models::NaturalPhenomenonLocationListQuery;
+    use crate::routes::natural_phenomenon_locations::services::{
+        NaturalPhenomenonLocationService, NaturalPhenomenonLocationServiceImpl,
+    };
+    use crate::routes::weather_locations::models::WeatherLocationListQuery;
+    use crate::routes::weather_locations::services::{
+        WeatherLocationService, WeatherLocationServiceImpl,
+    };
+    use crate::tests::tests::TestApp;
+
+    #[sqlx::test]
+    async fn test_tags(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user = test_app.users[0].user.id;
+        let other: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('other@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let mut weather_ids = Vec::new();
+        for (owner, name) in [(user, "Bern"), (user, "Thun"), (other, "Sion")] {
+            let id: DatabaseId = sqlx::query_scalar(
+                "INSERT INTO weather_locations (user_id, name, latitude, longitude) VALUES ($1, $2, 46.9, 7.4) RETURNING id",
+            )
+            .bind(owner.0)
+            .bind(name)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            weather_ids.push(id);
+        }
+        let (bern, thun, sion) = (weather_ids[0], weather_ids[1], weather_ids[2]);
+        let etna: DatabaseId = sqlx::query_scalar(
+            "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+             VALUES ($1, 'Etna', 37.75, 14.99, 10, NULL) RETURNING id",
+        )
+        .bind(user.0)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let service = TagService::new(pool.clone());
+        let weather = WeatherLocationService { db: pool.clone() };
+        let phenomena = NaturalPhenomenonLocationService::new(pool.clone());
+        let save = |name: &str| SaveTagRequest {
+            name: name.to_string(),
+        };
+
+        // names are unique per user, ignoring case
+        let favorite = service.create(user, &save("Favorite")).await.unwrap();
+        let volcano = service.create(user, &save("volcano")).await.unwrap();
+        let err = service.create(user, &save("FAVORITE")).await.unwrap_err();
+        assert!(matches!(err, ApiError::AlreadyExists(_)));
+        service.create(other, &save("favorite")).await.unwrap();
+        let err = service
+            .update(user, volcano.id, &save("favorite"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::AlreadyExists(_)));
+        let names: Vec<_> = service
+            .get_all(user)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, ["Favorite", "volcano"]);
+
+        // only locations the user sees can be tagged, and then all or none
+        let ids = |weather: &[DatabaseId], phenomena: &[DatabaseId]| LocationIds {
+            weather_location_ids: weather.to_vec(),
+            natural_phenomenon_location_ids: phenomena.to_vec(),
+        };
+        let err = service
+            .add_locations(user, favorite.id, &ids(&[bern, sion], &[etna]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Validation(_)));
+        assert_eq!(
+            service.locations(user, favorite.id).await.unwrap(),
+            LocationIds::default()
+        );
+        let err = service
+            .add_locations(other, favorite.id, &ids(&[sion], &[]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+
+        // adding twice is harmless
+        service
+            .add_locations(user, favorite.id, &ids(&[bern], &[etna]))
+            .await
+            .unwrap();
+        let tagged = service
+            .add_locations(user, favorite.id, &ids(&[thun, bern], &[]))
+            .await
+            .unwrap();
+        assert_eq!(tagged, ids(&[bern, thun], &[etna]));
+        let tagged = service
+            .remove_locations(user, favorite.id, &ids(&[thun, sion], &[]))
+            .await
+            .unwrap();
+        assert_eq!(tagged, ids(&[bern], &[etna]));
+
+        // the lists filter by tag, only with the user's own tags
+        let listed = weather
+            .get_all(
+                &user,
+                &WeatherLocationListQuery {
+                    tag: Some(favorite.id),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(listed.iter().map(|l| l.item.id).collect::<Vec<_>>(), [bern]);
+        let listed = phenomena
+            .get_all(
+                user,
+                &NaturalPhenomenonLocationListQuery {
+                    tag: Some(volcano.id),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .items;
+        assert!(listed.is_empty());
+        let listed = weather
+            .get_all(
+                &other,
+                &WeatherLocationListQuery {
+                    tag: Some(favorite.id),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .items;
+        assert!(listed.is_empty());
+
+        // deleting the tag keeps the locations
+        service.delete(user, favorite.id).await.unwrap();
+        let err = service.get_by_id(user, favorite.id).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        assert_eq!(
+            weather
+                .get_all(&user, &Default::default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            2
+        );
+    }
+}
@@ -1,14 +1,17 @@
 use crate::routes::auth::middlewares::auth;
 use crate::routes::auth::services::AuthService;
-use crate::routes::uploads::models::UploadError;
+use crate::routes::uploads::models::{Photo, PhotoListQuery};
 use crate::routes::uploads::services::{UploadsService, UploadsServiceImpl};
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::http_cache::file_etag;
 use crate::shared::models::AppState;
+use crate::shared::pagination::Page;
+use crate::shared::validation::ValidatedQuery;
 use axum::http::header;
 use axum::{
-    extract::{Path, State},
+    extract::{OriginalUri, Path, State},
     http::StatusCode,
-    response::IntoResponse,
-    Json,
+    response::{IntoResponse, Response},
 };
 use bytes::Bytes;
 use mime_guess::MimeGuess;
@@ -17,28 +20,28 @@ use tokio::fs;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 
-/// Fetch all uploaded photos for the current user.
+/// Fetch a page of uploaded photos for the current user.
 #[utoipa::path(
     get,
     path = "/uploads",
+    params(PhotoListQuery),
     responses(
-        (status = 200, description = "All user locations"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "One page of photos", body = Page<Photo>,
+            headers(("Link" = String, description = "Links to the `first` and `next` page"))),
+        (status = 422, description = "Invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn list_photos<S>(
     State(service): State<Arc<S>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<UploadError>)>
+    OriginalUri(uri): OriginalUri,
+    ValidatedQuery(query): ValidatedQuery<PhotoListQuery>,
+) -> Result<Response, ApiError>
 where
     S: UploadsServiceImpl + Send + Sync + 'static,
 {
-    match service.list_photos().await {
-        Ok(photos) => Ok((StatusCode::OK, Json(photos))),
-        Err(_) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(UploadError::NotFound),
-        )),
-    }
+    let page = service.list_photos(&query).await?;
+    Ok(page.into_response_with_links(&uri))
 }
 
 /// Fetch a single uploaded photo by its filename.
@@ -46,31 +49,36 @@ where
     get,
     path = "/uploads/{filename}",
     responses(
-        (status = 200, description = "All user locations"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Photo content",
+            headers(("ETag" = String, description = "Validator for `If-None-Match`"))),
+        (status = 304, description = "Photo not modified since the `If-None-Match` ETag"),
+        (status = 404, description = "File not found", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
-pub async fn get_photo(
-    Path(filename): Path<String>,
-) -> Result<impl IntoResponse, (StatusCode, Json<UploadError>)> {
+pub async fn get_photo(Path(filename): Path<String>) -> Result<impl IntoResponse, ApiError> {
     let path = format!("uploads/{}", filename);
 
     // Try to read the file from disk
     let data = fs::read(&path)
         .await
-        .map_err(|_| (StatusCode::NOT_FOUND, Json(UploadError::NotFound)))?;
+        .map_err(|_| ApiError::NotFound("File not found".to_string()))?;
 
     // Guess a MIME type from the extension
     let mime = MimeGuess::from_path(&path)
         .first_or_octet_stream()
         .to_string();
 
-    // Return a (status, headers, body) tuple
-    Ok((
+    let mut response = (
         StatusCode::OK,
         [(header::CONTENT_TYPE, mime)],
         Bytes::from(data),
-    ))
+    )
+        .into_response();
+    // stored photos are never modified, their size and mtime identify the content
+    if let Some(etag) = fs::metadata(&path).await.ok().as_ref().and_then(file_etag) {
+        response.headers_mut().insert(header::ETAG, etag);
+    }
+    Ok(response)
 }
 
 /// Generic router allowing injection of any implementation of the domain service
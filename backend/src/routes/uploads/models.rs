@@ -1,37 +1,60 @@
+use crate::shared::pagination::{SortOrder, MAX_PAGE_LIMIT};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
 /// A single uploaded photo entry.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Photo {
     /// The bare filename (e.g. "uuid_pic.png")
     pub filename: String,
     /// The public URL under which it's served (e.g. "/uploads/uuid_pic.png")
     pub url: String,
+    /// When the file was stored
+    pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(tag = "type", content = "data")]
-pub enum UploadError {
-    /// The file already exists
-    AlreadyExists,
-    /// The file is too large
-    TooLarge,
-    /// The file is not an image
-    NotAnImage,
-    /// The file could not be saved
-    SaveFailed,
-    NotFound,
+/// Sort of the photo list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PhotoSort {
+    #[default]
+    Name,
+    CreatedAt,
 }
 
-impl Display for UploadError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl PhotoSort {
+    pub fn key(self) -> &'static str {
         match self {
-            UploadError::AlreadyExists => write!(f, "File already exists"),
-            UploadError::TooLarge => write!(f, "File is too large"),
-            UploadError::NotAnImage => write!(f, "File is not an image"),
-            UploadError::SaveFailed => write!(f, "Failed to save file"),
-            UploadError::NotFound => write!(f, "File not found"),
+            PhotoSort::Name => "name",
+            PhotoSort::CreatedAt => "created_at",
         }
     }
 }
+
+/// Query parameters for listing photos.
+#[derive(Debug, Default, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct PhotoListQuery {
+    /// Page size (default 50).
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT))]
+    #[param(minimum = 1, maximum = 200)]
+    pub limit: Option<u32>,
+
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+
+    /// Sort field (default `name`).
+    #[serde(default)]
+    #[param(inline)]
+    pub sort: PhotoSort,
+
+    /// Sort direction (default `asc`).
+    #[serde(default)]
+    #[param(inline)]
+    pub order: SortOrder,
+
+    /// Only filenames containing this text, ignoring case.
+    #[validate(length(min = 1, max = 255))]
+    pub name: Option<String>,
+}
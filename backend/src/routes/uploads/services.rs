@@ -1,7 +1,8 @@
-use crate::routes::uploads::models::Photo;
+use crate::routes::uploads::models::{Photo, PhotoListQuery, PhotoSort};
+use crate::shared::error::ApiError;
+use crate::shared::pagination::{Page, PageRequest};
 use async_trait::async_trait;
 use std::fs;
-use std::io;
 use std::path::PathBuf;
 
 /// Service for reading the `uploads/` folder.
@@ -18,8 +19,8 @@ pub trait UploadsServiceImpl: Send + Sync + 'static {
     /// Create a new service pointing at `uploads/` and URL prefix `/uploads`
     async fn new() -> Self;
 
-    /// Read `uploads/` and produce a list of `Photo` entries.
-    async fn list_photos(&self) -> io::Result<Vec<Photo>>;
+    /// Read `uploads/` and produce one page of `Photo` entries.
+    async fn list_photos(&self, query: &PhotoListQuery) -> Result<Page<Photo>, ApiError>;
 }
 
 #[async_trait]
@@ -32,8 +33,16 @@ impl UploadsServiceImpl for UploadsService {
         }
     }
 
-    /// Read `uploads/` and produce a list of `Photo` entries.
-    async fn list_photos(&self) -> io::Result<Vec<Photo>> {
+    /// Read `uploads/` and produce one page of `Photo` entries.
+    async fn list_photos(&self, query: &PhotoListQuery) -> Result<Page<Photo>, ApiError> {
+        let page = PageRequest::new(
+            query.limit,
+            query.cursor.as_deref(),
+            query.sort.key(),
+            query.order,
+        )?;
+        let name = query.name.as_deref().map(str::to_lowercase);
+
         let mut photos = Vec::new();
         // Ensure directory exists
         if !self.directory.exists() {
@@ -44,10 +53,30 @@ impl UploadsServiceImpl for UploadsService {
             let file_type = entry.file_type()?;
             if file_type.is_file() {
                 let filename = entry.file_name().to_string_lossy().to_string();
+                if let Some(name) = &name {
+                    if !filename.to_lowercase().contains(name) {
+                        continue;
+                    }
+                }
+                let metadata = entry.metadata()?;
+                let created_at = metadata.created().or_else(|_| metadata.modified())?;
                 let url = format!("{}/{}", self.url_prefix, filename);
-                photos.push(Photo { filename, url });
+                photos.push(Photo {
+                    filename,
+                    url,
+                    created_at: created_at.into(),
+                });
             }
         }
-        Ok(photos)
+
+        // filenames are unique, so they also break ties between equal timestamps
+        Ok(page.paginate(photos, |photo| match query.sort {
+            PhotoSort::Name => photo.filename.clone(),
+            PhotoSort::CreatedAt => format!(
+                "{:020}|{}",
+                photo.created_at.timestamp_micros(),
+                photo.filename
+            ),
+        }))
     }
 }
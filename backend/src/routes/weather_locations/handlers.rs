@@ -1,49 +1,88 @@
 use crate::routes::auth::middlewares::auth;
 use crate::routes::auth::models::UserDb;
 use crate::routes::auth::services::AuthService;
-use crate::routes::weather_locations::models::{CreateWeatherLocationRequest, WeatherLocation};
+use crate::routes::sharing::models::WithAccess;
+use crate::routes::weather_locations::models::{
+    CreateWeatherLocationRequest, ReorderWeatherLocationsRequest, ReplaceWeatherLocationRequest,
+    UpdateWeatherLocationRequest, WeatherLocation, WeatherLocationListQuery,
+};
 use crate::routes::weather_locations::services::{
     WeatherLocationService, WeatherLocationServiceImpl,
 };
+use crate::shared::error::{ApiError, ProblemDetails};
+use crate::shared::geo::{Nearby, NearbyQuery};
+use crate::shared::idempotency::{idempotent, Idempotency};
 use crate::shared::models::{AppState, DatabaseId};
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use crate::shared::pagination::Page;
+use crate::shared::preconditions::{precondition_failed, require_if_match, version_etag, IfMatch};
+use crate::shared::validation::{ValidatedJson, ValidatedQuery};
+use axum::extract::{OriginalUri, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
 use std::sync::Arc;
 use utoipa::ToSchema;
+use utoipa_axum::router::UtoipaMethodRouterExt;
 use utoipa_axum::routes;
 
-/// Fetch all weather‐report locations for the current user.
+/// Fetch a page of the weather‐report locations of the current user and of those shared with
+/// them.
 #[utoipa::path(
     get,
     path = "/weather_locations",
+    params(WeatherLocationListQuery),
     responses(
-        (status = 200, description = "All user locations", body = Vec<WeatherLocation>, content_type = "application/json"),
-        (status = 500, description = "Internal server error", content_type = "application/json")
+        (status = 200, description = "One page of user locations", body = Page<WithAccess<WeatherLocation>>, content_type = "application/json",
+            headers(("Link" = String, description = "Links to the `first` and `next` page"))),
+        (status = 422, description = "Invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_all_locations<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-) -> anyhow::Result<Json<Vec<WeatherLocation>>, (StatusCode, String)>
+    OriginalUri(uri): OriginalUri,
+    ValidatedQuery(query): ValidatedQuery<WeatherLocationListQuery>,
+) -> Result<Response, ApiError>
 where
     S: WeatherLocationServiceImpl,
 {
-    let locations = service
-        .get_all(&user.id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let page = service.get_all(&user.id, &query).await?;
+    Ok(page.into_response_with_links(&uri))
+}
+
+/// Fetch the weather‐report locations of the current user nearest to a point.
+#[utoipa::path(
+    get,
+    path = "/weather_locations/nearby",
+    params(NearbyQuery),
+    responses(
+        (status = 200, description = "Locations with their distance, closest first", body = [Nearby<WeatherLocation>]),
+        (status = 422, description = "Invalid query", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_nearby_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedQuery(query): ValidatedQuery<NearbyQuery>,
+) -> Result<Json<Vec<Nearby<WeatherLocation>>>, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    let locations = service.nearby(&user.id, &query).await?;
     Ok(Json(locations))
 }
 
-/// Fetch a single weather‐report location by its ID for the current user.
+/// Fetch a single weather‐report location by its ID, of the current user or shared with them.
 #[utoipa::path(
     get,
     path = "/weather_locations/{id}",
     responses(
-        (status = 200, description = "Location found", body = WeatherLocation),
-        (status = 404, description = "Location not found"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Location found", body = WithAccess<WeatherLocation>,
+            headers(("ETag" = String, description = "Version of the location, for `If-Match`"))),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     ),
     params(
         ("id" = i32, Path, description = "Location ID")
@@ -53,15 +92,15 @@ pub async fn get_location_by_id<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
     Path(id): Path<i32>,
-) -> anyhow::Result<Json<WeatherLocation>, (StatusCode, String)>
+) -> Result<impl IntoResponse, ApiError>
 where
     S: WeatherLocationServiceImpl,
 {
-    let location = service
-        .get_by_id(&user.id, &DatabaseId(id))
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    Ok(Json(location))
+    let location = service.get_by_id(&user.id, &DatabaseId(id)).await?;
+    Ok((
+        [(header::ETAG, version_etag(location.item.version))],
+        Json(location),
+    ))
 }
 
 /// Create a new weather‐report location for the current user.
@@ -69,16 +108,20 @@ where
     post,
     path = "/weather_locations",
     request_body = CreateWeatherLocationRequest,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Makes retries return the first response instead of creating a duplicate")),
     responses(
-        (status = 201, description = "Location created", body = WeatherLocation),
-        (status = 500, description = "Internal server error")
+        (status = 201, description = "Location created", body = WeatherLocation,
+            headers(("ETag" = String, description = "Version of the location, for `If-Match`"))),
+        (status = 409, description = "Idempotency-Key reused for a different or still running request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn create_location<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
-    Json(request): Json<CreateWeatherLocationRequest>,
-) -> anyhow::Result<Json<WeatherLocation>, (StatusCode, String)>
+    ValidatedJson(request): ValidatedJson<CreateWeatherLocationRequest>,
+) -> Result<impl IntoResponse, ApiError>
 where
     S: WeatherLocationServiceImpl,
 {
@@ -91,38 +134,293 @@ where
         description: request.description,
     };
 
-    let created_location = service
-        .create(&location)
+    let created_location = service.create(&location).await?;
+    Ok((
+        StatusCode::CREATED,
+        [(header::ETAG, version_etag(created_location.version))],
+        Json(created_location),
+    ))
+}
+
+/// Replace the name, coordinates, default flag and description of a weather‐report location.
+///
+/// Users the location is shared with to edit may replace it too; the default flag is then
+/// left as the owner set it.
+///
+/// With `If-Match` the update only applies to the given version; otherwise the current
+/// location is returned with a 412.
+#[utoipa::path(
+    put,
+    path = "/weather_locations/{id}",
+    request_body = ReplaceWeatherLocationRequest,
+    responses(
+        (status = 200, description = "Location updated", body = WeatherLocation,
+            headers(("ETag" = String, description = "New version of the location"))),
+        (status = 403, description = "Location is shared read-only", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Location was modified, the current one is returned", body = WithAccess<WeatherLocation>),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Location ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being updated")
+    )
+)]
+pub async fn replace_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    if_match: IfMatch,
+    ValidatedJson(request): ValidatedJson<ReplaceWeatherLocationRequest>,
+) -> Result<Response, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    apply_update(service.as_ref(), &user.id, &id, &request.into(), &if_match).await
+}
+
+/// Change some fields of a weather‐report location; the fields left out are kept.
+///
+/// Also allowed to users the location is shared with to edit, except for the default flag.
+///
+/// With `If-Match` the update only applies to the given version; otherwise the current
+/// location is returned with a 412.
+#[utoipa::path(
+    patch,
+    path = "/weather_locations/{id}",
+    request_body = UpdateWeatherLocationRequest,
+    responses(
+        (status = 200, description = "Location updated", body = WeatherLocation,
+            headers(("ETag" = String, description = "New version of the location"))),
+        (status = 403, description = "Location is shared read-only", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Location was modified, the current one is returned", body = WithAccess<WeatherLocation>),
+        (status = 422, description = "Validation failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "If-Match is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Location ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being updated")
+    )
+)]
+pub async fn update_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+    if_match: IfMatch,
+    ValidatedJson(request): ValidatedJson<UpdateWeatherLocationRequest>,
+) -> Result<Response, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    apply_update(service.as_ref(), &user.id, &id, &request, &if_match).await
+}
+
+/// Run an update, answering a stale `If-Match` with the current location.
+async fn apply_update<S>(
+    service: &S,
+    user_id: &DatabaseId,
+    id: &DatabaseId,
+    changes: &UpdateWeatherLocationRequest,
+    if_match: &IfMatch,
+) -> Result<Response, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    match service
+        .update(user_id, id, changes, if_match)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    Ok(Json(created_location))
+        .map_err(ApiError::from)
+    {
+        Ok(updated) => Ok((
+            [(header::ETAG, version_etag(updated.version))],
+            Json(updated),
+        )
+            .into_response()),
+        Err(ApiError::PreconditionFailed(_)) => {
+            let current = service.get_by_id(user_id, id).await?;
+            Ok(precondition_failed(&current, current.item.version))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Make a weather‐report location the default of the current user.
+///
+/// The previous default is unset in the same transaction.
+#[utoipa::path(
+    post,
+    path = "/weather_locations/{id}/default",
+    responses(
+        (status = 200, description = "Location is now the default", body = WeatherLocation,
+            headers(("ETag" = String, description = "Version of the location"))),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn set_default_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<impl IntoResponse, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    let location = service.set_default(&user.id, &id).await?;
+    Ok((
+        [(header::ETAG, version_etag(location.version))],
+        Json(location),
+    ))
+}
+
+/// Set the order of the weather‐report locations of the current user.
+///
+/// The body lists every location of the user once; the list is then sorted by `position`
+/// unless another sort is requested.
+#[utoipa::path(
+    post,
+    path = "/weather_locations/reorder",
+    request_body = ReorderWeatherLocationsRequest,
+    responses(
+        (status = 200, description = "All locations in their new order", body = [WeatherLocation]),
+        (status = 422, description = "The IDs are not exactly the user's locations", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn reorder_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    ValidatedJson(request): ValidatedJson<ReorderWeatherLocationsRequest>,
+) -> Result<Json<Vec<WeatherLocation>>, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    let locations = service.reorder(&user.id, &request.ids).await?;
+    Ok(Json(locations))
 }
 
-/// Delete a weather‐report location by its ID for the current user.
+/// Move a weather‐report location of the current user to the trash.
+///
+/// It can be restored until it is purged, after the retention or on request.
 #[utoipa::path(
     delete,
     path = "/weather_locations/{id}",
     responses(
-        (status = 204, description = "Location deleted", content_type = "application/json"),
-        (status = 404, description = "Location not found", content_type = "application/json"),
-        (status = 500, description = "Internal server error", content_type = "application/json")
+        (status = 204, description = "Location moved to the trash", content_type = "application/json"),
+        (status = 403, description = "Location is only shared with the user", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Location not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Location was modified, the current one is returned", body = WithAccess<WeatherLocation>),
+        (status = 428, description = "If-Match is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
     ),
     params(
-        ("id" = i32, Path, description = "Location ID")
+        ("id" = i32, Path, description = "Location ID"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version being deleted")
     )
 )]
 pub async fn delete_location<S>(
     State(service): State<Arc<S>>,
     Extension(user): Extension<UserDb>,
     Path(id): Path<DatabaseId>,
-) -> anyhow::Result<StatusCode, (StatusCode, String)>
+    if_match: IfMatch,
+) -> Result<Response, ApiError>
 where
     S: WeatherLocationServiceImpl,
 {
-    service
-        .delete(&user.id, &id)
+    match service
+        .delete(&user.id, &id, &if_match)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(ApiError::from)
+    {
+        Ok(()) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(ApiError::PreconditionFailed(_)) => {
+            let current = service.get_by_id(&user.id, &id).await?;
+            Ok(precondition_failed(&current, current.item.version))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// List the weather‐report locations of the current user in the trash, most recently deleted
+/// first.
+#[utoipa::path(
+    get,
+    path = "/weather_locations/trash",
+    responses(
+        (status = 200, description = "Deleted locations, with their `deleted_at`", body = [WeatherLocation]),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_trashed_locations<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+) -> Result<Json<Vec<WeatherLocation>>, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    let locations = service.trash(&user.id).await?;
+    Ok(Json(locations))
+}
+
+/// Restore a weather‐report location of the current user from the trash.
+///
+/// It goes to the end of the user's order and is not the default.
+#[utoipa::path(
+    post,
+    path = "/weather_locations/trash/{id}/restore",
+    responses(
+        (status = 200, description = "Location restored", body = WeatherLocation,
+            headers(("ETag" = String, description = "New version of the location"))),
+        (status = 404, description = "Location not in the trash", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn restore_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<impl IntoResponse, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    let location = service.restore(&user.id, &id).await?;
+    Ok((
+        [(header::ETAG, version_etag(location.version))],
+        Json(location),
+    ))
+}
+
+/// Permanently delete a weather‐report location of the current user from the trash.
+#[utoipa::path(
+    delete,
+    path = "/weather_locations/trash/{id}",
+    responses(
+        (status = 204, description = "Location purged"),
+        (status = 404, description = "Location not in the trash", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    params(
+        ("id" = i32, Path, description = "Location ID")
+    )
+)]
+pub async fn purge_location<S>(
+    State(service): State<Arc<S>>,
+    Extension(user): Extension<UserDb>,
+    Path(id): Path<DatabaseId>,
+) -> Result<StatusCode, ApiError>
+where
+    S: WeatherLocationServiceImpl,
+{
+    service.purge(&user.id, &id).await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -135,11 +433,32 @@ pub fn router(app: AppState) -> utoipa_axum::router::OpenApiRouter {
         http: Default::default(),
     });
 
+    let trash = utoipa_axum::router::OpenApiRouter::new()
+        .routes(routes!(get_trashed_locations))
+        .routes(routes!(restore_location))
+        .routes(routes!(purge_location));
+
     let router = utoipa_axum::router::OpenApiRouter::new()
         .routes(routes!(get_all_locations))
+        .routes(routes!(get_nearby_locations))
         .routes(routes!(get_location_by_id))
-        .routes(routes!(create_location))
+        .routes(
+            routes!(create_location).layer(axum::middleware::from_fn_with_state(
+                Idempotency::new(&app),
+                idempotent,
+            )),
+        )
+        .routes(routes!(replace_location))
+        .routes(routes!(update_location))
+        .routes(routes!(set_default_location))
+        .routes(routes!(reorder_locations))
         .routes(routes!(delete_location))
+        .layer(axum::middleware::from_fn_with_state(
+            app.settings.concurrency.clone(),
+            require_if_match,
+        ))
+        // a trashed location has no version worth sending back
+        .merge(trash)
         .layer(axum::middleware::from_fn_with_state(auth_service, auth))
         .with_state(weather_service);
 
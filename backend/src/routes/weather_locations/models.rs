@@ -1,9 +1,15 @@
+use crate::shared::error::ApiError;
+use crate::shared::geo::{BoundingBox, LocationFilter, LocationSort};
 use crate::shared::models::DatabaseId;
+use crate::shared::pagination::{SortOrder, MAX_PAGE_LIMIT};
+use crate::shared::validation::finite;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use sqlx::{Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
 /// A user’s saved weather‐report location.
-#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, sqlx::FromRow)]
 pub struct WeatherLocation {
     /// The unique ID of this location record.
     pub id: DatabaseId,
@@ -26,26 +32,151 @@ pub struct WeatherLocation {
     /// Optional user‐provided description or notes about this location.
     pub description: String,
 
+    /// Place in the user’s own order of their locations, from 0.
+    pub position: i32,
+
+    /// Incremented on every update, sent as the ETag.
+    pub version: i32,
+
     /// Timestamp when this record was first created.
     pub created_at: chrono::DateTime<chrono::Utc>,
 
     /// Timestamp when this record was last updated.
     pub updated_at: chrono::DateTime<chrono::Utc>,
+
+    /// When the location was moved to the trash; only set for locations in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Query parameters for listing weather locations.
+#[derive(Debug, Default, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct WeatherLocationListQuery {
+    /// Page size (default 50).
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT))]
+    #[param(minimum = 1, maximum = 200)]
+    pub limit: Option<u32>,
+
+    /// `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+
+    /// Sort field (default `position`); `distance` needs `near_lat` and `near_lon`.
+    #[serde(default)]
+    #[param(inline)]
+    pub sort: WeatherLocationSort,
+
+    /// Sort direction (default `asc`).
+    #[serde(default)]
+    #[param(inline)]
+    pub order: SortOrder,
+
+    /// Only names containing this text, ignoring case.
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+
+    /// Only locations created at or after this time.
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only locations created before this time.
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Only locations inside `min_lon,min_lat,max_lon,max_lat`.
+    #[param(value_type = Option<String>, example = "5.9,45.8,10.5,47.8")]
+    pub bbox: Option<BoundingBox>,
+
+    /// Only locations with this tag of the user.
+    #[param(value_type = Option<i32>)]
+    pub tag: Option<DatabaseId>,
+
+    /// Only locations in this collection of the user or shared with them.
+    #[param(value_type = Option<i32>)]
+    pub collection: Option<DatabaseId>,
+
+    /// Latitude of the reference point for `sort=distance`.
+    #[validate(range(min = -90.0, max = 90.0), custom(function = "finite"))]
+    pub near_lat: Option<f64>,
+
+    /// Longitude of the reference point for `sort=distance`.
+    #[validate(range(min = -180.0, max = 180.0), custom(function = "finite"))]
+    pub near_lon: Option<f64>,
+}
+
+impl WeatherLocationListQuery {
+    pub fn filter(&self) -> LocationFilter {
+        LocationFilter {
+            name: self.name.clone(),
+            created_after: self.created_after,
+            created_before: self.created_before,
+            bbox: self.bbox,
+        }
+    }
+}
+
+/// Sort of the weather location list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherLocationSort {
+    /// The user’s own order, see `POST /weather_locations/reorder`.
+    #[default]
+    Position,
+    Name,
+    CreatedAt,
+    /// Closest to `near_lat`/`near_lon` first.
+    Distance,
+}
+
+impl WeatherLocationSort {
+    pub fn key(self) -> &'static str {
+        match self {
+            WeatherLocationSort::Position => "position",
+            WeatherLocationSort::Name => LocationSort::Name.key(),
+            WeatherLocationSort::CreatedAt => LocationSort::CreatedAt.key(),
+            WeatherLocationSort::Distance => LocationSort::Distance.key(),
+        }
+    }
+
+    /// SQL expression and Postgres type of the sort key.
+    pub fn sql(self, near: (Option<f64>, Option<f64>)) -> Result<(String, &'static str), ApiError> {
+        match self {
+            WeatherLocationSort::Position => Ok(("position".to_string(), "int4")),
+            WeatherLocationSort::Name => LocationSort::Name.sql(near),
+            WeatherLocationSort::CreatedAt => LocationSort::CreatedAt.sql(near),
+            WeatherLocationSort::Distance => LocationSort::Distance.sql(near),
+        }
+    }
+
+    /// Join the reference point of a distance sort into the `FROM` of `query`.
+    pub fn push_reference_point(
+        self,
+        query: &mut QueryBuilder<'static, Postgres>,
+        near: (Option<f64>, Option<f64>),
+    ) {
+        if self == WeatherLocationSort::Distance {
+            LocationSort::Distance.push_reference_point(query, near);
+        }
+    }
 }
 
 /// Payload for creating a new weather location.
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub struct CreateWeatherLocationRequest {
     /// The ID of the user creating this location.
     pub user_id: DatabaseId,
 
     /// A friendly name for the new location.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
     pub name: String,
 
     /// Latitude coordinate of the new location.
+    #[validate(range(min = -90.0, max = 90.0))]
+    #[schema(minimum = -90, maximum = 90)]
     pub latitude: f64,
 
     /// Longitude coordinate of the new location.
+    #[validate(range(min = -180.0, max = 180.0))]
+    #[schema(minimum = -180, maximum = 180)]
     pub longitude: f64,
 
     /// Whether this new location should be marked as the user’s default.
@@ -55,6 +186,76 @@ pub struct CreateWeatherLocationRequest {
     pub description: String,
 }
 
+/// Payload replacing all editable fields of a weather location.
+#[derive(Debug, Clone, Deserialize, ToSchema, Validate)]
+pub struct ReplaceWeatherLocationRequest {
+    /// New name.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub name: String,
+
+    /// New latitude.
+    #[validate(range(min = -90.0, max = 90.0))]
+    #[schema(minimum = -90, maximum = 90)]
+    pub latitude: f64,
+
+    /// New longitude.
+    #[validate(range(min = -180.0, max = 180.0))]
+    #[schema(minimum = -180, maximum = 180)]
+    pub longitude: f64,
+
+    /// Whether the location becomes, or stays, the user’s default.
+    pub is_default: bool,
+
+    /// New description.
+    pub description: String,
+}
+
+/// Payload changing some fields of a weather location; missing fields are kept.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema, Validate)]
+pub struct UpdateWeatherLocationRequest {
+    /// New name, if changing.
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub name: Option<String>,
+
+    /// New latitude, if changing.
+    #[validate(range(min = -90.0, max = 90.0))]
+    #[schema(minimum = -90, maximum = 90)]
+    pub latitude: Option<f64>,
+
+    /// New longitude, if changing.
+    #[validate(range(min = -180.0, max = 180.0))]
+    #[schema(minimum = -180, maximum = 180)]
+    pub longitude: Option<f64>,
+
+    /// `true` makes the location the user’s default, unsetting the previous one.
+    pub is_default: Option<bool>,
+
+    /// New description, if changing.
+    pub description: Option<String>,
+}
+
+impl From<ReplaceWeatherLocationRequest> for UpdateWeatherLocationRequest {
+    fn from(request: ReplaceWeatherLocationRequest) -> Self {
+        UpdateWeatherLocationRequest {
+            name: Some(request.name),
+            latitude: Some(request.latitude),
+            longitude: Some(request.longitude),
+            is_default: Some(request.is_default),
+            description: Some(request.description),
+        }
+    }
+}
+
+/// Payload of a reorder: every weather location of the user, in the new order.
+#[derive(Debug, Clone, Deserialize, ToSchema, Validate)]
+pub struct ReorderWeatherLocationsRequest {
+    /// IDs of all the user’s weather locations, first one first.
+    #[validate(length(min = 1))]
+    pub ids: Vec<DatabaseId>,
+}
+
 /// Response returned after successfully creating a weather location.
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct WeatherLocationCreateRequestSuccess {
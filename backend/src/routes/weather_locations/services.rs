@@ -1,7 +1,19 @@
-use crate::routes::weather_locations::models::{CreateWeatherLocationRequest, WeatherLocation};
+use crate::routes::location_transfer::models::LocationKind;
+use crate::routes::sharing::models::{Access, WithAccess};
+use crate::routes::sharing::services::location_access;
+use crate::routes::weather_locations::models::{
+    CreateWeatherLocationRequest, UpdateWeatherLocationRequest, WeatherLocation,
+    WeatherLocationListQuery,
+};
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::geo::{Nearby, NearbyQuery};
+use crate::shared::memberships::{COLLECTIONS, TAGS};
 use crate::shared::models::DatabaseId;
+use crate::shared::pagination::{Keyed, Page, PageRequest};
+use crate::shared::preconditions::IfMatch;
 use anyhow::Result;
 use async_trait::async_trait;
+use sqlx::{Postgres, Transaction};
 
 /// Defines the core CRUD operations for managing weather‐location records.
 ///
@@ -16,30 +28,88 @@ pub trait WeatherLocationServiceImpl: Clone + Send + Sync + 'static {
     /// Returns the freshly‐created `WeatherLocation` with all its fields populated.
     async fn create(&self, location: &CreateWeatherLocationRequest) -> Result<WeatherLocation>;
 
-    /// Fetch all weather locations belonging to `user_id`.
+    /// Fetch one page of the weather locations of `user_id` and of those shared with them,
+    /// each with the user's access.
     ///
-    /// Returns a vector of `WeatherLocation`. If none exist, returns an empty `Vec`.
-    async fn get_all(&self, user_id: &DatabaseId) -> Result<Vec<WeatherLocation>>;
-
-    /// Fetch a single weather location by its `id` for the given user.
+    /// Filters, sort and cursor come from `query`; an empty page has no items.
+    async fn get_all(
+        &self,
+        user_id: &DatabaseId,
+        query: &WeatherLocationListQuery,
+    ) -> Result<Page<WithAccess<WeatherLocation>>>;
+
+    /// Fetch the weather locations of `user_id` nearest to the point of `query`, closest
+    /// first, each with its great-circle distance.
+    async fn nearby(
+        &self,
+        user_id: &DatabaseId,
+        query: &NearbyQuery,
+    ) -> Result<Vec<Nearby<WeatherLocation>>>;
+
+    /// Fetch a single weather location by its `id` for the given user, their own or shared
+    /// with them.
+    ///
+    /// Returns the location with the user's access if found, or an error if not found or on
+    /// failure.
+    async fn get_by_id(
+        &self,
+        user_id: &DatabaseId,
+        id: &DatabaseId,
+    ) -> Result<WithAccess<WeatherLocation>>;
+
+    /// Update the fields of weather location `id` that are set in `changes`.
+    ///
+    /// If `changes.is_default` is `true`, any previous default for that user is unset in the
+    /// same transaction. Users the location is shared with to edit can update it too, but
+    /// the default stays the owner's choice.
+    ///
+    /// Returns the updated `WeatherLocation`, `ApiError::NotFound` if it does not exist,
+    /// `ApiError::Forbidden` if it is shared read-only or `ApiError::PreconditionFailed` if
+    /// its version does not match `if_match`.
+    async fn update(
+        &self,
+        user_id: &DatabaseId,
+        id: &DatabaseId,
+        changes: &UpdateWeatherLocationRequest,
+        if_match: &IfMatch,
+    ) -> Result<WeatherLocation>;
+
+    /// Make weather location `id` the default of the user, unsetting the previous one.
     ///
-    /// Returns `Ok(WeatherLocation)` if found, or an error if not found or on failure.
-    async fn get_by_id(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<WeatherLocation>;
+    /// Returns the new default, or `ApiError::NotFound` if it does not exist.
+    async fn set_default(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<WeatherLocation>;
 
-    /// Update an existing weather location.
+    /// Put the weather locations of the user in the order of `ids`, which must list each
+    /// of them exactly once.
     ///
-    /// The provided `location` struct must contain the `id` and `user_id` of the record
-    /// to update. If `location.is_default` is `true`, any previous default for that user
-    /// will be unset.
+    /// Returns all the locations in their new order.
+    async fn reorder(
+        &self,
+        user_id: &DatabaseId,
+        ids: &[DatabaseId],
+    ) -> Result<Vec<WeatherLocation>>;
+
+    /// Move the weather location with the given `id` for the specified user to the trash.
+    ///
+    /// It is no longer listed, shared or the default, and is purged after the retention
+    /// unless restored. Returns `Ok(())` on success, `ApiError::Forbidden` if it is only
+    /// shared with the user, `ApiError::PreconditionFailed` if the current version does not
+    /// match `if_match`, or an error if the deletion failed.
+    async fn delete(&self, user_id: &DatabaseId, id: &DatabaseId, if_match: &IfMatch)
+        -> Result<()>;
+
+    /// The weather locations of `user_id` in the trash, most recently deleted first.
+    async fn trash(&self, user_id: &DatabaseId) -> Result<Vec<WeatherLocation>>;
+
+    /// Take weather location `id` out of the trash, at the end of the user's order.
     ///
-    /// Returns the updated `WeatherLocation`.
-    async fn update(&self, location: &WeatherLocation) -> Result<WeatherLocation>;
+    /// Returns the restored location, or `ApiError::NotFound` if it is not in the trash.
+    async fn restore(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<WeatherLocation>;
 
-    /// Delete the weather location with the given `id` for the specified user.
+    /// Delete weather location `id` from the trash for good.
     ///
-    /// Returns `Ok(())` on success, or an error if the record did not exist or the
-    /// deletion failed.
-    async fn delete(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<()>;
+    /// Returns `ApiError::NotFound` if it is not in the trash.
+    async fn purge(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<()>;
 }
 
 /// Postgres‐backed implementation of `WeatherLocationServiceImpl`.
@@ -52,26 +122,57 @@ pub struct WeatherLocationService {
     pub db: sqlx::PgPool,
 }
 
+impl WeatherLocationService {
+    /// Serialize the writes touching the default or the order of the locations of a user.
+    ///
+    /// The partial unique index `one_default_weather_location_per_user` is checked row by
+    /// row, so two concurrent switches of the default would otherwise fail.
+    async fn lock_user(tx: &mut Transaction<'_, Postgres>, user_id: &DatabaseId) -> Result<()> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(format!("weather_locations:{}", user_id.0))
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    /// Unset the default of the user unless it is `keep`, before another one is set.
+    async fn clear_default(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &DatabaseId,
+        keep: Option<&DatabaseId>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE weather_locations
+             SET is_default = false, version = version + 1, updated_at = now()
+             WHERE user_id = $1 AND is_default AND id IS DISTINCT FROM $2",
+            user_id.0,
+            keep.map(|id| id.0),
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl WeatherLocationServiceImpl for WeatherLocationService {
+    #[tracing::instrument(skip_all, fields(user_id = location.user_id.0))]
     async fn create(&self, location: &CreateWeatherLocationRequest) -> Result<WeatherLocation> {
         let mut tx = self.db.begin().await?;
 
+        Self::lock_user(&mut tx, &location.user_id).await?;
         if location.is_default {
-            sqlx::query!(
-                "UPDATE weather_locations SET is_default = false WHERE user_id = $1",
-                location.user_id.0
-            )
-            .execute(&mut *tx)
-            .await?;
+            Self::clear_default(&mut tx, &location.user_id, None).await?;
         }
 
-        // This query now RETURNING all the columns that map to WeatherLocation
+        // This query now RETURNING all the columns that map to WeatherLocation;
+        // new locations go to the end of the user's order
         let rec = sqlx::query_as!(
             WeatherLocation,
             r#"
-            INSERT INTO weather_locations (user_id, name, latitude, longitude, is_default, description)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO weather_locations (user_id, name, latitude, longitude, is_default, description, position)
+            VALUES ($1, $2, $3, $4, $5, $6,
+                    (SELECT COALESCE(MAX(position) + 1, 0) FROM weather_locations WHERE user_id = $1))
             RETURNING *
             "#,
             location.user_id.0,
@@ -88,84 +189,317 @@ impl WeatherLocationServiceImpl for WeatherLocationService {
         Ok(rec)
     }
 
-    async fn get_all(&self, user_id: &DatabaseId) -> Result<Vec<WeatherLocation>> {
-        let locations = sqlx::query_as!(
-            WeatherLocation,
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn get_all(
+        &self,
+        user_id: &DatabaseId,
+        query: &WeatherLocationListQuery,
+    ) -> Result<Page<WithAccess<WeatherLocation>>> {
+        let (sort_expr, sort_type) = query.sort.sql((query.near_lat, query.near_lon))?;
+        let page = PageRequest::new(
+            query.limit,
+            query.cursor.as_deref(),
+            query.sort.key(),
+            query.order,
+        )?;
+
+        let mut sql = page.select(
+            "weather_locations JOIN weather_location_access ON weather_location_id = id",
+            &sort_expr,
+        );
+        query
+            .sort
+            .push_reference_point(&mut sql, (query.near_lat, query.near_lon));
+        sql.push(" WHERE viewer_id = ").push_bind(user_id.0);
+        query.filter().push_sql(&mut sql);
+        if let Some(tag) = query.tag {
+            TAGS.push_filter(&mut sql, "weather_location_id", *user_id, tag);
+        }
+        if let Some(collection) = query.collection {
+            COLLECTIONS.push_filter(&mut sql, "weather_location_id", *user_id, collection);
+        }
+        page.push_keyset(&mut sql, &sort_expr, sort_type)?;
+
+        let rows: Vec<Keyed<WithAccess<WeatherLocation>>> =
+            sql.build_query_as().fetch_all(&self.db).await?;
+        Ok(page.finish(rows))
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn nearby(
+        &self,
+        user_id: &DatabaseId,
+        query: &NearbyQuery,
+    ) -> Result<Vec<Nearby<WeatherLocation>>> {
+        let rows = query
+            .select("weather_locations", *user_id)
+            .build_query_as()
+            .fetch_all(&self.db)
+            .await?;
+        Ok(rows)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn get_by_id(
+        &self,
+        user_id: &DatabaseId,
+        id: &DatabaseId,
+    ) -> Result<WithAccess<WeatherLocation>> {
+        let rec = sqlx::query_as(
             r#"
             SELECT *
-            FROM weather_locations
-            WHERE user_id = $1
+            FROM weather_locations JOIN weather_location_access ON weather_location_id = id
+            WHERE id = $1 AND viewer_id = $2
             "#,
-            user_id.0
         )
-        .fetch_all(&self.db)
+        .bind(id.0)
+        .bind(user_id.0)
+        .fetch_one(&self.db)
         .await?;
 
-        Ok(locations)
+        Ok(rec)
     }
 
-    async fn get_by_id(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<WeatherLocation> {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn update(
+        &self,
+        user_id: &DatabaseId,
+        id: &DatabaseId,
+        changes: &UpdateWeatherLocationRequest,
+        if_match: &IfMatch,
+    ) -> Result<WeatherLocation> {
+        let versions = if_match.versions();
+        let mut tx = self.db.begin().await?;
+
+        // the default is the owner's choice, editors leave it as it is
+        let is_default = match changes.is_default {
+            Some(_)
+                if location_access(&mut *tx, LocationKind::Weather, *user_id, *id).await?
+                    != Access::Owner =>
+            {
+                None
+            }
+            is_default => is_default,
+        };
+        if is_default == Some(true) {
+            Self::lock_user(&mut tx, user_id).await?;
+            Self::clear_default(&mut tx, user_id, Some(id)).await?;
+        }
+
         let rec = sqlx::query_as!(
             WeatherLocation,
             r#"
-            SELECT *
-            FROM weather_locations
-            WHERE id = $1 AND user_id = $2
+            UPDATE weather_locations
+            SET name        = COALESCE($1, name),
+                latitude    = COALESCE($2, latitude),
+                longitude   = COALESCE($3, longitude),
+                is_default  = COALESCE($4, is_default),
+                description = COALESCE($5, description),
+                version = version + 1, updated_at = now()
+            WHERE id = $6 AND ($8::int4[] IS NULL OR version = ANY($8))
+              AND id IN (SELECT weather_location_id FROM weather_location_access
+                         WHERE viewer_id = $7 AND access <> 'read')
+            RETURNING *
             "#,
+            changes.name,
+            changes.latitude,
+            changes.longitude,
+            is_default,
+            changes.description,
             id.0,
-            user_id.0
+            user_id.0,
+            versions.as_deref(),
         )
-        .fetch_one(&self.db)
+        .fetch_optional(&mut *tx)
         .await?;
 
+        let Some(rec) = rec else {
+            // rolls back the cleared default
+            drop(tx);
+            location_access(&self.db, LocationKind::Weather, *user_id, *id)
+                .await?
+                .require(Access::Edit)?;
+            return Err(ApiError::PreconditionFailed(
+                "Location was modified in the meantime".to_string(),
+            )
+            .into());
+        };
+
+        tx.commit().await?;
         Ok(rec)
     }
 
-    async fn update(&self, location: &WeatherLocation) -> Result<WeatherLocation> {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn set_default(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<WeatherLocation> {
         let mut tx = self.db.begin().await?;
 
-        if location.is_default {
-            sqlx::query!(
-                "UPDATE weather_locations SET is_default = false WHERE user_id = $1",
-                location.user_id.0
-            )
-            .execute(&mut *tx)
-            .await?;
-        }
-
+        Self::lock_user(&mut tx, user_id).await?;
+        Self::clear_default(&mut tx, user_id, Some(id)).await?;
         let rec = sqlx::query_as!(
             WeatherLocation,
             r#"
             UPDATE weather_locations
-            SET name = $1, latitude = $2, longitude = $3, is_default = $4, description = $5
-            WHERE id = $6 AND user_id = $7
+            SET is_default = true,
+                version = version + CASE WHEN is_default THEN 0 ELSE 1 END,
+                updated_at = CASE WHEN is_default THEN updated_at ELSE now() END
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
             RETURNING *
             "#,
-            location.name,
-            location.latitude,
-            location.longitude,
-            location.is_default,
-            location.description,
-            location.id.0,
-            location.user_id.0,
+            id.0,
+            user_id.0,
         )
-        .fetch_one(&mut *tx)
-        .await?;
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Location not found".to_string()))?;
 
         tx.commit().await?;
-
         Ok(rec)
     }
 
-    async fn delete(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<()> {
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn reorder(
+        &self,
+        user_id: &DatabaseId,
+        ids: &[DatabaseId],
+    ) -> Result<Vec<WeatherLocation>> {
+        let mut tx = self.db.begin().await?;
+
+        Self::lock_user(&mut tx, user_id).await?;
+        let mut current: Vec<i32> = sqlx::query_scalar!(
+            "SELECT id FROM weather_locations WHERE user_id = $1 AND deleted_at IS NULL",
+            user_id.0
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut requested: Vec<i32> = ids.iter().map(|id| id.0).collect();
+        current.sort_unstable();
+        requested.sort_unstable();
+        if current != requested {
+            return Err(ApiError::Validation(vec![FieldError::new(
+                "ids",
+                "mismatch",
+                "must list each of your weather locations exactly once",
+            )])
+            .into());
+        }
+
+        let ids: Vec<i32> = ids.iter().map(|id| id.0).collect();
         sqlx::query!(
-            "DELETE FROM weather_locations WHERE id = $1 AND user_id = $2",
-            id.0,
+            r#"
+            UPDATE weather_locations w
+            SET position = o.ordinality - 1, version = version + 1, updated_at = now()
+            FROM unnest($2::int4[]) WITH ORDINALITY AS o(id, ordinality)
+            WHERE w.user_id = $1 AND w.id = o.id AND w.position <> o.ordinality - 1
+              AND w.deleted_at IS NULL
+            "#,
+            user_id.0,
+            &ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+        let rows = sqlx::query_as!(
+            WeatherLocation,
+            "SELECT * FROM weather_locations
+             WHERE user_id = $1 AND deleted_at IS NULL ORDER BY position, id",
             user_id.0
         )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(rows)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn delete(
+        &self,
+        user_id: &DatabaseId,
+        id: &DatabaseId,
+        if_match: &IfMatch,
+    ) -> Result<()> {
+        let versions = if_match.versions();
+        // the default is unset so a restored location does not compete with the current one
+        let deleted = sqlx::query!(
+            "UPDATE weather_locations SET deleted_at = now(), is_default = false
+             WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+               AND ($3::int4[] IS NULL OR version = ANY($3))",
+            id.0,
+            user_id.0,
+            versions.as_deref(),
+        )
         .execute(&self.db)
         .await?;
 
+        if deleted.rows_affected() == 0 {
+            // deleting a missing location stays a no-op, a stale version is reported
+            match location_access(&self.db, LocationKind::Weather, *user_id, *id).await {
+                Err(ApiError::NotFound(_)) => {}
+                Ok(Access::Owner) if versions.is_some() => {
+                    return Err(ApiError::PreconditionFailed(
+                        "Location was modified in the meantime".to_string(),
+                    )
+                    .into());
+                }
+                Ok(access) => access.require(Access::Owner)?,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0))]
+    async fn trash(&self, user_id: &DatabaseId) -> Result<Vec<WeatherLocation>> {
+        let rows = sqlx::query_as!(
+            WeatherLocation,
+            "SELECT * FROM weather_locations
+             WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id",
+            user_id.0
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn restore(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<WeatherLocation> {
+        let mut tx = self.db.begin().await?;
+
+        Self::lock_user(&mut tx, user_id).await?;
+        let rec = sqlx::query_as!(
+            WeatherLocation,
+            r#"
+            UPDATE weather_locations
+            SET deleted_at = NULL, version = version + 1, updated_at = now(),
+                position = (SELECT COALESCE(MAX(position) + 1, 0) FROM weather_locations
+                            WHERE user_id = $2 AND deleted_at IS NULL)
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL
+            RETURNING *
+            "#,
+            id.0,
+            user_id.0,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Location not found in the trash".to_string()))?;
+
+        tx.commit().await?;
+        Ok(rec)
+    }
+
+    #[tracing::instrument(skip_all, fields(user_id = user_id.0, id = id.0))]
+    async fn purge(&self, user_id: &DatabaseId, id: &DatabaseId) -> Result<()> {
+        let deleted = sqlx::query!(
+            "DELETE FROM weather_locations
+             WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL",
+            id.0,
+            user_id.0,
+        )
+        .execute(&self.db)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(ApiError::NotFound("Location not found in the trash".to_string()).into());
+        }
         Ok(())
     }
 }
@@ -173,8 +507,10 @@ impl WeatherLocationServiceImpl for WeatherLocationService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::routes::weather_locations::models::WeatherLocationSort;
     use crate::routes::weather_locations::services::WeatherLocationService;
     use crate::shared::models::DatabaseId;
+    use crate::shared::pagination::SortOrder;
     use crate::tests::tests::TestApp;
     use sqlx::{Executor, PgPool};
 
@@ -227,13 +563,17 @@ mod tests {
         let id1 = created1.id;
 
         // 2) get_all should return exactly one
-        let all1 = svc.get_all(&user_id).await.unwrap();
+        let all1 = svc
+            .get_all(&user_id, &Default::default())
+            .await
+            .unwrap()
+            .items;
         assert_eq!(all1.len(), 1);
-        assert_eq!(all1[0].id, id1);
+        assert_eq!(all1[0].item.id, id1);
 
         // 3) get_by_id should return the same
         let fetched1 = svc.get_by_id(&user_id, &id1).await.unwrap();
-        assert_eq!(fetched1.id, id1);
+        assert_eq!(fetched1.item.id, id1);
 
         // 4) create a default location, which should unset previous defaults
         let location2 = CreateWeatherLocationRequest {
@@ -248,33 +588,357 @@ mod tests {
         assert!(created2.is_default);
         let id2 = created2.id;
 
-        let all2 = svc.get_all(&user_id).await.unwrap();
+        let all2 = svc
+            .get_all(&user_id, &Default::default())
+            .await
+            .unwrap()
+            .items;
         // Only one default location should exist
-        let defaults: Vec<_> = all2.iter().filter(|l| l.is_default).collect();
+        let defaults: Vec<_> = all2.iter().filter(|l| l.item.is_default).collect();
         assert_eq!(defaults.len(), 1);
-        assert_eq!(defaults[0].id, id2);
+        assert_eq!(defaults[0].item.id, id2);
 
         // 5) update the first location to become default
-        let mut to_update = created1.clone();
-        to_update.name = "Home Updated".into();
-        to_update.is_default = true;
-        let updated1 = svc.update(&to_update).await.unwrap();
+        let to_update = UpdateWeatherLocationRequest {
+            name: Some("Home Updated".into()),
+            is_default: Some(true),
+            ..Default::default()
+        };
+        let updated1 = svc
+            .update(&user_id, &id1, &to_update, &IfMatch::Absent)
+            .await
+            .unwrap();
         assert_eq!(updated1.name, "Home Updated");
         assert!(updated1.is_default);
 
-        let all3 = svc.get_all(&user_id).await.unwrap();
-        let defaults3: Vec<_> = all3.iter().filter(|l| l.is_default).collect();
+        let all3 = svc
+            .get_all(&user_id, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        let defaults3: Vec<_> = all3.iter().filter(|l| l.item.is_default).collect();
         assert_eq!(defaults3.len(), 1);
-        assert_eq!(defaults3[0].id, updated1.id);
+        assert_eq!(defaults3[0].item.id, updated1.id);
 
         // 6) delete the updated location
-        svc.delete(&user_id, &updated1.id).await.unwrap();
-        let all4 = svc.get_all(&user_id).await.unwrap();
+        svc.delete(&user_id, &updated1.id, &IfMatch::Absent)
+            .await
+            .unwrap();
+        let all4 = svc
+            .get_all(&user_id, &Default::default())
+            .await
+            .unwrap()
+            .items;
         assert_eq!(all4.len(), 1);
-        assert_eq!(all4[0].id, id2);
+        assert_eq!(all4[0].item.id, id2);
 
         // 7) get_by_id for non-existent should error
         let err = svc.get_by_id(&user_id, &DatabaseId(9999)).await;
         assert!(err.is_err());
     }
+
+    #[sqlx::test]
+    async fn test_weather_location_pagination(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_rec = sqlx::query!(
+            "INSERT INTO users (email, password_hash) VALUES ($1, $2) RETURNING id",
+            "pages@example.com",
+            "pass"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let user_id = DatabaseId(user_rec.id);
+        let svc = WeatherLocationService {
+            db: test_app.app.db.clone(),
+        };
+
+        for (name, latitude, longitude) in [
+            ("Bern", 46.95, 7.45),
+            ("Zurich", 47.37, 8.54),
+            ("Geneva", 46.2, 6.14),
+            ("Sydney", -33.87, 151.21),
+            ("Basel", 47.56, 7.59),
+        ] {
+            let location = CreateWeatherLocationRequest {
+                user_id,
+                name: name.into(),
+                latitude,
+                longitude,
+                is_default: false,
+                description: String::new(),
+            };
+            svc.create(&location).await.unwrap();
+        }
+
+        // walk all pages sorted by name, descending
+        let mut query = WeatherLocationListQuery {
+            limit: Some(2),
+            sort: WeatherLocationSort::Name,
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        let mut names = Vec::new();
+        loop {
+            let page = svc.get_all(&user_id, &query).await.unwrap();
+            assert!(page.items.len() <= 2);
+            names.extend(page.items.into_iter().map(|l| l.item.name));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(names, vec!["Zurich", "Sydney", "Geneva", "Bern", "Basel"]);
+
+        // closest to Bern first, only inside a box around Switzerland
+        let query = WeatherLocationListQuery {
+            sort: WeatherLocationSort::Distance,
+            near_lat: Some(46.95),
+            near_lon: Some(7.45),
+            bbox: Some("5.9,45.8,10.5,47.8".parse().unwrap()),
+            ..Default::default()
+        };
+        let page = svc.get_all(&user_id, &query).await.unwrap();
+        let names: Vec<_> = page.items.iter().map(|l| l.item.name.as_str()).collect();
+        assert_eq!(names, vec!["Bern", "Basel", "Zurich", "Geneva"]);
+        assert!(page.next_cursor.is_none());
+
+        let query = WeatherLocationListQuery {
+            name: Some("E".into()),
+            ..Default::default()
+        };
+        let page = svc.get_all(&user_id, &query).await.unwrap();
+        let names: Vec<_> = page.items.iter().map(|l| l.item.name.as_str()).collect();
+        assert_eq!(names, vec!["Bern", "Geneva", "Sydney", "Basel"]);
+
+        // sorting by distance needs a reference point
+        let query = WeatherLocationListQuery {
+            sort: WeatherLocationSort::Distance,
+            ..Default::default()
+        };
+        assert!(svc.get_all(&user_id, &query).await.is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_weather_location_nearby(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let svc = WeatherLocationService {
+            db: test_app.app.db.clone(),
+        };
+
+        for (name, latitude, longitude) in [
+            ("Bern", 46.95, 7.45),
+            ("Zurich", 47.37, 8.54),
+            ("Geneva", 46.2, 6.14),
+            ("Sydney", -33.87, 151.21),
+            ("Fiji", -17.71, 178.07),
+        ] {
+            let location = CreateWeatherLocationRequest {
+                user_id,
+                name: name.into(),
+                latitude,
+                longitude,
+                is_default: false,
+                description: String::new(),
+            };
+            svc.create(&location).await.unwrap();
+        }
+        let near = |lat, lon| NearbyQuery {
+            lat,
+            lon,
+            within_km: None,
+            bbox: None,
+            limit: None,
+        };
+
+        // the two nearest to Bern, with their distance
+        let query = NearbyQuery {
+            limit: Some(2),
+            ..near(46.95, 7.45)
+        };
+        let found = svc.nearby(&user_id, &query).await.unwrap();
+        let names: Vec<_> = found.iter().map(|l| l.item.name.as_str()).collect();
+        assert_eq!(names, vec!["Bern", "Zurich"]);
+        assert!(found[0].distance_km < 0.001);
+        assert!((found[1].distance_km - 95.5).abs() < 1.0);
+
+        // everything within 150 km, closest first
+        let query = NearbyQuery {
+            within_km: Some(150.0),
+            ..near(46.95, 7.45)
+        };
+        let found = svc.nearby(&user_id, &query).await.unwrap();
+        let names: Vec<_> = found.iter().map(|l| l.item.name.as_str()).collect();
+        assert_eq!(names, vec!["Bern", "Zurich", "Geneva"]);
+
+        // the circle may cross the antimeridian
+        let query = NearbyQuery {
+            within_km: Some(3000.0),
+            ..near(-17.0, -179.0)
+        };
+        let found = svc.nearby(&user_id, &query).await.unwrap();
+        let names: Vec<_> = found.iter().map(|l| l.item.name.as_str()).collect();
+        assert_eq!(names, vec!["Fiji"]);
+
+        let query = NearbyQuery {
+            bbox: Some("150,-40,-170,0".parse().unwrap()),
+            ..near(46.95, 7.45)
+        };
+        let found = svc.nearby(&user_id, &query).await.unwrap();
+        let names: Vec<_> = found.iter().map(|l| l.item.name.as_str()).collect();
+        assert_eq!(names, vec!["Fiji", "Sydney"]);
+    }
+
+    #[sqlx::test]
+    async fn test_weather_location_default_and_order(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let svc = WeatherLocationService {
+            db: test_app.app.db.clone(),
+        };
+
+        let mut ids = Vec::new();
+        for (name, is_default) in [("Home", true), ("Office", false), ("Cabin", false)] {
+            let location = CreateWeatherLocationRequest {
+                user_id,
+                name: name.into(),
+                latitude: 46.95,
+                longitude: 7.45,
+                is_default,
+                description: String::new(),
+            };
+            let created = svc.create(&location).await.unwrap();
+            assert_eq!(created.position, ids.len() as i32);
+            ids.push(created.id);
+        }
+        let names = |locations: &[WeatherLocation]| {
+            locations.iter().map(|l| l.name.clone()).collect::<Vec<_>>()
+        };
+        let default = || async {
+            let page = svc.get_all(&user_id, &Default::default()).await.unwrap();
+            let defaults: Vec<_> = page
+                .items
+                .into_iter()
+                .filter(|l| l.item.is_default)
+                .collect();
+            assert_eq!(defaults.len(), 1);
+            defaults[0].item.id
+        };
+
+        // switching the default, concurrently too, always leaves exactly one
+        let office = svc.set_default(&user_id, &ids[1]).await.unwrap();
+        assert!(office.is_default);
+        assert_eq!(default().await, ids[1]);
+        let (a, b) = futures_util::join!(
+            svc.set_default(&user_id, &ids[0]),
+            svc.set_default(&user_id, &ids[2])
+        );
+        a.unwrap();
+        b.unwrap();
+        assert!([ids[0], ids[2]].contains(&default().await));
+        assert!(svc.set_default(&user_id, &DatabaseId(9999)).await.is_err());
+
+        // a stale update is rejected and leaves the default alone
+        let current = svc.get_by_id(&user_id, &ids[1]).await.unwrap();
+        let changes = UpdateWeatherLocationRequest {
+            is_default: Some(true),
+            ..Default::default()
+        };
+        let stale = IfMatch::Versions(vec![current.item.version - 1]);
+        let err = svc.update(&user_id, &ids[1], &changes, &stale).await;
+        let err = ApiError::from(err.unwrap_err());
+        assert!(matches!(err, ApiError::PreconditionFailed(_)));
+        assert_ne!(default().await, ids[1]);
+        let updated = svc
+            .update(
+                &user_id,
+                &ids[1],
+                &changes,
+                &IfMatch::Versions(vec![current.item.version]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.version, current.item.version + 1);
+        assert_eq!(default().await, ids[1]);
+
+        let reordered = svc
+            .reorder(&user_id, &[ids[2], ids[0], ids[1]])
+            .await
+            .unwrap();
+        assert_eq!(names(&reordered), vec!["Cabin", "Home", "Office"]);
+        let page = svc.get_all(&user_id, &Default::default()).await.unwrap();
+        let listed: Vec<_> = page.items.into_iter().map(|l| l.item).collect();
+        assert_eq!(names(&listed), vec!["Cabin", "Home", "Office"]);
+
+        // every location exactly once
+        assert!(svc.reorder(&user_id, &[ids[2], ids[0]]).await.is_err());
+        assert!(svc
+            .reorder(&user_id, &[ids[2], ids[0], ids[0]])
+            .await
+            .is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_weather_location_trash(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user_id = test_app.users[0].user.id;
+        let svc = WeatherLocationService {
+            db: test_app.app.db.clone(),
+        };
+
+        let mut ids = Vec::new();
+        for (name, is_default) in [("Home", true), ("Office", false)] {
+            let location = CreateWeatherLocationRequest {
+                user_id,
+                name: name.into(),
+                latitude: 46.95,
+                longitude: 7.45,
+                is_default,
+                description: String::new(),
+            };
+            ids.push(svc.create(&location).await.unwrap().id);
+        }
+        let (home, office) = (ids[0], ids[1]);
+
+        // a deleted location is only found in the trash; deleting it again is a no-op
+        svc.delete(&user_id, &home, &IfMatch::Absent).await.unwrap();
+        svc.delete(&user_id, &home, &IfMatch::Absent).await.unwrap();
+        let listed = svc.get_all(&user_id, &Default::default()).await.unwrap();
+        assert_eq!(listed.items.len(), 1);
+        let err = ApiError::from(svc.get_by_id(&user_id, &home).await.unwrap_err());
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let trash = svc.trash(&user_id).await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].id, home);
+        assert!(trash[0].deleted_at.is_some() && !trash[0].is_default);
+        let err = svc.set_default(&user_id, &home).await.unwrap_err();
+        assert!(matches!(ApiError::from(err), ApiError::NotFound(_)));
+
+        // restored after the remaining ones, no longer the default
+        let restored = svc.restore(&user_id, &home).await.unwrap();
+        assert_eq!(restored.deleted_at, None);
+        assert!(!restored.is_default);
+        let order = svc.reorder(&user_id, &[office, home]).await.unwrap();
+        assert_eq!(
+            order.iter().map(|l| l.id).collect::<Vec<_>>(),
+            [office, home]
+        );
+        let err = svc.restore(&user_id, &home).await.unwrap_err();
+        assert!(matches!(ApiError::from(err), ApiError::NotFound(_)));
+
+        // only locations in the trash can be purged
+        let err = svc.purge(&user_id, &office).await.unwrap_err();
+        assert!(matches!(ApiError::from(err), ApiError::NotFound(_)));
+        svc.delete(&user_id, &office, &IfMatch::Absent)
+            .await
+            .unwrap();
+        svc.purge(&user_id, &office).await.unwrap();
+        assert!(svc.trash(&user_id).await.unwrap().is_empty());
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM weather_locations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(left, 1);
+    }
 }
@@ -0,0 +1,173 @@
+use crate::config::DbPoolSettings;
+use crate::shared::metrics::{record_job_run, record_pool_stats};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Snapshot of the connection pool usage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolStats {
+    /// Connections currently open (idle + in use).
+    pub size: u32,
+    /// Open connections waiting for work.
+    pub idle: u32,
+    /// Connections checked out by handlers.
+    pub in_use: u32,
+    /// Configured upper bound.
+    pub max: u32,
+    /// `in_use / max`, between 0 and 1.
+    pub saturation: f64,
+}
+
+impl PoolStats {
+    pub fn from_pool(pool: &PgPool) -> PoolStats {
+        let size = pool.size();
+        let idle = pool.num_idle() as u32;
+        let max = pool.options().get_max_connections();
+        let in_use = size.saturating_sub(idle);
+        PoolStats {
+            size,
+            idle,
+            in_use,
+            max,
+            saturation: if max == 0 {
+                0.0
+            } else {
+                in_use as f64 / max as f64
+            },
+        }
+    }
+}
+
+/// Delay before the next connection attempt: doubles with every attempt, capped at `max`.
+pub fn backoff_delay(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    initial
+        .checked_mul(2u32.saturating_pow(attempt))
+        .unwrap_or(max)
+        .min(max)
+}
+
+/// Build the Postgres connection options, including the per-statement timeout.
+fn connect_options(database_url: &str, settings: &DbPoolSettings) -> PgConnectOptions {
+    PgConnectOptions::from_str(database_url)
+        .expect("DATABASE_URL is not a valid Postgres connection string")
+        .options([(
+            "statement_timeout",
+            settings.statement_timeout.as_millis().to_string(),
+        )])
+}
+
+/// Connect to Postgres, retrying with exponential backoff until `connect_max_wait` elapses.
+///
+/// In docker compose the backend often starts before Postgres accepts connections, so a
+/// single failed attempt must not bring the process down.
+pub async fn init_db(database_url: &str, settings: &DbPoolSettings) -> PgPool {
+    let options = connect_options(database_url, settings);
+    let pool_options = PgPoolOptions::new()
+        .max_connections(settings.max_connections)
+        .min_connections(settings.min_connections)
+        .idle_timeout(Some(settings.idle_timeout))
+        .max_lifetime(Some(settings.max_lifetime))
+        .acquire_timeout(settings.acquire_timeout);
+
+    let started = Instant::now();
+    let mut attempt = 0;
+    loop {
+        match pool_options.clone().connect_with(options.clone()).await {
+            Ok(pool) => {
+                info!(attempt, "Connected to database");
+                return pool;
+            }
+            Err(e) => {
+                let delay = backoff_delay(
+                    settings.connect_retry_initial,
+                    settings.connect_retry_max,
+                    attempt,
+                );
+                if started.elapsed() + delay > settings.connect_max_wait {
+                    panic!(
+                        "Failed to connect to database after {} attempts: {}",
+                        attempt + 1,
+                        e
+                    );
+                }
+                warn!(
+                    attempt,
+                    retry_in_ms = delay.as_millis() as u64,
+                    "Database not reachable yet: {}",
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Periodically log pool usage and warn when the pool is close to exhaustion.
+///
+/// The task stops when `token` is cancelled.
+pub fn spawn_pool_monitor(pool: PgPool, settings: &DbPoolSettings, token: CancellationToken) {
+    let interval = settings.monitor_interval;
+    let warn_ratio = settings.saturation_warn_ratio;
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+
+            let stats = PoolStats::from_pool(&pool);
+            record_pool_stats(&stats);
+            if stats.saturation >= warn_ratio {
+                warn!(
+                    size = stats.size,
+                    idle = stats.idle,
+                    in_use = stats.in_use,
+                    max = stats.max,
+                    saturation = stats.saturation,
+                    "Database pool is saturated"
+                );
+            } else {
+                debug!(
+                    size = stats.size,
+                    idle = stats.idle,
+                    in_use = stats.in_use,
+                    max = stats.max,
+                    saturation = stats.saturation,
+                    "Database pool stats"
+                );
+            }
+
+            match sqlx::query("SELECT 1").execute(&pool).await {
+                Ok(_) => record_job_run("pool_monitor", "success"),
+                Err(e) => {
+                    warn!("Database health check failed: {}", e);
+                    record_job_run("pool_monitor", "failure");
+                }
+            }
+        }
+        debug!("Pool monitor stopped");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay_is_capped() {
+        let initial = Duration::from_millis(500);
+        let max = Duration::from_secs(10);
+
+        assert_eq!(backoff_delay(initial, max, 0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(initial, max, 1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(initial, max, 3), Duration::from_secs(4));
+        assert_eq!(backoff_delay(initial, max, 5), max);
+        assert_eq!(backoff_delay(initial, max, 40), max);
+    }
+}
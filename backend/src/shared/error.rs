@@ -0,0 +1,316 @@
+use axum::extract::multipart::MultipartError;
+use axum::extract::multipart::MultipartRejection;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// Media type of every error body.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Detail shown to clients for 5xx errors; the real cause is only logged.
+const INTERNAL_ERROR_DETAIL: &str = "An unexpected error occurred";
+
+/// A single invalid field of a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    /// Name of the offending field, e.g. `latitude`.
+    pub field: String,
+
+    /// Stable machine readable reason, e.g. `range`.
+    pub code: String,
+
+    /// Human readable explanation.
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(
+        field: impl Into<String>,
+        code: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        FieldError {
+            field: field.into(),
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// RFC 7807 problem details, the body of every error response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ProblemDetails {
+    /// Problem type URI; `about:blank` as we do not publish problem documents.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+
+    /// Short summary, the reason phrase of the status code.
+    pub title: String,
+
+    /// HTTP status code.
+    pub status: u16,
+
+    /// Human readable explanation of this occurrence.
+    pub detail: String,
+
+    /// Stable machine readable error code, e.g. `not_found`.
+    pub code: String,
+
+    /// Invalid fields, only present for validation errors.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// The error type returned by every handler.
+///
+/// Each variant maps to one status code and one stable `code`; responses are rendered as
+/// `application/problem+json`. Internal errors keep their cause for the logs but never send it
+/// to the client.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request is malformed (400).
+    BadRequest(String),
+
+    /// One or more fields failed validation (422).
+    Validation(Vec<FieldError>),
+
+    /// Missing or invalid credentials (401).
+    Unauthorized(String),
+
+    /// Wrong email or password (401).
+    InvalidCredentials,
+
+    /// The caller may not access the resource (403).
+    Forbidden(String),
+
+    /// The resource does not exist or is not visible to the caller (404).
+    NotFound(String),
+
+    /// The resource already exists (409).
+    AlreadyExists(String),
+
+    /// The request did not complete in time (408).
+    RequestTimeout(String),
+
+    /// The request conflicts with the current state of the resource (409).
+    Conflict(String),
+
+    /// The `If-Match` precondition does not hold for the current version (412).
+    PreconditionFailed(String),
+
+    /// The request body is too large (413).
+    PayloadTooLarge(String),
+
+    /// The request body has an unsupported media type (415).
+    UnsupportedMediaType(String),
+
+    /// The request must be conditional, `If-Match` is missing (428).
+    PreconditionRequired(String),
+
+    /// The caller exhausted its rate limit (429).
+    TooManyRequests(String),
+
+    /// An upstream service failed (502).
+    BadGateway(String),
+
+    /// The server is overloaded, the request was not handled (503).
+    ServiceUnavailable(String),
+
+    /// Anything else (500); the message is logged only.
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized(_) | ApiError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::AlreadyExists(_) => StatusCode::CONFLICT,
+            ApiError::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable machine readable code, also used as metric label.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::InvalidCredentials => "invalid_credentials",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::AlreadyExists(_) => "already_exists",
+            ApiError::RequestTimeout(_) => "request_timeout",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PreconditionFailed(_) => "precondition_failed",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ApiError::PreconditionRequired(_) => "precondition_required",
+            ApiError::TooManyRequests(_) => "rate_limited",
+            ApiError::BadGateway(_) => "bad_gateway",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Detail sent to the client.
+    pub fn detail(&self) -> String {
+        match self {
+            ApiError::Validation(_) => "Request validation failed".to_string(),
+            ApiError::InvalidCredentials => "Invalid email or password".to_string(),
+            ApiError::BadGateway(_) => "Upstream service failed".to_string(),
+            ApiError::Internal(_) => INTERNAL_ERROR_DETAIL.to_string(),
+            ApiError::BadRequest(m)
+            | ApiError::Unauthorized(m)
+            | ApiError::Forbidden(m)
+            | ApiError::NotFound(m)
+            | ApiError::AlreadyExists(m)
+            | ApiError::RequestTimeout(m)
+            | ApiError::Conflict(m)
+            | ApiError::PreconditionFailed(m)
+            | ApiError::PayloadTooLarge(m)
+            | ApiError::UnsupportedMediaType(m)
+            | ApiError::PreconditionRequired(m)
+            | ApiError::TooManyRequests(m)
+            | ApiError::ServiceUnavailable(m) => m.clone(),
+        }
+    }
+
+    pub fn problem(&self) -> ProblemDetails {
+        let status = self.status();
+        ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code: self.code().to_string(),
+            errors: match self {
+                ApiError::Validation(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Validation(errors) => {
+                write!(f, "Validation failed on {} field(s)", errors.len())
+            }
+            ApiError::BadGateway(m) | ApiError::Internal(m) => write!(f, "{}", m),
+            _ => write!(f, "{}", self.detail()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match &self {
+            ApiError::Internal(m) => tracing::error!(code = self.code(), "{}", m),
+            ApiError::BadGateway(m) => tracing::warn!(code = self.code(), "{}", m),
+            _ => tracing::debug!(code = self.code(), "{}", self),
+        }
+
+        let mut response = (self.status(), Json(self.problem())).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Resource not found".to_string()),
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                ApiError::AlreadyExists("Resource already exists".to_string())
+            }
+            _ => ApiError::Internal(format!("Database error: {}", e)),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<ApiError>() {
+            Ok(api) => return api,
+            Err(e) => e,
+        };
+        match e.downcast::<sqlx::Error>() {
+            Ok(db) => db.into(),
+            Err(e) => ApiError::Internal(format!("{:#}", e)),
+        }
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(e: std::io::Error) -> Self {
+        ApiError::Internal(format!("I/O error: {}", e))
+    }
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(e: MultipartError) -> Self {
+        match e.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge(e.body_text()),
+            _ => ApiError::BadRequest(format!("Invalid multipart body: {}", e.body_text())),
+        }
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(rejection: MultipartRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_problem_json_response() {
+        let response = ApiError::Validation(vec![FieldError::new(
+            "latitude",
+            "range",
+            "must be between -90 and 90",
+        )])
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let problem: ProblemDetails = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem.code, "validation_failed");
+        assert_eq!(problem.status, 422);
+        assert_eq!(problem.errors[0].field, "latitude");
+
+        // internal causes stay in the logs
+        let problem = ApiError::from(sqlx::Error::PoolTimedOut).problem();
+        assert_eq!(problem.status, 500);
+        assert_eq!(problem.detail, INTERNAL_ERROR_DETAIL);
+
+        let problem = ApiError::from(anyhow::Error::new(sqlx::Error::RowNotFound)).problem();
+        assert_eq!(problem.code, "not_found");
+    }
+}
@@ -0,0 +1,755 @@
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::models::DatabaseId;
+use crate::shared::pagination::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::shared::validation::finite;
+use geo::{
+    Closest, Coord, HaversineClosestPoint, Intersects, LineString, MultiPolygon, Point, Polygon,
+    Rect, Validation,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, Postgres, QueryBuilder, Row};
+use std::str::FromStr;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
+
+/// Mean radius of the Earth, in kilometers.
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Half the circumference of the Earth, the largest great-circle distance, in kilometers.
+pub const MAX_DISTANCE_KM: f64 = 20_016.0;
+
+/// Most vertices of an area, over all of its rings.
+pub const MAX_AREA_VERTICES: usize = 1000;
+
+/// Farthest a vertex of an area may be from the point of its location, in kilometers.
+pub const MAX_AREA_EXTENT_KM: f64 = 500.0;
+
+/// A `min_lon,min_lat,max_lon,max_lat` box, the order used by GeoJSON.
+///
+/// A box with `min_lon > max_lon` crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "must be four comma separated numbers".to_string())?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err("must be four comma separated numbers".to_string());
+        };
+
+        if ![min_lon, max_lon]
+            .iter()
+            .all(|lon| (-180.0..=180.0).contains(lon))
+        {
+            return Err("longitudes must be between -180 and 180".to_string());
+        }
+        if ![min_lat, max_lat]
+            .iter()
+            .all(|lat| (-90.0..=90.0).contains(lat))
+        {
+            return Err("latitudes must be between -90 and 90".to_string());
+        }
+        if min_lat > max_lat {
+            return Err("min_lat must not be greater than max_lat".to_string());
+        }
+
+        Ok(BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for BoundingBox {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl BoundingBox {
+    /// Append ` AND <inside the box>` for the `latitude`/`longitude` columns.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'static, Postgres>) {
+        query
+            .push(" AND latitude BETWEEN ")
+            .push_bind(self.min_lat)
+            .push(" AND ")
+            .push_bind(self.max_lat);
+        let join = if self.min_lon <= self.max_lon {
+            " AND "
+        } else {
+            " OR "
+        };
+        query
+            .push(" AND (longitude >= ")
+            .push_bind(self.min_lon)
+            .push(join)
+            .push("longitude <= ")
+            .push_bind(self.max_lon)
+            .push(")");
+    }
+
+    /// Like `push_sql`, but rows with an `area` only need their point within
+    /// `MAX_AREA_EXTENT_KM` of the box; `intersects` then tells whether the area is in it.
+    pub fn push_area_sql(&self, query: &mut QueryBuilder<'static, Postgres>) {
+        query.push(" AND ((area IS NULL");
+        self.push_sql(query);
+        query.push(") OR (area IS NOT NULL");
+        self.expanded(MAX_AREA_EXTENT_KM).push_sql(query);
+        query.push("))");
+    }
+
+    /// Box containing every point at most `km` from this one.
+    fn expanded(&self, km: f64) -> BoundingBox {
+        let angle = km / EARTH_RADIUS_KM;
+        let min_lat = (self.min_lat - angle.to_degrees()).max(-90.0);
+        let max_lat = (self.max_lat + angle.to_degrees()).min(90.0);
+
+        // the widest span of longitudes is at the latitude of the box farthest from the equator
+        let widest = self.min_lat.abs().max(self.max_lat.abs()).to_radians();
+        let ratio = angle.sin() / widest.cos();
+        let width = if self.min_lon <= self.max_lon {
+            self.max_lon - self.min_lon
+        } else {
+            self.max_lon - self.min_lon + 360.0
+        };
+        let delta = if ratio < 1.0 {
+            ratio.asin().to_degrees()
+        } else {
+            180.0
+        };
+        if width + 2.0 * delta >= 360.0 {
+            return BoundingBox {
+                min_lon: -180.0,
+                min_lat,
+                max_lon: 180.0,
+                max_lat,
+            };
+        }
+        let wrap = |lon: f64| (lon + 180.0).rem_euclid(360.0) - 180.0;
+        BoundingBox {
+            min_lon: wrap(self.min_lon - delta),
+            min_lat,
+            max_lon: wrap(self.max_lon + delta),
+            max_lat,
+        }
+    }
+
+    /// Whether `area` has a point inside the box.
+    pub fn intersects(&self, area: &MultiPolygon<f64>) -> bool {
+        let rect = |min_lon: f64, max_lon: f64| {
+            Rect::new(
+                Coord {
+                    x: min_lon,
+                    y: self.min_lat,
+                },
+                Coord {
+                    x: max_lon,
+                    y: self.max_lat,
+                },
+            )
+        };
+        if self.min_lon <= self.max_lon {
+            area.intersects(&rect(self.min_lon, self.max_lon))
+        } else {
+            area.intersects(&rect(self.min_lon, 180.0))
+                || area.intersects(&rect(-180.0, self.max_lon))
+        }
+    }
+}
+
+/// `ll_to_earth` of the reference point joined by `push_reference_point`.
+pub const REFERENCE_EARTH_SQL: &str = "ll_to_earth(ref.ref_lat, ref.ref_lon)";
+
+/// Append ` CROSS JOIN` of the reference point `lat`/`lon` as `ref.ref_lat` and
+/// `ref.ref_lon`, read by `distance_sql` and `REFERENCE_EARTH_SQL`.
+///
+/// It goes in the `FROM` of the query; the point is bound, so the expressions reading it can
+/// be reused in `SELECT`, `WHERE` and `ORDER BY`.
+pub fn push_reference_point(query: &mut QueryBuilder<'static, Postgres>, lat: f64, lon: f64) {
+    query
+        .push(" CROSS JOIN (SELECT ")
+        .push_bind(lat)
+        .push("::float8 AS ref_lat, ")
+        .push_bind(lon)
+        .push("::float8 AS ref_lon) ref");
+}
+
+/// Great-circle distance in kilometers from the reference point of `push_reference_point`
+/// to the `latitude`/`longitude` columns.
+pub fn distance_sql() -> String {
+    format!(
+        "{} * 2 * asin(least(1.0, sqrt(power(sin(radians(latitude - ref.ref_lat) / 2), 2) \
+         + cos(radians(ref.ref_lat)) * cos(radians(latitude)) \
+         * power(sin(radians(longitude - ref.ref_lon) / 2), 2))))",
+        EARTH_RADIUS_KM
+    )
+}
+
+/// Great-circle distance between two points in kilometers, the haversine of `distance_sql`.
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let a = ((lat2 - lat1).to_radians() / 2.0).sin().powi(2)
+        + lat1.to_radians().cos()
+            * lat2.to_radians().cos()
+            * ((lon2 - lon1).to_radians() / 2.0).sin().powi(2);
+    EARTH_RADIUS_KM * 2.0 * a.sqrt().min(1.0).asin()
+}
+
+/// A latitude or longitude written in decimal degrees or as degrees, minutes and seconds.
+///
+/// Accepts e.g. `46.9521`, `-46,9521`, `46°57'07.6"N`, `N 46 57 7.6` and `46°57.127'`;
+/// `positive` and `negative` are the hemisphere letters, `N` and `S` or `E` and `W`.
+/// The range is left to the caller.
+pub fn parse_degrees(text: &str, positive: char, negative: char) -> Result<f64, String> {
+    let mut text = text.trim().to_uppercase();
+    let mut sign = 1.0;
+    let hemisphere = [text.chars().next(), text.chars().last()]
+        .into_iter()
+        .flatten()
+        .find(|c| c.is_ascii_alphabetic());
+    if let Some(letter) = hemisphere {
+        if letter == negative {
+            sign = -1.0;
+        } else if letter != positive {
+            return Err(format!(
+                "must be in the {} or {} hemisphere",
+                positive, negative
+            ));
+        }
+        text = text.trim_matches(letter).to_string();
+    }
+    if let Some(rest) = text.trim_start().strip_prefix('-') {
+        if hemisphere.is_some() {
+            return Err("must not have both a sign and a hemisphere".to_string());
+        }
+        sign = -1.0;
+        text = rest.to_string();
+    }
+    // spreadsheets in many locales write decimal commas
+    if !text.contains('.') {
+        text = text.replace(',', ".");
+    }
+
+    let parts: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || "°º'\"′″:".contains(c))
+        .filter(|part| !part.is_empty())
+        .collect();
+    let numbers: Vec<f64> = parts
+        .iter()
+        .map(|part| {
+            part.parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| "must be decimal degrees or degrees, minutes and seconds".to_string())?;
+    let degrees = match numbers[..] {
+        [degrees] => degrees,
+        [degrees, minutes] if degrees.fract() == 0.0 && minutes < 60.0 => degrees + minutes / 60.0,
+        [degrees, minutes, seconds]
+            if degrees.fract() == 0.0
+                && minutes.fract() == 0.0
+                && minutes < 60.0
+                && seconds < 60.0 =>
+        {
+            degrees + minutes / 60.0 + seconds / 3600.0
+        }
+        _ => return Err("must be decimal degrees or degrees, minutes and seconds".to_string()),
+    };
+    Ok(sign * degrees)
+}
+
+/// An area given as a GeoJSON Polygon or MultiPolygon geometry.
+///
+/// Coordinates are `[longitude, latitude]` and containment is planar in them, so an area
+/// must not cross the antimeridian.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Area(pub geojson::Geometry);
+
+impl FromStr for Area {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl From<&Area> for serde_json::Value {
+    fn from(area: &Area) -> Self {
+        serde_json::Value::Object((&area.0).into())
+    }
+}
+
+impl TryFrom<serde_json::Value> for Area {
+    type Error = geojson::Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        geojson::Geometry::from_json_value(value).map(Area)
+    }
+}
+
+impl Area {
+    /// The polygons of the area, or why it is not a valid one.
+    ///
+    /// Rings must be closed and have at least four positions, the whole area at most
+    /// `MAX_AREA_VERTICES`; rings may not intersect themselves or each other.
+    pub fn polygons(&self) -> Result<MultiPolygon<f64>, String> {
+        let polygons = match &self.0.value {
+            geojson::Value::Polygon(rings) => vec![rings],
+            geojson::Value::MultiPolygon(polygons) => polygons.iter().collect(),
+            _ => return Err("must be a GeoJSON Polygon or MultiPolygon".to_string()),
+        };
+        if polygons.is_empty() || polygons.iter().any(|rings| rings.is_empty()) {
+            return Err("must have at least one polygon with an exterior ring".to_string());
+        }
+
+        let positions = || polygons.iter().flat_map(|rings| rings.iter().flatten());
+        if positions().count() > MAX_AREA_VERTICES {
+            return Err(format!("must have at most {} vertices", MAX_AREA_VERTICES));
+        }
+        for position in positions() {
+            let [lon, lat] = position[..] else {
+                return Err("positions must be [longitude, latitude]".to_string());
+            };
+            if !(-180.0..=180.0).contains(&lon) || !(-90.0..=90.0).contains(&lat) {
+                return Err("coordinates must be valid longitudes and latitudes".to_string());
+            }
+        }
+        let (min_lon, max_lon) = positions().fold((f64::MAX, f64::MIN), |(min, max), p| {
+            (min.min(p[0]), max.max(p[0]))
+        });
+        if max_lon - min_lon > 180.0 {
+            return Err("must not cross the antimeridian".to_string());
+        }
+
+        let mut shape = Vec::with_capacity(polygons.len());
+        for rings in polygons {
+            let mut rings = rings.iter().map(|ring| {
+                if ring.len() < 4 || ring.first() != ring.last() {
+                    return Err("rings must be closed and have at least four positions");
+                }
+                Ok(LineString::from_iter(
+                    ring.iter().map(|p| Coord { x: p[0], y: p[1] }),
+                ))
+            });
+            let exterior = rings.next().expect("checked above")?;
+            let interiors = rings.collect::<Result<_, _>>()?;
+            shape.push(Polygon::new(exterior, interiors));
+        }
+        let shape = MultiPolygon(shape);
+        shape
+            .check_validation()
+            .map_err(|e| format!("is not a valid geometry: {}", e))?;
+        Ok(shape)
+    }
+
+    /// Fail with a 422 on `area` when a vertex is more than `MAX_AREA_EXTENT_KM` from the point.
+    pub fn check_extent(&self, lat: f64, lon: f64) -> Result<(), ApiError> {
+        let shape = self
+            .polygons()
+            .map_err(|e| ApiError::Validation(vec![FieldError::new("area", "geometry", e)]))?;
+        let far = shape
+            .iter()
+            .flat_map(|polygon| polygon.exterior().coords())
+            .any(|c| haversine_km(lat, lon, c.y, c.x) > MAX_AREA_EXTENT_KM);
+        if far {
+            return Err(ApiError::Validation(vec![FieldError::new(
+                "area",
+                "extent",
+                format!(
+                    "must lie within {} km of the latitude and longitude",
+                    MAX_AREA_EXTENT_KM
+                ),
+            )]));
+        }
+        Ok(())
+    }
+}
+
+/// `validator` rule for an `Area`.
+pub fn validate_area(area: &Area) -> Result<(), ValidationError> {
+    area.polygons()
+        .map(|_| ())
+        .map_err(|e| ValidationError::new("geometry").with_message(e.into()))
+}
+
+/// Great-circle distance in kilometers from a point to the closest point of `area`, zero when
+/// the point is inside or on its boundary.
+pub fn area_distance_km(area: &MultiPolygon<f64>, lat: f64, lon: f64) -> f64 {
+    let point = Point::new(lon, lat);
+    if area.intersects(&point) {
+        return 0.0;
+    }
+    match area.haversine_closest_point(&point) {
+        Closest::SinglePoint(closest) | Closest::Intersection(closest) => {
+            haversine_km(lat, lon, closest.y(), closest.x())
+        }
+        // only for degenerate rings, which validation rules out
+        Closest::Indeterminate => area
+            .iter()
+            .flat_map(|polygon| polygon.exterior().coords())
+            .map(|c| haversine_km(lat, lon, c.y, c.x))
+            .fold(f64::INFINITY, f64::min),
+    }
+}
+
+/// Query parameters of the nearby endpoints.
+#[derive(Debug, Deserialize, IntoParams, Validate)]
+#[into_params(parameter_in = Query)]
+pub struct NearbyQuery {
+    /// Latitude of the reference point.
+    #[validate(range(min = -90.0, max = 90.0), custom(function = "finite"))]
+    pub lat: f64,
+
+    /// Longitude of the reference point.
+    #[validate(range(min = -180.0, max = 180.0), custom(function = "finite"))]
+    pub lon: f64,
+
+    /// Only locations at most this many kilometers away.
+    #[validate(
+        range(exclusive_min = 0.0, max = MAX_DISTANCE_KM),
+        custom(function = "finite")
+    )]
+    pub within_km: Option<f64>,
+
+    /// Only locations inside `min_lon,min_lat,max_lon,max_lat`.
+    #[param(value_type = Option<String>, example = "5.9,45.8,10.5,47.8")]
+    pub bbox: Option<BoundingBox>,
+
+    /// Number of nearest locations returned (default 50).
+    #[validate(range(min = 1, max = MAX_PAGE_LIMIT))]
+    #[param(minimum = 1, maximum = 200)]
+    pub limit: Option<u32>,
+}
+
+impl NearbyQuery {
+    /// `SELECT` of the locations of `user_id` in `table` nearest to the point first, each
+    /// with its `distance_km`.
+    ///
+    /// The GiST index on `ll_to_earth(latitude, longitude)` serves both the `earth_box`
+    /// prefilter and the nearest-first order; the reported distance is the haversine one of
+    /// `distance_sql`, like the distance sort of the lists.
+    pub fn select(&self, table: &str, user_id: DatabaseId) -> QueryBuilder<'static, Postgres> {
+        let distance = distance_sql();
+
+        let mut query = QueryBuilder::new(format!(
+            "SELECT *, {} AS distance_km FROM {}",
+            distance, table
+        ));
+        push_reference_point(&mut query, self.lat, self.lon);
+        query
+            .push(" WHERE deleted_at IS NULL AND user_id = ")
+            .push_bind(user_id.0);
+        if let Some(within_km) = self.within_km {
+            // earth_box works in units of earth(), its radius in meters
+            query
+                .push(format!(" AND earth_box({}, ", REFERENCE_EARTH_SQL))
+                .push_bind(within_km)
+                .push(format!(
+                    " * earth() / {}) @> ll_to_earth(latitude, longitude) AND {} <= ",
+                    EARTH_RADIUS_KM, distance
+                ))
+                .push_bind(within_km);
+        }
+        if let Some(bbox) = &self.bbox {
+            bbox.push_sql(&mut query);
+        }
+        // chord distances order like great-circle ones
+        query
+            .push(format!(
+                " ORDER BY ll_to_earth(latitude, longitude) <-> {}, id LIMIT ",
+                REFERENCE_EARTH_SQL
+            ))
+            .push_bind(i64::from(self.limit.unwrap_or(DEFAULT_PAGE_LIMIT)));
+        query
+    }
+}
+
+/// A location found by a nearby query.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Nearby<T> {
+    #[serde(flatten)]
+    #[schema(inline)]
+    pub item: T,
+
+    /// Great-circle distance from the reference point, in kilometers.
+    pub distance_km: f64,
+}
+
+impl<T> Nearby<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Nearby<U> {
+        Nearby {
+            item: f(self.item),
+            distance_km: self.distance_km,
+        }
+    }
+}
+
+impl<'r, T: FromRow<'r, PgRow>> FromRow<'r, PgRow> for Nearby<T> {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Nearby {
+            item: T::from_row(row)?,
+            distance_km: row.try_get("distance_km")?,
+        })
+    }
+}
+
+/// Sort of a location list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationSort {
+    Name,
+    #[default]
+    CreatedAt,
+    /// Closest to `near_lat`/`near_lon` first.
+    Distance,
+}
+
+impl LocationSort {
+    pub fn key(self) -> &'static str {
+        match self {
+            LocationSort::Name => "name",
+            LocationSort::CreatedAt => "created_at",
+            LocationSort::Distance => "distance",
+        }
+    }
+
+    /// SQL expression and Postgres type of the sort key.
+    ///
+    /// The distance reads the reference point of `push_reference_point`.
+    pub fn sql(self, near: (Option<f64>, Option<f64>)) -> Result<(String, &'static str), ApiError> {
+        match (self, near) {
+            (LocationSort::Name, _) => Ok(("name".to_string(), "text")),
+            (LocationSort::CreatedAt, _) => Ok(("created_at".to_string(), "timestamptz")),
+            (LocationSort::Distance, (Some(_), Some(_))) => Ok((distance_sql(), "float8")),
+            (LocationSort::Distance, (lat, lon)) => {
+                let missing = [("near_lat", lat), ("near_lon", lon)]
+                    .into_iter()
+                    .filter(|(_, value)| value.is_none())
+                    .map(|(field, _)| {
+                        FieldError::new(field, "required", "is required when sorting by distance")
+                    })
+                    .collect();
+                Err(ApiError::Validation(missing))
+            }
+        }
+    }
+
+    /// Join the reference point of a distance sort into the `FROM` of `query`.
+    pub fn push_reference_point(
+        self,
+        query: &mut QueryBuilder<'static, Postgres>,
+        near: (Option<f64>, Option<f64>),
+    ) {
+        if let (LocationSort::Distance, (Some(lat), Some(lon))) = (self, near) {
+            push_reference_point(query, lat, lon);
+        }
+    }
+}
+
+/// Filters shared by the location lists.
+#[derive(Debug, Clone, Default)]
+pub struct LocationFilter {
+    /// Case-insensitive part of the name.
+    pub name: Option<String>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub bbox: Option<BoundingBox>,
+}
+
+impl LocationFilter {
+    /// Append one ` AND ...` condition per set filter.
+    pub fn push_sql(&self, query: &mut QueryBuilder<'static, Postgres>) {
+        if let Some(name) = &self.name {
+            query
+                .push(" AND name ILIKE '%' || ")
+                .push_bind(escape_like(name))
+                .push(" || '%'");
+        }
+        if let Some(after) = self.created_after {
+            query.push(" AND created_at >= ").push_bind(after);
+        }
+        if let Some(before) = self.created_before {
+            query.push(" AND created_at < ").push_bind(before);
+        }
+        if let Some(bbox) = &self.bbox {
+            bbox.push_sql(query);
+        }
+    }
+}
+
+/// Escape the `LIKE` wildcards so `s` only matches itself.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounding_box_parsing() {
+        let bbox: BoundingBox = "5.9, 45.8,10.5,47.8".parse().unwrap();
+        assert_eq!(bbox.min_lon, 5.9);
+        assert_eq!(bbox.max_lat, 47.8);
+
+        // crossing the antimeridian is allowed, inverted latitudes are not
+        assert!("170,-20,-170,20".parse::<BoundingBox>().is_ok());
+        assert!("0,20,10,10".parse::<BoundingBox>().is_err());
+        assert!("0,0,10".parse::<BoundingBox>().is_err());
+        assert!("0,0,190,10".parse::<BoundingBox>().is_err());
+        assert_eq!(escape_like("100%_"), "100\\%\\_");
+    }
+
+    #[test]
+    fn test_bounding_box_around_areas() {
+        let bbox: BoundingBox = "8.4,45.9,8.5,46.1".parse().unwrap();
+        let expanded = bbox.expanded(MAX_AREA_EXTENT_KM);
+        assert!(haversine_km(46.0, 8.45, 46.0, expanded.min_lon) > MAX_AREA_EXTENT_KM);
+        assert!(haversine_km(46.1, 8.5, expanded.max_lat, 8.5) >= MAX_AREA_EXTENT_KM - 1e-6);
+
+        // wrapping around the antimeridian, and all longitudes close to a pole
+        let pacific = "179,0,180,1"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .expanded(500.0);
+        assert!(pacific.min_lon > pacific.max_lon && pacific.max_lon < -175.0);
+        let arctic = "0,87,1,88".parse::<BoundingBox>().unwrap().expanded(500.0);
+        assert_eq!(
+            (arctic.min_lon, arctic.max_lon, arctic.max_lat),
+            (-180.0, 180.0, 90.0)
+        );
+
+        let valley: Area = r#"{"type":"Polygon","coordinates":[[[8,45.9],[8.6,45.9],[8.6,46.1],[8,46.1],[8,45.9]]]}"#
+            .parse()
+            .unwrap();
+        let valley = valley.polygons().unwrap();
+        assert!(bbox.intersects(&valley));
+        assert!(!"8.7,45.9,9,46.1"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .intersects(&valley));
+        assert!(!"179,0,8.1,1"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .intersects(&valley));
+        assert!("179,45,8.1,47"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .intersects(&valley));
+    }
+
+    #[test]
+    fn test_nearby_point_must_be_finite() {
+        let parse = |query: &str| {
+            let uri = format!("/weather_locations/nearby?{}", query)
+                .parse()
+                .unwrap();
+            axum::extract::Query::<NearbyQuery>::try_from_uri(&uri)
+                .unwrap()
+                .0
+        };
+        assert!(parse("lat=46.9&lon=7.4&within_km=10").validate().is_ok());
+        // "NaN" is a valid f64 and passes every range, it would end up in the SQL
+        for query in [
+            "lat=NaN&lon=0",
+            "lat=0&lon=nan",
+            "lat=0&lon=0&within_km=NaN",
+        ] {
+            let errors = parse(query).validate().unwrap_err();
+            let codes: Vec<_> = errors
+                .field_errors()
+                .values()
+                .flat_map(|errors| errors.iter().map(|e| e.code.to_string()))
+                .collect();
+            assert!(
+                codes.contains(&"finite".to_string()),
+                "{}: {:?}",
+                query,
+                codes
+            );
+        }
+    }
+
+    #[test]
+    fn test_haversine() {
+        // Bern to Zurich, and a quarter of the equator
+        assert!((haversine_km(46.95, 7.45, 47.37, 8.54) - 94.72).abs() < 0.01);
+        let quarter = haversine_km(0.0, 0.0, 0.0, 90.0);
+        assert!((quarter - EARTH_RADIUS_KM * std::f64::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!(haversine_km(-17.0, 179.5, -17.0, -179.5) < 110.0);
+    }
+
+    #[test]
+    fn test_parse_degrees() {
+        let latitude = |text: &str| parse_degrees(text, 'N', 'S');
+        let close = |text: &str, expected: f64| {
+            let parsed = latitude(text).unwrap();
+            assert!((parsed - expected).abs() < 1e-6, "{}: {}", text, parsed);
+        };
+        close("46.9521", 46.9521);
+        close(" -46,9521 ", -46.9521);
+        close("46°57'07.56\"N", 46.9521);
+        close("S 46 57 7.56", -46.9521);
+        close("46°57.126'", 46.9521);
+        close("46º 57′ 7.56″ n", 46.9521);
+        assert_eq!(parse_degrees("8.5W", 'E', 'W'), Ok(-8.5));
+
+        assert!(parse_degrees("46.9N", 'E', 'W').is_err());
+        for invalid in [
+            "",
+            "north",
+            "-46.9S",
+            "46°75'",
+            "46.5°30'",
+            "46 57 7 1",
+            "nan",
+        ] {
+            assert!(latitude(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_area_validation() {
+        let area = |json: &str| json.parse::<Area>().unwrap();
+        let square =
+            area(r#"{"type":"Polygon","coordinates":[[[7,46],[8,46],[8,47],[7,47],[7,46]]]}"#);
+        let shape = square.polygons().unwrap();
+        assert!(square.check_extent(46.5, 7.5).is_ok());
+        assert!(square.check_extent(40.0, 7.5).is_err());
+
+        // inside and on the boundary count as contained, outside is measured to the edge
+        assert_eq!(area_distance_km(&shape, 46.5, 7.5), 0.0);
+        assert_eq!(area_distance_km(&shape, 46.0, 7.5), 0.0);
+        let outside = area_distance_km(&shape, 46.5, 9.0);
+        assert!((outside - haversine_km(46.5, 9.0, 46.5, 8.0)).abs() < 0.5);
+
+        let invalid = [
+            r#"{"type":"Point","coordinates":[7,46]}"#,
+            r#"{"type":"Polygon","coordinates":[[[7,46],[8,46],[8,47],[7,47]]]}"#,
+            r#"{"type":"Polygon","coordinates":[[[7,46],[8,47],[8,46],[7,47],[7,46]]]}"#,
+            r#"{"type":"Polygon","coordinates":[[[7,46],[8,46],[8,95],[7,46]]]}"#,
+            r#"{"type":"Polygon","coordinates":[[[179,0],[-179,0],[-179,1],[179,0]]]}"#,
+        ];
+        for json in invalid {
+            assert!(validate_area(&area(json)).is_err(), "{}", json);
+        }
+    }
+}
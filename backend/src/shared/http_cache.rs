@@ -0,0 +1,261 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http_body_util::BodyExt;
+use sha2::{Digest, Sha256};
+use std::fs::Metadata;
+use std::time::UNIX_EPOCH;
+
+/// Bodies are hashed into ETags only for this media type; other bodies are passed through.
+const HASHED_CONTENT_TYPE: &str = "application/json";
+
+/// Caching policy of a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Tokens and health: never stored.
+    NoStore,
+    /// Per-user data: cached by the browser only and revalidated with the ETag on every use.
+    PrivateRevalidate,
+    /// Content addressed files: cached by the browser for a year.
+    PrivateImmutable,
+}
+
+impl CachePolicy {
+    pub fn of(path: &str) -> CachePolicy {
+        match path {
+            "/healthz" | "/readyz" | "/status" => CachePolicy::NoStore,
+            _ if path.starts_with("/auth/") => CachePolicy::NoStore,
+            // stored photo names start with a random uuid and are never overwritten
+            _ if path.starts_with("/uploads/") => CachePolicy::PrivateImmutable,
+            _ => CachePolicy::PrivateRevalidate,
+        }
+    }
+
+    pub fn header_value(self) -> HeaderValue {
+        HeaderValue::from_static(match self {
+            CachePolicy::NoStore => "no-store",
+            CachePolicy::PrivateRevalidate => "private, no-cache",
+            CachePolicy::PrivateImmutable => "private, max-age=31536000, immutable",
+        })
+    }
+}
+
+/// Strong ETag of a representation, a truncated SHA-256 of its bytes.
+pub fn content_etag(bytes: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(bytes);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    HeaderValue::from_str(&format!("\"{}\"", hex)).expect("hex is a valid header value")
+}
+
+/// Strong ETag of a file that is only ever replaced, never modified in place.
+pub fn file_etag(metadata: &Metadata) -> Option<HeaderValue> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    HeaderValue::from_str(&format!(
+        "\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_nanos()
+    ))
+    .ok()
+}
+
+/// Make the strong ETag a handler set specific to the `Content-Encoding` of the response, e.g.
+/// `"v3"` becomes `"v3-gzip"`; identical validators would claim the encodings are the same bytes.
+///
+/// Weak ETags may be shared by every encoding and are kept.
+fn tag_encoding(headers: &mut HeaderMap) {
+    let Some(coding) = headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .filter(|coding| !coding.eq_ignore_ascii_case("identity"))
+    else {
+        return;
+    };
+    let Some(opaque) = headers
+        .get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|etag| etag.strip_prefix('"')?.strip_suffix('"'))
+    else {
+        return;
+    };
+    if let Ok(etag) = HeaderValue::from_str(&format!("\"{}-{}\"", opaque, coding)) {
+        headers.insert(header::ETAG, etag);
+    }
+}
+
+/// Whether `If-None-Match` matches `etag`, using the weak comparison of RFC 9110.
+fn none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let etag = strip_weak(etag.to_str().unwrap_or_default());
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || strip_weak(candidate) == etag)
+}
+
+fn strip_weak(etag: &str) -> &str {
+    etag.strip_prefix("W/").unwrap_or(etag)
+}
+
+/// Middleware adding `Cache-Control`, ETags and conditional GET support.
+///
+/// JSON responses without an ETag get one hashed from their (possibly compressed) body, and
+/// the ETags set by handlers get the content coding appended, so each encoding has its own
+/// strong ETag. A matching `If-None-Match` turns a 200 into a bodiless 304.
+pub async fn cache_headers(req: Request<Body>, next: Next) -> Response {
+    let conditional = matches!(*req.method(), Method::GET | Method::HEAD);
+    let policy = CachePolicy::of(req.uri().path());
+    let request_headers = req.headers().clone();
+
+    let mut response = next.run(req).await;
+    tag_encoding(response.headers_mut());
+    if !conditional || response.status() != StatusCode::OK {
+        return response;
+    }
+    response
+        .headers_mut()
+        .entry(header::CACHE_CONTROL)
+        .or_insert_with(|| policy.header_value());
+    if policy == CachePolicy::NoStore {
+        return response;
+    }
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(HASHED_CONTENT_TYPE));
+    if !response.headers().contains_key(header::ETAG) && is_json {
+        let (mut parts, body) = response.into_parts();
+        let bytes = match body.collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to buffer response body for its ETag");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        parts.headers.insert(header::ETAG, content_etag(&bytes));
+        response = Response::from_parts(parts, Body::from(bytes));
+    }
+
+    let Some(etag) = response.headers().get(header::ETAG).cloned() else {
+        return response;
+    };
+    if !none_match(&request_headers, &etag) {
+        return response;
+    }
+
+    // a 304 repeats the validators and caching headers of the 200 it stands for
+    let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+    for name in [
+        header::ETAG,
+        header::CACHE_CONTROL,
+        header::VARY,
+        header::CONTENT_LOCATION,
+        header::LINK,
+    ] {
+        for value in response.headers().get_all(&name) {
+            not_modified
+                .headers_mut()
+                .append(name.clone(), value.clone());
+        }
+    }
+    not_modified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::preconditions::{version_etag, IfMatch};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use tower::ServiceExt;
+    use tower_http::compression::CompressionLayer;
+
+    #[tokio::test]
+    async fn test_conditional_get() {
+        let router = Router::new()
+            .route(
+                "/weather_locations",
+                get(|| async { Json(vec!["Prague", "Brno"]) }),
+            )
+            .route("/healthz", get(|| async { Json("ok") }))
+            .layer(axum::middleware::from_fn(cache_headers));
+        let call = |uri: &'static str, etag: Option<&HeaderValue>| {
+            let mut req = Request::get(uri);
+            if let Some(etag) = etag {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            router.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        let response = call("/weather_locations", None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "private, no-cache"
+        );
+        let etag = response.headers()[header::ETAG].clone();
+        assert_eq!(etag, content_etag(br#"["Prague","Brno"]"#));
+
+        let response = call("/weather_locations", Some(&etag)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+
+        let stale = HeaderValue::from_static("W/\"stale\"");
+        let response = call("/weather_locations", Some(&stale)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // health checks are never cached
+        let response = call("/healthz", None).await.unwrap();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        assert!(response.headers().get(header::ETAG).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_version_etag_per_encoding() {
+        // the same layers, in the same order, as the application router
+        let router = Router::new()
+            .route(
+                "/weather_locations/{id}",
+                get(|| async { ([(header::ETAG, version_etag(3))], Json(vec!["Bern"; 20])) }),
+            )
+            .layer(CompressionLayer::new())
+            .layer(axum::middleware::from_fn(cache_headers));
+        let call = |encoding: Option<&'static str>, etag: Option<&HeaderValue>| {
+            let mut req = Request::get("/weather_locations/1");
+            if let Some(encoding) = encoding {
+                req = req.header(header::ACCEPT_ENCODING, encoding);
+            }
+            if let Some(etag) = etag {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            router.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        let identity = call(None, None).await.unwrap();
+        assert_eq!(identity.headers()[header::ETAG], version_etag(3));
+        let gzip = call(Some("gzip"), None).await.unwrap();
+        assert_eq!(gzip.headers()[header::CONTENT_ENCODING], "gzip");
+        let gzip_etag = gzip.headers()[header::ETAG].clone();
+        assert_eq!(gzip_etag, "\"v3-gzip\"");
+        let br = call(Some("br"), None).await.unwrap();
+        assert_eq!(br.headers()[header::ETAG], "\"v3-br\"");
+
+        // each validator only revalidates its own encoding
+        let response = call(Some("gzip"), Some(&gzip_etag)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], gzip_etag);
+        let response = call(None, Some(&gzip_etag)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // and still names the version for If-Match
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, gzip_etag);
+        assert_eq!(IfMatch::from_headers(&headers), IfMatch::Versions(vec![3]));
+    }
+}
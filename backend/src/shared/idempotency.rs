@@ -0,0 +1,519 @@
+use crate::config::IdempotencySettings;
+use crate::routes::auth::models::UserDb;
+use crate::shared::error::ApiError;
+use crate::shared::metrics::record_job_run;
+use crate::shared::models::{AppState, DatabaseId};
+use axum::body::Body;
+use axum::extract::{Extension, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures_util::{stream, StreamExt};
+use http_body_util::BodyExt;
+use sha2::{Digest, Sha256};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on responses replayed from an earlier request with the same key.
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// State of the `idempotent` middleware.
+#[derive(Debug, Clone)]
+pub struct Idempotency {
+    db: PgPool,
+    /// How long a stored response is replayed.
+    ttl: Duration,
+    /// After this long an unfinished request is considered abandoned and its key reusable.
+    lease: Duration,
+}
+
+/// A row of `idempotency_keys`.
+#[derive(Debug, sqlx::FromRow)]
+struct StoredResponse {
+    fingerprint: Option<String>,
+    status: Option<i16>,
+    headers: Option<Json<Vec<(String, String)>>>,
+    body: Option<Vec<u8>>,
+}
+
+/// Headers that only concern one connection, or are recomputed for the replayed body.
+const UNSTORED_HEADERS: [HeaderName; 8] = [
+    header::CONNECTION,
+    header::CONTENT_LENGTH,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// The end-to-end headers of a response, in order, as stored in `idempotency_keys`.
+fn stored_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| !UNSTORED_HEADERS.contains(name) && *name != "keep-alive")
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+impl StoredResponse {
+    fn replay(self) -> Response {
+        let status = self
+            .status
+            .and_then(|status| StatusCode::from_u16(status as u16).ok())
+            .unwrap_or(StatusCode::OK);
+        let mut response = (status, self.body.unwrap_or_default()).into_response();
+        let headers = response.headers_mut();
+        headers.remove(header::CONTENT_TYPE);
+        for (name, value) in self.headers.map(|headers| headers.0).unwrap_or_default() {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+impl Idempotency {
+    pub fn new(app: &AppState) -> Self {
+        Idempotency {
+            db: app.db.clone(),
+            ttl: app.settings.idempotency.ttl,
+            lease: app.settings.http.upload_timeout,
+        }
+    }
+
+    /// Reserve `key` for a new request; `false` when it is taken by a live or stored request.
+    ///
+    /// Expired responses and abandoned requests give up their key.
+    async fn claim(&self, user_id: DatabaseId, key: &str) -> Result<bool, ApiError> {
+        let claimed = sqlx::query(
+            "INSERT INTO idempotency_keys (user_id, key) VALUES ($1, $2) \
+             ON CONFLICT (user_id, key) DO UPDATE SET fingerprint = NULL, status = NULL, \
+             headers = NULL, body = NULL, created_at = now() \
+             WHERE idempotency_keys.created_at < now() - make_interval(secs => $3) \
+             OR (idempotency_keys.status IS NULL \
+             AND idempotency_keys.created_at < now() - make_interval(secs => $4)) \
+             RETURNING user_id",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(self.ttl.as_secs_f64())
+        .bind(self.lease.as_secs_f64())
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(claimed.is_some())
+    }
+
+    async fn get(
+        &self,
+        user_id: DatabaseId,
+        key: &str,
+    ) -> Result<Option<StoredResponse>, ApiError> {
+        Ok(sqlx::query_as::<_, StoredResponse>(
+            "SELECT fingerprint, status, headers, body \
+             FROM idempotency_keys WHERE user_id = $1 AND key = $2",
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_optional(&self.db)
+        .await?)
+    }
+
+    /// Store `response` under `key` and hand it back.
+    async fn save(
+        &self,
+        user_id: DatabaseId,
+        key: &str,
+        fingerprint: &str,
+        response: Response,
+    ) -> Result<Response, ApiError> {
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to buffer response: {}", e)))?
+            .to_bytes();
+        sqlx::query(
+            "UPDATE idempotency_keys SET fingerprint = $3, status = $4, headers = $5, body = $6 \
+             WHERE user_id = $1 AND key = $2",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(fingerprint)
+        .bind(parts.status.as_u16() as i16)
+        .bind(Json(stored_headers(&parts.headers)))
+        .bind(body.as_ref())
+        .execute(&self.db)
+        .await?;
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Free `key` so a retry is processed again.
+    async fn release(&self, user_id: DatabaseId, key: &str) {
+        let result = sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2")
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.db)
+            .await;
+        if let Err(e) = result {
+            warn!(error = %e, "Failed to release idempotency key");
+        }
+    }
+}
+
+/// SHA-256 of the method, path and body of a request.
+///
+/// The body is hashed while the handler streams it, so uploads are not buffered. Multipart
+/// boundaries are random per attempt and left out, so a resent form has the same hash.
+#[derive(Clone)]
+struct Fingerprint {
+    state: Arc<Mutex<FingerprintState>>,
+    complete: Arc<AtomicBool>,
+}
+
+struct FingerprintState {
+    hasher: Sha256,
+    /// Multipart boundary to leave out of the hash.
+    boundary: Option<Vec<u8>>,
+    /// Tail of the bytes read so far that may be the start of a boundary.
+    pending: Vec<u8>,
+}
+
+impl FingerprintState {
+    fn update(&mut self, chunk: &[u8]) {
+        let Some(boundary) = &self.boundary else {
+            self.hasher.update(chunk);
+            return;
+        };
+        self.pending.extend_from_slice(chunk);
+
+        let mut hashed = Vec::with_capacity(self.pending.len());
+        let mut i = 0;
+        while i + boundary.len() <= self.pending.len() {
+            if self.pending[i..].starts_with(boundary) {
+                i += boundary.len();
+            } else {
+                hashed.push(self.pending[i]);
+                i += 1;
+            }
+        }
+        self.hasher.update(&hashed);
+        self.pending.drain(..i);
+    }
+
+    fn finish(&self) -> String {
+        let mut hasher = self.hasher.clone();
+        hasher.update(&self.pending);
+        hex(&hasher.finalize())
+    }
+}
+
+impl Fingerprint {
+    fn new(req: &Request<Body>) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(req.method().as_str());
+        hasher.update(b" ");
+        hasher.update(req.uri().path());
+        hasher.update(b"\n");
+        let boundary = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| multer::parse_boundary(value).ok())
+            .map(String::into_bytes);
+
+        Fingerprint {
+            state: Arc::new(Mutex::new(FingerprintState {
+                hasher,
+                boundary,
+                pending: Vec::new(),
+            })),
+            complete: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// `body`, hashing every chunk read from it.
+    fn wrap(&self, body: Body) -> Body {
+        let state = self.state.clone();
+        let complete = self.complete.clone();
+        let chunks = body.into_data_stream().map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                state
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .update(bytes);
+            }
+            chunk
+        });
+        let end = stream::poll_fn(move |_| {
+            complete.store(true, Ordering::Relaxed);
+            Poll::Ready(None)
+        });
+        Body::from_stream(chunks.chain(end))
+    }
+
+    /// Hash of everything, `None` unless the body was read to its end.
+    fn finish(&self) -> Option<String> {
+        if !self.complete.load(Ordering::Relaxed) {
+            return None;
+        }
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        Some(state.finish())
+    }
+
+    /// Read all of `body` and return the hash.
+    async fn read(self, body: Body) -> Result<String, ApiError> {
+        let mut chunks = self.wrap(body).into_data_stream();
+        while let Some(chunk) = chunks.next().await {
+            chunk.map_err(|e| ApiError::BadRequest(format!("Failed to read body: {}", e)))?;
+        }
+        self.finish()
+            .ok_or_else(|| ApiError::Internal("Body was not read to its end".to_string()))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Middleware making a create route safe to retry with an `Idempotency-Key` header.
+///
+/// The first request with a key is processed and its response stored for the user; retries
+/// with the same method, path and body get the stored response back, marked with
+/// `Idempotent-Replayed`. Reusing a key for a different request, or while the first one is
+/// still running, is a 409. Server errors are not stored, so they can be retried.
+pub async fn idempotent(
+    State(idempotency): State<Idempotency>,
+    Extension(user): Extension<UserDb>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.run(req).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "Idempotency-Key must be 1 to {} visible ASCII characters",
+                MAX_KEY_LENGTH
+            ))
+        })?
+        .to_string();
+    let fingerprint = Fingerprint::new(&req);
+    let (parts, body) = req.into_parts();
+
+    if !idempotency.claim(user.id, &key).await? {
+        return match idempotency.get(user.id, &key).await? {
+            Some(stored) if stored.status.is_some() => {
+                if stored.fingerprint.as_deref() != Some(fingerprint.read(body).await?.as_str()) {
+                    return Err(ApiError::Conflict(
+                        "Idempotency-Key was already used for a different request".to_string(),
+                    ));
+                }
+                debug!(key, "Replaying stored response");
+                Ok(stored.replay())
+            }
+            _ => Err(ApiError::Conflict(
+                "A request with this Idempotency-Key is still being processed".to_string(),
+            )),
+        };
+    }
+
+    let response = next
+        .run(Request::from_parts(parts, fingerprint.wrap(body)))
+        .await;
+    match fingerprint.finish() {
+        Some(hash) if !response.status().is_server_error() => {
+            idempotency.save(user.id, &key, &hash, response).await
+        }
+        // without the whole body a retry could not be compared
+        _ => {
+            idempotency.release(user.id, &key).await;
+            Ok(response)
+        }
+    }
+}
+
+/// Periodically delete stored responses older than the replay window.
+///
+/// The task stops when `token` is cancelled.
+pub fn spawn_idempotency_purge(
+    pool: PgPool,
+    settings: &IdempotencySettings,
+    token: CancellationToken,
+) {
+    let interval = settings.purge_interval;
+    let ttl = settings.ttl;
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+
+            let result = sqlx::query(
+                "DELETE FROM idempotency_keys WHERE created_at < now() - make_interval(secs => $1)",
+            )
+            .bind(ttl.as_secs_f64())
+            .execute(&pool)
+            .await;
+            match result {
+                Ok(done) => {
+                    debug!(deleted = done.rows_affected(), "Purged idempotency keys");
+                    record_job_run("idempotency_purge", "success");
+                }
+                Err(e) => {
+                    warn!("Failed to purge idempotency keys: {}", e);
+                    record_job_run("idempotency_purge", "failure");
+                }
+            }
+        }
+        debug!("Idempotency purge stopped");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::natural_phenomenon_locations::handlers::router as phenomenon_router;
+    use crate::routes::weather_locations::handlers::router;
+    use crate::tests::tests::TestApp;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    async fn fingerprint(boundary: &str, chunk_size: usize) -> String {
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nEtna\r\n--{b}--\r\n",
+            b = boundary
+        );
+        let req = Request::post("/natural_phenomenon_locations")
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let chunks: Vec<Result<_, std::io::Error>> = body
+            .into_bytes()
+            .chunks(chunk_size)
+            .map(|chunk| Ok(chunk.to_vec()))
+            .collect();
+        Fingerprint::new(&req)
+            .read(Body::from_stream(stream::iter(chunks)))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_ignores_multipart_boundary() {
+        let first = fingerprint("----curl1a2b3c", 1024).await;
+        // boundaries split across chunks are recognized too
+        assert_eq!(fingerprint("----axios9z8y7x", 5).await, first);
+    }
+
+    #[sqlx::test]
+    async fn test_idempotent_create(pool: PgPool) {
+        let test_app = TestApp::new(pool.clone()).await;
+        let user = &test_app.users[0];
+        let (router, _) = router(test_app.app.clone()).split_for_parts();
+        let call = |router: Router, key: &str, name: &str| {
+            let mut req = Request::post("/weather_locations")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(IDEMPOTENCY_KEY_HEADER, key);
+            for (name, value) in &user.header {
+                req = req.header(name, value);
+            }
+            let body = format!(
+                r#"{{"user_id":0,"name":"{}","latitude":50.1,"longitude":14.4,"is_default":false,"description":""}}"#,
+                name
+            );
+            router.oneshot(req.body(Body::from(body)).unwrap())
+        };
+
+        let first = call(router.clone(), "retry-1", "Prague").await.unwrap();
+        let status = first.status();
+        let first_body = first.into_body().collect().await.unwrap().to_bytes();
+        assert!(status.is_success());
+
+        let retry = call(router.clone(), "retry-1", "Prague").await.unwrap();
+        assert_eq!(retry.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        let retry_body = retry.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(retry_body, first_body);
+
+        let reused = call(router.clone(), "retry-1", "Brno").await.unwrap();
+        assert_eq!(reused.status(), StatusCode::CONFLICT);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM weather_locations")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[sqlx::test]
+    async fn test_replay_keeps_headers(pool: PgPool) {
+        // sqlx tests run on async-std, multipart uploads need the blocking pool of tokio
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _tokio = runtime.enter();
+        let test_app = TestApp::new(pool.clone()).await;
+        let user = &test_app.users[0];
+        let (router, _) = phenomenon_router(test_app.app.clone()).split_for_parts();
+        let call = |router: Router, boundary: &str| {
+            let mut req = Request::post("/natural_phenomenon_locations")
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={}", boundary),
+                )
+                .header(IDEMPOTENCY_KEY_HEADER, "retry-1");
+            for (name, value) in &user.header {
+                req = req.header(name, value);
+            }
+            let body = [
+                ("name", "Etna"),
+                ("latitude", "37.75"),
+                ("longitude", "14.99"),
+                ("radius", "10"),
+            ]
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                    boundary, name, value
+                )
+            })
+            .collect::<String>()
+                + &format!("--{}--\r\n", boundary);
+            router.oneshot(req.body(Body::from(body)).unwrap())
+        };
+
+        let first = call(router.clone(), "first").await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+        let retry = call(router.clone(), "second").await.unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+        for name in [header::ETAG, header::CONTENT_TYPE] {
+            assert_eq!(retry.headers().get(&name), first.headers().get(&name));
+        }
+        assert!(retry.headers().contains_key(header::ETAG));
+    }
+}
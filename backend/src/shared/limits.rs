@@ -0,0 +1,194 @@
+use crate::config::HttpSettings;
+use crate::shared::error::ApiError;
+use crate::shared::metrics::record_aborted_request;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Method, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+/// Kinds of routes that get their own limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Health checks; never rate limited or shed.
+    Probe,
+    /// Routes handing out tokens.
+    Auth,
+    /// Routes receiving file uploads.
+    Upload,
+    /// Everything else.
+    Api,
+}
+
+impl RouteClass {
+    pub fn of(method: &Method, path: &str) -> RouteClass {
+        match path {
+            "/healthz" | "/readyz" | "/status" => RouteClass::Probe,
+            "/auth/register" | "/auth/login" | "/auth/refresh" | "/auth/google" => RouteClass::Auth,
+            "/natural_phenomenon_locations" | "/locations/import" if method == Method::POST => {
+                RouteClass::Upload
+            }
+            _ => RouteClass::Api,
+        }
+    }
+}
+
+/// State of the `limit_requests` middleware.
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    settings: HttpSettings,
+    in_flight: Arc<Semaphore>,
+}
+
+impl RequestLimits {
+    pub fn new(settings: &HttpSettings) -> Self {
+        RequestLimits {
+            settings: settings.clone(),
+            in_flight: Arc::new(Semaphore::new(settings.max_concurrent_requests)),
+        }
+    }
+}
+
+/// Logs requests whose client went away before the response was ready.
+///
+/// Dropping the request future cancels the handler at its next `.await`, which also removes
+/// any temporary upload files.
+struct InFlight {
+    method: Method,
+    path: String,
+    started: Instant,
+    finished: bool,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if !self.finished {
+            record_aborted_request("disconnect");
+            debug!(
+                method = %self.method,
+                path = %self.path,
+                elapsed_ms = self.started.elapsed().as_millis() as u64,
+                "Client disconnected before the response was ready"
+            );
+        }
+    }
+}
+
+/// Middleware shedding load above the concurrency limit (503) and timing out slow requests (408).
+///
+/// Uploads get a longer timeout than the other routes; health checks are never shed so
+/// orchestrators can still see the instance while it is busy.
+pub async fn limit_requests(
+    State(limits): State<RequestLimits>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let class = RouteClass::of(req.method(), req.uri().path());
+
+    let _permit = if class == RouteClass::Probe {
+        None
+    } else {
+        match limits.in_flight.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                record_aborted_request("shed");
+                let mut response =
+                    ApiError::ServiceUnavailable("Server is busy, try again shortly".to_string())
+                        .into_response();
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(1));
+                return response;
+            }
+        }
+    };
+
+    let timeout = match class {
+        RouteClass::Upload => limits.settings.upload_timeout,
+        _ => limits.settings.request_timeout,
+    };
+    let mut in_flight = InFlight {
+        method: req.method().clone(),
+        path: req.uri().path().to_string(),
+        started: Instant::now(),
+        finished: false,
+    };
+
+    let response = match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            record_aborted_request("timeout");
+            ApiError::RequestTimeout(format!(
+                "Request did not complete within {} seconds",
+                timeout.as_secs()
+            ))
+            .into_response()
+        }
+    };
+    in_flight.finished = true;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_limit_requests() {
+        let settings = HttpSettings {
+            request_timeout: Duration::from_millis(50),
+            max_concurrent_requests: 1,
+            ..Default::default()
+        };
+        let router = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    "done"
+                }),
+            )
+            .route("/healthz", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                RequestLimits::new(&settings),
+                limit_requests,
+            ));
+        let call = |path: &'static str| {
+            router
+                .clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+        };
+
+        let response = call("/slow").await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+
+        // the only slot is taken while a request runs, health checks still get through
+        let busy = tokio::spawn(call("/slow"));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let response = call("/slow").await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let response = call("/healthz").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        busy.await.unwrap().unwrap();
+
+        // imports carry files too and get the upload timeout
+        assert_eq!(
+            RouteClass::of(&Method::POST, "/locations/import"),
+            RouteClass::Upload
+        );
+        assert_eq!(
+            RouteClass::of(&Method::GET, "/locations/export"),
+            RouteClass::Api
+        );
+    }
+}
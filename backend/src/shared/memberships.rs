@@ -0,0 +1,280 @@
+use crate::routes::sharing::models::Access;
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::models::DatabaseId;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use utoipa::ToSchema;
+use validator::Validate;
+
+/// Locations of both kinds: those to add to or remove from a tag or collection, or those it
+/// holds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema, Validate)]
+pub struct LocationIds {
+    /// IDs of weather locations.
+    #[serde(default)]
+    #[validate(length(max = 1000))]
+    #[schema(max_items = 1000)]
+    pub weather_location_ids: Vec<DatabaseId>,
+
+    /// IDs of natural phenomenon locations.
+    #[serde(default)]
+    #[validate(length(max = 1000))]
+    #[schema(max_items = 1000)]
+    pub natural_phenomenon_location_ids: Vec<DatabaseId>,
+}
+
+/// Many-to-many links between the groups of one user (tags or collections) and the
+/// locations that user sees.
+///
+/// The group tables have a `user_id`; the link tables hold the group ID and either a
+/// `weather_location_id` or a `natural_phenomenon_location_id`. Links to locations no
+/// longer shared with the user are kept but not shown. Groups with an access view can be
+/// shared; the users they are shared with see them, only their owner changes them.
+#[derive(Debug, Clone, Copy)]
+pub struct Membership {
+    /// Table of the groups.
+    groups: &'static str,
+    /// Table linking groups and locations.
+    links: &'static str,
+    /// Column of `links` referencing `groups`.
+    group_id: &'static str,
+    /// View of the users seeing each group by `group_id`, for groups that can be shared.
+    access: Option<&'static str>,
+    /// What a group is called in errors.
+    noun: &'static str,
+}
+
+pub const TAGS: Membership = Membership {
+    groups: "tags",
+    links: "location_tags",
+    group_id: "tag_id",
+    access: None,
+    noun: "Tag",
+};
+
+pub const COLLECTIONS: Membership = Membership {
+    groups: "collections",
+    links: "collection_locations",
+    group_id: "collection_id",
+    access: Some("collection_access"),
+    noun: "Collection",
+};
+
+impl Membership {
+    /// Access of `user_id` to the group `group_id`: owner, or shared to read or edit.
+    ///
+    /// Returns `ApiError::NotFound` when the user has none.
+    pub async fn group_access(
+        &self,
+        db: &PgPool,
+        user_id: DatabaseId,
+        group_id: DatabaseId,
+    ) -> Result<Access, ApiError> {
+        let sql = match self.access {
+            Some(view) => format!(
+                "SELECT access FROM {} WHERE {} = $1 AND viewer_id = $2",
+                view, self.group_id
+            ),
+            None => format!(
+                "SELECT 'owner' FROM {} WHERE id = $1 AND user_id = $2",
+                self.groups
+            ),
+        };
+        let access: Option<String> = sqlx::query_scalar(&sql)
+            .bind(group_id.0)
+            .bind(user_id.0)
+            .fetch_optional(db)
+            .await?;
+        access
+            .ok_or_else(|| ApiError::NotFound(format!("{} not found", self.noun)))?
+            .try_into()
+            .map_err(ApiError::Internal)
+    }
+
+    /// `ApiError::Forbidden` unless the group `group_id` belongs to `user_id`,
+    /// `ApiError::NotFound` if they cannot see it.
+    pub async fn require_group(
+        &self,
+        db: &PgPool,
+        user_id: DatabaseId,
+        group_id: DatabaseId,
+    ) -> Result<(), ApiError> {
+        match self.group_access(db, user_id, group_id).await? {
+            Access::Owner => Ok(()),
+            _ => Err(ApiError::Forbidden(format!(
+                "Only the owner of the {} can do this",
+                self.noun.to_lowercase()
+            ))),
+        }
+    }
+
+    /// The locations of the group that `user_id` still sees, by ID.
+    pub async fn members(
+        &self,
+        db: &PgPool,
+        user_id: DatabaseId,
+        group_id: DatabaseId,
+    ) -> Result<LocationIds, ApiError> {
+        self.group_access(db, user_id, group_id).await?;
+
+        let rows: Vec<(Option<i32>, Option<i32>)> = sqlx::query_as(&format!(
+            r#"
+            SELECT weather_location_id, natural_phenomenon_location_id
+            FROM {links}
+            WHERE {group_id} = $1
+              AND (weather_location_id IN (SELECT weather_location_id FROM weather_location_access
+                                           WHERE viewer_id = $2)
+                   OR natural_phenomenon_location_id IN
+                      (SELECT natural_phenomenon_location_id FROM natural_phenomenon_location_access
+                       WHERE viewer_id = $2))
+            ORDER BY weather_location_id, natural_phenomenon_location_id
+            "#,
+            links = self.links,
+            group_id = self.group_id,
+        ))
+        .bind(group_id.0)
+        .bind(user_id.0)
+        .fetch_all(db)
+        .await?;
+
+        let mut ids = LocationIds::default();
+        for (weather, phenomenon) in rows {
+            ids.weather_location_ids.extend(weather.map(DatabaseId));
+            ids.natural_phenomenon_location_ids
+                .extend(phenomenon.map(DatabaseId));
+        }
+        Ok(ids)
+    }
+
+    /// Add the locations to the group, skipping those already in it; returns all members.
+    ///
+    /// Every location must be one `user_id` sees, else `ApiError::Validation` and nothing is
+    /// added.
+    pub async fn add(
+        &self,
+        db: &PgPool,
+        user_id: DatabaseId,
+        group_id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError> {
+        self.require_group(db, user_id, group_id).await?;
+
+        let weather = raw_ids(&ids.weather_location_ids);
+        let phenomena = raw_ids(&ids.natural_phenomenon_location_ids);
+        let mut errors = Vec::new();
+        for (field, view, column, requested) in [
+            (
+                "weather_location_ids",
+                "weather_location_access",
+                "weather_location_id",
+                &weather,
+            ),
+            (
+                "natural_phenomenon_location_ids",
+                "natural_phenomenon_location_access",
+                "natural_phenomenon_location_id",
+                &phenomena,
+            ),
+        ] {
+            let visible: Vec<i32> = sqlx::query_scalar(&format!(
+                "SELECT {column} FROM {view} WHERE viewer_id = $1 AND {column} = ANY($2)"
+            ))
+            .bind(user_id.0)
+            .bind(requested)
+            .fetch_all(db)
+            .await?;
+            let unknown: Vec<String> = requested
+                .iter()
+                .filter(|id| !visible.contains(id))
+                .map(|id| id.to_string())
+                .collect();
+            if !unknown.is_empty() {
+                errors.push(FieldError::new(
+                    field,
+                    "unknown",
+                    format!("locations not found: {}", unknown.join(", ")),
+                ));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(ApiError::Validation(errors));
+        }
+
+        let mut tx = db.begin().await?;
+        for (column, requested) in [
+            ("weather_location_id", &weather),
+            ("natural_phenomenon_location_id", &phenomena),
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO {links} ({group_id}, {column})
+                 SELECT $1, unnest($2::int4[])
+                 ON CONFLICT DO NOTHING",
+                links = self.links,
+                group_id = self.group_id,
+            ))
+            .bind(group_id.0)
+            .bind(requested)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        self.members(db, user_id, group_id).await
+    }
+
+    /// Remove the locations from the group, ignoring those not in it; returns all members.
+    pub async fn remove(
+        &self,
+        db: &PgPool,
+        user_id: DatabaseId,
+        group_id: DatabaseId,
+        ids: &LocationIds,
+    ) -> Result<LocationIds, ApiError> {
+        self.require_group(db, user_id, group_id).await?;
+
+        sqlx::query(&format!(
+            "DELETE FROM {links}
+             WHERE {group_id} = $1
+               AND (weather_location_id = ANY($2) OR natural_phenomenon_location_id = ANY($3))",
+            links = self.links,
+            group_id = self.group_id,
+        ))
+        .bind(group_id.0)
+        .bind(raw_ids(&ids.weather_location_ids))
+        .bind(raw_ids(&ids.natural_phenomenon_location_ids))
+        .execute(db)
+        .await?;
+
+        self.members(db, user_id, group_id).await
+    }
+
+    /// Append ` AND id IN (...)` keeping the locations in the group `group_id` that `user_id`
+    /// sees; `location_column` is the link column of the listed kind.
+    pub fn push_filter(
+        &self,
+        query: &mut QueryBuilder<'static, Postgres>,
+        location_column: &str,
+        user_id: DatabaseId,
+        group_id: DatabaseId,
+    ) {
+        let (groups, viewer) = match self.access {
+            Some(view) => (format!("{} g ON g.{}", view, self.group_id), "viewer_id"),
+            None => (format!("{} g ON g.id", self.groups), "user_id"),
+        };
+        query
+            .push(format!(
+                " AND id IN (SELECT l.{location_column} FROM {links} l \
+                 JOIN {groups} = l.{group_id} WHERE l.{group_id} = ",
+                links = self.links,
+                group_id = self.group_id,
+            ))
+            .push_bind(group_id.0)
+            .push(format!(" AND g.{} = ", viewer))
+            .push_bind(user_id.0)
+            .push(")");
+    }
+}
+
+fn raw_ids(ids: &[DatabaseId]) -> Vec<i32> {
+    ids.iter().map(|id| id.0).collect()
+}
@@ -0,0 +1,157 @@
+use crate::shared::db::PoolStats;
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+pub const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+pub const HTTP_REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+pub const DB_POOL_CONNECTIONS: &str = "db_pool_connections";
+pub const DB_POOL_MAX_CONNECTIONS: &str = "db_pool_max_connections";
+pub const AUTH_FAILURES_TOTAL: &str = "auth_failures_total";
+pub const UPLOAD_BYTES_TOTAL: &str = "upload_bytes_total";
+pub const UPLOADS_TOTAL: &str = "uploads_total";
+pub const BACKGROUND_JOB_RUNS_TOTAL: &str = "background_job_runs_total";
+pub const RATE_LIMITED_TOTAL: &str = "rate_limited_requests_total";
+pub const HTTP_REQUESTS_ABORTED_TOTAL: &str = "http_requests_aborted_total";
+
+/// Label used for requests that did not match any route.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static PROMETHEUS: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Install the global Prometheus recorder once and return its handle.
+///
+/// Safe to call repeatedly (e.g. from every router built in tests).
+pub fn init_metrics() -> PrometheusHandle {
+    PROMETHEUS
+        .get_or_init(|| {
+            let handle = PrometheusBuilder::new()
+                .set_buckets_for_metric(
+                    Matcher::Full(HTTP_REQUEST_DURATION_SECONDS.to_string()),
+                    LATENCY_BUCKETS,
+                )
+                .expect("latency buckets must not be empty")
+                .install_recorder()
+                .expect("Failed to install Prometheus recorder");
+            describe_metrics();
+            handle
+        })
+        .clone()
+}
+
+fn describe_metrics() {
+    describe_counter!(
+        HTTP_REQUESTS_TOTAL,
+        "HTTP requests by method, route template and status"
+    );
+    describe_histogram!(
+        HTTP_REQUEST_DURATION_SECONDS,
+        metrics::Unit::Seconds,
+        "HTTP request latency by method and route template"
+    );
+    describe_counter!(
+        HTTP_REQUESTS_ABORTED_TOTAL,
+        "HTTP requests shed, timed out or abandoned by the client, by reason"
+    );
+    describe_gauge!(
+        DB_POOL_CONNECTIONS,
+        "Database pool connections by state (idle/in_use)"
+    );
+    describe_gauge!(DB_POOL_MAX_CONNECTIONS, "Configured pool size");
+    describe_counter!(AUTH_FAILURES_TOTAL, "Authentication failures by kind");
+    describe_counter!(
+        UPLOAD_BYTES_TOTAL,
+        metrics::Unit::Bytes,
+        "Bytes of uploaded files written to disk"
+    );
+    describe_counter!(UPLOADS_TOTAL, "Uploaded files written to disk");
+    describe_counter!(
+        BACKGROUND_JOB_RUNS_TOTAL,
+        "Background job runs by job and outcome"
+    );
+    describe_counter!(
+        RATE_LIMITED_TOTAL,
+        "Requests rejected by the rate limiter by budget"
+    );
+}
+
+/// Middleware recording request count and latency.
+///
+/// Requests are labelled with the matched route template (`/weather_locations/{id}`), never the
+/// raw path, so ids do not blow up the label cardinality.
+pub async fn track_metrics(req: Request<Body>, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+
+    let response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+    counter!(
+        HTTP_REQUESTS_TOTAL,
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status
+    )
+    .increment(1);
+    histogram!(
+        HTTP_REQUEST_DURATION_SECONDS,
+        "method" => method,
+        "route" => route
+    )
+    .record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Publish the current pool usage.
+pub fn record_pool_stats(stats: &PoolStats) {
+    gauge!(DB_POOL_CONNECTIONS, "state" => "idle").set(stats.idle as f64);
+    gauge!(DB_POOL_CONNECTIONS, "state" => "in_use").set(stats.in_use as f64);
+    gauge!(DB_POOL_MAX_CONNECTIONS).set(stats.max as f64);
+}
+
+/// Count a failed authentication attempt, labelled by the error kind.
+pub fn record_auth_failure(kind: &str) {
+    counter!(AUTH_FAILURES_TOTAL, "kind" => kind.to_owned()).increment(1);
+}
+
+/// Count an uploaded file and its size.
+pub fn record_upload(bytes: usize) {
+    counter!(UPLOADS_TOTAL).increment(1);
+    counter!(UPLOAD_BYTES_TOTAL).increment(bytes as u64);
+}
+
+/// Count a run of a background job (`outcome` is e.g. "success" or "failure").
+pub fn record_job_run(job: &str, outcome: &str) {
+    counter!(
+        BACKGROUND_JOB_RUNS_TOTAL,
+        "job" => job.to_owned(),
+        "outcome" => outcome.to_owned()
+    )
+    .increment(1);
+}
+
+/// Count a request rejected by the rate limiter.
+pub fn record_rate_limited(budget: &str) {
+    counter!(RATE_LIMITED_TOTAL, "budget" => budget.to_owned()).increment(1);
+}
+
+/// Count a request that was shed, timed out or abandoned by its client.
+pub fn record_aborted_request(reason: &str) {
+    counter!(HTTP_REQUESTS_ABORTED_TOTAL, "reason" => reason.to_owned()).increment(1);
+}
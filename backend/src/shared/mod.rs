@@ -1 +1,17 @@
+pub mod db;
+pub mod error;
+pub mod geo;
+pub mod http_cache;
+pub mod idempotency;
+pub mod limits;
+pub mod memberships;
+pub mod metrics;
 pub mod models;
+pub mod multipart;
+pub mod pagination;
+pub mod preconditions;
+pub mod rate_limit;
+pub mod telemetry;
+pub mod trash;
+pub mod validation;
+pub mod versioning;
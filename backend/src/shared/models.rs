@@ -1,12 +1,17 @@
 use crate::config::WapSettings;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
 use utoipa::ToSchema;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: sqlx::PgPool,
     pub settings: WapSettings,
+    /// Cancelled once graceful shutdown begins.
+    pub shutdown: CancellationToken,
+    /// When the process started.
+    pub started_at: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(
@@ -49,7 +54,3 @@ pub enum AppStage {
     Production,
     Testing,
 }
-
-pub enum GlobalError {
-    ServerError(String),
-}
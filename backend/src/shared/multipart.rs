@@ -0,0 +1,374 @@
+use crate::shared::error::{ApiError, FieldError};
+use axum::extract::{FromRequest, Multipart, Request};
+use std::collections::HashMap;
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::str::FromStr;
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+use validator::Validate;
+
+/// Size limit of a text field unless its `FieldSpec` says otherwise.
+pub const DEFAULT_TEXT_LIMIT: usize = 16 * 1024;
+
+/// What a multipart field carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Buffered in memory as UTF-8 text.
+    Text,
+    /// Streamed to a temporary file.
+    File,
+}
+
+/// Declaration of one accepted multipart field.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldSpec {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// Largest accepted size in bytes.
+    pub max_size: usize,
+}
+
+impl FieldSpec {
+    pub const fn text(name: &'static str) -> Self {
+        FieldSpec {
+            name,
+            kind: FieldKind::Text,
+            max_size: DEFAULT_TEXT_LIMIT,
+        }
+    }
+
+    pub const fn file(name: &'static str, max_size: usize) -> Self {
+        FieldSpec {
+            name,
+            kind: FieldKind::File,
+            max_size,
+        }
+    }
+}
+
+/// A file part written to a temporary file.
+///
+/// The file is removed when the value is dropped, unless it was moved away with `persist`.
+#[derive(Debug)]
+pub struct TempFile {
+    path: TempPath,
+
+    /// File name sent by the client, if any.
+    pub file_name: Option<String>,
+
+    /// Content type sent by the client, if any.
+    pub content_type: Option<String>,
+
+    /// Size in bytes.
+    pub size: usize,
+}
+
+impl TempFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the file to `dest`, copying when a rename is not possible.
+    pub async fn persist(self, dest: impl AsRef<Path>) -> std::io::Result<()> {
+        let dest = dest.as_ref();
+        match self.path.persist(dest) {
+            Ok(()) => {}
+            // renames fail across file systems; the temporary file is removed after the copy
+            Err(e) => {
+                tokio::fs::copy(&e.path, dest).await?;
+            }
+        }
+        // temporary files are private, stored uploads get the usual permissions
+        tokio::fs::set_permissions(dest, Permissions::from_mode(0o644)).await
+    }
+}
+
+/// Types that can be built from a multipart body, see `TypedMultipart`.
+pub trait FromMultipart: Sized {
+    /// Accepted fields; any other field is reported as an error.
+    const FIELDS: &'static [FieldSpec];
+
+    /// Build the value from the parsed `form`.
+    ///
+    /// Missing or malformed fields are recorded on the form and reported together, so this
+    /// never fails itself.
+    fn from_multipart(form: &mut MultipartForm) -> Self;
+}
+
+/// The parsed parts of a multipart body.
+#[derive(Debug, Default)]
+pub struct MultipartForm {
+    texts: HashMap<String, String>,
+    files: HashMap<String, TempFile>,
+    errors: Vec<FieldError>,
+}
+
+impl MultipartForm {
+    /// Read every part of `multipart`, enforcing the size limit of each field.
+    ///
+    /// Text fields are kept in memory, file fields are streamed to temporary files.
+    pub async fn read(multipart: &mut Multipart, fields: &[FieldSpec]) -> Result<Self, ApiError> {
+        let mut form = MultipartForm::default();
+
+        while let Some(mut field) = multipart.next_field().await? {
+            let name = field.name().unwrap_or_default().to_string();
+            let Some(spec) = fields.iter().find(|spec| spec.name == name) else {
+                form.error(&name, "unknown", "is not an accepted field");
+                continue;
+            };
+            if form.texts.contains_key(&name) || form.files.contains_key(&name) {
+                form.error(&name, "duplicate", "must only be sent once");
+                continue;
+            }
+
+            match spec.kind {
+                FieldKind::Text => {
+                    let mut buf = Vec::new();
+                    while let Some(chunk) = field.chunk().await? {
+                        if buf.len() + chunk.len() > spec.max_size {
+                            return Err(too_large(spec));
+                        }
+                        buf.extend_from_slice(&chunk);
+                    }
+                    match String::from_utf8(buf) {
+                        Ok(text) => {
+                            form.texts.insert(name, text);
+                        }
+                        Err(_) => form.error(&name, "type", "must be UTF-8 text"),
+                    }
+                }
+                FieldKind::File => {
+                    let file_name = field.file_name().map(str::to_string);
+                    let content_type = field.content_type().map(str::to_string);
+                    let (file, path) = tempfile::Builder::new()
+                        .prefix("upload-")
+                        .tempfile()?
+                        .into_parts();
+                    let mut file = tokio::fs::File::from_std(file);
+
+                    let mut size = 0;
+                    while let Some(chunk) = field.chunk().await? {
+                        size += chunk.len();
+                        if size > spec.max_size {
+                            return Err(too_large(spec));
+                        }
+                        file.write_all(&chunk).await?;
+                    }
+                    file.flush().await?;
+
+                    // browsers send an empty part for a file input left blank
+                    if size > 0 {
+                        let temp = TempFile {
+                            path,
+                            file_name,
+                            content_type,
+                            size,
+                        };
+                        form.files.insert(name, temp);
+                    }
+                }
+            }
+        }
+
+        Ok(form)
+    }
+
+    /// Required text field parsed as `T`.
+    ///
+    /// Returns `T::default()` and records an error when the field is missing or invalid.
+    pub fn required<T: FromStr + Default>(&mut self, name: &str) -> T {
+        if !self.texts.contains_key(name) {
+            self.error(name, "required", "is required");
+        }
+        self.optional(name).unwrap_or_default()
+    }
+
+    /// Optional text field parsed as `T`; an invalid value is recorded as an error.
+    pub fn optional<T: FromStr>(&mut self, name: &str) -> Option<T> {
+        let text = self.texts.get(name)?;
+        match text.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                let message = format!("must be a valid {}", type_label::<T>());
+                self.error(name, "type", message);
+                None
+            }
+        }
+    }
+
+    /// Optional file field.
+    pub fn file(&mut self, name: &str) -> Option<TempFile> {
+        self.files.remove(name)
+    }
+
+    /// Fail with all recorded field errors, if any.
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        let mut errors = self.errors;
+        errors.sort_by(|a, b| a.field.cmp(&b.field));
+        Err(ApiError::Validation(errors))
+    }
+
+    fn error(&mut self, field: &str, code: &str, message: impl Into<String>) {
+        self.errors.push(FieldError::new(field, code, message));
+    }
+}
+
+/// Human readable name of the expected type, e.g. `number` for `f64`.
+fn type_label<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    match name.rsplit("::").next().unwrap_or(name) {
+        "f32" | "f64" => "number",
+        "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" | "usize" | "DatabaseId" => {
+            "integer"
+        }
+        "bool" => "boolean",
+        _ => "value",
+    }
+}
+
+fn too_large(spec: &FieldSpec) -> ApiError {
+    ApiError::PayloadTooLarge(format!(
+        "Field `{}` exceeds {} bytes",
+        spec.name, spec.max_size
+    ))
+}
+
+/// `multipart/form-data` extractor producing a `FromMultipart` type.
+///
+/// Unknown, duplicate, missing and mistyped fields are reported per field with a 422, as are
+/// failed `validator` rules of `T`.
+#[derive(Debug)]
+pub struct TypedMultipart<T>(pub T);
+
+impl<T, S> FromRequest<S> for TypedMultipart<T>
+where
+    T: FromMultipart + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let mut multipart = Multipart::from_request(req, state).await?;
+        let mut form = MultipartForm::read(&mut multipart, T::FIELDS).await?;
+        let value = T::from_multipart(&mut form);
+        form.finish()?;
+        value.validate()?;
+        Ok(TypedMultipart(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, StatusCode};
+
+    #[derive(Debug, Validate)]
+    struct Upload {
+        #[validate(length(min = 1))]
+        name: String,
+        radius: i32,
+        image: Option<TempFile>,
+    }
+
+    impl FromMultipart for Upload {
+        const FIELDS: &'static [FieldSpec] = &[
+            FieldSpec::text("name"),
+            FieldSpec::text("radius"),
+            FieldSpec::file("image", 8),
+        ];
+
+        fn from_multipart(form: &mut MultipartForm) -> Self {
+            Upload {
+                name: form.required("name"),
+                radius: form.required("radius"),
+                image: form.file("image"),
+            }
+        }
+    }
+
+    fn request(parts: &[(&str, Option<&str>, &str)]) -> Request {
+        let mut body = String::new();
+        for (name, file_name, value) in parts {
+            body.push_str("--X\r\n");
+            match file_name {
+                Some(file_name) => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n",
+                    name, file_name
+                )),
+                None => body.push_str(&format!(
+                    "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                    name
+                )),
+            }
+            body.push_str(value);
+            body.push_str("\r\n");
+        }
+        body.push_str("--X--\r\n");
+        Request::builder()
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=X")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn extract(parts: &[(&str, Option<&str>, &str)]) -> Result<Upload, ApiError> {
+        TypedMultipart::<Upload>::from_request(request(parts), &())
+            .await
+            .map(|TypedMultipart(upload)| upload)
+    }
+
+    #[tokio::test]
+    async fn test_typed_multipart() {
+        let upload = extract(&[
+            ("name", None, "Etna"),
+            ("radius", None, " 25 "),
+            ("image", Some("etna.png"), "png!"),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(upload.name, "Etna");
+        assert_eq!(upload.radius, 25);
+
+        // the file is streamed to disk and removed once dropped
+        let image = upload.image.unwrap();
+        let path = image.path().to_path_buf();
+        assert_eq!(image.file_name.as_deref(), Some("etna.png"));
+        assert_eq!(image.size, 4);
+        assert_eq!(std::fs::read(&path).unwrap(), b"png!");
+        drop(image);
+        assert!(!path.exists());
+
+        // every bad field is reported, not just the first one
+        let Err(ApiError::Validation(errors)) =
+            extract(&[("radius", None, "far"), ("colour", None, "red")]).await
+        else {
+            panic!("expected a validation error");
+        };
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| (e.field.as_str(), e.code.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("colour", "unknown"),
+                ("name", "required"),
+                ("radius", "type")
+            ]
+        );
+
+        let err = extract(&[
+            ("name", None, "Etna"),
+            ("radius", None, "25"),
+            ("image", Some("etna.png"), "too large!"),
+        ])
+        .await
+        .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
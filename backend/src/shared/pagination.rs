@@ -0,0 +1,332 @@
+use crate::shared::error::{ApiError, FieldError};
+use crate::shared::models::DatabaseId;
+use axum::http::{header, HeaderValue, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::{FromRow, Postgres, QueryBuilder, Row};
+use utoipa::ToSchema;
+
+/// Page size used when a request does not ask for one.
+pub const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+/// Largest page size a client may ask for.
+pub const MAX_PAGE_LIMIT: u32 = 200;
+
+/// One page of a list endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    /// Items of this page.
+    pub items: Vec<T>,
+
+    /// Pass as `cursor` to fetch the next page; `null` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+
+    /// JSON response with `Link` headers to the first and the next page of `uri`.
+    pub fn into_response_with_links(self, uri: &Uri) -> Response
+    where
+        T: Serialize,
+    {
+        let mut links = vec![format!("<{}>; rel=\"first\"", page_uri(uri, None))];
+        if let Some(cursor) = &self.next_cursor {
+            links.push(format!("<{}>; rel=\"next\"", page_uri(uri, Some(cursor))));
+        }
+
+        let mut response = Json(self).into_response();
+        if let Ok(value) = HeaderValue::from_str(&links.join(", ")) {
+            response.headers_mut().insert(header::LINK, value);
+        }
+        response
+    }
+}
+
+/// `uri` with its `cursor` query parameter replaced.
+fn page_uri(uri: &Uri, cursor: Option<&str>) -> String {
+    let mut params: Vec<String> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("cursor="))
+        .map(str::to_string)
+        .collect();
+    // cursors are URL-safe base64, they need no escaping
+    if let Some(cursor) = cursor {
+        params.push(format!("cursor={}", cursor));
+    }
+
+    if params.is_empty() {
+        uri.path().to_string()
+    } else {
+        format!("{}?{}", uri.path(), params.join("&"))
+    }
+}
+
+/// Direction of a sort.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    fn sql(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+
+    /// Row comparison selecting the items after the cursor.
+    fn after(self) -> &'static str {
+        match self {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        }
+    }
+}
+
+/// Position after the last item of a page, handed to clients as an opaque string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cursor {
+    /// Sort the cursor was issued for.
+    sort: String,
+
+    order: SortOrder,
+
+    /// Sort key of the last item; rendered by Postgres for database lists.
+    key: String,
+
+    /// Id of the last item, breaks ties between equal keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<DatabaseId>,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Result<Self, ApiError> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| cursor_error("is not a valid cursor"))
+    }
+}
+
+fn cursor_error(message: &str) -> ApiError {
+    ApiError::Validation(vec![FieldError::new("cursor", "invalid", message)])
+}
+
+/// A row together with the keyset columns selected by `PageRequest::select`.
+#[derive(Debug)]
+pub struct Keyed<T> {
+    pub item: T,
+    id: DatabaseId,
+    sort_key: String,
+}
+
+impl<'r, T: FromRow<'r, PgRow>> FromRow<'r, PgRow> for Keyed<T> {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Keyed {
+            item: T::from_row(row)?,
+            id: row.try_get("id")?,
+            sort_key: row.try_get("sort_key")?,
+        })
+    }
+}
+
+/// Limit, sort and position of one list request.
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    pub limit: u32,
+    pub sort: &'static str,
+    pub order: SortOrder,
+    after: Option<Cursor>,
+}
+
+impl PageRequest {
+    /// Check the `cursor` of a request; it must come from a page with the same sort.
+    pub fn new(
+        limit: Option<u32>,
+        cursor: Option<&str>,
+        sort: &'static str,
+        order: SortOrder,
+    ) -> Result<Self, ApiError> {
+        let after = cursor.map(Cursor::decode).transpose()?;
+        if let Some(after) = &after {
+            if after.sort != sort || after.order != order {
+                return Err(cursor_error("was issued for a different sort"));
+            }
+        }
+
+        Ok(PageRequest {
+            limit: limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT),
+            sort,
+            order,
+            after,
+        })
+    }
+
+    /// Start a `SELECT * FROM table` query, also selecting the keyset of `sort_expr`; the
+    /// caller goes on with joins and ` WHERE `.
+    ///
+    /// `sort_expr` is inserted as is and must not contain user input.
+    pub fn select(&self, table: &str, sort_expr: &str) -> QueryBuilder<'static, Postgres> {
+        QueryBuilder::new(format!(
+            "SELECT *, ({})::text AS sort_key FROM {}",
+            sort_expr, table
+        ))
+    }
+
+    /// Finish a query from `select` with the cursor condition, ordering and limit.
+    ///
+    /// `sort_type` is the Postgres type of `sort_expr`, used to read the cursor key back.
+    pub fn push_keyset(
+        &self,
+        query: &mut QueryBuilder<'static, Postgres>,
+        sort_expr: &str,
+        sort_type: &str,
+    ) -> Result<(), ApiError> {
+        if let Some(after) = &self.after {
+            let id = after
+                .id
+                .ok_or_else(|| cursor_error("is not a valid cursor"))?;
+            query
+                .push(format!(
+                    " AND (({}), id) {} (",
+                    sort_expr,
+                    self.order.after()
+                ))
+                .push_bind(after.key.clone())
+                .push(format!("::{}, ", sort_type))
+                .push_bind(id.0)
+                .push(")");
+        }
+
+        let order = self.order.sql();
+        query
+            .push(format!(
+                " ORDER BY ({}) {}, id {} LIMIT ",
+                sort_expr, order, order
+            ))
+            // one extra row tells whether there is a next page
+            .push_bind(i64::from(self.limit) + 1);
+        Ok(())
+    }
+
+    /// Turn the rows of a `push_keyset` query into a page.
+    pub fn finish<T>(&self, mut rows: Vec<Keyed<T>>) -> Page<T> {
+        let more = rows.len() > self.limit as usize;
+        rows.truncate(self.limit as usize);
+
+        let next_cursor = match rows.last() {
+            Some(last) if more => Some(self.cursor(last.sort_key.clone(), Some(last.id))),
+            _ => None,
+        };
+        Page {
+            items: rows.into_iter().map(|row| row.item).collect(),
+            next_cursor,
+        }
+    }
+
+    /// Sort and page items held in memory.
+    ///
+    /// `key` must be unique per item and order the items like the requested sort.
+    pub fn paginate<T>(&self, mut items: Vec<T>, key: impl Fn(&T) -> String) -> Page<T> {
+        items.sort_by_cached_key(|item| key(item));
+        if self.order == SortOrder::Desc {
+            items.reverse();
+        }
+        items.retain(|item| self.follows(&key(item)));
+
+        let more = items.len() > self.limit as usize;
+        items.truncate(self.limit as usize);
+        let next_cursor = match items.last() {
+            Some(last) if more => Some(self.cursor(key(last), None)),
+            _ => None,
+        };
+        Page { items, next_cursor }
+    }
+
+    /// Whether an item with the `paginate` key `key` comes after the cursor.
+    pub fn follows(&self, key: &str) -> bool {
+        match (&self.after, self.order) {
+            (None, _) => true,
+            (Some(after), SortOrder::Asc) => key > after.key.as_str(),
+            (Some(after), SortOrder::Desc) => key < after.key.as_str(),
+        }
+    }
+
+    /// Sort key of the item the cursor points after.
+    pub fn after_key(&self) -> Option<&str> {
+        self.after.as_ref().map(|after| after.key.as_str())
+    }
+
+    /// The same request continuing after `row`, for fetching more rows than one query gave.
+    pub fn after_row<T>(&self, row: &Keyed<T>) -> PageRequest {
+        PageRequest {
+            after: Some(Cursor {
+                sort: self.sort.to_string(),
+                order: self.order,
+                key: row.sort_key.clone(),
+                id: Some(row.id),
+            }),
+            ..self.clone()
+        }
+    }
+
+    fn cursor(&self, key: String, id: Option<DatabaseId>) -> String {
+        Cursor {
+            sort: self.sort.to_string(),
+            order: self.order,
+            key,
+            id,
+        }
+        .encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginate_follows_cursors() {
+        let first = PageRequest::new(Some(2), None, "name", SortOrder::Desc).unwrap();
+        let page = first.paginate(vec!["b", "d", "a", "c", "e"], |s| s.to_string());
+        assert_eq!(page.items, vec!["e", "d"]);
+
+        let cursor = page.next_cursor.unwrap();
+        let uri: Uri = "/uploads?limit=2&cursor=old&order=desc".parse().unwrap();
+        assert_eq!(
+            page_uri(&uri, Some(&cursor)),
+            format!("/uploads?limit=2&order=desc&cursor={}", cursor)
+        );
+
+        let next = PageRequest::new(Some(2), Some(&cursor), "name", SortOrder::Desc).unwrap();
+        let page = next.paginate(vec!["b", "d", "a", "c", "e"], |s| s.to_string());
+        assert_eq!(page.items, vec!["c", "b"]);
+        assert!(page.next_cursor.is_some());
+
+        // a cursor only fits the sort it was issued for
+        let err = PageRequest::new(None, Some(&cursor), "name", SortOrder::Asc).unwrap_err();
+        assert!(matches!(err, ApiError::Validation(_)));
+        assert!(PageRequest::new(None, Some("garbage"), "name", SortOrder::Asc).is_err());
+    }
+}
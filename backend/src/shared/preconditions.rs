@@ -0,0 +1,190 @@
+use crate::config::ConcurrencySettings;
+use crate::shared::error::ApiError;
+use axum::body::Body;
+use axum::extract::{FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use std::convert::Infallible;
+
+/// Strong ETag of a row version, e.g. `"v3"`.
+///
+/// Compressed responses carry it with the content coding appended, e.g. `"v3-gzip"`, see
+/// `shared::http_cache`.
+pub fn version_etag(version: i32) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"v{}\"", version)).expect("digits are a valid header value")
+}
+
+/// The `If-Match` header of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfMatch {
+    /// No `If-Match`, the write is unconditional.
+    Absent,
+    /// `If-Match: *`, any existing version matches.
+    Any,
+    /// The listed versions; weak and foreign ETags never match, so this may be empty.
+    Versions(Vec<i32>),
+}
+
+impl IfMatch {
+    pub fn from_headers(headers: &HeaderMap) -> IfMatch {
+        let mut values = headers.get_all(header::IF_MATCH).iter().peekable();
+        if values.peek().is_none() {
+            return IfMatch::Absent;
+        }
+        let tags: Vec<&str> = values
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        if tags.contains(&"*") {
+            return IfMatch::Any;
+        }
+        // RFC 9110 uses the strong comparison for If-Match; every encoding of a version is
+        // that version
+        IfMatch::Versions(
+            tags.into_iter()
+                .filter_map(|tag| {
+                    let tag = tag.strip_prefix("\"v")?.strip_suffix('"')?;
+                    let version = tag.split_once('-').map_or(tag, |(version, _)| version);
+                    version.parse().ok()
+                })
+                .collect(),
+        )
+    }
+
+    /// The versions a write must match, `None` when any version will do.
+    pub fn versions(&self) -> Option<Vec<i32>> {
+        match self {
+            IfMatch::Absent | IfMatch::Any => None,
+            IfMatch::Versions(versions) => Some(versions.clone()),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(IfMatch::from_headers(&parts.headers))
+    }
+}
+
+/// 412 carrying the current representation and its ETag, so the client can merge and retry.
+pub fn precondition_failed<T: Serialize>(current: &T, version: i32) -> Response {
+    (
+        StatusCode::PRECONDITION_FAILED,
+        [(header::ETAG, version_etag(version))],
+        Json(current),
+    )
+        .into_response()
+}
+
+/// Middleware rejecting updates and deletes without `If-Match` with a 428, when enabled.
+pub async fn require_if_match(
+    State(settings): State<ConcurrencySettings>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let is_write = matches!(*req.method(), Method::PUT | Method::PATCH | Method::DELETE);
+    if settings.require_if_match && is_write && !req.headers().contains_key(header::IF_MATCH) {
+        return Err(ApiError::PreconditionRequired(
+            "Send the ETag of the resource in If-Match".to_string(),
+        ));
+    }
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::natural_phenomenon_locations::handlers::router;
+    use crate::tests::tests::TestApp;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_parse_if_match() {
+        let parse = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+            IfMatch::from_headers(&headers)
+        };
+        assert_eq!(IfMatch::from_headers(&HeaderMap::new()), IfMatch::Absent);
+        assert_eq!(parse("*"), IfMatch::Any);
+        assert_eq!(parse("\"v3\", \"v4\""), IfMatch::Versions(vec![3, 4]));
+        assert_eq!(parse("\"v3-gzip\""), IfMatch::Versions(vec![3]));
+        assert_eq!(parse("W/\"v3\", \"abc\""), IfMatch::Versions(vec![]));
+        assert_eq!(
+            parse(version_etag(7).to_str().unwrap()),
+            IfMatch::Versions(vec![7])
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_conditional_update(pool: PgPool) {
+        let mut test_app = TestApp::new(pool.clone()).await;
+        let user = test_app.users[0].clone();
+        let id: i32 = sqlx::query_scalar(
+            "INSERT INTO natural_phenomenon_locations (user_id, name, latitude, longitude, radius, image_path)
+             VALUES ($1, 'Etna', 37.75, 14.99, 10, NULL) RETURNING id",
+        )
+        .bind(user.user.id.0)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let uri = format!("/natural_phenomenon_locations/{}", id);
+
+        test_app.app.settings.concurrency.require_if_match = true;
+        let (router, _) = router(test_app.app.clone()).split_for_parts();
+        let call = |method: Method, if_match: Option<HeaderValue>, body: &'static str| {
+            let mut req = Request::builder()
+                .method(method)
+                .uri(&uri)
+                .header(header::CONTENT_TYPE, "application/json");
+            for (name, value) in &user.header {
+                req = req.header(name, value);
+            }
+            if let Some(if_match) = if_match {
+                req = req.header(header::IF_MATCH, if_match);
+            }
+            router.clone().oneshot(req.body(Body::from(body)).unwrap())
+        };
+
+        let response = call(Method::GET, None, "").await.unwrap();
+        assert_eq!(response.headers()[header::ETAG], version_etag(1));
+
+        let response = call(Method::PUT, None, r#"{"radius":20}"#).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+        let response = call(Method::PUT, Some(version_etag(1)), r#"{"radius":20}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], version_etag(2));
+
+        // a second device still holding version 1 gets the current state instead
+        let response = call(Method::PUT, Some(version_etag(1)), r#"{"radius":30}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(response.headers()[header::ETAG], version_etag(2));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let current: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(current["radius"], 20);
+        assert_eq!(current["version"], 2);
+
+        let response = call(Method::DELETE, Some(version_etag(1)), "")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let response = call(Method::DELETE, Some(version_etag(2)), "")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -0,0 +1,502 @@
+use crate::config::{RateBudget, RateLimitSettings, RateLimitStoreKind};
+use crate::routes::auth::models::TokenClaims;
+use crate::shared::error::ApiError;
+use crate::shared::limits::RouteClass;
+use crate::shared::metrics::{record_job_run, record_rate_limited};
+use crate::shared::models::AppState;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+pub const RATE_LIMIT_LIMIT_HEADER: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATE_LIMIT_REMAINING_HEADER: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATE_LIMIT_RESET_HEADER: HeaderName = HeaderName::from_static("ratelimit-reset");
+pub const RATE_LIMIT_POLICY_HEADER: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Number of buckets from which the memory store drops the full ones.
+const MEMORY_PRUNE_THRESHOLD: usize = 10_000;
+
+/// Outcome of taking a token from a bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// Size of the bucket.
+    pub limit: u32,
+    /// Whole tokens left after this request.
+    pub remaining: u32,
+    /// Until the bucket is full again.
+    pub reset: Duration,
+    /// Until the next token is available; zero when allowed.
+    pub retry_after: Duration,
+}
+
+/// Refill a bucket holding `tokens` for `elapsed` and try to take one token.
+///
+/// Returns the tokens left in the bucket and the decision. A new bucket starts full.
+pub fn take_token(tokens: f64, elapsed: Duration, budget: &RateBudget) -> (f64, RateLimitDecision) {
+    let capacity = f64::from(budget.requests);
+    let per_sec = capacity / budget.period.as_secs_f64();
+    let mut tokens = (tokens + elapsed.as_secs_f64() * per_sec).min(capacity);
+
+    let allowed = tokens >= 1.0;
+    let retry_after = if allowed {
+        tokens -= 1.0;
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64((1.0 - tokens) / per_sec)
+    };
+
+    let decision = RateLimitDecision {
+        allowed,
+        limit: budget.requests,
+        remaining: tokens.floor() as u32,
+        reset: Duration::from_secs_f64((capacity - tokens) / per_sec),
+        retry_after,
+    };
+    (tokens, decision)
+}
+
+/// Keeps the token buckets.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket of `key`.
+    async fn take(&self, key: &str, budget: &RateBudget) -> anyhow::Result<RateLimitDecision>;
+}
+
+/// Buckets in process memory; each instance limits on its own.
+#[derive(Debug, Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    period: Duration,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(&self, key: &str, budget: &RateBudget) -> anyhow::Result<RateLimitDecision> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MEMORY_PRUNE_THRESHOLD {
+            // a bucket idle for its whole period is full, forgetting it changes nothing
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < bucket.period);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: f64::from(budget.requests),
+            updated_at: now,
+            period: budget.period,
+        });
+        let (tokens, decision) =
+            take_token(bucket.tokens, now.duration_since(bucket.updated_at), budget);
+        bucket.tokens = tokens;
+        bucket.updated_at = now;
+        bucket.period = budget.period;
+        Ok(decision)
+    }
+}
+
+/// Buckets in the `rate_limit_buckets` table, shared by every instance.
+///
+/// Idle buckets are deleted by `spawn_rate_limit_prune`.
+#[derive(Debug)]
+pub struct PostgresRateLimitStore {
+    db: PgPool,
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(db: PgPool) -> Self {
+        PostgresRateLimitStore { db }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresRateLimitStore {
+    async fn take(&self, key: &str, budget: &RateBudget) -> anyhow::Result<RateLimitDecision> {
+        // `take_token` in one statement, the row lock of the upsert serializes concurrent
+        // requests of the same key. A denied request leaves the bucket as it was, so the
+        // request was allowed when the row has the `now()` of this statement.
+        let refilled =
+            "least($2, b.tokens + greatest(0, EXTRACT(EPOCH FROM now() - b.updated_at)) * $2 / $3)";
+        let (tokens, allowed, elapsed): (f64, bool, f64) = sqlx::query_as(&format!(
+            "INSERT INTO rate_limit_buckets AS b (key, tokens, updated_at) VALUES ($1, $2 - 1, now()) \
+             ON CONFLICT (key) DO UPDATE SET \
+             tokens = CASE WHEN {r} >= 1 THEN {r} - 1 ELSE b.tokens END, \
+             updated_at = CASE WHEN {r} >= 1 THEN now() ELSE b.updated_at END \
+             RETURNING tokens, updated_at = now(), \
+             greatest(0, EXTRACT(EPOCH FROM now() - updated_at))::float8",
+            r = refilled
+        ))
+        .bind(key)
+        .bind(f64::from(budget.requests))
+        .bind(budget.period.as_secs_f64())
+        .fetch_one(&self.db)
+        .await?;
+
+        // take again from the bucket as it was before, for the rest of the decision
+        let (_, decision) = if allowed {
+            take_token(tokens + 1.0, Duration::ZERO, budget)
+        } else {
+            take_token(tokens, Duration::from_secs_f64(elapsed), budget)
+        };
+        Ok(decision)
+    }
+}
+
+/// Periodically delete the buckets of the Postgres store idle for a day, they are full again
+/// by now. Nothing is started for the memory store, it prunes itself.
+///
+/// The task stops when `token` is cancelled.
+pub fn spawn_rate_limit_prune(
+    pool: PgPool,
+    settings: &RateLimitSettings,
+    token: CancellationToken,
+) {
+    if !settings.enabled || settings.store != RateLimitStoreKind::Postgres {
+        return;
+    }
+    let interval = settings.prune_interval;
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+
+            let result = sqlx::query(
+                "DELETE FROM rate_limit_buckets WHERE updated_at < now() - interval '1 day'",
+            )
+            .execute(&pool)
+            .await;
+            match result {
+                Ok(done) => {
+                    debug!(deleted = done.rows_affected(), "Pruned rate limit buckets");
+                    record_job_run("rate_limit_prune", "success");
+                }
+                Err(e) => {
+                    warn!("Failed to prune rate limit buckets: {}", e);
+                    record_job_run("rate_limit_prune", "failure");
+                }
+            }
+        }
+        debug!("Rate limit prune stopped");
+    });
+}
+
+/// State of the `rate_limit` middleware.
+#[derive(Clone)]
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    jwt_secret: String,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+    /// Limiter using the store configured in the settings of `app`.
+    pub fn new(app: &AppState) -> Self {
+        let store: Arc<dyn RateLimitStore> = match app.settings.rate_limit.store {
+            RateLimitStoreKind::Memory => Arc::new(MemoryRateLimitStore::default()),
+            RateLimitStoreKind::Postgres => Arc::new(PostgresRateLimitStore::new(app.db.clone())),
+        };
+        RateLimiter::with_store(
+            app.settings.rate_limit.clone(),
+            app.settings.jwt_secret.clone(),
+            store,
+        )
+    }
+
+    pub fn with_store(
+        settings: RateLimitSettings,
+        jwt_secret: String,
+        store: Arc<dyn RateLimitStore>,
+    ) -> Self {
+        RateLimiter {
+            settings,
+            jwt_secret,
+            store,
+        }
+    }
+
+    /// Name and budget of a route; `None` for routes that are never limited.
+    fn budget(&self, method: &Method, path: &str) -> Option<(&'static str, RateBudget)> {
+        match RouteClass::of(method, path) {
+            RouteClass::Probe => None,
+            RouteClass::Auth => Some(("auth", self.settings.auth)),
+            RouteClass::Upload => Some(("upload", self.settings.upload)),
+            RouteClass::Api => Some(("default", self.settings.default)),
+        }
+    }
+
+    /// Bucket key of a request: the authenticated user, else the client address.
+    ///
+    /// The auth budget is always keyed by address, it guards the routes handing out tokens.
+    fn key(&self, budget: &str, req: &Request<Body>) -> String {
+        let user = (budget != "auth")
+            .then(|| self.user_id(req.headers()))
+            .flatten();
+        match user {
+            Some(user_id) => format!("{}:user:{}", budget, user_id),
+            None => {
+                let peer = req
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip());
+                match peer {
+                    Some(peer) => format!("{}:ip:{}", budget, self.client_ip(peer, req.headers())),
+                    None => format!("{}:ip:unknown", budget),
+                }
+            }
+        }
+    }
+
+    /// Subject of a valid bearer token.
+    fn user_id(&self, headers: &HeaderMap) -> Option<String> {
+        let token = headers
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+        decode::<TokenClaims>(
+            token,
+            &DecodingKey::from_secret(self.jwt_secret.as_ref()),
+            &Validation::default(),
+        )
+        .ok()
+        .map(|data| data.claims.sub)
+    }
+
+    /// Address of the client, following `X-Forwarded-For` only through trusted proxies.
+    ///
+    /// Hops are read from the right, the last one was added by the proxy we talk to; the first
+    /// hop not sent by a trusted proxy is the client.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let hops: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            if !self.is_trusted(client) {
+                break;
+            }
+            match hop.parse::<IpAddr>() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.settings
+            .trusted_proxies
+            .iter()
+            .any(|net| net.contains(&ip))
+    }
+}
+
+/// Middleware limiting requests per user or client address with token buckets.
+///
+/// Limited responses carry the `RateLimit-*` headers; rejected requests get a 429 with
+/// `Retry-After`. When the store fails the request is let through.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if !limiter.settings.enabled {
+        return next.run(req).await;
+    }
+    let Some((name, budget)) = limiter.budget(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+
+    let key = limiter.key(name, &req);
+    let decision = match limiter.store.take(&key, &budget).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            warn!(error = %e, "Rate limit store failed, not limiting the request");
+            return next.run(req).await;
+        }
+    };
+
+    let mut response = if decision.allowed {
+        next.run(req).await
+    } else {
+        record_rate_limited(name);
+        let retry_after = ceil_secs(decision.retry_after);
+        let mut response = ApiError::TooManyRequests(format!(
+            "Rate limit exceeded, retry in {} seconds",
+            retry_after
+        ))
+        .into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        response
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(
+        RATE_LIMIT_REMAINING_HEADER,
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(
+        RATE_LIMIT_RESET_HEADER,
+        HeaderValue::from(ceil_secs(decision.reset)),
+    );
+    if let Ok(policy) = HeaderValue::from_str(&format!(
+        "{};w={}",
+        budget.requests,
+        budget.period.as_secs()
+    )) {
+        headers.insert(RATE_LIMIT_POLICY_HEADER, policy);
+    }
+    response
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::tests::init_app_state;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_token_bucket_refills() {
+        let budget = RateBudget::new(2, 10);
+        let (tokens, first) = take_token(2.0, Duration::ZERO, &budget);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+
+        let (tokens, _) = take_token(tokens, Duration::ZERO, &budget);
+        let (tokens, denied) = take_token(tokens, Duration::ZERO, &budget);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after, Duration::from_secs(5));
+        assert_eq!(denied.reset, Duration::from_secs(10));
+
+        // one token comes back every five seconds
+        let (_, later) = take_token(tokens, Duration::from_secs(5), &budget);
+        assert!(later.allowed);
+    }
+
+    #[test]
+    fn test_client_ip_trusts_only_configured_proxies() {
+        let settings = RateLimitSettings {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        };
+        let limiter = RateLimiter::with_store(
+            settings,
+            "secret".to_string(),
+            Arc::new(MemoryRateLimitStore::default()),
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED_FOR_HEADER,
+            HeaderValue::from_static("6.6.6.6, 1.2.3.4, 10.0.0.7"),
+        );
+
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            limiter.client_ip(proxy, &headers),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+        // anyone else could send any header
+        let stranger: IpAddr = "5.5.5.5".parse().unwrap();
+        assert_eq!(limiter.client_ip(stranger, &headers), stranger);
+    }
+
+    #[sqlx::test]
+    async fn test_postgres_store_refills(pool: PgPool) {
+        let store = PostgresRateLimitStore::new(pool.clone());
+        let budget = RateBudget::new(2, 10);
+        assert_eq!(store.take("k", &budget).await.unwrap().remaining, 1);
+        assert!(store.take("k", &budget).await.unwrap().allowed);
+
+        let denied = store.take("k", &budget).await.unwrap();
+        assert!(!denied.allowed);
+        assert!(denied.retry_after > Duration::from_secs(4));
+        assert!(denied.reset > Duration::from_secs(9));
+
+        // a denied request takes nothing, the bucket refills from the last allowed one
+        sqlx::query("UPDATE rate_limit_buckets SET updated_at = updated_at - interval '5 seconds'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let later = store.take("k", &budget).await.unwrap();
+        assert!(later.allowed);
+        assert_eq!(later.remaining, 0);
+        assert!(!store.take("k", &budget).await.unwrap().allowed);
+    }
+
+    #[sqlx::test]
+    async fn test_rate_limit_middleware(pool: PgPool) {
+        let mut app = init_app_state(pool).await;
+        app.settings.rate_limit.store = RateLimitStoreKind::Postgres;
+        app.settings.rate_limit.auth = RateBudget::new(2, 60);
+        let router = Router::new()
+            .route("/auth/login", get(|| async { "ok" }))
+            .route("/healthz", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                RateLimiter::new(&app),
+                rate_limit,
+            ));
+
+        let call = |path: &'static str| {
+            let router = router.clone();
+            async move {
+                router
+                    .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let response = call("/auth/login").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[RATE_LIMIT_REMAINING_HEADER], "1");
+        assert_eq!(response.headers()[RATE_LIMIT_POLICY_HEADER], "2;w=60");
+        call("/auth/login").await;
+
+        let response = call("/auth/login").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+
+        // health checks are never limited
+        let response = call("/healthz").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(RATE_LIMIT_LIMIT_HEADER).is_none());
+    }
+}
@@ -0,0 +1,400 @@
+use crate::config::{LogFormat, LogRotation, WapSettings, WapSettingsImpl};
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::path::Path;
+use tracing::{info, warn, Instrument, Level};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Header carrying the request id, generated by `SetRequestIdLayer` when the client sent none.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// W3C trace context header.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Keeps the tracer provider and the log file writer alive; call `shutdown` to flush pending
+/// spans on exit.
+pub struct TelemetryGuard {
+    provider: SdkTracerProvider,
+    _log_file: Option<WorkerGuard>,
+}
+
+impl TelemetryGuard {
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.shutdown() {
+            warn!("Failed to flush spans: {}", e);
+        }
+    }
+}
+
+/// Subscriber the formatting layers are stacked on.
+type FilteredRegistry = Layered<EnvFilter, Registry>;
+
+/// Build the OpenTelemetry tracer provider.
+///
+/// Spans always get W3C trace ids so logs and `traceparent` headers can be correlated; they are
+/// only exported when an OTLP endpoint is configured. Exporter errors are returned alongside the
+/// provider because the subscriber that could log them is not installed yet.
+fn tracer_provider(settings: &WapSettings) -> (SdkTracerProvider, Option<String>) {
+    let resource = Resource::builder()
+        .with_service_name(settings.telemetry.service_name.clone())
+        .with_attribute(KeyValue::new(
+            "service.version",
+            env!("CARGO_PKG_VERSION").to_string(),
+        ))
+        .build();
+    let builder = SdkTracerProvider::builder().with_resource(resource);
+
+    match &settings.telemetry.otlp_endpoint {
+        Some(endpoint) => match opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint.clone())
+            .build()
+        {
+            Ok(exporter) => (builder.with_batch_exporter(exporter).build(), None),
+            Err(e) => (
+                builder.build(),
+                Some(format!(
+                    "Failed to create OTLP exporter for {}: {}",
+                    endpoint, e
+                )),
+            ),
+        },
+        None => (builder.build(), None),
+    }
+}
+
+/// Formatting layer for the configured log format.
+///
+/// Every format carries a UTC RFC 3339 timestamp. The JSON format puts the event fields at the
+/// top level and the request metadata (`method`, `route`, `request_id`, `trace_id`, `user_id`)
+/// under `span`, so the field names stay the same for every log line.
+fn fmt_layer<W>(
+    format: LogFormat,
+    writer: W,
+    ansi: bool,
+) -> Box<dyn Layer<FilteredRegistry> + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_file(true)
+        .with_line_number(true);
+
+    match format {
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
+
+/// Rotating file appender in `dir`, named `backend.<date>.log`.
+fn log_file_appender(
+    dir: &Path,
+    rotation: LogRotation,
+) -> Result<RollingFileAppender, tracing_appender::rolling::InitError> {
+    let rotation = match rotation {
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix("backend")
+        .filename_suffix("log")
+        .build(dir)
+}
+
+/// Install the global tracing subscriber: env filter, formatted logs (stdout and optionally a
+/// rotating file) and OpenTelemetry spans.
+pub async fn init_tracing(settings: &WapSettings) -> TelemetryGuard {
+    let development = settings.is_development().await;
+    let telemetry = &settings.telemetry;
+
+    let mut filter = EnvFilter::builder()
+        .with_default_directive(Level::DEBUG.into())
+        .from_env()
+        .unwrap()
+        .add_directive("sqlx=info".parse().unwrap());
+
+    if development {
+        filter = filter.add_directive("backend=debug".parse().unwrap())
+    } else {
+        filter = filter.add_directive("backend=info".parse().unwrap())
+    }
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let (provider, exporter_error) = tracer_provider(settings);
+    let tracer = provider.tracer("backend");
+
+    let mut layers = vec![fmt_layer(
+        telemetry.log_format,
+        std::io::stdout,
+        telemetry.log_format != LogFormat::Json,
+    )];
+    let mut log_file = None;
+    let mut log_file_error = None;
+    if let Some(dir) = &telemetry.log_dir {
+        match log_file_appender(dir, telemetry.log_rotation) {
+            Ok(appender) => {
+                let (writer, guard) = tracing_appender::non_blocking(appender);
+                layers.push(fmt_layer(telemetry.log_format, writer, false));
+                log_file = Some(guard);
+            }
+            Err(e) => log_file_error = Some(e),
+        }
+    }
+
+    let _r = tracing_subscriber::registry()
+        .with(filter)
+        .with(layers)
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init();
+
+    info!(
+        stage = ?settings.stage,
+        log_format = ?telemetry.log_format,
+        log_dir = ?telemetry.log_dir,
+        otlp_endpoint = ?telemetry.otlp_endpoint,
+        "Tracing initialized"
+    );
+    if let Some(e) = exporter_error {
+        warn!("{}", e);
+    }
+    if let Some(e) = log_file_error {
+        warn!(
+            "Failed to open log directory {:?}: {}",
+            telemetry.log_dir, e
+        );
+    }
+
+    TelemetryGuard {
+        provider,
+        _log_file: log_file,
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// Middleware wrapping every request in an `http_request` span.
+///
+/// The span carries the request id, continues the caller's trace when a `traceparent` header is
+/// present and leaves `user_id` empty for the auth middleware to fill in. The resulting
+/// `traceparent` is sent back so clients can correlate their logs with ours.
+pub async fn request_span(req: Request<Body>, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "http_request",
+        method = %req.method(),
+        route = %route,
+        request_id = %request_id,
+        trace_id = tracing::field::Empty,
+        user_id = tracing::field::Empty,
+    );
+
+    let parent = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(req.headers())));
+    span.set_parent(parent);
+    let span_context = span.context().span().span_context().clone();
+    if span_context.is_valid() {
+        span.record("trace_id", span_context.trace_id().to_string());
+    }
+
+    let mut response = next.run(req).instrument(span.clone()).await;
+
+    let cx = span.context();
+    global::get_text_map_propagator(|p| {
+        p.inject_context(&cx, &mut HeaderInjector(response.headers_mut()))
+    });
+    response
+}
+
+/// Attach the authenticated user to the current request span.
+pub fn record_user_id(user_id: i32) {
+    tracing::Span::current().record("user_id", user_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+    use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+
+    /// Log output collected in memory.
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The lines logged for one event in a request span with `format`.
+    fn log_lines(format: LogFormat) -> Vec<String> {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(EnvFilter::new("info"))
+            .with(fmt_layer(format, move || writer.clone(), false));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("http_request", request_id = "req-1");
+            span.in_scope(|| info!(location_id = 7, "Location saved"));
+        });
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn test_log_formats() {
+        // one JSON object per event, fields at the top level and the span under `span`
+        let lines = log_lines(LogFormat::Json);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        let line: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["message"], "Location saved");
+        assert_eq!(line["location_id"], 7);
+        assert_eq!(line["span"]["request_id"], "req-1");
+        assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+
+        let lines = log_lines(LogFormat::Compact);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(lines[0].contains("Location saved"));
+        assert!(lines[0].contains("request_id"));
+        assert!(serde_json::from_str::<serde_json::Value>(&lines[0]).is_err());
+
+        // the pretty format spreads an event over several lines
+        let lines = log_lines(LogFormat::Pretty);
+        assert!(lines.len() > 1, "{:?}", lines);
+        assert!(lines.iter().any(|l| l.contains("Location saved")));
+    }
+
+    const CALLER_TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const CALLER_SPAN_ID: &str = "00f067aa0ba902b7";
+
+    /// The trace id the handler runs under, as the response body.
+    async fn current_trace_id() -> String {
+        let cx = tracing::Span::current().context();
+        cx.span().span_context().trace_id().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_request_span() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let _subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+            .set_default();
+        // the same layers, in the same order, as the application router
+        let router = Router::new()
+            .route("/trace", get(current_trace_id))
+            .layer(axum::middleware::from_fn(request_span))
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
+        let call = |headers: &[(&'static str, &'static str)]| {
+            let mut req = Request::get("/trace");
+            for (name, value) in headers {
+                req = req.header(*name, *value);
+            }
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(req.body(Body::empty()).unwrap()).await;
+                let response = response.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let headers = response.headers().clone();
+                let body = response.into_body().collect().await.unwrap().to_bytes();
+                (headers, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        // the request id of the client is echoed, otherwise one is generated
+        let (headers, _) = call(&[(REQUEST_ID_HEADER, "client-42")]).await;
+        assert_eq!(headers[REQUEST_ID_HEADER], "client-42");
+        let (headers, _) = call(&[]).await;
+        let generated = headers[REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok(), "{}", generated);
+
+        // a traceparent makes the request span a child of the caller's span
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let (headers, trace_id) = call(&[(TRACEPARENT_HEADER, traceparent)]).await;
+        assert_eq!(trace_id, CALLER_TRACE_ID);
+        let sent = headers[TRACEPARENT_HEADER].to_str().unwrap();
+        let parts: Vec<_> = sent.split('-').collect();
+        assert_eq!(parts.len(), 4, "{}", sent);
+        assert_eq!(parts[1], CALLER_TRACE_ID);
+        assert_ne!(parts[2], CALLER_SPAN_ID);
+
+        // without a usable one a new trace starts
+        for headers in [&[][..], &[(TRACEPARENT_HEADER, "00-garbage-01")][..]] {
+            let (response_headers, trace_id) = call(headers).await;
+            assert_ne!(trace_id, CALLER_TRACE_ID);
+            assert_ne!(trace_id, opentelemetry::trace::TraceId::INVALID.to_string());
+            let sent = response_headers[TRACEPARENT_HEADER].to_str().unwrap();
+            assert!(sent.contains(&trace_id), "{}", sent);
+        }
+    }
+}
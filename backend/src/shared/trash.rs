@@ -0,0 +1,138 @@
+use crate::config::TrashSettings;
+use crate::shared::metrics::record_job_run;
+use sqlx::PgPool;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tokio::fs;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Remove an uploaded image from disk; one already gone is fine.
+pub async fn remove_upload(path: &str) {
+    match fs::remove_file(path).await {
+        Ok(()) => debug!(path, "Removed upload"),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => warn!(path, "Failed to remove upload: {}", e),
+    }
+}
+
+/// Permanently delete the locations in the trash for longer than `retention`, and the images
+/// of the phenomenon locations among them.
+///
+/// Returns how many locations were deleted.
+pub async fn purge_expired(pool: &PgPool, retention: Duration) -> Result<u64, sqlx::Error> {
+    let secs = retention.as_secs_f64();
+    let weather = sqlx::query!(
+        "DELETE FROM weather_locations WHERE deleted_at < now() - make_interval(secs => $1)",
+        secs,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    let images = sqlx::query_scalar!(
+        "DELETE FROM natural_phenomenon_locations
+         WHERE deleted_at < now() - make_interval(secs => $1)
+         RETURNING image_path",
+        secs,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // the rows are gone first, so a failed removal leaves a stray file but never a broken row
+    for path in images.iter().flatten() {
+        remove_upload(path).await;
+    }
+    Ok(weather + images.len() as u64)
+}
+
+/// Periodically purge the locations kept in the trash longer than the retention.
+///
+/// The task stops when `token` is cancelled.
+pub fn spawn_trash_purge(pool: PgPool, settings: &TrashSettings, token: CancellationToken) {
+    let interval = settings.purge_interval;
+    let retention = settings.retention;
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+
+            match purge_expired(&pool, retention).await {
+                Ok(deleted) => {
+                    debug!(deleted, "Purged trashed locations");
+                    record_job_run("trash_purge", "success");
+                }
+                Err(e) => {
+                    warn!("Failed to purge trashed locations: {}", e);
+                    record_job_run("trash_purge", "failure");
+                }
+            }
+        }
+        debug!("Trash purge stopped");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_purge_expired(pool: PgPool) {
+        // sqlx tests run on async-std, removing images needs the blocking pool of tokio
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _tokio = runtime.enter();
+        let user: i32 = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash) VALUES ('trash@wap.com', 'pass') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut images = Vec::new();
+        for (name, deleted) in [("old", Some(40)), ("recent", Some(1)), ("live", None)] {
+            let path = dir.path().join(format!("{}.jpg", name));
+            std::fs::write(&path, b"jpeg").unwrap();
+            sqlx::query(
+                "INSERT INTO natural_phenomenon_locations
+                     (user_id, name, latitude, longitude, radius, image_path, deleted_at)
+                 VALUES ($1, $2, 46.9, 7.4, 10, $3, now() - make_interval(days => $4))",
+            )
+            .bind(user)
+            .bind(name)
+            .bind(path.to_str().unwrap())
+            .bind(deleted)
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query(
+                "INSERT INTO weather_locations (user_id, name, latitude, longitude, deleted_at)
+                 VALUES ($1, $2, 46.9, 7.4, now() - make_interval(days => $3))",
+            )
+            .bind(user)
+            .bind(name)
+            .bind(deleted)
+            .execute(&pool)
+            .await
+            .unwrap();
+            images.push(path);
+        }
+
+        // only what was deleted before the retention goes, with its image
+        let deleted = purge_expired(&pool, Duration::from_secs(30 * 24 * 60 * 60))
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+        let exists: Vec<bool> = images.iter().map(|path| path.exists()).collect();
+        assert_eq!(exists, [false, true, true]);
+        let left: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM weather_locations UNION ALL SELECT name FROM natural_phenomenon_locations
+             ORDER BY 1",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(left, ["live", "live", "recent", "recent"]);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::shared::error::{ApiError, FieldError};
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Query, Request};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::de::DeserializeOwned;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+/// `Json` extractor that also runs the `validator` rules of `T`.
+///
+/// Malformed bodies and failed rules are rejected with an `ApiError`, so handlers only ever see
+/// valid payloads.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        value.validate()?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// `Query` extractor that also runs the `validator` rules of `T`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        value.validate()?;
+        Ok(ValidatedQuery(value))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection.status() {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                ApiError::UnsupportedMediaType(rejection.body_text())
+            }
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge(rejection.body_text()),
+            // the JSON is well-formed but does not match the expected shape
+            StatusCode::UNPROCESSABLE_ENTITY => ApiError::Validation(vec![FieldError::new(
+                "body",
+                "invalid",
+                rejection.body_text(),
+            )]),
+            _ => ApiError::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::Validation(vec![FieldError::new(
+            "query",
+            "invalid",
+            rejection.body_text(),
+        )])
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        ApiError::Validation(field_errors(&errors))
+    }
+}
+
+/// Custom rule for numbers parsed from text: `range` lets `NaN` through, as every comparison
+/// with it is false, and rejects infinities only by luck of the bounds.
+pub fn finite(value: f64) -> Result<(), ValidationError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("finite"))
+    }
+}
+
+/// Flatten (possibly nested) validation errors into one entry per failed rule.
+///
+/// Nested fields are reported with dotted paths (`payload.name`), list items with their index
+/// (`items[2].name`).
+pub fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut out = Vec::new();
+    collect(errors, "", &mut out);
+    out.sort_by(|a, b| a.field.cmp(&b.field));
+    out
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = format!("{}{}", prefix, field);
+        match kind {
+            ValidationErrorsKind::Field(errs) => out.extend(
+                errs.iter()
+                    .map(|e| FieldError::new(path.clone(), e.code.to_string(), message(e))),
+            ),
+            ValidationErrorsKind::Struct(inner) => collect(inner, &format!("{}.", path), out),
+            ValidationErrorsKind::List(items) => {
+                for (index, inner) in items {
+                    collect(inner, &format!("{}[{}].", path, index), out)
+                }
+            }
+        }
+    }
+}
+
+/// The rule's own message, or one derived from its code and parameters.
+fn message(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+    match error.code.as_ref() {
+        "email" => "must be a valid email address".to_string(),
+        "finite" => "must be a finite number".to_string(),
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must be between {} and {}", min, max),
+            (Some(min), None) => format!("must be at least {}", min),
+            (None, Some(max)) => format!("must be at most {}", max),
+            (None, None) => "is out of range".to_string(),
+        },
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => {
+                format!("length must be between {} and {}", min, max)
+            }
+            (Some(min), None) => format!("must be at least {} characters long", min),
+            (None, Some(max)) => format!("must be at most {} characters long", max),
+            (None, None) => "has an invalid length".to_string(),
+        },
+        code => format!("failed the `{}` rule", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::natural_phenomenon_locations::models::PostNaturalPhenomenonLocationForm;
+    use crate::routes::weather_locations::models::CreateWeatherLocationRequest;
+    use crate::shared::models::DatabaseId;
+
+    #[test]
+    fn test_validation_errors_are_reported_per_field() {
+        let request = CreateWeatherLocationRequest {
+            user_id: DatabaseId(1),
+            name: String::new(),
+            latitude: 91.0,
+            longitude: -180.0,
+            is_default: false,
+            description: String::new(),
+        };
+
+        let errors = field_errors(&request.validate().unwrap_err());
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["latitude", "name"]);
+        assert_eq!(errors[0].code, "range");
+        assert_eq!(errors[0].message, "must be between -90.0 and 90.0");
+
+        let api_error = ApiError::from(request.validate().unwrap_err());
+        assert_eq!(api_error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn test_non_finite_coordinates_are_rejected() {
+        // form fields are parsed from text, where "NaN" is a valid f64
+        let form = PostNaturalPhenomenonLocationForm {
+            name: "Etna".to_string(),
+            latitude: "NaN".parse().unwrap(),
+            longitude: f64::INFINITY,
+            description: String::new(),
+            phenomenon_type: Default::default(),
+            radius: 10,
+            area: None,
+            image: None,
+        };
+
+        let errors = field_errors(&form.validate().unwrap_err());
+        let fields: Vec<_> = errors
+            .iter()
+            .map(|e| (e.field.as_str(), e.code.as_str()))
+            .collect();
+        assert!(fields.contains(&("latitude", "finite")), "{:?}", fields);
+        assert!(fields.contains(&("longitude", "finite")), "{:?}", fields);
+        assert_eq!(errors[0].message, "must be a finite number");
+    }
+}
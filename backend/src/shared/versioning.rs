@@ -0,0 +1,168 @@
+use crate::config::ApiSettings;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderName, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use utoipa::openapi::OpenApi;
+
+/// Prefix of the current API version.
+pub const API_V1_PREFIX: &str = "/api/v1";
+
+pub const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+/// Routes of one API version together with their OpenAPI document.
+pub struct ApiVersion {
+    /// Path prefix, e.g. `/api/v1`.
+    pub prefix: &'static str,
+    pub router: Router,
+    pub docs: OpenApi,
+}
+
+impl ApiVersion {
+    /// Version mounted at `prefix`; the paths of `docs` are relative to it.
+    pub fn new(prefix: &'static str, router: Router, mut docs: OpenApi) -> Self {
+        docs.paths.paths = std::mem::take(&mut docs.paths.paths)
+            .into_iter()
+            .map(|(path, item)| (format!("{}{}", prefix, path), item))
+            .collect();
+        ApiVersion {
+            prefix,
+            router,
+            docs,
+        }
+    }
+
+    /// The routes under the prefix plus the OpenAPI document at `<prefix>/openapi.json`.
+    pub fn into_router(self) -> Router {
+        let docs = self.docs;
+        Router::new().nest(self.prefix, self.router).route(
+            &format!("{}/openapi.json", self.prefix),
+            get(|| async move { Json(docs) }),
+        )
+    }
+
+    /// The routes at their unprefixed legacy paths, marked as deprecated.
+    ///
+    /// Empty when legacy routes are disabled.
+    pub fn legacy_router(&self, settings: &ApiSettings) -> Router {
+        if !settings.legacy_routes {
+            return Router::new();
+        }
+        let legacy = LegacyRoutes {
+            successor_prefix: self.prefix,
+            settings: settings.clone(),
+        };
+        self.router
+            .clone()
+            .layer(axum::middleware::from_fn_with_state(
+                legacy,
+                deprecate_legacy,
+            ))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LegacyRoutes {
+    successor_prefix: &'static str,
+    settings: ApiSettings,
+}
+
+/// Middleware adding `Deprecation`, `Sunset` and a `successor-version` link to legacy responses.
+async fn deprecate_legacy(
+    State(legacy): State<LegacyRoutes>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let successor = match req.uri().query() {
+        Some(query) => format!("{}{}?{}", legacy.successor_prefix, req.uri().path(), query),
+        None => format!("{}{}", legacy.successor_prefix, req.uri().path()),
+    };
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    if let Some(deprecated_at) = legacy.settings.legacy_deprecated_at {
+        // RFC 9745: a structured field date, seconds since the epoch
+        if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecated_at.timestamp())) {
+            headers.insert(DEPRECATION_HEADER, value);
+        }
+    }
+    if let Some(sunset) = legacy.settings.legacy_sunset {
+        if let Ok(value) = HeaderValue::from_str(&http_date(sunset)) {
+            headers.insert(SUNSET_HEADER, value);
+        }
+    }
+    // appended, list responses already carry pagination links
+    if let Ok(value) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.append(header::LINK, value);
+    }
+    response
+}
+
+/// IMF-fixdate of RFC 9110, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use tower::ServiceExt;
+    use utoipa::openapi::path::{HttpMethod, OperationBuilder, PathItem, PathsBuilder};
+    use utoipa::openapi::OpenApiBuilder;
+
+    #[tokio::test]
+    async fn test_versioned_and_legacy_routes() {
+        let docs = OpenApiBuilder::new()
+            .paths(PathsBuilder::new().path(
+                "/items",
+                PathItem::new(HttpMethod::Get, OperationBuilder::new().build()),
+            ))
+            .build();
+        let version = ApiVersion::new(
+            API_V1_PREFIX,
+            Router::new().route("/items", get(|| async { "items" })),
+            docs,
+        );
+        assert!(version.docs.paths.paths.contains_key("/api/v1/items"));
+
+        let settings = ApiSettings {
+            legacy_routes: true,
+            legacy_deprecated_at: Some("2026-10-01T00:00:00Z".parse().unwrap()),
+            legacy_sunset: Some("2027-04-01T00:00:00Z".parse().unwrap()),
+        };
+        let router = Router::new()
+            .merge(version.legacy_router(&settings))
+            .merge(version.into_router());
+        let call = |uri: &'static str| {
+            router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = call("/api/v1/items").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(DEPRECATION_HEADER).is_none());
+
+        let response = call("/items?limit=2").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[DEPRECATION_HEADER], "@1790812800");
+        assert_eq!(
+            response.headers()[SUNSET_HEADER],
+            "Thu, 01 Apr 2027 00:00:00 GMT"
+        );
+        assert_eq!(
+            response.headers()[header::LINK],
+            "</api/v1/items?limit=2>; rel=\"successor-version\""
+        );
+
+        let response = call("/api/v1/openapi.json").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -46,8 +46,20 @@ pub mod tests {
                 google_oauth_client_id: None,
                 google_oauth_client_secret: None,
                 google_oauth_redirect_url: None,
+                shutdown_drain: Default::default(),
                 stage: crate::shared::models::AppStage::Testing,
+                db_pool: Default::default(),
+                telemetry: Default::default(),
+                rate_limit: Default::default(),
+                http: Default::default(),
+                api: Default::default(),
+                idempotency: Default::default(),
+                concurrency: Default::default(),
+                metrics: Default::default(),
+                trash: Default::default(),
             },
+            shutdown: Default::default(),
+            started_at: chrono::Utc::now(),
         }
     }
 
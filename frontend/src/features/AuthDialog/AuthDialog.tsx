@@ -121,8 +121,7 @@ export function AuthDialog({ open = false, onOpenChange }: AuthDialogProps) {
                 err &&
                 err.response &&
                 err.response.data &&
-                err.response.data.type &&
-                err.response.data.type === 'UserAlreadyExists'
+                err.response.data.code === 'already_exists'
             ) {
                 setError('User already exists');
             } else {
@@ -3,6 +3,8 @@ import * as jose from 'jose';
 import { logger } from './logger';
 import useAuthStore from './store/auth';
 
+// Versioned API prefix, proxied to the backend as is
+const API_PREFIX = '/api/v1';
 const EXPIRATION_THRESHOLD = 60 * 5; // in seconds
 
 const isTokenExpired = (token: string): boolean => {
@@ -51,7 +53,7 @@ export const checkToken = () => {
 
         axios({
             method: 'post',
-            url: `/api/auth/refresh`,
+            url: `${API_PREFIX}/auth/refresh`,
             headers: {
                 Authorization: `Bearer ${refreshToken}`,
             },
@@ -68,7 +70,7 @@ export const checkToken = () => {
 };
 
 const api = axios.create({
-    baseURL: '/api',
+    baseURL: API_PREFIX,
     withCredentials: true,
 });
 
@@ -84,4 +86,26 @@ api.interceptors.request.use((config) => {
     return config;
 });
 
+/** One page of a list endpoint. */
+export type Page<T> = {
+    items: T[];
+    next_cursor: string | null;
+};
+
+/** Fetch every page of a list endpoint by following `next_cursor`. */
+export const getAllPages = async <T>(url: string, params: Record<string, string | number> = {}) => {
+    const items: T[] = [];
+    let cursor: string | null = null;
+
+    do {
+        const res: { data: Page<T> } = await api.get<Page<T>>(url, {
+            params: { limit: 200, ...params, ...(cursor ? { cursor } : {}) },
+        });
+        items.push(...res.data.items);
+        cursor = res.data.next_cursor;
+    } while (cursor);
+
+    return items;
+};
+
 export default api;
@@ -6,10 +6,16 @@ export function cn(...inputs: ClassValue[]) {
 }
 
 export const getErrorMessage = (err: unknown, fallback: string) => {
-    // Axios-like error shape
+    // Axios-like error with an application/problem+json body
     // eslint-disable-next-line @typescript-eslint/no-explicit-any
     const maybe = err as any;
-    return maybe?.response?.data?.data || maybe?.message || fallback;
+    const problem = maybe?.response?.data;
+    // validation problems list the offending fields
+    const field = problem?.errors?.[0];
+    if (field) {
+        return `${field.field} ${field.message}`;
+    }
+    return problem?.detail || maybe?.message || fallback;
 };
 
 export const numberPreprocess = (val: unknown) => {
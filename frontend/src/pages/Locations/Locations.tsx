@@ -3,7 +3,7 @@ import Map from '@/components/Map';
 import { Button } from '@/components/ui/button';
 import { Tabs, TabsList, TabsTrigger } from '@/components/ui/tabs';
 import { useToast } from '@/hooks/use-toast';
-import api from '@/lib/api';
+import api, { getAllPages } from '@/lib/api';
 import useAuthStore from '@/lib/store/auth';
 import useLocationStore from '@/lib/store/location';
 import { LayoutGrid, LayoutPanelLeftIcon, PlusIcon } from 'lucide-react';
@@ -43,8 +43,7 @@ function Locations() {
 
     const fetchLocations = async () => {
         try {
-            const res = await api.get('/natural_phenomenon_locations');
-            const data = res.data;
+            const data = await getAllPages<any>('/natural_phenomenon_locations');
 
             const failed = await fetchLocationImages(data);
             setLocations(data);
@@ -1,4 +1,4 @@
-import api from '@/lib/api';
+import api, { getAllPages } from '@/lib/api';
 import useAuthStore, { useIsAuthorized } from '@/lib/store/auth';
 import WeatherDashboard from '@/pages/Weather/WeatherDashboard.tsx';
 import WeatherSelect from '@/pages/Weather/WeatherSelect.tsx';
@@ -50,9 +50,7 @@ function Weather() {
         setHasMounted(false);
 
         try {
-            const res = await api.get('/weather_locations');
-
-            const data: GetLocationDB[] = res.data;
+            const data = await getAllPages<GetLocationDB>('/weather_locations');
 
             const locationsDB: Location[] = data.map((loc) => ({
                 id: loc.id,
@@ -17,7 +17,6 @@ export default defineConfig(({mode}) => {
                 '/api': {
                     target: env.VITE_API_URL,
                     changeOrigin: true,
-                    rewrite: (path) => path.replace(/^\/api/, ''),
                 },
             },
         },